@@ -56,7 +56,7 @@ fn main() {
 
 fn git_revision_hash() -> Option<String> {
     let result = process::Command::new("git")
-        .args(&["rev-parse", "--short=10", "HEAD"])
+        .args(["rev-parse", "--short=10", "HEAD"])
         .output();
     result.ok().and_then(|output| {
         let v = String::from_utf8_lossy(&output.stdout).trim().to_string();
@@ -89,7 +89,7 @@ fn generate_man_page<P: AsRef<Path>>(outdir: P) -> io::Result<()> {
     tpl = tpl.replace("{OPTIONS}", &formatted_options()?);
 
     let githash = git_revision_hash();
-    let githash = githash.as_ref().map(|x| &**x);
+    let githash = githash.as_deref();
     tpl = tpl.replace("{VERSION}", &app::long_version(githash));
 
     File::create(&txt_path)?.write_all(tpl.as_bytes())?;
@@ -109,7 +109,7 @@ fn generate_man_page<P: AsRef<Path>>(outdir: P) -> io::Result<()> {
 
 fn formatted_options() -> io::Result<String> {
     let mut args = app::all_args_and_flags();
-    args.sort_by(|x1, x2| x1.name.cmp(&x2.name));
+    args.sort_by(|x1, x2| x1.name.cmp(x2.name));
 
     let mut formatted = vec![];
     for arg in args {
@@ -180,5 +180,5 @@ fn formatted_doc_txt(arg: &RGArg) -> io::Result<String> {
 }
 
 fn ioerr(msg: String) -> io::Error {
-    io::Error::new(io::ErrorKind::Other, msg)
+    io::Error::other(msg)
 }
@@ -63,7 +63,7 @@ impl MatchStrategy {
         } else if let Some(prefix) = pat.prefix() {
             MatchStrategy::Prefix(prefix)
         } else if let Some((suffix, component)) = pat.suffix() {
-            MatchStrategy::Suffix { suffix: suffix, component: component }
+            MatchStrategy::Suffix { suffix, component }
         } else if let Some(ext) = pat.required_ext() {
             MatchStrategy::RequiredExtension(ext)
         } else {
@@ -107,6 +107,7 @@ impl fmt::Display for Glob {
 #[derive(Clone, Debug)]
 pub struct GlobMatcher {
     /// The underlying pattern.
+    #[allow(dead_code)]
     pat: Glob,
     /// The pattern, as a compiled regex.
     re: Regex,
@@ -131,6 +132,7 @@ struct GlobStrategic {
     /// The match strategy to use.
     strategy: MatchStrategy,
     /// The underlying pattern.
+    #[allow(dead_code)]
     pat: Glob,
     /// The pattern, as a compiled regex.
     re: Regex,
@@ -248,7 +250,7 @@ impl Glob {
             .expect("regex compilation shouldn't fail");
         GlobMatcher {
             pat: self.clone(),
-            re: re,
+            re,
         }
     }
 
@@ -263,9 +265,9 @@ impl Glob {
         let re = new_regex(&self.re)
             .expect("regex compilation shouldn't fail");
         GlobStrategic {
-            strategy: strategy,
+            strategy,
             pat: self.clone(),
-            re: re,
+            re,
         }
     }
 
@@ -312,7 +314,7 @@ impl Glob {
         if self.opts.case_insensitive {
             return None;
         }
-        let start = match self.tokens.get(0) {
+        let start = match self.tokens.first() {
             Some(&Token::RecursivePrefix) => 1,
             Some(_) => 0,
             _ => return None,
@@ -429,7 +431,7 @@ impl Glob {
             return None;
         }
         let mut lit = String::new();
-        let (start, entire) = match self.tokens.get(0) {
+        let (start, entire) = match self.tokens.first() {
             Some(&Token::RecursivePrefix) => {
                 // We only care if this follows a path component if the next
                 // token is a literal.
@@ -482,7 +484,7 @@ impl Glob {
         if self.opts.case_insensitive {
             return None;
         }
-        let start = match self.tokens.get(0) {
+        let start = match self.tokens.first() {
             Some(&Token::RecursivePrefix) => 1,
             _ => {
                 // With nothing to gobble up the parent portion of a path,
@@ -528,10 +530,7 @@ impl Glob {
     /// The basic format of these patterns is `**/{literal}`, where `{literal}`
     /// does not contain a path separator.
     fn basename_literal(&self) -> Option<String> {
-        let tokens = match self.basename_tokens() {
-            None => return None,
-            Some(tokens) => tokens,
-        };
+        let tokens = self.basename_tokens()?;
         let mut lit = String::new();
         for t in tokens {
             match *t {
@@ -549,7 +548,7 @@ impl<'a> GlobBuilder<'a> {
     /// The pattern is not compiled until `build` is called.
     pub fn new(glob: &'a str) -> GlobBuilder<'a> {
         GlobBuilder {
-            glob: glob,
+            glob,
             opts: GlobOptions::default(),
         }
     }
@@ -557,7 +556,7 @@ impl<'a> GlobBuilder<'a> {
     /// Parses and builds the pattern.
     pub fn build(&self) -> Result<Glob, Error> {
         let mut p = Parser {
-            glob: &self.glob,
+            glob: self.glob,
             stack: vec![Tokens::default()],
             chars: self.glob.chars().peekable(),
             prev: None,
@@ -581,7 +580,7 @@ impl<'a> GlobBuilder<'a> {
                 glob: self.glob.to_string(),
                 re: tokens.to_regex_with(&self.opts),
                 opts: self.opts,
-                tokens: tokens,
+                tokens,
             })
         }
     }
@@ -632,7 +631,7 @@ impl Tokens {
             re.push('$');
             return re;
         }
-        self.tokens_to_regex(options, &self, &mut re);
+        self.tokens_to_regex(options, self, &mut re);
         re.push('$');
         re
     }
@@ -652,7 +651,7 @@ impl Tokens {
                     if options.literal_separator {
                         re.push_str("[^/]");
                     } else {
-                        re.push_str(".");
+                        re.push('.');
                     }
                 }
                 Token::ZeroOrMore => {
@@ -692,7 +691,7 @@ impl Tokens {
                     let mut parts = vec![];
                     for pat in patterns {
                         let mut altre = String::new();
-                        self.tokens_to_regex(options, &pat, &mut altre);
+                        self.tokens_to_regex(options, pat, &mut altre);
                         if !altre.is_empty() {
                             parts.push(altre);
                         }
@@ -742,7 +741,7 @@ struct Parser<'a> {
 
 impl<'a> Parser<'a> {
     fn error(&self, kind: ErrorKind) -> Error {
-        Error { glob: Some(self.glob.to_string()), kind: kind }
+        Error { glob: Some(self.glob.to_string()), kind }
     }
 
     fn parse(&mut self) -> Result<(), Error> {
@@ -765,7 +764,8 @@ impl<'a> Parser<'a> {
         if self.stack.len() > 1 {
             return Err(self.error(ErrorKind::NestedAlternates));
         }
-        Ok(self.stack.push(Tokens::default()))
+        self.stack.push(Tokens::default());
+        Ok(())
     }
 
     fn pop_alternate(&mut self) -> Result<(), Error> {
@@ -778,7 +778,10 @@ impl<'a> Parser<'a> {
 
     fn push_token(&mut self, tok: Token) -> Result<(), Error> {
         if let Some(ref mut pat) = self.stack.last_mut() {
-            return Ok(pat.push(tok));
+            return {
+                let _: () = pat.push(tok);
+                Ok(())
+            };
         }
         Err(self.error(ErrorKind::UnopenedAlternates))
     }
@@ -793,7 +796,7 @@ impl<'a> Parser<'a> {
     fn have_tokens(&self) -> Result<bool, Error> {
         match self.stack.last() {
             None => Err(self.error(ErrorKind::UnopenedAlternates)),
-            Some(ref pat) => Ok(!pat.is_empty()),
+            Some(pat) => Ok(!pat.is_empty()),
         }
     }
 
@@ -804,7 +807,8 @@ impl<'a> Parser<'a> {
         if self.stack.len() <= 1 {
             self.push_token(Token::Literal(','))
         } else {
-            Ok(self.stack.push(Tokens::default()))
+            self.stack.push(Tokens::default());
+            Ok(())
         }
     }
 
@@ -838,12 +842,11 @@ impl<'a> Parser<'a> {
             return Ok(());
         }
         self.pop_token()?;
-        if !prev.map(is_separator).unwrap_or(false) {
-            if self.stack.len() <= 1
-                || (prev != Some(',') && prev != Some('{')) {
+        if !prev.map(is_separator).unwrap_or(false)
+            && (self.stack.len() <= 1
+                || (prev != Some(',') && prev != Some('{'))) {
                 return Err(self.error(ErrorKind::InvalidRecursive));
             }
-        }
         match self.chars.peek() {
             None => {
                 assert!(self.bump().is_none());
@@ -909,7 +912,7 @@ impl<'a> Parser<'a> {
                         // invariant: in_range is only set when there is
                         // already at least one character seen.
                         let r = ranges.last_mut().unwrap();
-                        add_to_last_range(&self.glob, r, '-')?;
+                        add_to_last_range(self.glob, r, '-')?;
                         in_range = false;
                     } else {
                         assert!(!ranges.is_empty());
@@ -921,7 +924,7 @@ impl<'a> Parser<'a> {
                         // invariant: in_range is only set when there is
                         // already at least one character seen.
                         add_to_last_range(
-                            &self.glob, ranges.last_mut().unwrap(), c)?;
+                            self.glob, ranges.last_mut().unwrap(), c)?;
                     } else {
                         ranges.push((c, c));
                     }
@@ -936,8 +939,8 @@ impl<'a> Parser<'a> {
             ranges.push(('-', '-'));
         }
         self.push_token(Token::Class {
-            negated: negated,
-            ranges: ranges,
+            negated,
+            ranges,
         })
     }
 
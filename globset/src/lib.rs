@@ -181,7 +181,7 @@ impl StdError for Error {
 impl Error {
     /// Return the glob that caused this error, if one exists.
     pub fn glob(&self) -> Option<&str> {
-        self.glob.as_ref().map(|s| &**s)
+        self.glob.as_deref()
     }
 
     /// Return the kind of this error.
@@ -454,6 +454,12 @@ pub struct GlobSetBuilder {
     pats: Vec<Glob>,
 }
 
+impl Default for GlobSetBuilder {
+    fn default() -> GlobSetBuilder {
+        GlobSetBuilder::new()
+    }
+}
+
 impl GlobSetBuilder {
     /// Create a new GlobSetBuilder. A GlobSetBuilder can be used to add new
     /// patterns. Once all patterns have been added, `build` should be called
@@ -504,7 +510,7 @@ impl<'a> Candidate<'a> {
 
     fn path_prefix(&self, max: usize) -> &[u8] {
         if self.path.len() <= max {
-            &*self.path
+            &self.path
         } else {
             &self.path[..max]
         }
@@ -512,7 +518,7 @@ impl<'a> Candidate<'a> {
 
     fn path_suffix(&self, max: usize) -> &[u8] {
         if self.path.len() <= max {
-            &*self.path
+            &self.path
         } else {
             &self.path[self.path.len() - max..]
         }
@@ -709,8 +715,8 @@ impl RequiredExtensionStrategy {
         match self.0.get(&*candidate.ext) {
             None => false,
             Some(regexes) => {
-                for &(_, ref re) in regexes {
-                    if re.is_match(&*candidate.path) {
+                for (_, re) in regexes {
+                    if re.is_match(&candidate.path) {
                         return true;
                     }
                 }
@@ -726,7 +732,7 @@ impl RequiredExtensionStrategy {
         }
         if let Some(regexes) = self.0.get(&*candidate.ext) {
             for &(global_index, ref re) in regexes {
-                if re.is_match(&*candidate.path) {
+                if re.is_match(&candidate.path) {
                     matches.push(global_index);
                 }
             }
@@ -742,11 +748,11 @@ struct RegexSetStrategy {
 
 impl RegexSetStrategy {
     fn is_match(&self, candidate: &Candidate) -> bool {
-        self.matcher.is_match(&*candidate.path)
+        self.matcher.is_match(&candidate.path)
     }
 
     fn matches_into(&self, candidate: &Candidate, matches: &mut Vec<usize>) {
-        for i in self.matcher.matches(&*candidate.path) {
+        for i in self.matcher.matches(&candidate.path) {
             matches.push(self.map[i]);
         }
     }
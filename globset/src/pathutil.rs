@@ -7,20 +7,17 @@ use std::path::Path;
 /// If the path terminates in ., .., or consists solely of a root of prefix,
 /// file_name will return None.
 #[cfg(unix)]
-pub fn file_name<'a, P: AsRef<Path> + ?Sized>(
-    path: &'a P,
-) -> Option<&'a OsStr> {
+pub fn file_name<P: AsRef<Path> + ?Sized>(
+    path: &P,
+) -> Option<&OsStr> {
     use std::os::unix::ffi::OsStrExt;
     use memchr::memrchr;
 
     let path = path.as_ref().as_os_str().as_bytes();
-    if path.is_empty() {
-        return None;
-    } else if path.len() == 1 && path[0] == b'.' {
-        return None;
-    } else if path.last() == Some(&b'.') {
-        return None;
-    } else if path.len() >= 2 && &path[path.len() - 2..] == &b".."[..] {
+    if path.is_empty()
+        || path.last() == Some(&b'.')
+        || (path.len() >= 2 && path[path.len() - 2..] == b".."[..])
+    {
         return None;
     }
     let last_slash = memrchr(b'/', path).map(|i| i + 1).unwrap_or(0);
@@ -54,7 +51,7 @@ pub fn file_name<'a, P: AsRef<Path> + ?Sized>(
 /// a pattern like `*.rs` is obviously trying to match files with a `rs`
 /// extension, but it also matches files like `.rs`, which doesn't have an
 /// extension according to std::path::Path::extension.
-pub fn file_name_ext(name: &OsStr) -> Option<Cow<[u8]>> {
+pub fn file_name_ext(name: &OsStr) -> Option<Cow<'_, [u8]>> {
     if name.is_empty() {
         return None;
     }
@@ -64,10 +61,7 @@ pub fn file_name_ext(name: &OsStr) -> Option<Cow<[u8]>> {
             .iter().enumerate().rev()
             .find(|&(_, &b)| b == b'.')
             .map(|(i, _)| i);
-        match result {
-            None => return None,
-            Some(i) => i,
-        }
+        result?
     };
     Some(match name {
         Cow::Borrowed(name) => Cow::Borrowed(&name[last_dot_at..]),
@@ -79,13 +73,13 @@ pub fn file_name_ext(name: &OsStr) -> Option<Cow<[u8]>> {
 }
 
 /// Return raw bytes of a path, transcoded to UTF-8 if necessary.
-pub fn path_bytes(path: &Path) -> Cow<[u8]> {
+pub fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
     os_str_bytes(path.as_os_str())
 }
 
 /// Return the raw bytes of the given OS string, possibly transcoded to UTF-8.
 #[cfg(unix)]
-pub fn os_str_bytes(s: &OsStr) -> Cow<[u8]> {
+pub fn os_str_bytes(s: &OsStr) -> Cow<'_, [u8]> {
     use std::os::unix::ffi::OsStrExt;
     Cow::Borrowed(s.as_bytes())
 }
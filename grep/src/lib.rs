@@ -14,9 +14,11 @@ use std::error;
 use std::fmt;
 use std::result;
 
+pub use matcher::{DynMatcher, Matcher};
 pub use search::{Grep, GrepBuilder, Iter, Match};
 
 mod literals;
+mod matcher;
 mod nonl;
 mod search;
 mod smart_case;
@@ -29,6 +31,7 @@ pub type Result<T> = result::Result<T, Error>;
 /// Error enumerates the list of possible error conditions when building or
 /// using a `Grep` line searcher.
 #[derive(Debug)]
+#[allow(clippy::manual_non_exhaustive)]
 pub enum Error {
     /// An error from parsing or compiling a regex.
     Regex(regex::Error),
@@ -43,17 +46,9 @@ pub enum Error {
 }
 
 impl error::Error for Error {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            Error::Regex(ref err) => err.description(),
-            Error::LiteralNotAllowed(_) => "use of forbidden literal",
-            Error::__Nonexhaustive => unreachable!(),
-        }
-    }
-
-    fn cause(&self) -> Option<&error::Error> {
-        match *self {
-            Error::Regex(ref err) => err.cause(),
+            Error::Regex(ref err) => Some(err),
             _ => None,
         }
     }
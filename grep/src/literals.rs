@@ -27,7 +27,7 @@ impl LiteralSets {
         LiteralSets {
             prefixes: Literals::prefixes(expr),
             suffixes: Literals::suffixes(expr),
-            required: required,
+            required,
         }
     }
 
@@ -86,7 +86,7 @@ impl LiteralSets {
         if req.len() > lit.len() && req_lits.len() > 1 && !any_empty {
             debug!("required literals found: {:?}", req_lits);
             let alts: Vec<String> =
-                req_lits.into_iter().map(|x| bytes_to_regex(x)).collect();
+                req_lits.iter().map(|x| bytes_to_regex(x)).collect();
             let mut builder = RegexBuilder::new(&alts.join("|"));
             builder.unicode(false);
             Some(builder)
@@ -94,7 +94,7 @@ impl LiteralSets {
             None
         } else {
             debug!("required literal found: {:?}", show(lit));
-            let mut builder = RegexBuilder::new(&bytes_to_regex(&lit));
+            let mut builder = RegexBuilder::new(&bytes_to_regex(lit));
             builder.unicode(false);
             Some(builder)
         }
@@ -121,7 +121,7 @@ fn union_required(expr: &Hir, lits: &mut Literals) {
             }
         }
         HirKind::Group(hir::Group { ref hir, .. }) => {
-            union_required(&**hir, lits);
+            union_required(hir, lits);
         }
         HirKind::Repetition(ref x) => {
             match x.kind {
@@ -197,7 +197,7 @@ fn repeat_range_literals<F: FnMut(&Hir, &mut Literals)>(
         if n < min as usize {
             lits.cut();
         }
-        if max.map_or(true, |max| min < max) {
+        if max.is_none_or(|max| min < max) {
             lits.cut();
         }
     }
@@ -0,0 +1,293 @@
+/*!
+The matcher module defines an object safe subset of `Grep`'s searching
+interface, along with an adapter for storing heterogeneous matchers behind a
+single concrete type.
+*/
+
+use regex::bytes::Regex;
+
+use search::{Grep, Match};
+
+/// Matcher is an object safe subset of `Grep`'s searching interface.
+///
+/// It exists so that applications can supply their own line matchers (e.g.,
+/// loaded as plugins) and store them heterogeneously via `DynMatcher`. The
+/// searchers in this crate's consumers are generic over this trait, with
+/// `Grep` as the canonical implementation.
+pub trait Matcher {
+    /// Fills in the next line that matches in the given buffer starting at
+    /// the position given.
+    ///
+    /// The range reported must correspond to whole lines, including their
+    /// terminators (except possibly at the end of the buffer). If no match
+    /// could be found, `false` is returned, otherwise, `true` is returned.
+    fn read_match(&self, mat: &mut Match, buf: &[u8], start: usize) -> bool;
+
+    /// Returns true if and only if the given buffer contains a match.
+    ///
+    /// Implementors may override this with something faster than finding the
+    /// enclosing line of the first match and throwing it away.
+    fn is_match(&self, buf: &[u8]) -> bool {
+        let mut mat = Match::default();
+        self.read_match(&mut mat, buf, 0)
+    }
+
+    /// Returns the end offset of the line containing the next match at or
+    /// after `start`, if any.
+    ///
+    /// Callers that only count matching lines use this to skip from one
+    /// matching line to the next without paying for anything else. The
+    /// default implementation delegates to `read_match`; implementors may
+    /// override it with something cheaper.
+    fn shortest_match(&self, buf: &[u8], start: usize) -> Option<usize> {
+        let mut mat = Match::default();
+        if self.read_match(&mut mat, buf, start) {
+            Some(mat.end())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the underlying regex, if there is one.
+    ///
+    /// This is a capability query: callers use the regex for specialized
+    /// behavior such as resolving the position of a match within a line
+    /// (column numbers, printing only the matching part, replacements) or
+    /// counting individual matches. Matchers that aren't backed by a regex
+    /// should return `None` (the default), in which case callers degrade to
+    /// whole line reporting.
+    fn regex(&self) -> Option<&Regex> {
+        None
+    }
+
+    /// Returns the line terminator this matcher assumes, if any.
+    ///
+    /// A matcher built for one terminator mis-identifies line boundaries
+    /// when run against input delimited by a different byte, so searchers
+    /// check this against their own configured terminator before
+    /// searching and reject a conflict. The default is `None`: the
+    /// matcher makes no assumption and works with any terminator.
+    fn line_terminator(&self) -> Option<u8> {
+        None
+    }
+}
+
+/// References to matchers are matchers, so `&dyn Matcher` can be handed
+/// to the searchers directly without generics leaking into every caller.
+/// Dispatch happens once per candidate line; the per-byte scanning all
+/// lives inside the wrapped matcher.
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn read_match(&self, mat: &mut Match, buf: &[u8], start: usize) -> bool {
+        (**self).read_match(mat, buf, start)
+    }
+
+    fn is_match(&self, buf: &[u8]) -> bool {
+        (**self).is_match(buf)
+    }
+
+    fn shortest_match(&self, buf: &[u8], start: usize) -> Option<usize> {
+        (**self).shortest_match(buf, start)
+    }
+
+    fn regex(&self) -> Option<&Regex> {
+        (**self).regex()
+    }
+
+    fn line_terminator(&self) -> Option<u8> {
+        (**self).line_terminator()
+    }
+}
+
+/// Boxed matchers are matchers, for callers that own their matchers as
+/// trait objects (see also `DynMatcher`, which adds `Send + Sync`).
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn read_match(&self, mat: &mut Match, buf: &[u8], start: usize) -> bool {
+        (**self).read_match(mat, buf, start)
+    }
+
+    fn is_match(&self, buf: &[u8]) -> bool {
+        (**self).is_match(buf)
+    }
+
+    fn shortest_match(&self, buf: &[u8], start: usize) -> Option<usize> {
+        (**self).shortest_match(buf, start)
+    }
+
+    fn regex(&self) -> Option<&Regex> {
+        (**self).regex()
+    }
+
+    fn line_terminator(&self) -> Option<u8> {
+        (**self).line_terminator()
+    }
+}
+
+impl Matcher for Grep {
+    fn read_match(&self, mat: &mut Match, buf: &[u8], start: usize) -> bool {
+        Grep::read_match(self, mat, buf, start)
+    }
+
+    fn is_match(&self, buf: &[u8]) -> bool {
+        Grep::regex(self).is_match(buf)
+    }
+
+    fn shortest_match(&self, buf: &[u8], start: usize) -> Option<usize> {
+        Grep::shortest_match(self, buf, start)
+    }
+
+    fn regex(&self) -> Option<&Regex> {
+        Some(Grep::regex(self))
+    }
+
+    fn line_terminator(&self) -> Option<u8> {
+        Grep::line_terminator(self)
+    }
+}
+
+/// DynMatcher is a matcher that wraps any other matcher behind a boxed
+/// trait object.
+///
+/// This is useful when the concrete matcher type isn't known until runtime,
+/// e.g., when matchers are provided by plugins. Note that since `Matcher` is
+/// only a subset of `Grep`'s interface, going through a `DynMatcher` gives up
+/// any specialized fast paths of the wrapped matcher beyond the ones the
+/// trait itself exposes.
+pub struct DynMatcher(Box<dyn Matcher + Send + Sync>);
+
+impl DynMatcher {
+    /// Create a new DynMatcher wrapping the given matcher.
+    pub fn new<M: Matcher + Send + Sync + 'static>(matcher: M) -> DynMatcher {
+        DynMatcher(Box::new(matcher))
+    }
+}
+
+impl Matcher for DynMatcher {
+    fn read_match(&self, mat: &mut Match, buf: &[u8], start: usize) -> bool {
+        self.0.read_match(mat, buf, start)
+    }
+
+    fn is_match(&self, buf: &[u8]) -> bool {
+        self.0.is_match(buf)
+    }
+
+    fn shortest_match(&self, buf: &[u8], start: usize) -> Option<usize> {
+        self.0.shortest_match(buf, start)
+    }
+
+    fn regex(&self) -> Option<&Regex> {
+        self.0.regex()
+    }
+
+    fn line_terminator(&self) -> Option<u8> {
+        self.0.line_terminator()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use memchr::{memchr, memrchr};
+
+    use search::{GrepBuilder, Match};
+    use super::{DynMatcher, Matcher};
+
+    /// A line matcher that looks for a fixed substring without using a
+    /// regex at all.
+    struct SubstringMatcher(&'static [u8]);
+
+    impl Matcher for SubstringMatcher {
+        fn read_match(
+            &self,
+            mat: &mut Match,
+            buf: &[u8],
+            start: usize,
+        ) -> bool {
+            let i = match buf[start..]
+                .windows(self.0.len())
+                .position(|w| w == self.0) {
+                None => return false,
+                Some(i) => start + i,
+            };
+            let s = memrchr(b'\n', &buf[..i]).map_or(0, |x| x + 1);
+            let e = memchr(b'\n', &buf[i..])
+                .map_or(buf.len(), |x| i + x + 1);
+            mat.set(s, e);
+            true
+        }
+    }
+
+    fn matches<M: Matcher>(m: &M, buf: &[u8]) -> Vec<(usize, usize)> {
+        let mut mats = vec![];
+        let mut mat = Match::default();
+        let mut start = 0;
+        while m.read_match(&mut mat, buf, start) {
+            mats.push((mat.start(), mat.end()));
+            start = mat.end();
+        }
+        mats
+    }
+
+    #[test]
+    fn shortest_match_line_end() {
+        let buf = &b"aaa\nbbb\nabc\nccc\n"[..];
+        let grep = GrepBuilder::new("b").build().unwrap();
+        assert_eq!(Some(8), grep.shortest_match(buf, 0));
+        assert_eq!(Some(12), grep.shortest_match(buf, 8));
+        assert_eq!(None, grep.shortest_match(buf, 12));
+        // The default implementation, built on read_match, agrees.
+        let subs = SubstringMatcher(b"b");
+        assert_eq!(Some(8), subs.shortest_match(buf, 0));
+        assert_eq!(Some(12), subs.shortest_match(buf, 8));
+        assert_eq!(None, subs.shortest_match(buf, 12));
+    }
+
+    #[test]
+    fn line_terminator_capability() {
+        let grep = GrepBuilder::new("b")
+            .line_terminator(b'\x00')
+            .build()
+            .unwrap();
+        assert_eq!(Some(0), Matcher::line_terminator(&grep));
+        // The capability survives trait object dispatch.
+        let dynref: &dyn Matcher = &grep;
+        assert_eq!(Some(0), dynref.line_terminator());
+        // Matchers without the override make no assumption, and neither
+        // does a grep left on its default terminator.
+        assert_eq!(None, SubstringMatcher(b"b").line_terminator());
+        let default = GrepBuilder::new("b").build().unwrap();
+        assert_eq!(None, Matcher::line_terminator(&default));
+    }
+
+    #[test]
+    fn trait_object_matchers() {
+        let buf = &b"aaa\nbbb\nabc\nccc\n"[..];
+        let grep = GrepBuilder::new("b").build().unwrap();
+        let expected = matches(&grep, buf);
+        // A bare trait object reference works wherever a matcher does.
+        let dynref: &dyn Matcher = &grep;
+        assert_eq!(expected, matches(&dynref, buf));
+        // So does a boxed trait object.
+        let boxed: Box<dyn Matcher> = Box::new(SubstringMatcher(b"b"));
+        assert_eq!(expected, matches(&boxed, buf));
+    }
+
+    #[test]
+    fn dyn_matches_grep() {
+        let buf = &b"aaa\nbbb\nabc\nccc\n"[..];
+        let grep = GrepBuilder::new("b").build().unwrap();
+        let expected = matches(&grep, buf);
+        let dynm = DynMatcher::new(grep);
+        assert_eq!(expected, matches(&dynm, buf));
+        assert!(dynm.regex().is_some());
+        assert!(dynm.is_match(buf));
+    }
+
+    #[test]
+    fn dyn_custom_matcher() {
+        let buf = &b"aaa\nbbb\nabc\nccc\n"[..];
+        let dynm = DynMatcher::new(SubstringMatcher(b"b"));
+        assert_eq!(vec![(4, 8), (8, 12)], matches(&dynm, buf));
+        assert!(dynm.regex().is_none());
+        assert!(dynm.is_match(buf));
+        assert!(!dynm.is_match(b"zzz\n"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::cmp;
+
 use memchr::{memchr, memrchr};
 use syntax::ParserBuilder;
 use syntax::hir::Hir;
@@ -33,6 +35,16 @@ impl Match {
     pub fn end(&self) -> usize {
         self.end
     }
+
+    /// Set the range of the line that matched.
+    ///
+    /// This is exposed so that `Matcher` implementations outside this crate
+    /// can report matches.
+    #[inline]
+    pub fn set(&mut self, start: usize, end: usize) {
+        self.start = start;
+        self.end = end;
+    }
 }
 
 /// A fast line oriented regex searcher.
@@ -55,6 +67,7 @@ struct Options {
     case_insensitive: bool,
     case_smart: bool,
     line_terminator: u8,
+    line_terminator_set: bool,
     size_limit: usize,
     dfa_size_limit: usize,
 }
@@ -65,6 +78,7 @@ impl Default for Options {
             case_insensitive: false,
             case_smart: false,
             line_terminator: b'\n',
+            line_terminator_set: false,
             size_limit: 10 * (1 << 20),
             dfa_size_limit: 10 * (1 << 20),
         }
@@ -92,6 +106,7 @@ impl GrepBuilder {
     pub fn line_terminator(mut self, ascii_byte: u8) -> GrepBuilder {
         assert!(ascii_byte <= 0x7F);
         self.opts.line_terminator = ascii_byte;
+        self.opts.line_terminator_set = true;
         self
     }
 
@@ -159,8 +174,8 @@ impl GrepBuilder {
             }
         };
         Ok(Grep {
-            re: re,
-            required: required,
+            re,
+            required,
             opts: self.opts,
         })
     }
@@ -220,15 +235,57 @@ impl Grep {
         &self.re
     }
 
+    /// Returns the line terminator this matcher was explicitly built
+    /// for, if one was configured on its builder.
+    ///
+    /// The compiled regex is guaranteed never to match the configured
+    /// byte, so running such a matcher against lines delimited by a
+    /// different byte silently mis-identifies line boundaries. Searchers
+    /// validate this against their own configuration; see
+    /// `Matcher::line_terminator`. A matcher left on the default `\n`
+    /// reports no assumption, preserving the long-standing practice of
+    /// running it under a searcher with a custom terminator.
+    pub fn line_terminator(&self) -> Option<u8> {
+        if self.opts.line_terminator_set {
+            Some(self.opts.line_terminator)
+        } else {
+            None
+        }
+    }
+
     /// Returns an iterator over all matches in the given buffer.
     pub fn iter<'b, 's>(&'s self, buf: &'b [u8]) -> Iter<'b, 's> {
         Iter {
             searcher: self,
-            buf: buf,
+            buf,
             start: 0,
         }
     }
 
+    /// Returns the range of the full physical lines covering the given span.
+    ///
+    /// The range reported starts at the beginning of the line containing
+    /// `start` and extends to the end of the line containing the last byte
+    /// before `end`, including its terminator when there is one. A span
+    /// ending exactly at a terminator therefore does not spill into the next
+    /// line, and a span ending at the end of the buffer without a trailing
+    /// terminator ends at the end of the buffer. An empty span is treated as
+    /// a point inside a single line.
+    ///
+    /// This is useful for callers that track ranges spanning several lines
+    /// (e.g., editor integrations that highlight whole lines) and need them
+    /// snapped outward to line boundaries. Both offsets are clamped to the
+    /// buffer's length.
+    pub fn line_span(&self, buf: &[u8], start: usize, end: usize) -> Match {
+        let start = cmp::min(start, buf.len());
+        let end = cmp::max(start, cmp::min(end, buf.len()));
+        let last = if end > start { end - 1 } else { start };
+        let (s, e) = self.find_line(buf, start, last);
+        let mut mat = Match::new();
+        mat.set(s, e);
+        mat
+    }
+
     /// Fills in the next line that matches in the given buffer starting at
     /// the position given.
     ///
@@ -273,6 +330,39 @@ impl Grep {
         }
     }
 
+    /// Returns the end offset of the line containing the next match at or
+    /// after `start`, without locating where that line begins.
+    ///
+    /// This is `read_match` minus the backwards scan for the line start,
+    /// for callers that only count matching lines and need just enough to
+    /// skip past each one.
+    pub fn shortest_match(
+        &self,
+        buf: &[u8],
+        mut start: usize,
+    ) -> Option<usize> {
+        if start >= buf.len() {
+            return None;
+        }
+        if let Some(ref req) = self.required {
+            while start < buf.len() {
+                let e = match req.shortest_match(&buf[start..]) {
+                    None => return None,
+                    Some(e) => start + e,
+                };
+                let (prevnl, nextnl) = self.find_line(buf, e, e);
+                match self.re.shortest_match(&buf[prevnl..nextnl]) {
+                    None => start = nextnl,
+                    Some(_) => return Some(nextnl),
+                }
+            }
+            None
+        } else {
+            let e = start + self.re.shortest_match(&buf[start..])?;
+            Some(self.find_line_end(buf, e))
+        }
+    }
+
     fn fill_match(&self, mat: &mut Match, start: usize, end: usize) {
         mat.start = start;
         mat.end = end;
@@ -323,7 +413,7 @@ mod tests {
 
     use super::{GrepBuilder, Match};
 
-    static SHERLOCK: &'static [u8] = include_bytes!("./data/sherlock.txt");
+    static SHERLOCK: &[u8] = include_bytes!("./data/sherlock.txt");
 
     fn find_lines(pat: &str, haystack: &[u8]) -> Vec<Match> {
         let re = Regex::new(pat).unwrap();
@@ -334,8 +424,8 @@ mod tests {
             let end = memchr(b'\n', &haystack[m.end()..])
                       .map_or(haystack.len(), |i| m.end() + i + 1);
             lines.push(Match {
-                start: start,
-                end: end,
+                start,
+                end,
             });
         }
         lines
@@ -353,4 +443,28 @@ mod tests {
         assert_eq!(expected.len(), got.len());
         assert_eq!(expected, got);
     }
+
+    #[test]
+    fn line_span() {
+        let g = GrepBuilder::new("x").build().unwrap();
+        let buf = b"abc\ndefg\nhij";
+        let span = |s, e| {
+            let mat = g.line_span(buf, s, e);
+            (mat.start(), mat.end())
+        };
+        // Within a single line.
+        assert_eq!((4, 9), span(5, 7));
+        // Crossing a line boundary.
+        assert_eq!((0, 9), span(2, 6));
+        // Starting at offset 0.
+        assert_eq!((0, 4), span(0, 1));
+        // Ending at EOF without a trailing terminator.
+        assert_eq!((9, 12), span(10, 12));
+        // Ending exactly at a terminator doesn't spill into the next line.
+        assert_eq!((0, 4), span(0, 4));
+        // An empty span is a point inside a single line.
+        assert_eq!((4, 9), span(6, 6));
+        // Offsets are clamped to the buffer.
+        assert_eq!((9, 12), span(20, 30));
+    }
 }
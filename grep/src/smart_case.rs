@@ -14,6 +14,7 @@ pub struct Cased {
     pub any_literal: bool,
 }
 
+#[allow(clippy::wrong_self_convention)]
 impl Cased {
     /// Returns a `Cased` value by doing analysis on the AST of `pattern`.
     ///
@@ -1,6 +1,5 @@
 #![allow(dead_code, unused_imports, unused_mut, unused_variables)]
 
-extern crate crossbeam;
 extern crate ignore;
 extern crate walkdir;
 
@@ -9,9 +8,9 @@ use std::io::{self, Write};
 use std::path::Path;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
 use std::thread;
 
-use crossbeam::sync::MsQueue;
 use ignore::WalkBuilder;
 use walkdir::WalkDir;
 
@@ -19,7 +18,7 @@ fn main() {
     let mut path = env::args().nth(1).unwrap();
     let mut parallel = false;
     let mut simple = false;
-    let queue: Arc<MsQueue<Option<DirEntry>>> = Arc::new(MsQueue::new());
+    let (tx, rx) = mpsc::channel::<DirEntry>();
     if path == "parallel" {
         path = env::args().nth(2).unwrap();
         parallel = true;
@@ -28,10 +27,9 @@ fn main() {
         simple = true;
     }
 
-    let stdout_queue = queue.clone();
     let stdout_thread = thread::spawn(move || {
         let mut stdout = io::BufWriter::new(io::stdout());
-        while let Some(dent) = stdout_queue.pop() {
+        for dent in rx {
             write_path(&mut stdout, dent.path());
         }
     });
@@ -39,11 +37,11 @@ fn main() {
     if parallel {
         let walker = WalkBuilder::new(path).threads(6).build_parallel();
         walker.run(|| {
-            let queue = queue.clone();
+            let tx = tx.clone();
             Box::new(move |result| {
                 use ignore::WalkState::*;
 
-                queue.push(Some(DirEntry::Y(result.unwrap())));
+                tx.send(DirEntry::Y(result.unwrap())).unwrap();
                 Continue
             })
         });
@@ -51,16 +49,16 @@ fn main() {
         let mut stdout = io::BufWriter::new(io::stdout());
         let walker = WalkDir::new(path);
         for result in walker {
-            queue.push(Some(DirEntry::X(result.unwrap())));
+            tx.send(DirEntry::X(result.unwrap())).unwrap();
         }
     } else {
         let mut stdout = io::BufWriter::new(io::stdout());
         let walker = WalkBuilder::new(path).build();
         for result in walker {
-            queue.push(Some(DirEntry::Y(result.unwrap())));
+            tx.send(DirEntry::Y(result.unwrap())).unwrap();
         }
     }
-    queue.push(None);
+    drop(tx);
     stdout_thread.join().unwrap();
 }
 
@@ -81,12 +79,12 @@ impl DirEntry {
 #[cfg(unix)]
 fn write_path<W: Write>(mut wtr: W, path: &Path) {
     use std::os::unix::ffi::OsStrExt;
-    wtr.write(path.as_os_str().as_bytes()).unwrap();
-    wtr.write(b"\n").unwrap();
+    wtr.write_all(path.as_os_str().as_bytes()).unwrap();
+    wtr.write_all(b"\n").unwrap();
 }
 
 #[cfg(not(unix))]
 fn write_path<W: Write>(mut wtr: W, path: &Path) {
-    wtr.write(path.to_string_lossy().as_bytes()).unwrap();
-    wtr.write(b"\n").unwrap();
+    wtr.write_all(path.to_string_lossy().as_bytes()).unwrap();
+    wtr.write_all(b"\n").unwrap();
 }
@@ -27,11 +27,13 @@ use {Error, Match, PartialErrorBuilder};
 /// IgnoreMatch represents information about where a match came from when using
 /// the `Ignore` matcher.
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 pub struct IgnoreMatch<'a>(IgnoreMatchInner<'a>);
 
 /// IgnoreMatchInner describes precisely where the match information came from.
 /// This is private to allow expansion to more matchers in the future.
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 enum IgnoreMatchInner<'a> {
     Override(overrides::Glob<'a>),
     Gitignore(&'a gitignore::Glob),
@@ -213,7 +215,7 @@ impl Ignore {
                 Gitignore::empty()
             } else {
                 let (m, err) =
-                    create_gitignore(&dir, &self.0.custom_ignore_filenames);
+                    create_gitignore(dir, &self.0.custom_ignore_filenames);
                 errs.maybe_push(err);
                 m
             };
@@ -221,7 +223,7 @@ impl Ignore {
             if !self.0.opts.ignore {
                 Gitignore::empty()
             } else {
-                let (m, err) = create_gitignore(&dir, &[".ignore"]);
+                let (m, err) = create_gitignore(dir, &[".ignore"]);
                 errs.maybe_push(err);
                 m
             };
@@ -229,7 +231,7 @@ impl Ignore {
             if !self.0.opts.git_ignore {
                 Gitignore::empty()
             } else {
-                let (m, err) = create_gitignore(&dir, &[".gitignore"]);
+                let (m, err) = create_gitignore(dir, &[".gitignore"]);
                 errs.maybe_push(err);
                 m
             };
@@ -237,7 +239,7 @@ impl Ignore {
             if !self.0.opts.git_exclude {
                 Gitignore::empty()
             } else {
-                let (m, err) = create_gitignore(&dir, &[".git/info/exclude"]);
+                let (m, err) = create_gitignore(dir, &[".git/info/exclude"]);
                 errs.maybe_push(err);
                 m
             };
@@ -385,16 +387,16 @@ impl Ignore {
             if !m_explicit.is_none() {
                 break;
             }
-            m_explicit = gi.matched(&path, is_dir).map(IgnoreMatch::gitignore);
+            m_explicit = gi.matched(path, is_dir).map(IgnoreMatch::gitignore);
         }
-        let m_global = self.0.git_global_matcher.matched(&path, is_dir)
+        let m_global = self.0.git_global_matcher.matched(path, is_dir)
                            .map(IgnoreMatch::gitignore);
 
         m_custom_ignore.or(m_ignore).or(m_gi).or(m_gi_exclude).or(m_global).or(m_explicit)
     }
 
     /// Returns an iterator over parent ignore matchers, including this one.
-    pub fn parents(&self) -> Parents {
+    pub fn parents(&self) -> Parents<'_> {
         Parents(Some(self))
     }
 
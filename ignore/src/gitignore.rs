@@ -43,7 +43,7 @@ pub struct Glob {
 impl Glob {
     /// Returns the file path that defined this glob.
     pub fn from(&self) -> Option<&Path> {
-        self.from.as_ref().map(|p| &**p)
+        self.from.as_deref()
     }
 
     /// The original glob as it was defined in a gitignore file.
@@ -157,7 +157,7 @@ impl Gitignore {
     ///
     /// All matches are done relative to this path.
     pub fn path(&self) -> &Path {
-        &*self.root
+        &self.root
     }
 
     /// Returns true if and only if this gitignore has zero globs, and
@@ -259,7 +259,7 @@ impl Gitignore {
         let _matches = self.matches.as_ref().unwrap().get_default();
         let mut matches = _matches.borrow_mut();
         let candidate = Candidate::new(path);
-        self.set.matches_candidate_into(&candidate, &mut *matches);
+        self.set.matches_candidate_into(&candidate, &mut matches);
         for &i in matches.iter().rev() {
             let glob = &self.globs[i];
             if !glob.is_only_dir() || is_dir {
@@ -347,7 +347,7 @@ impl GitignoreBuilder {
                 }
             })?;
         Ok(Gitignore {
-            set: set,
+            set,
             root: self.root.clone(),
             globs: self.globs.clone(),
             num_ignores: nignore as u64,
@@ -420,13 +420,13 @@ impl GitignoreBuilder {
             return Ok(self);
         }
         if !line.ends_with("\\ ") {
-            line = line.trim_right();
+            line = line.trim_end();
         }
         if line.is_empty() {
             return Ok(self);
         }
         let mut glob = Glob {
-            from: from,
+            from,
             original: line.to_string(),
             actual: String::new(),
             is_whitelist: false,
@@ -558,10 +558,7 @@ fn parse_excludes_file(data: &[u8]) -> Option<PathBuf> {
         static ref RE: Regex = Regex::new(
             r"(?ium)^\s*excludesfile\s*=\s*(.+)\s*$").unwrap();
     };
-    let caps = match RE.captures(data) {
-        None => return None,
-        Some(caps) => caps,
-    };
+    let caps = RE.captures(data)?;
     str::from_utf8(&caps[1]).ok().map(|s| PathBuf::from(expand_tilde(s)))
 }
 
@@ -611,7 +608,7 @@ mod tests {
         };
     }
 
-    const ROOT: &'static str = "/home/foobar/rust/rg";
+    const ROOT: &str = "/home/foobar/rust/rg";
 
     ignored!(ig1, ROOT, "months", "months");
     ignored!(ig2, ROOT, "*.lock", "Cargo.lock");
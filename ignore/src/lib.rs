@@ -46,7 +46,6 @@ See the documentation for `WalkBuilder` for many other options.
 
 #![deny(missing_docs)]
 
-extern crate crossbeam;
 extern crate globset;
 #[macro_use]
 extern crate lazy_static;
@@ -137,13 +136,13 @@ impl Clone for Error {
         match *self {
             Error::Partial(ref errs) => Error::Partial(errs.clone()),
             Error::WithLineNumber { line, ref err } => {
-                Error::WithLineNumber { line: line, err: err.clone() }
+                Error::WithLineNumber { line, err: err.clone() }
             }
             Error::WithPath { ref path, ref err } => {
                 Error::WithPath { path: path.clone(), err: err.clone() }
             }
             Error::WithDepth { depth, ref err } => {
-                Error::WithDepth { depth: depth, err: err.clone() }
+                Error::WithDepth { depth, err: err.clone() }
             }
             Error::Loop { ref ancestor, ref child } => {
                 Error::Loop {
@@ -222,7 +221,7 @@ impl Error {
     /// Turn an error into a tagged error with the given depth.
     fn with_depth(self, depth: usize) -> Error {
         Error::WithDepth {
-            depth: depth,
+            depth,
             err: Box::new(self),
         }
     }
@@ -245,7 +244,7 @@ impl Error {
         let depth = err.depth();
         if let (Some(anc), Some(child)) = (err.loop_ancestor(), err.path()) {
             return Error::WithDepth {
-                depth: depth,
+                depth,
                 err: Box::new(Error::Loop {
                     ancestor: anc.to_path_buf(),
                     child: child.to_path_buf(),
@@ -256,7 +255,7 @@ impl Error {
         let mut ig_err = Error::Io(io::Error::from(err));
         if let Some(path) = path {
             ig_err = Error::WithPath {
-                path: path,
+                path,
                 err: Box::new(ig_err),
             };
         }
@@ -265,17 +264,13 @@ impl Error {
 }
 
 impl error::Error for Error {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            Error::Partial(_) => "partial error",
-            Error::WithLineNumber { ref err, .. } => err.description(),
-            Error::WithPath { ref err, .. } => err.description(),
-            Error::WithDepth { ref err, .. } => err.description(),
-            Error::Loop { .. } => "file system loop found",
-            Error::Io(ref err) => err.description(),
-            Error::Glob { ref err, .. } => err,
-            Error::UnrecognizedFileType(_) => "unrecognized file type",
-            Error::InvalidDefinition => "invalid definition",
+            Error::WithLineNumber { ref err, .. } => Some(&**err),
+            Error::WithPath { ref err, .. } => Some(&**err),
+            Error::WithDepth { ref err, .. } => Some(&**err),
+            Error::Io(ref err) => Some(err),
+            _ => None,
         }
     }
 }
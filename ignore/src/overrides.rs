@@ -21,9 +21,11 @@ use {Error, Match};
 /// The lifetime `'a` refers to the lifetime of the matcher that produced
 /// this glob.
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 pub struct Glob<'a>(GlobInner<'a>);
 
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 enum GlobInner<'a> {
     /// No glob matched, but the file path should still be ignored.
     UnmatchedIgnore,
@@ -155,7 +157,7 @@ impl OverrideBuilder {
 mod tests {
     use super::{Override, OverrideBuilder};
 
-    const ROOT: &'static str = "/home/andrew/foo";
+    const ROOT: &str = "/home/andrew/foo";
 
     fn ov(globs: &[&str]) -> Override {
         let mut builder = OverrideBuilder::new(ROOT);
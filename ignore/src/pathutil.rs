@@ -7,7 +7,7 @@ pub fn is_hidden<P: AsRef<Path>>(path: P) -> bool {
     use std::os::unix::ffi::OsStrExt;
 
     if let Some(name) = file_name(path.as_ref()) {
-        name.as_bytes().get(0) == Some(&b'.')
+        name.as_bytes().first() == Some(&b'.')
     } else {
         false
     }
@@ -38,7 +38,7 @@ pub fn strip_prefix<'a, P: AsRef<Path> + ?Sized>(
     if prefix.len() > path.len() || prefix != &path[0..prefix.len()] {
         None
     } else {
-        Some(&Path::new(OsStr::from_bytes(&path[prefix.len()..])))
+        Some(Path::new(OsStr::from_bytes(&path[prefix.len()..])))
     }
 }
 
@@ -76,20 +76,17 @@ pub fn is_file_name<P: AsRef<Path>>(path: P) -> bool {
 /// If the path terminates in ., .., or consists solely of a root of prefix,
 /// file_name will return None.
 #[cfg(unix)]
-pub fn file_name<'a, P: AsRef<Path> + ?Sized>(
-    path: &'a P,
-) -> Option<&'a OsStr> {
+pub fn file_name<P: AsRef<Path> + ?Sized>(
+    path: &P,
+) -> Option<&OsStr> {
     use std::os::unix::ffi::OsStrExt;
     use memchr::memrchr;
 
     let path = path.as_ref().as_os_str().as_bytes();
-    if path.is_empty() {
-        return None;
-    } else if path.len() == 1 && path[0] == b'.' {
-        return None;
-    } else if path.last() == Some(&b'.') {
-        return None;
-    } else if path.len() >= 2 && &path[path.len() - 2..] == &b".."[..] {
+    if path.is_empty()
+        || path.last() == Some(&b'.')
+        || (path.len() >= 2 && path[path.len() - 2..] == b".."[..])
+    {
         return None;
     }
     let last_slash = memrchr(b'/', path).map(|i| i + 1).unwrap_or(0);
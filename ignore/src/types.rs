@@ -96,7 +96,7 @@ use thread_local::ThreadLocal;
 use pathutil::file_name;
 use {Error, Match};
 
-const DEFAULT_TYPES: &'static [(&'static str, &'static [&'static str])] = &[
+const DEFAULT_TYPES: &[(&str, &[&str])] = &[
     ("agda", &["*.agda", "*.lagda"]),
     ("aidl", &["*.aidl"]),
     ("amake", &["*.mk", "*.bp"]),
@@ -316,9 +316,11 @@ const DEFAULT_TYPES: &'static [(&'static str, &'static [&'static str])] = &[
 /// The lifetime `'a` refers to the lifetime of the underlying file type
 /// definition, which corresponds to the lifetime of the file type matcher.
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 pub struct Glob<'a>(GlobInner<'a>);
 
 #[derive(Clone, Debug)]
+#[allow(dead_code)]
 enum GlobInner<'a> {
     /// No glob matched, but the file path should still be ignored.
     UnmatchedIgnore,
@@ -482,7 +484,7 @@ impl Types {
             }
         };
         let mut matches = self.matches.get_default().borrow_mut();
-        self.set.matches_into(name, &mut *matches);
+        self.set.matches_into(name, &mut matches);
         // The highest precedent match is the last one.
         if let Some(&i) = matches.last() {
             let (isel, iglob) = self.glob_to_selection[i];
@@ -513,6 +515,12 @@ pub struct TypesBuilder {
     selections: Vec<Selection<()>>,
 }
 
+impl Default for TypesBuilder {
+    fn default() -> TypesBuilder {
+        TypesBuilder::new()
+    }
+}
+
 impl TypesBuilder {
     /// Create a new builder for a file type matcher.
     ///
@@ -562,11 +570,11 @@ impl TypesBuilder {
             Error::Glob { glob: None, err: err.to_string() }
         })?;
         Ok(Types {
-            defs: defs,
-            selections: selections,
-            has_selected: has_selected,
-            glob_to_selection: glob_to_selection,
-            set: set,
+            defs,
+            selections,
+            has_selected,
+            glob_to_selection,
+            set,
             matches: Arc::new(ThreadLocal::default()),
         })
     }
@@ -640,13 +648,15 @@ impl TypesBuilder {
 
     /// Add a new file type definition specified in string form. There are two
     /// valid formats:
-    /// 1. `{name}:{glob}`.  This defines a 'root' definition that associates the
-    ///     given name with the given glob.
+    ///
+    /// 1. `{name}:{glob}`. This defines a 'root' definition that associates
+    ///    the given name with the given glob.
     /// 2. `{name}:include:{comma-separated list of already defined names}.
-    ///     This defines an 'include' definition that associates the given name
-    ///     with the definitions of the given existing types.
-    /// Names may not include any characters that are not
-    /// Unicode letters or numbers.
+    ///    This defines an 'include' definition that associates the given name
+    ///    with the definitions of the given existing types.
+    ///
+    /// Names may not include any characters that are not Unicode letters or
+    /// numbers.
     pub fn add_def(&mut self, def: &str) -> Result<(), Error> {
         let parts: Vec<&str> = def.split(':').collect();
         match parts.len() {
@@ -684,7 +694,7 @@ impl TypesBuilder {
 
     /// Add a set of default file type definitions.
     pub fn add_defaults(&mut self) -> &mut TypesBuilder {
-        static MSG: &'static str = "adding a default type should never fail";
+        static MSG: &str = "adding a default type should never fail";
         for &(name, exts) in DEFAULT_TYPES {
             for ext in exts {
                 self.add(name, ext).expect(MSG);
@@ -739,22 +749,22 @@ mod tests {
         ]
     }
 
-    matched!(match1, types(), vec!["rust"], vec![], "lib.rs");
-    matched!(match2, types(), vec!["html"], vec![], "index.html");
-    matched!(match3, types(), vec!["html"], vec![], "index.htm");
-    matched!(match4, types(), vec!["html", "rust"], vec![], "main.rs");
-    matched!(match5, types(), vec![], vec![], "index.html");
-    matched!(match6, types(), vec![], vec!["rust"], "index.html");
-    matched!(match7, types(), vec!["foo"], vec!["rust"], "main.foo");
-    matched!(match8, types(), vec!["combo"], vec![], "index.html");
-    matched!(match9, types(), vec!["combo"], vec![], "lib.rs");
-
-    matched!(not, matchnot1, types(), vec!["rust"], vec![], "index.html");
-    matched!(not, matchnot2, types(), vec![], vec!["rust"], "main.rs");
-    matched!(not, matchnot3, types(), vec!["foo"], vec!["rust"], "main.rs");
-    matched!(not, matchnot4, types(), vec!["rust"], vec!["foo"], "main.rs");
-    matched!(not, matchnot5, types(), vec!["rust"], vec!["foo"], "main.foo");
-    matched!(not, matchnot6, types(), vec!["combo"], vec![], "leftpad.js");
+    matched!(match1, types(), ["rust"], [], "lib.rs");
+    matched!(match2, types(), ["html"], [], "index.html");
+    matched!(match3, types(), ["html"], [], "index.htm");
+    matched!(match4, types(), ["html", "rust"], [], "main.rs");
+    matched!(match5, types(), [], [], "index.html");
+    matched!(match6, types(), [], ["rust"], "index.html");
+    matched!(match7, types(), ["foo"], ["rust"], "main.foo");
+    matched!(match8, types(), ["combo"], [], "index.html");
+    matched!(match9, types(), ["combo"], [], "lib.rs");
+
+    matched!(not, matchnot1, types(), ["rust"], [], "index.html");
+    matched!(not, matchnot2, types(), [], ["rust"], "main.rs");
+    matched!(not, matchnot3, types(), ["foo"], ["rust"], "main.rs");
+    matched!(not, matchnot4, types(), ["rust"], ["foo"], "main.rs");
+    matched!(not, matchnot5, types(), ["rust"], ["foo"], "main.foo");
+    matched!(not, matchnot6, types(), ["combo"], [], "leftpad.js");
 
     #[test]
     fn test_invalid_defs() {
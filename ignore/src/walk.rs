@@ -1,16 +1,16 @@
 use std::cmp;
+use std::collections::VecDeque;
 use std::ffi::OsStr;
 use std::fmt;
 use std::fs::{self, FileType, Metadata};
 use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::thread;
 use std::time::Duration;
 use std::vec;
 
-use crossbeam::sync::MsQueue;
 use same_file::Handle;
 use walkdir::{self, WalkDir};
 
@@ -104,14 +104,14 @@ impl DirEntry {
     fn new_walkdir(dent: walkdir::DirEntry, err: Option<Error>) -> DirEntry {
         DirEntry {
             dent: DirEntryInner::Walkdir(dent),
-            err: err,
+            err,
         }
     }
 
     fn new_raw(dent: DirEntryRaw, err: Option<Error>) -> DirEntry {
         DirEntry {
             dent: DirEntryInner::Raw(dent),
-            err: err,
+            err,
         }
     }
 }
@@ -153,18 +153,15 @@ impl DirEntryInner {
     }
 
     fn is_stdin(&self) -> bool {
-        match *self {
-            DirEntryInner::Stdin => true,
-            _ => false,
-        }
+        matches!(*self, DirEntryInner::Stdin)
     }
 
     fn metadata(&self) -> Result<Metadata, Error> {
         use self::DirEntryInner::*;
         match *self {
             Stdin => {
-                let err = Error::Io(io::Error::new(
-                    io::ErrorKind::Other, "<stdin> has no metadata"));
+                let err = Error::Io(io::Error::other(
+                    "<stdin> has no metadata"));
                 Err(err.with_path("<stdin>"))
             }
             Walkdir(ref x) => {
@@ -301,7 +298,7 @@ impl DirEntryRaw {
             fs::metadata(&self.path)
         } else {
             fs::symlink_metadata(&self.path)
-        }.map_err(|err| Error::Io(io::Error::from(err)).with_path(&self.path))
+        }.map_err(|err| Error::Io(err).with_path(&self.path))
     }
 
     fn file_type(&self) -> FileType {
@@ -326,9 +323,9 @@ impl DirEntryRaw {
         ent: &fs::DirEntry,
     ) -> Result<DirEntryRaw, Error> {
         let ty = ent.file_type().map_err(|err| {
-            let err = Error::Io(io::Error::from(err)).with_path(ent.path());
+            let err = Error::Io(err).with_path(ent.path());
             Error::WithDepth {
-                depth: depth,
+                depth,
                 err: Box::new(err),
             }
         })?;
@@ -367,9 +364,9 @@ impl DirEntryRaw {
 
         Ok(DirEntryRaw {
             path: ent.path(),
-            ty: ty,
+            ty,
             follow_link: false,
-            depth: depth,
+            depth,
             ino: ent.ino(),
         })
     }
@@ -399,7 +396,7 @@ impl DirEntryRaw {
             path: pb,
             ty: md.file_type(),
             follow_link: true,
-            depth: depth,
+            depth,
             ino: md.ino(),
         })
     }
@@ -427,32 +424,33 @@ impl DirEntryRaw {
 /// the rules assume a default configuration.
 ///
 /// * First, glob overrides are checked. If a path matches a glob override,
-/// then matching stops. The path is then only skipped if the glob that matched
-/// the path is an ignore glob. (An override glob is a whitelist glob unless it
-/// starts with a `!`, in which case it is an ignore glob.)
+///   then matching stops. The path is then only skipped if the glob that
+///   matched the path is an ignore glob. (An override glob is a whitelist
+///   glob unless it starts with a `!`, in which case it is an ignore glob.)
 /// * Second, ignore files are checked. Ignore files currently only come from
-/// git ignore files (`.gitignore`, `.git/info/exclude` and the configured
-/// global gitignore file), plain `.ignore` files, which have the same format
-/// as gitignore files, or explicitly added ignore files. The precedence order
-/// is: `.ignore`, `.gitignore`, `.git/info/exclude`, global gitignore and
-/// finally explicitly added ignore files. Note that precedence between
-/// different types of ignore files is not impacted by the directory hierarchy;
-/// any `.ignore` file overrides all `.gitignore` files. Within each precedence
-/// level, more nested ignore files have a higher precedence than less nested
-/// ignore files.
+///   git ignore files (`.gitignore`, `.git/info/exclude` and the configured
+///   global gitignore file), plain `.ignore` files, which have the same
+///   format as gitignore files, or explicitly added ignore files. The
+///   precedence order is: `.ignore`, `.gitignore`, `.git/info/exclude`,
+///   global gitignore and finally explicitly added ignore files. Note that
+///   precedence between different types of ignore files is not impacted by
+///   the directory hierarchy; any `.ignore` file overrides all `.gitignore`
+///   files. Within each precedence level, more nested ignore files have a
+///   higher precedence than less nested ignore files.
 /// * Third, if the previous step yields an ignore match, then all matching
-/// is stopped and the path is skipped. If it yields a whitelist match, then
-/// matching continues. A whitelist match can be overridden by a later matcher.
+///   is stopped and the path is skipped. If it yields a whitelist match,
+///   then matching continues. A whitelist match can be overridden by a later
+///   matcher.
 /// * Fourth, unless the path is a directory, the file type matcher is run on
-/// the path. As above, if it yields an ignore match, then all matching is
-/// stopped and the path is skipped. If it yields a whitelist match, then
-/// matching continues.
+///   the path. As above, if it yields an ignore match, then all matching is
+///   stopped and the path is skipped. If it yields a whitelist match, then
+///   matching continues.
 /// * Fifth, if the path hasn't been whitelisted and it is hidden, then the
-/// path is skipped.
+///   path is skipped.
 /// * Sixth, unless the path is a directory, the size of the file is compared
-/// against the max filesize limit. If it exceeds the limit, it is skipped.
+///   against the max filesize limit. If it exceeds the limit, it is skipped.
 /// * Seventh, if the path has made it this far then it is yielded in the
-/// iterator.
+///   iterator.
 #[derive(Clone)]
 pub struct WalkBuilder {
     paths: Vec<PathBuf>,
@@ -461,12 +459,15 @@ pub struct WalkBuilder {
     max_depth: Option<usize>,
     max_filesize: Option<u64>,
     follow_links: bool,
-    sorter: Option<Arc<
-        Fn(&OsStr, &OsStr) -> cmp::Ordering + Send + Sync + 'static
-    >>,
+    sorter: Option<SortPredicate>,
     threads: usize,
 }
 
+/// A predicate for sorting directory entries by file name, as set by
+/// `WalkBuilder::sort_by_file_name`.
+type SortPredicate =
+    Arc<dyn Fn(&OsStr, &OsStr) -> cmp::Ordering + Send + Sync + 'static>;
+
 impl fmt::Debug for WalkBuilder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("WalkBuilder")
@@ -526,7 +527,7 @@ impl WalkBuilder {
         }).collect::<Vec<_>>().into_iter();
         let ig_root = self.ig_builder.build();
         Walk {
-            its: its,
+            its,
             it: None,
             ig_root: ig_root.clone(),
             ig: ig_root.clone(),
@@ -786,10 +787,11 @@ impl Walk {
         let is_dir = walkdir_entry_is_dir(ent);
         let max_size = self.max_filesize;
         let should_skip_path = skip_path(&self.ig, ent.path(), is_dir);
-        let should_skip_filesize = if !is_dir && max_size.is_some() {
-            skip_filesize(max_size.unwrap(), ent.path(), &ent.metadata().ok())
-        } else {
-            false
+        let should_skip_filesize = match (is_dir, max_size) {
+            (false, Some(max_size)) => {
+                skip_filesize(max_size, ent.path(), &ent.metadata().ok())
+            }
+            _ => false,
         };
 
         should_skip_path || should_skip_filesize
@@ -962,10 +964,10 @@ impl WalkParallel {
     pub fn run<F>(
         self,
         mut mkf: F,
-    ) where F: FnMut() -> Box<FnMut(Result<DirEntry, Error>) -> WalkState + Send + 'static> {
+    ) where F: FnMut() -> Box<dyn FnMut(Result<DirEntry, Error>) -> WalkState + Send + 'static> {
         let mut f = mkf();
         let threads = self.threads();
-        let queue = Arc::new(MsQueue::new());
+        let queue = Arc::new(MessageQueue::new());
         let mut any_work = false;
         // Send the initial set of root paths to the pool of workers.
         // Note that we only send directories. For files, we send to them the
@@ -986,7 +988,7 @@ impl WalkParallel {
                     }
                 };
             queue.push(Message::Work(Work {
-                dent: dent,
+                dent,
                 ignore: self.ig_root.clone(),
             }));
             any_work = true;
@@ -1009,7 +1011,7 @@ impl WalkParallel {
                 is_quitting: false,
                 num_waiting: num_waiting.clone(),
                 num_quitting: num_quitting.clone(),
-                threads: threads,
+                threads,
                 parents: self.parents,
                 max_depth: self.max_depth,
                 max_filesize: self.max_filesize,
@@ -1041,6 +1043,28 @@ enum Message {
     Quit,
 }
 
+/// A simple multi-producer multi-consumer queue of messages.
+///
+/// Workers never block on the queue; they poll it via `try_pop` and handle
+/// an empty queue themselves (see `Worker::get_work`).
+struct MessageQueue {
+    queue: Mutex<VecDeque<Message>>,
+}
+
+impl MessageQueue {
+    fn new() -> MessageQueue {
+        MessageQueue { queue: Mutex::new(VecDeque::new()) }
+    }
+
+    fn push(&self, msg: Message) {
+        self.queue.lock().unwrap().push_back(msg);
+    }
+
+    fn try_pop(&self) -> Option<Message> {
+        self.queue.lock().unwrap().pop_front()
+    }
+}
+
 /// A unit of work for each worker to process.
 ///
 /// Each unit of work corresponds to a directory that should be descended
@@ -1060,7 +1084,7 @@ impl Work {
 
     /// Returns true if and only if this work item is a symlink.
     fn is_symlink(&self) -> bool {
-        self.dent.file_type().map_or(false, |ft| ft.is_symlink())
+        self.dent.file_type().is_some_and(|ft| ft.is_symlink())
     }
 
     /// Adds ignore rules for parent directories.
@@ -1108,9 +1132,9 @@ impl Work {
 /// Note that a worker is *both* a producer and a consumer.
 struct Worker {
     /// The caller's callback.
-    f: Box<FnMut(Result<DirEntry, Error>) -> WalkState + Send + 'static>,
+    f: Box<dyn FnMut(Result<DirEntry, Error>) -> WalkState + Send + 'static>,
     /// A queue of work items. This is multi-producer and multi-consumer.
-    queue: Arc<MsQueue<Message>>,
+    queue: Arc<MessageQueue>,
     /// Whether all workers should quit at the next opportunity. Note that
     /// this is distinct from quitting because of exhausting the contents of
     /// a directory. Instead, this is used when the caller's callback indicates
@@ -1183,7 +1207,7 @@ impl Worker {
                     return;
                 }
             }
-            if self.max_depth.map_or(false, |max| depth >= max) {
+            if self.max_depth.is_some_and(|max| depth >= max) {
                 continue;
             }
             for result in readdir {
@@ -1225,7 +1249,7 @@ impl Worker {
                 return (self.f)(Err(err));
             }
         };
-        let is_symlink = dent.file_type().map_or(false, |ft| ft.is_symlink());
+        let is_symlink = dent.file_type().is_some_and(|ft| ft.is_symlink());
         if self.follow_links && is_symlink {
             let path = dent.path().to_path_buf();
             dent = match DirEntryRaw::from_link(depth, path) {
@@ -1243,15 +1267,16 @@ impl Worker {
         let is_dir = dent.is_dir();
         let max_size = self.max_filesize;
         let should_skip_path = skip_path(ig, dent.path(), is_dir);
-        let should_skip_filesize = if !is_dir && max_size.is_some() {
-            skip_filesize(max_size.unwrap(), dent.path(), &dent.metadata().ok())
-        } else {
-            false
+        let should_skip_filesize = match (is_dir, max_size) {
+            (false, Some(max_size)) => {
+                skip_filesize(max_size, dent.path(), &dent.metadata().ok())
+            }
+            _ => false,
         };
 
         if !should_skip_path && !should_skip_filesize {
             self.queue.push(Message::Work(Work {
-                dent: dent,
+                dent,
                 ignore: ig.clone(),
             }));
         }
@@ -1406,10 +1431,7 @@ fn skip_filesize(
     path: &Path,
     ent: &Option<Metadata>
 ) -> bool {
-    let filesize = match *ent {
-        Some(ref md) => Some(md.len()),
-        None => None
-    };
+    let filesize = (*ent).as_ref().map(|md| md.len());
 
     if let Some(fs) = filesize {
         if fs > max_filesize {
@@ -1618,7 +1640,7 @@ mod tests {
         wfile(td.path().join("a/bar"), "");
 
         let mut builder = WalkBuilder::new(td.path());
-        builder.add_custom_ignore_filename(&custom_ignore);
+        builder.add_custom_ignore_filename(custom_ignore);
         assert_paths(td.path(), &builder, &["bar", "a", "a/bar"]);
     }
 
@@ -1638,7 +1660,7 @@ mod tests {
         builder.git_ignore(false);
         builder.git_global(false);
         builder.git_exclude(false);
-        builder.add_custom_ignore_filename(&custom_ignore);
+        builder.add_custom_ignore_filename(custom_ignore);
         assert_paths(td.path(), &builder, &["bar", "a", "a/bar"]);
     }
 
@@ -1743,7 +1765,7 @@ mod tests {
         assert_paths(td.path(), &builder, &[
             "a", "a/b", "a/b/foo", "z",
         ]);
-        assert_paths(td.path(), &builder.follow_links(true), &[
+        assert_paths(td.path(), builder.follow_links(true), &[
             "a", "a/b", "a/b/foo", "z", "z/foo",
         ]);
     }
@@ -1759,7 +1781,7 @@ mod tests {
         assert_paths(td.path(), &builder, &[
             "a", "a/b", "a/b/c",
         ]);
-        assert_paths(td.path(), &builder.follow_links(true), &[
+        assert_paths(td.path(), builder.follow_links(true), &[
             "a", "a/b",
         ]);
     }
@@ -6,7 +6,7 @@ use std::path::Path;
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
 
 
-const IGNORE_FILE: &'static str = "tests/gitignore_matched_path_or_any_parents_tests.gitignore";
+const IGNORE_FILE: &str = "tests/gitignore_matched_path_or_any_parents_tests.gitignore";
 
 
 fn get_gitignore() -> Gitignore {
@@ -23,7 +23,7 @@ fn test_path_should_be_under_root() {
     let gitignore = get_gitignore();
     let path = "/tmp/some_file";
     gitignore.matched_path_or_any_parents(Path::new(path), false);
-    assert!(false);
+    unreachable!();
 }
 
 
@@ -162,7 +162,10 @@ pub struct RGArg {
 /// type. Some state is only known to clap. There isn't any particular reason
 /// why; the state we do capture is motivated by use cases (like generating
 /// documentation).
+// Some of the fields below are only read by the doc generation in build.rs,
+// which compiles this file as its own module.
 #[derive(Clone)]
+#[allow(dead_code)]
 pub enum RGArgKind {
     /// A positional argument.
     Positional {
@@ -245,12 +248,12 @@ impl RGArg {
     fn positional(name: &'static str, value_name: &'static str) -> RGArg {
         RGArg {
             claparg: Arg::with_name(name).value_name(value_name),
-            name: name,
+            name,
             doc_short: "",
             doc_long: "",
             hidden: false,
             kind: RGArgKind::Positional {
-                value_name: value_name,
+                value_name,
                 multiple: false,
             },
         }
@@ -268,7 +271,7 @@ impl RGArg {
         let claparg = Arg::with_name(long_name)
             .long(long_name);
         RGArg {
-            claparg: claparg,
+            claparg,
             name: long_name,
             doc_short: "",
             doc_long: "",
@@ -298,7 +301,7 @@ impl RGArg {
             .takes_value(true)
             .number_of_values(1);
         RGArg {
-            claparg: claparg,
+            claparg,
             name: long_name,
             doc_short: "",
             doc_long: "",
@@ -306,7 +309,7 @@ impl RGArg {
             kind: RGArgKind::Flag {
                 long: long_name,
                 short: None,
-                value_name: value_name,
+                value_name,
                 multiple: false,
                 possible_values: vec![],
             }
@@ -834,10 +837,12 @@ The argument accepts the same size suffixes as allowed in with the
 fn flag_encoding(args: &mut Vec<RGArg>) {
     const SHORT: &str = "Specify the text encoding of files to search.";
     const LONG: &str = long!("\
-Specify the text encoding that ripgrep will use on all files searched. The
-default value is 'auto', which will cause ripgrep to do a best effort automatic
-detection of encoding on a per-file basis. Other supported values can be found
-in the list of labels here:
+Specify the text encoding that ripgrep will use on all files searched. By
+default, ripgrep only detects an encoding via a BOM, and otherwise assumes
+UTF-8. The special value 'auto' will additionally cause ripgrep to do a best
+effort heuristic detection of encoding on a per-file basis, while the special
+value 'none' disables all detection, including via a BOM. Other supported
+values can be found in the list of labels here:
 https://encoding.spec.whatwg.org/#concept-encoding-get
 ");
     let arg = RGArg::flag("encoding", "ENCODING").short("E")
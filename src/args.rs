@@ -21,6 +21,7 @@ use atty;
 use ignore::overrides::{Override, OverrideBuilder};
 use ignore::types::{FileTypeDef, Types, TypesBuilder};
 use ignore;
+use decoder::EncodingDetection;
 use printer::{ColorSpecs, Printer};
 use unescape::unescape;
 use worker::{Worker, WorkerBuilder};
@@ -43,6 +44,7 @@ pub struct Args {
     count: bool,
     count_matches: bool,
     encoding: Option<&'static Encoding>,
+    encoding_detection: EncodingDetection,
     files_with_matches: bool,
     files_without_matches: bool,
     eol: u8,
@@ -228,7 +230,7 @@ impl Args {
     }
 
     /// Create a new writer for single-threaded searching with color support.
-    pub fn stdout(&self) -> Box<termcolor::WriteColor> {
+    pub fn stdout(&self) -> Box<dyn termcolor::WriteColor> {
         if atty::is(atty::Stream::Stdout) {
             Box::new(termcolor::StandardStream::stdout(self.color_choice))
         } else {
@@ -277,6 +279,7 @@ impl Args {
             .count(self.count)
             .count_matches(self.count_matches)
             .encoding(self.encoding)
+            .encoding_detection(self.encoding_detection)
             .files_with_matches(self.files_with_matches)
             .files_without_matches(self.files_without_matches)
             .eol(self.eol)
@@ -381,17 +384,18 @@ impl<'a> ArgMatches<'a> {
         let (count, count_matches) = self.counts();
         let quiet = self.is_present("quiet");
         let args = Args {
-            paths: paths,
-            after_context: after_context,
-            before_context: before_context,
+            paths,
+            after_context,
+            before_context,
             byte_offset: self.is_present("byte-offset"),
             color_choice: self.color_choice(),
             colors: self.color_specs()?,
             column: self.column(),
             context_separator: self.context_separator(),
-            count: count,
-            count_matches: count_matches,
+            count,
+            count_matches,
             encoding: self.encoding()?,
+            encoding_detection: self.encoding_detection(),
             files_with_matches: self.is_present("files-with-matches"),
             files_without_matches: self.is_present("files-without-match"),
             eol: b'\n',
@@ -403,13 +407,13 @@ impl<'a> ArgMatches<'a> {
             hidden: self.hidden(),
             ignore_files: self.ignore_files(),
             invert_match: self.is_present("invert-match"),
-            line_number: line_number,
+            line_number,
             line_per_match: self.is_present("vimgrep"),
             max_columns: self.usize_of_nonzero("max-columns")?,
             max_count: self.usize_of("max-count")?.map(|n| n as u64),
             max_depth: self.usize_of("max-depth")?,
             max_filesize: self.max_filesize()?,
-            mmap: mmap,
+            mmap,
             no_ignore: self.no_ignore(),
             no_ignore_messages: self.is_present("no-ignore-messages"),
             no_ignore_parent: self.no_ignore_parent(),
@@ -418,7 +422,7 @@ impl<'a> ArgMatches<'a> {
             null: self.is_present("null"),
             only_matching: self.is_present("only-matching"),
             path_separator: self.path_separator()?,
-            quiet: quiet,
+            quiet,
             quiet_matched: QuietMatched::new(quiet),
             replace: self.replace(),
             sort_files: self.is_present("sort-files"),
@@ -427,7 +431,7 @@ impl<'a> ArgMatches<'a> {
             threads: self.threads()?,
             type_list: self.is_present("type-list"),
             types: self.types()?,
-            with_filename: with_filename,
+            with_filename,
             search_zip_files: self.is_present("search-zip"),
             stats: self.stats()
         };
@@ -461,7 +465,7 @@ impl<'a> ArgMatches<'a> {
     /// Return the default path that ripgrep should search.
     fn default_path(&self) -> PathBuf {
         let file_is_stdin =
-            self.values_of_os("file").map_or(false, |mut files| {
+            self.values_of_os("file").is_some_and(|mut files| {
                 files.any(|f| f == "-")
             });
         let search_cwd = atty::is(atty::Stream::Stdin)
@@ -479,7 +483,7 @@ impl<'a> ArgMatches<'a> {
     /// Return all of the ignore files given on the command line.
     fn ignore_files(&self) -> Vec<PathBuf> {
         match self.values_of_os("ignore-file") {
-            None => return vec![],
+            None => vec![],
             Some(vals) => vals.map(|p| Path::new(p).to_path_buf()).collect(),
         }
     }
@@ -624,7 +628,7 @@ impl<'a> ArgMatches<'a> {
             self.is_present("with-filename")
             || self.is_present("vimgrep")
             || paths.len() > 1
-            || paths.get(0).map_or(false, |p| path_is_dir(p))
+            || paths.first().is_some_and(|p| path_is_dir(p))
         }
     }
 
@@ -834,7 +838,7 @@ impl<'a> ArgMatches<'a> {
         match self.value_of_lossy("encoding") {
             None => Ok(None),
             Some(label) => {
-                if label == "auto" {
+                if label == "auto" || label == "none" {
                     return Ok(None);
                 }
                 match Encoding::for_label_no_replacement(label.as_bytes()) {
@@ -846,8 +850,28 @@ impl<'a> ArgMatches<'a> {
         }
     }
 
-    /// Returns whether status should be tracked for this run of ripgrep
+    /// Return the encoding detection strategy specified.
+    ///
+    /// By default, encodings are only detected via a BOM. `-E auto` enables
+    /// heuristic detection over the beginning of each file and `-E none`
+    /// disables detection entirely.
+    fn encoding_detection(&self) -> EncodingDetection {
+        match self.value_of_lossy("encoding") {
+            None => EncodingDetection::BomOnly,
+            Some(label) => {
+                if label == "auto" {
+                    EncodingDetection::Auto
+                } else if label == "none" {
+                    EncodingDetection::Off
+                } else {
+                    EncodingDetection::BomOnly
+                }
+            }
+        }
+    }
 
+    /// Returns whether status should be tracked for this run of ripgrep.
+    ///
     /// This is automatically disabled if we're asked to only list the
     /// files that wil be searched, files with matches or files
     /// without matches.
@@ -1023,7 +1047,7 @@ impl<'a> ArgMatches<'a> {
     /// Like values_of_lossy, but returns an empty vec if the flag is not
     /// present.
     fn values_of_lossy_vec(&self, name: &str) -> Vec<String> {
-        self.values_of_lossy(name).unwrap_or_else(Vec::new)
+        self.values_of_lossy(name).unwrap_or_default()
     }
 
     /// Safely reads an arg value with the given name, and if it's present,
@@ -1139,8 +1163,6 @@ fn human_readable_to_usize(
     arg_name: &str,
     value: Option<u64>,
 ) -> Result<Option<usize>> {
-    use std::usize;
-
     match value {
         None => Ok(None),
         Some(v) => {
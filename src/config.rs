@@ -11,6 +11,10 @@ use std::path::{Path, PathBuf};
 
 use Result;
 
+/// The result of parsing a config file: the shell arguments parsed, along
+/// with any errors that occurred while parsing individual lines.
+type ParsedArgs = (Vec<OsString>, Vec<Box<dyn Error>>);
+
 /// Return a sequence of arguments derived from ripgrep rc configuration files.
 ///
 /// If no_messages is false and there was a problem reading a config file,
@@ -53,11 +57,9 @@ pub fn args(no_messages: bool) -> Vec<OsString> {
 /// If the file could not be read, then an error is returned. If there was
 /// a problem parsing one or more lines in the file, then errors are returned
 /// for each line in addition to successfully parsed arguments.
-fn parse<P: AsRef<Path>>(
-    path: P,
-) -> Result<(Vec<OsString>, Vec<Box<Error>>)> {
+fn parse<P: AsRef<Path>>(path: P) -> Result<ParsedArgs> {
     let path = path.as_ref();
-    match File::open(&path) {
+    match File::open(path) {
         Ok(file) => parse_reader(file),
         Err(err) => errored!("{}: {}", path.display(), err),
     }
@@ -74,9 +76,7 @@ fn parse<P: AsRef<Path>>(
 /// If the reader could not be read, then an error is returned. If there was a
 /// problem parsing one or more lines, then errors are returned for each line
 /// in addition to successfully parsed arguments.
-fn parse_reader<R: io::Read>(
-    rdr: R,
-) -> Result<(Vec<OsString>, Vec<Box<Error>>)> {
+fn parse_reader<R: io::Read>(rdr: R) -> Result<ParsedArgs> {
     let mut bufrdr = io::BufReader::new(rdr);
     let (mut args, mut errs) = (vec![], vec![]);
     let mut line = vec![];
@@ -1,7 +1,48 @@
 use std::cmp;
 use std::io::{self, Read};
+use std::str;
+
+use encoding_rs::{Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/// The number of bytes to inspect when heuristically detecting an encoding.
+const SNIFF_LEN: usize = 8 * (1 << 10);
+
+/// The minimum confidence required before a heuristically detected encoding
+/// is used to transcode the input.
+const MIN_CONFIDENCE: f64 = 0.75;
+
+/// The strategy used to detect the character encoding of a stream when no
+/// encoding has been explicitly specified.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EncodingDetection {
+    /// Never detect an encoding. The input is passed through unchanged.
+    Off,
+    /// Detect an encoding only when the stream starts with a BOM. This is
+    /// the default.
+    #[default]
+    BomOnly,
+    /// Detect an encoding from a BOM and, failing that, from heuristics
+    /// applied to the first few KB of the stream. Heuristics only select a
+    /// transcoder when confidence is high.
+    Auto,
+}
 
-use encoding_rs::{Decoder, Encoding, UTF_8};
+/// The result of encoding detection on a stream.
+///
+/// This describes which encoding was selected, how confident the detection
+/// was and whether it came from a BOM or from content heuristics. Callers can
+/// use this to report why output was transcoded.
+#[derive(Clone, Copy, Debug)]
+pub struct DetectedEncoding {
+    /// The encoding that was detected.
+    pub encoding: &'static Encoding,
+    /// The confidence of the detection, in `(0, 1]`. BOM detection always
+    /// has a confidence of `1.0`.
+    pub confidence: f64,
+    /// Whether the encoding was detected from a BOM (as opposed to content
+    /// heuristics).
+    pub via_bom: bool,
+}
 
 /// A BOM is at least 2 bytes and at most 3 bytes.
 ///
@@ -47,7 +88,7 @@ impl<R: io::Read> BomPeeker<R> {
     /// The first three bytes can be read using the `peek_bom` method, but
     /// will not advance the reader.
     fn new(rdr: R) -> BomPeeker<R> {
-        BomPeeker { rdr: rdr, bom: None, nread: 0 }
+        BomPeeker { rdr, bom: None, nread: 0 }
     }
 
     /// Peek at the first three bytes of the underlying reader.
@@ -141,6 +182,13 @@ pub struct DecodeReader<R, B> {
     last: bool,
     /// The underlying text decoder derived from the BOM, if one exists.
     decoder: Option<Decoder>,
+    /// The detection strategy to use when no explicit encoding is given.
+    detection: EncodingDetection,
+    /// Whether a BOM at offset zero is used to select an encoding and is
+    /// stripped from the stream.
+    bom_sniffing: bool,
+    /// The result of detection, if detection ran and succeeded.
+    detected: Option<DetectedEncoding>,
 }
 
 impl<R: io::Read, B: AsMut<[u8]>> DecodeReader<R, B> {
@@ -162,15 +210,54 @@ impl<R: io::Read, B: AsMut<[u8]>> DecodeReader<R, B> {
     ) -> DecodeReader<R, B> {
         DecodeReader {
             rdr: BomPeeker::new(rdr),
-            buf: buf,
+            buf,
             buflen: 0,
             pos: 0,
             first: enc.is_none(),
             last: false,
             decoder: enc.map(|enc| enc.new_decoder_with_bom_removal()),
+            detection: EncodingDetection::default(),
+            bom_sniffing: true,
+            detected: None,
         }
     }
 
+    /// Set the encoding detection strategy to use.
+    ///
+    /// This has no effect if an explicit encoding was given to `new`, since
+    /// an explicit encoding always wins over detection.
+    pub fn encoding_detection(
+        mut self,
+        detection: EncodingDetection,
+    ) -> DecodeReader<R, B> {
+        self.detection = detection;
+        self
+    }
+
+    /// When disabled, a BOM at the start of the stream is neither used to
+    /// select an encoding nor stripped; its bytes are passed through to the
+    /// caller like any others. Content heuristics (`EncodingDetection::Auto`)
+    /// still apply.
+    ///
+    /// This is enabled by default, so that a UTF-8 BOM never breaks
+    /// `^pattern` matches on the first line.
+    #[allow(dead_code)]
+    pub fn bom_sniffing(mut self, yes: bool) -> DecodeReader<R, B> {
+        self.bom_sniffing = yes;
+        self
+    }
+
+    /// Return the result of encoding detection on this stream, if detection
+    /// ran and selected an encoding.
+    ///
+    /// This is `None` if an explicit encoding was given, if detection is
+    /// disabled or if no encoding could be detected with enough confidence.
+    /// Note that detection only runs on the first read from this reader.
+    #[allow(dead_code)]
+    pub fn detected_encoding(&self) -> Option<DetectedEncoding> {
+        self.detected
+    }
+
     /// Fill the internal buffer from the underlying reader.
     ///
     /// If there are unread bytes in the internal buffer, then we move them
@@ -182,8 +269,7 @@ impl<R: io::Read, B: AsMut<[u8]>> DecodeReader<R, B> {
     fn fill(&mut self) -> io::Result<()> {
         if self.pos < self.buflen {
             if self.buflen >= self.buf.as_mut().len() {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
+                return Err(io::Error::other(
                     "DecodeReader: internal buffer exhausted"));
             }
             let newlen = self.buflen - self.pos;
@@ -251,10 +337,125 @@ impl<R: io::Read, B: AsMut<[u8]>> DecodeReader<R, B> {
 
     #[inline(never)] // impacts perf...
     fn detect(&mut self) -> io::Result<()> {
-        let bom = self.rdr.peek_bom()?;
-        self.decoder = bom.decoder();
+        if self.detection == EncodingDetection::Off {
+            return Ok(());
+        }
+        if self.bom_sniffing {
+            let bom = self.rdr.peek_bom()?;
+            self.decoder = bom.decoder();
+            if self.decoder.is_some() {
+                if let Some((enc, _)) = Encoding::for_bom(bom.as_slice()) {
+                    self.detected = Some(DetectedEncoding {
+                        encoding: enc,
+                        confidence: 1.0,
+                        via_bom: true,
+                    });
+                }
+                return Ok(());
+            }
+            // A UTF-8 BOM selects no transcoder, but its bytes would
+            // otherwise reach the caller and break anchored matches on the
+            // first line. Consume it here.
+            if let Some((enc, len)) = Encoding::for_bom(bom.as_slice()) {
+                if enc == UTF_8 {
+                    read_full(&mut self.rdr, &mut [0u8; 3][..len])?;
+                }
+            }
+        }
+        if self.detection == EncodingDetection::Auto {
+            self.sniff()?;
+        }
         Ok(())
     }
+
+    /// Run content heuristics over a prefix of the stream and select a
+    /// decoder if an encoding is detected with enough confidence.
+    ///
+    /// The prefix read here is buffered internally, so no input is lost
+    /// regardless of whether detection succeeds.
+    fn sniff(&mut self) -> io::Result<()> {
+        let sniff_len = cmp::min(SNIFF_LEN, self.buf.as_mut().len());
+        self.buflen = read_full(&mut self.rdr, &mut self.buf.as_mut()[..sniff_len])?;
+        self.pos = 0;
+        if let Some(detected) = detect_encoding(&self.buf.as_mut()[..self.buflen]) {
+            debug!(
+                "detected encoding {} (confidence: {:.2}, via_bom: {})",
+                detected.encoding.name(), detected.confidence,
+                detected.via_bom);
+            self.decoder =
+                Some(detected.encoding.new_decoder_with_bom_removal());
+            self.detected = Some(detected);
+        }
+        Ok(())
+    }
+}
+
+/// Use heuristics to guess the encoding of the given buffer, which should be
+/// a prefix of a stream or file.
+///
+/// This looks for the alternating-NUL patterns produced by mostly-ASCII
+/// UTF-16 text and, failing that, distinguishes Latin-1 style single byte
+/// encodings from UTF-8. An encoding is only returned when the heuristic has
+/// high confidence, so this errs on the side of passing input through
+/// unchanged.
+pub fn detect_encoding(buf: &[u8]) -> Option<DetectedEncoding> {
+    if buf.len() < 4 {
+        return None;
+    }
+    // UTF-16 text that is mostly ASCII has a NUL in every other byte. Count
+    // the pairs that look like little endian ("a\x00") and big endian
+    // ("\x00a") code units and see if either pattern dominates.
+    let (mut le, mut be) = (0u64, 0u64);
+    let npairs = buf.len() / 2;
+    for pair in buf.chunks_exact(2) {
+        if pair[1] == 0 && pair[0] != 0 {
+            le += 1;
+        } else if pair[0] == 0 && pair[1] != 0 {
+            be += 1;
+        }
+    }
+    let le_confidence = le as f64 / npairs as f64;
+    let be_confidence = be as f64 / npairs as f64;
+    if le_confidence >= MIN_CONFIDENCE && le_confidence > be_confidence {
+        return Some(DetectedEncoding {
+            encoding: UTF_16LE,
+            confidence: le_confidence,
+            via_bom: false,
+        });
+    }
+    if be_confidence >= MIN_CONFIDENCE && be_confidence > le_confidence {
+        return Some(DetectedEncoding {
+            encoding: UTF_16BE,
+            confidence: be_confidence,
+            via_bom: false,
+        });
+    }
+    // If the buffer contains NUL bytes that don't look like UTF-16, then
+    // it's probably binary data. Let binary detection deal with it.
+    if buf.contains(&0) {
+        return None;
+    }
+    // If the buffer is valid UTF-8, there's nothing to transcode. A buffer
+    // that is only invalid because it ends in the middle of a codepoint is
+    // treated as valid.
+    match str::from_utf8(buf) {
+        Ok(_) => return None,
+        Err(err) if err.error_len().is_none() => return None,
+        Err(_) => {}
+    }
+    // Otherwise, guess Latin-1 if the non-ASCII bytes predominantly fall in
+    // the printable `0xA0-0xFF` range.
+    let high = buf.iter().filter(|&&b| b >= 0x80).count();
+    let printable = buf.iter().filter(|&&b| b >= 0xA0).count();
+    let confidence = printable as f64 / high as f64;
+    if confidence >= MIN_CONFIDENCE {
+        return Some(DetectedEncoding {
+            encoding: WINDOWS_1252,
+            confidence,
+            via_bom: false,
+        });
+    }
+    None
 }
 
 impl<R: io::Read, B: AsMut<[u8]>> io::Read for DecodeReader<R, B> {
@@ -264,6 +465,16 @@ impl<R: io::Read, B: AsMut<[u8]>> io::Read for DecodeReader<R, B> {
             self.detect()?;
         }
         if self.decoder.is_none() {
+            // If detection buffered a prefix of the stream but did not
+            // select an encoding, drain that prefix before passing the
+            // reader through.
+            if self.pos < self.buflen {
+                let len = cmp::min(buf.len(), self.buflen - self.pos);
+                buf[..len].copy_from_slice(
+                    &self.buf.as_mut()[self.pos..self.pos + len]);
+                self.pos += len;
+                return Ok(len);
+            }
             return self.rdr.read(buf);
         }
         // When decoding UTF-8, we need at least 4 bytes of space to guarantee
@@ -272,8 +483,7 @@ impl<R: io::Read, B: AsMut<[u8]>> io::Read for DecodeReader<R, B> {
         // error. Since `0` would be interpreted as a possibly premature EOF,
         // we opt for an error.
         if buf.len() < 4 {
-            return Err(io::Error::new(
-                io::ErrorKind::Other,
+            return Err(io::Error::other(
                 "DecodeReader: byte buffer must have length at least 4"));
         }
         self.transcode(buf)
@@ -284,9 +494,11 @@ impl<R: io::Read, B: AsMut<[u8]>> io::Read for DecodeReader<R, B> {
 mod tests {
     use std::io::Read;
 
-    use encoding_rs::Encoding;
+    use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, WINDOWS_1252};
 
-    use super::{Bom, BomPeeker, DecodeReader};
+    use super::{
+        Bom, BomPeeker, DecodeReader, EncodingDetection, detect_encoding,
+    };
 
     fn read_to_string<R: Read>(mut rdr: R) -> String {
         let mut s = String::new();
@@ -400,10 +612,35 @@ mod tests {
         let n = rdr.read(&mut dstbuf).unwrap();
         assert_eq!(&*srcbuf, &dstbuf[..n]);
 
+        // A UTF-8 BOM selects no transcoder, but is stripped.
         let srcbuf = vec![0xEF, 0xBB, 0xBF];
         let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None);
         let n = rdr.read(&mut dstbuf).unwrap();
-        assert_eq!(&*srcbuf, &dstbuf[..n]);
+        assert_eq!(0, n);
+    }
+
+    // A UTF-8 BOM is stripped before the data reaches the caller, and left
+    // alone when sniffing is disabled.
+    #[test]
+    fn trans_utf8_bom_stripped() {
+        let srcbuf = b"\xEF\xBB\xBFfoo\n".to_vec();
+        let rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None);
+        assert_eq!(b"foo\n", &*read_all(rdr));
+
+        let rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .bom_sniffing(false);
+        assert_eq!(&*srcbuf, &*read_all(rdr));
+    }
+
+    // With sniffing disabled, a UTF-16 BOM is passed through unchanged and
+    // selects no transcoder.
+    #[test]
+    fn trans_utf16_bom_not_sniffed() {
+        let srcbuf = vec![0xFF, 0xFE, 0x61, 0x00];
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .bom_sniffing(false);
+        assert_eq!(&*srcbuf, &*read_all(&mut rdr));
+        assert!(rdr.detected_encoding().is_none());
     }
 
     // Test basic UTF-16 decoding.
@@ -427,6 +664,205 @@ mod tests {
         assert_eq!("a\u{FFFD}", read_to_string(&mut rdr));
     }
 
+    // Test heuristic detection of BOM-less UTF-16LE.
+    #[test]
+    fn detect_utf16le_no_bom() {
+        let srcbuf = b"a\x00b\x00c\x00\n\x00".to_vec();
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .encoding_detection(EncodingDetection::Auto);
+        assert_eq!("abc\n", read_to_string(&mut rdr));
+        let detected = rdr.detected_encoding().unwrap();
+        assert_eq!(UTF_16LE, detected.encoding);
+        assert!(!detected.via_bom);
+        assert!(detected.confidence >= 0.75);
+    }
+
+    // Test heuristic detection of BOM-less UTF-16BE.
+    #[test]
+    fn detect_utf16be_no_bom() {
+        let srcbuf = b"\x00a\x00b\x00c\x00\n".to_vec();
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .encoding_detection(EncodingDetection::Auto);
+        assert_eq!("abc\n", read_to_string(&mut rdr));
+        assert_eq!(UTF_16BE, rdr.detected_encoding().unwrap().encoding);
+    }
+
+    // Without Auto detection, BOM-less UTF-16 is passed through unchanged.
+    #[test]
+    fn detect_utf16le_no_bom_bom_only() {
+        let srcbuf = b"a\x00b\x00c\x00\n\x00".to_vec();
+        let mut dstbuf = vec![];
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None);
+        rdr.read_to_end(&mut dstbuf).unwrap();
+        assert_eq!(srcbuf, dstbuf);
+        assert!(rdr.detected_encoding().is_none());
+    }
+
+    // Test heuristic detection of Latin-1.
+    #[test]
+    fn detect_latin1() {
+        let srcbuf = b"caf\xE9\n".to_vec();
+        let mut dstbuf = vec![0; 8 * (1<<10)];
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .encoding_detection(EncodingDetection::Auto);
+        let n = rdr.read(&mut dstbuf).unwrap();
+        assert_eq!("café\n".as_bytes(), &dstbuf[..n]);
+        assert_eq!(WINDOWS_1252, rdr.detected_encoding().unwrap().encoding);
+    }
+
+    // Valid UTF-8 must never be selected for transcoding by heuristics.
+    #[test]
+    fn detect_valid_utf8() {
+        assert!(detect_encoding("Жabcdef\n".as_bytes()).is_none());
+    }
+
+    // An explicit encoding always wins over detection.
+    #[test]
+    fn detect_explicit_encoding_wins() {
+        let srcbuf = b"\x00a\x00b\x00c\x00\n".to_vec();
+        let enc = Encoding::for_label(b"utf-16be");
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], enc)
+            .encoding_detection(EncodingDetection::Auto);
+        assert_eq!("abc\n", read_to_string(&mut rdr));
+        assert!(rdr.detected_encoding().is_none());
+    }
+
+    // Read everything through a buffer big enough to satisfy the decoder's
+    // minimum destination length on every call.
+    fn read_all<R: Read>(mut rdr: R) -> Vec<u8> {
+        let mut dstbuf = vec![0; 8 * (1<<10)];
+        let mut out = vec![];
+        loop {
+            let n = rdr.read(&mut dstbuf).unwrap();
+            if n == 0 {
+                return out;
+            }
+            out.extend_from_slice(&dstbuf[..n]);
+        }
+    }
+
+    fn utf16le_bytes(s: &str) -> Vec<u8> {
+        let mut buf = vec![];
+        for cu in s.encode_utf16() {
+            buf.push(cu as u8);
+            buf.push((cu >> 8) as u8);
+        }
+        buf
+    }
+
+    fn utf16be_bytes(s: &str) -> Vec<u8> {
+        let mut buf = vec![];
+        for cu in s.encode_utf16() {
+            buf.push((cu >> 8) as u8);
+            buf.push(cu as u8);
+        }
+        buf
+    }
+
+    // A PNG header has sparse NULs that don't follow the UTF-16 pattern.
+    #[test]
+    fn detect_png_not_utf16() {
+        let mut srcbuf = b"\x89PNG\r\n\x1a\n".to_vec();
+        // IHDR chunk: length, type, 100x100, bit depth/color/etc.
+        srcbuf.extend_from_slice(b"\x00\x00\x00\x0dIHDR");
+        srcbuf.extend_from_slice(b"\x00\x00\x00\x64\x00\x00\x00\x64");
+        srcbuf.extend_from_slice(b"\x08\x06\x00\x00\x00");
+        assert!(detect_encoding(&srcbuf).is_none());
+    }
+
+    // A tar header is mostly NUL padding, which pairs up as zero-zero and
+    // should count as neither endianness.
+    #[test]
+    fn detect_tar_header_not_utf16() {
+        let mut srcbuf = vec![0; 512];
+        srcbuf[..7].copy_from_slice(b"foo.txt");
+        srcbuf[100..108].copy_from_slice(b"0000644\x00");
+        srcbuf[124..136].copy_from_slice(b"00000000052\x00");
+        srcbuf[257..262].copy_from_slice(b"ustar");
+        assert!(detect_encoding(&srcbuf).is_none());
+    }
+
+    // Realistic multi-line BOM-less UTF-16LE text, including non-ASCII
+    // codepoints whose pairs don't match either NUL pattern.
+    #[test]
+    fn detect_utf16le_realistic() {
+        let text = "For the Doctor Watsons of this world,\n\
+                    as opposed to the café Sherlock Holmeses,\n\
+                    success is the result of luck — mostly.\n";
+        let srcbuf = utf16le_bytes(text);
+        let detected = detect_encoding(&srcbuf).unwrap();
+        assert_eq!(UTF_16LE, detected.encoding);
+        assert!(detected.confidence >= 0.75);
+
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .encoding_detection(EncodingDetection::Auto);
+        assert_eq!(text.as_bytes(), &*read_all(&mut rdr));
+    }
+
+    // Same corpus, big endian.
+    #[test]
+    fn detect_utf16be_realistic() {
+        let text = "For the Doctor Watsons of this world,\n\
+                    as opposed to the café Sherlock Holmeses,\n\
+                    success is the result of luck — mostly.\n";
+        let srcbuf = utf16be_bytes(text);
+        let detected = detect_encoding(&srcbuf).unwrap();
+        assert_eq!(UTF_16BE, detected.encoding);
+        assert!(detected.confidence >= 0.75);
+
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .encoding_detection(EncodingDetection::Auto);
+        assert_eq!(text.as_bytes(), &*read_all(&mut rdr));
+    }
+
+    // A reader that returns one byte per call, so every UTF-16 code unit
+    // (and the BOM itself) is split across read boundaries.
+    struct OneByteReader<'a>(&'a [u8]);
+
+    impl<'a> Read for OneByteReader<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> ::std::io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    // Code unit sequences split across read boundaries must decode
+    // identically to a single contiguous read. This exercises the internal
+    // buffering in `transcode`: with one source byte per read, no single
+    // fill ever holds a complete code unit pair up front.
+    #[test]
+    fn trans_utf16_split_reads() {
+        let text = "For the Doctor Watsons café — Ж\n";
+        let mut srcbuf = vec![0xFF, 0xFE];
+        srcbuf.extend_from_slice(&utf16le_bytes(text));
+        let rdr = DecodeReader::new(
+            OneByteReader(&srcbuf), vec![0; 8 * (1<<10)], None);
+        assert_eq!(text.as_bytes(), &*read_all(rdr));
+
+        // Same again, big endian and without a BOM, via heuristics.
+        let srcbuf = utf16be_bytes(text);
+        let rdr = DecodeReader::new(
+            OneByteReader(&srcbuf), vec![0; 8 * (1<<10)], None)
+            .encoding_detection(EncodingDetection::Auto);
+        assert_eq!(text.as_bytes(), &*read_all(rdr));
+    }
+
+    // BOM detection is reported with full confidence.
+    #[test]
+    fn detect_bom_reported() {
+        let srcbuf = vec![0xFF, 0xFE, 0x61, 0x00];
+        let mut rdr = DecodeReader::new(&*srcbuf, vec![0; 8 * (1<<10)], None)
+            .encoding_detection(EncodingDetection::Auto);
+        assert_eq!("a", read_to_string(&mut rdr));
+        let detected = rdr.detected_encoding().unwrap();
+        assert!(detected.via_bom);
+        assert_eq!(1.0, detected.confidence);
+    }
+
     macro_rules! test_trans_simple {
         ($name:ident, $enc:expr, $srcbytes:expr, $dst:expr) => {
             #[test]
@@ -132,8 +132,8 @@ impl DecompressionReader {
         child: process::Child,
     ) -> DecompressionReader {
         DecompressionReader {
-            cmd: cmd,
-            child: child,
+            cmd,
+            child,
             done: false,
         }
     }
@@ -146,11 +146,11 @@ impl DecompressionReader {
 
         Ok(if errstr.is_empty() {
             let msg = format!("decompression command failed: '{}'", self.cmd);
-            io::Error::new(io::ErrorKind::Other, msg)
+            io::Error::other(msg)
         } else {
             let msg = format!(
                 "decompression command '{}' failed: {}", self.cmd, errstr);
-            io::Error::new(io::ErrorKind::Other, msg)
+            io::Error::other(msg)
         })
     }
 }
@@ -12,7 +12,7 @@ use log::{self, Log};
 #[derive(Debug)]
 pub struct Logger(());
 
-const LOGGER: &'static Logger = &Logger(());
+const LOGGER: &Logger = &Logger(());
 
 impl Logger {
     /// Create a new logger that logs to stderr and initialize it as the
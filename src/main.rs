@@ -1,5 +1,7 @@
 extern crate atty;
 extern crate bytecount;
+#[cfg(feature = "bytes")]
+extern crate bytes;
 #[macro_use]
 extern crate clap;
 extern crate encoding_rs;
@@ -34,7 +36,7 @@ use worker::Work;
 
 macro_rules! errored {
     ($($tt:tt)*) => {
-        return Err(From::from(format!($($tt)*)));
+        return Err(From::from(format!($($tt)*)))
     }
 }
 
@@ -48,10 +50,11 @@ mod pathutil;
 mod printer;
 mod search_buffer;
 mod search_stream;
+mod sink;
 mod unescape;
 mod worker;
 
-pub type Result<T> = result::Result<T, Box<Error>>;
+pub type Result<T> = result::Result<T, Box<dyn Error>>;
 
 fn main() {
     reset_sigpipe();
@@ -144,11 +147,10 @@ fn run_parallel(args: &Arc<Args>) -> Result<u64> {
             Continue
         })
     });
-    if !args.paths().is_empty() && paths_searched.load(Ordering::SeqCst) == 0 {
-        if !args.no_messages() {
+    if !args.paths().is_empty() && paths_searched.load(Ordering::SeqCst) == 0
+        && !args.no_messages() {
             eprint_nothing_searched();
         }
-    }
     let match_line_count = match_line_count.load(Ordering::SeqCst) as u64;
     let paths_searched = paths_searched.load(Ordering::SeqCst) as u64;
     let paths_matched = paths_matched.load(Ordering::SeqCst) as u64;
@@ -202,11 +204,10 @@ fn run_one_thread(args: &Arc<Args>) -> Result<u64> {
             paths_matched += 1;
         }
     }
-    if !args.paths().is_empty() && paths_searched == 0 {
-        if !args.no_messages() {
+    if !args.paths().is_empty() && paths_searched == 0
+        && !args.no_messages() {
             eprint_nothing_searched();
         }
-    }
     if args.stats() {
         print_stats(
             match_line_count,
@@ -342,7 +343,7 @@ fn ignore_entry_is_dir(dent: &ignore::DirEntry) -> bool {
 /// directory.
 #[cfg(not(windows))]
 fn ignore_entry_is_dir(dent: &ignore::DirEntry) -> bool {
-    dent.file_type().map_or(false, |ft| ft.is_dir())
+    dent.file_type().is_some_and(|ft| ft.is_dir())
 }
 
 /// Returns true if and only if the given `ignore::DirEntry` points to a
@@ -359,7 +360,7 @@ fn ignore_entry_is_file(dent: &ignore::DirEntry) -> bool {
 /// file.
 #[cfg(not(windows))]
 fn ignore_entry_is_file(dent: &ignore::DirEntry) -> bool {
-    dent.file_type().map_or(false, |ft| ft.is_file())
+    dent.file_type().is_some_and(|ft| ft.is_file())
 }
 
 fn is_stdout_file(
@@ -1,7 +1,7 @@
 use std::error;
 use std::fmt;
 use std::path::Path;
-use std::str::FromStr;
+use std::str::{self, FromStr};
 
 use regex::bytes::{Captures, Match, Regex, Replacer};
 use termcolor::{Color, ColorSpec, ParseColorError, WriteColor};
@@ -18,7 +18,7 @@ struct Offset {
 
 impl Offset {
     fn new(start: usize, end: usize) -> Offset {
-        Offset { start: start, end: end }
+        Offset { start, end }
     }
 }
 
@@ -42,7 +42,7 @@ impl<'r> CountingReplacer<'r> {
         count: &'r mut usize,
         offsets: &'r mut Vec<Offset>,
     ) -> CountingReplacer<'r> {
-        CountingReplacer { replace: replace, count: count, offsets: offsets, }
+        CountingReplacer { replace, count, offsets, }
     }
 }
 
@@ -58,6 +58,18 @@ impl<'r> Replacer for CountingReplacer<'r> {
     }
 }
 
+/// The granularity at which matches are reported by a printer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ReportGranularity {
+    /// Report one event per matching line, regardless of how many matches
+    /// the line contains. This is the default.
+    #[default]
+    PerLine,
+    /// Report one event per individual match, so a line with three matches
+    /// produces three events.
+    PerMatch,
+}
+
 /// Printer encapsulates all output logic for searching.
 ///
 /// Note that we currently ignore all write errors. It's probably worthwhile
@@ -82,8 +94,9 @@ pub struct Printer<W> {
     ///
     /// N.B. If with_filename is false, then this setting has no effect.
     heading: bool,
-    /// Whether to show every match on its own line.
-    line_per_match: bool,
+    /// The granularity at which matches are reported. `PerMatch` causes
+    /// every match to be shown on its own line.
+    granularity: ReportGranularity,
     /// Whether to print NUL bytes after a file path instead of new lines
     /// or `:`.
     null: bool,
@@ -99,20 +112,27 @@ pub struct Printer<W> {
     path_separator: Option<u8>,
     /// Restrict lines to this many columns.
     max_columns: Option<usize>,
+    /// Whether the buffers given to this printer contain UTF-16LE encoded
+    /// text. When set, column numbers are reported in code units instead of
+    /// bytes.
+    utf16le: bool,
+    /// When true, column numbers are reported in UTF-16 code units within
+    /// the line instead of bytes.
+    utf16_columns: bool,
 }
 
 impl<W: WriteColor> Printer<W> {
     /// Create a new printer that writes to wtr with the given color settings.
     pub fn new(wtr: W) -> Printer<W> {
         Printer {
-            wtr: wtr,
+            wtr,
             has_printed: false,
             column: false,
             context_separator: "--".to_string().into_bytes(),
             eol: b'\n',
             file_separator: None,
             heading: false,
-            line_per_match: false,
+            granularity: ReportGranularity::default(),
             null: false,
             only_matching: false,
             replace: None,
@@ -120,6 +140,8 @@ impl<W: WriteColor> Printer<W> {
             colors: ColorSpecs::default(),
             path_separator: None,
             max_columns: None,
+            utf16le: false,
+            utf16_columns: false,
         }
     }
 
@@ -136,6 +158,29 @@ impl<W: WriteColor> Printer<W> {
         self
     }
 
+    /// When set, the buffers given to this printer are treated as UTF-16LE
+    /// encoded text and column numbers are reported in code units instead of
+    /// bytes.
+    #[allow(dead_code)]
+    pub fn utf16le(mut self, yes: bool) -> Printer<W> {
+        self.utf16le = yes;
+        self
+    }
+
+    /// When set, column numbers are reported as UTF-16 code-unit offsets
+    /// within the line instead of byte offsets. This is the unit used by
+    /// positions in the Language Server Protocol, so editor consumers can
+    /// use the reported column directly. If the line prefix before the
+    /// match isn't valid UTF-8, the column falls back to a byte offset.
+    ///
+    /// This has no effect unless column numbers are enabled, and is ignored
+    /// when searching UTF-16LE text natively.
+    #[allow(dead_code)]
+    pub fn utf16_columns(mut self, yes: bool) -> Printer<W> {
+        self.utf16_columns = yes;
+        self
+    }
+
     /// Set the context separator. The default is `--`.
     pub fn context_separator(mut self, sep: Vec<u8>) -> Printer<W> {
         self.context_separator = sep;
@@ -165,7 +210,25 @@ impl<W: WriteColor> Printer<W> {
 
     /// Whether to show every match on its own line.
     pub fn line_per_match(mut self, yes: bool) -> Printer<W> {
-        self.line_per_match = yes;
+        self.granularity =
+            if yes {
+                ReportGranularity::PerMatch
+            } else {
+                ReportGranularity::PerLine
+            };
+        self
+    }
+
+    /// Set the granularity at which matches are reported.
+    ///
+    /// Note that `line_per_match` and `only_matching` set this implicitly,
+    /// so this should be called after them to take precedence.
+    #[allow(dead_code)]
+    pub fn granularity(
+        mut self,
+        granularity: ReportGranularity,
+    ) -> Printer<W> {
+        self.granularity = granularity;
         self
     }
 
@@ -179,6 +242,9 @@ impl<W: WriteColor> Printer<W> {
     /// Print only the matched (non-empty) parts of a matching line
     pub fn only_matching(mut self, yes: bool) -> Printer<W> {
         self.only_matching = yes;
+        if yes {
+            self.granularity = ReportGranularity::PerMatch;
+        }
         self
     }
 
@@ -261,9 +327,16 @@ impl<W: WriteColor> Printer<W> {
         self.write_eol();
     }
 
+    /// Prints a matched line.
+    ///
+    /// The regex, if given, is used to resolve the position of individual
+    /// matches within the line. When it's absent (e.g., for matchers that
+    /// aren't backed by a regex), the printer degrades to reporting whole
+    /// lines, even at per match granularity.
+    #[allow(clippy::too_many_arguments)]
     pub fn matched<P: AsRef<Path>>(
         &mut self,
-        re: &Regex,
+        re: Option<&Regex>,
         path: P,
         buf: &[u8],
         start: usize,
@@ -271,23 +344,36 @@ impl<W: WriteColor> Printer<W> {
         line_number: Option<u64>,
         byte_offset: Option<u64>
     ) {
-        if !self.line_per_match && !self.only_matching {
-            let mat =
-                if !self.needs_match() {
-                    (0, 0)
-                } else {
-                    re.find(&buf[start..end])
-                        .map(|m| (m.start(), m.end()))
-                        .unwrap_or((0, 0))
-                };
-            return self.write_match(
-                re, path, buf, start, end, line_number,
-                byte_offset, mat.0, mat.1);
-        }
-        for m in re.find_iter(&buf[start..end]) {
-            self.write_match(
-                re, path.as_ref(), buf, start, end, line_number,
-                byte_offset, m.start(), m.end());
+        match self.granularity {
+            ReportGranularity::PerLine => {
+                let mat =
+                    if !self.needs_match() {
+                        (0, 0)
+                    } else {
+                        re.and_then(|re| re.find(&buf[start..end]))
+                            .map(|m| (m.start(), m.end()))
+                            .unwrap_or((0, 0))
+                    };
+                self.write_match(
+                    re, path, buf, start, end, line_number,
+                    byte_offset, mat.0, mat.1);
+            }
+            ReportGranularity::PerMatch => {
+                match re {
+                    Some(re) => {
+                        for m in re.find_iter(&buf[start..end]) {
+                            self.write_match(
+                                Some(re), path.as_ref(), buf, start, end,
+                                line_number, byte_offset, m.start(), m.end());
+                        }
+                    }
+                    None => {
+                        self.write_match(
+                            None, path, buf, start, end, line_number,
+                            byte_offset, 0, 0);
+                    }
+                }
+            }
         }
     }
 
@@ -297,9 +383,10 @@ impl<W: WriteColor> Printer<W> {
         || self.only_matching
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn write_match<P: AsRef<Path>>(
         &mut self,
-        re: &Regex,
+        re: Option<&Regex>,
         path: P,
         buf: &[u8],
         start: usize,
@@ -321,7 +408,16 @@ impl<W: WriteColor> Printer<W> {
             self.line_number(line_number, b':');
         }
         if self.column {
-            self.column_number(match_start as u64 + 1, b':');
+            let col =
+                if self.utf16le {
+                    (match_start / 2) as u64
+                } else if self.utf16_columns {
+                    utf16_code_units(&buf[start..start + match_start])
+                        .unwrap_or(match_start as u64)
+                } else {
+                    match_start as u64
+                };
+            self.column_number(col + 1, b':');
         }
         if let Some(byte_offset) = byte_offset {
             if self.only_matching {
@@ -331,12 +427,15 @@ impl<W: WriteColor> Printer<W> {
                 self.write_byte_offset(byte_offset + (start as u64), b':');
             }
         }
-        if self.replace.is_some() {
+        // Replacements require the regex. Without one, fall through and
+        // print the line untouched.
+        let replace = if re.is_some() { self.replace.as_ref() } else { None };
+        if let (Some(replace), Some(re)) = (replace, re) {
             let mut count = 0;
             let mut offsets = Vec::new();
             let line = {
                 let replacer = CountingReplacer::new(
-                    self.replace.as_ref().unwrap(), &mut count, &mut offsets);
+                    replace, &mut count, &mut offsets);
                 if self.only_matching {
                     re.replace_all(
                         &buf[start + match_start..start + match_end], replacer)
@@ -344,30 +443,37 @@ impl<W: WriteColor> Printer<W> {
                     re.replace_all(&buf[start..end], replacer)
                 }
             };
-            if self.max_columns.map_or(false, |m| line.len() > m) {
+            if self.max_columns.is_some_and(|m| line.len() > m) {
                 let msg = format!(
                     "[Omitted long line with {} replacements]", count);
                 self.write_colored(msg.as_bytes(), |colors| colors.matched());
                 self.write_eol();
                 return;
             }
-            self.write_matched_line(offsets, &*line, false);
+            self.write_matched_line(offsets, &line, false);
         } else {
             let buf = if self.only_matching {
                 &buf[start + match_start..start + match_end]
             } else {
                 &buf[start..end]
             };
-            if self.max_columns.map_or(false, |m| buf.len() > m) {
-                let count = re.find_iter(buf).count();
+            if self.max_columns.is_some_and(|m| buf.len() > m) {
+                let count =
+                    re.map_or(0, |re| re.find_iter(buf).count());
                 let msg = format!("[Omitted long line with {} matches]", count);
                 self.write_colored(msg.as_bytes(), |colors| colors.matched());
                 self.write_eol();
                 return;
             }
             let only_match = self.only_matching;
-            self.write_matched_line(
-                re.find_iter(buf).map(|x| Offset::from(&x)), buf, only_match);
+            match re {
+                Some(re) => {
+                    self.write_matched_line(
+                        re.find_iter(buf).map(|x| Offset::from(&x)),
+                        buf, only_match);
+                }
+                None => self.write_matched_line(None, buf, only_match),
+            }
         }
     }
 
@@ -421,7 +527,7 @@ impl<W: WriteColor> Printer<W> {
         if let Some(byte_offset) = byte_offset {
             self.write_byte_offset(byte_offset + (start as u64), b'-');
         }
-        if self.max_columns.map_or(false, |m| end - start > m) {
+        if self.max_columns.is_some_and(|m| end - start > m) {
             self.write(b"[Omitted long context line]");
             self.write_eol();
             return;
@@ -550,7 +656,7 @@ impl error::Error for Error {
         }
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         None
     }
 }
@@ -835,10 +941,39 @@ impl FromStr for Style {
     }
 }
 
+/// Returns the length of the given line prefix in UTF-16 code units, or
+/// `None` if the prefix isn't valid UTF-8.
+///
+/// This is the byte column to UTF-16 column conversion needed by consumers
+/// of Language Server Protocol positions: calling it with the bytes of a
+/// line up to the start (or end) of a match yields the corresponding UTF-16
+/// code-unit offset in a single pass, with surrogate pairs accounted for.
+pub fn utf16_code_units(buf: &[u8]) -> Option<u64> {
+    let s = match str::from_utf8(buf) {
+        Ok(s) => s,
+        Err(_) => return None,
+    };
+    Some(s.chars().map(|c| c.len_utf16() as u64).sum())
+}
+
 #[cfg(test)]
 mod tests {
     use termcolor::{Color, ColorSpec};
     use super::{ColorSpecs, Error, OutType, Spec, SpecValue, Style};
+    use super::utf16_code_units;
+
+    #[test]
+    fn utf16_columns() {
+        // ASCII is one code unit per byte.
+        assert_eq!(Some(3), utf16_code_units(b"abc"));
+        // Multi-byte characters in the BMP are one code unit.
+        assert_eq!(Some(6), utf16_code_units("Шерлок".as_bytes()));
+        // Characters outside the BMP are surrogate pairs.
+        assert_eq!(Some(2), utf16_code_units("𝕊".as_bytes()));
+        // Invalid UTF-8 has no UTF-16 column.
+        assert_eq!(None, utf16_code_units(b"\xff\xfe"));
+        assert_eq!(Some(0), utf16_code_units(b""));
+    }
 
     #[test]
     fn merge() {
@@ -919,7 +1054,7 @@ mod tests {
         let err = "line:fg:brown".parse::<Spec>().unwrap_err();
         match err {
             Error::UnrecognizedColor(name, _) => assert_eq!(name, "brown"),
-            err => assert!(false, "unexpected error: {:?}", err),
+            err => panic!("unexpected error: {:?}", err),
         }
 
         let err = "foo:fg:brown".parse::<Spec>().unwrap_err();
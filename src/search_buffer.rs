@@ -7,48 +7,89 @@ Note that this module doesn't quite support everything that `search_stream`
 does. Notably, showing contexts.
 */
 use std::cmp;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::path::Path;
 
-use grep::Grep;
-use termcolor::WriteColor;
+use memchr::{memchr, memrchr};
 
-use printer::Printer;
-use search_stream::{IterLines, Options, count_lines, is_binary};
+use grep::{Match, Matcher};
 
-pub struct BufferSearcher<'a, W: 'a> {
+use sink::{Sink, SinkMatch};
+use search_stream::{
+    FinalLinePolicy, Indent, IterLines, LineTerminator, Options, RandomSample,
+    binary_heuristic_offset,
+    column_of, count_lines, count_lines_utf16le, detect_crlf,
+    is_anchored_match, is_binary, indent_of, is_empty_line, line_number_at,
+    trim_crlf,
+};
+
+pub struct BufferSearcher<'a, S: 'a, M: 'a + ?Sized> {
     opts: Options,
-    printer: &'a mut Printer<W>,
-    grep: &'a Grep,
+    printer: &'a mut S,
+    grep: &'a M,
     path: &'a Path,
     buf: &'a [u8],
+    cancel: Option<Arc<AtomicBool>>,
+    convert: Option<u8>,
+    heuristic: Option<(f64, usize)>,
+    binary_sniff: usize,
     match_line_count: u64,
     match_count: Option<u64>,
     line_count: Option<u64>,
     byte_offset: Option<u64>,
     last_line: usize,
+    exclusions: Vec<(usize, usize)>,
+    next_sample_line: u64,
+    next_sample_byte: u64,
+    lines_seen: u64,
 }
 
-impl<'a, W: WriteColor> BufferSearcher<'a, W> {
+impl<'a, S: Sink, M: Matcher + ?Sized> BufferSearcher<'a, S, M> {
     pub fn new(
-        printer: &'a mut Printer<W>,
-        grep: &'a Grep,
+        printer: &'a mut S,
+        grep: &'a M,
         path: &'a Path,
         buf: &'a [u8],
-    ) -> BufferSearcher<'a, W> {
+    ) -> BufferSearcher<'a, S, M> {
         BufferSearcher {
             opts: Options::default(),
-            printer: printer,
-            grep: grep,
-            path: path,
-            buf: buf,
+            printer,
+            grep,
+            path,
+            buf,
+            cancel: None,
+            convert: None,
+            heuristic: None,
+            binary_sniff: 10_240,
             match_line_count: 0,
             match_count: None,
             line_count: None,
             byte_offset: None,
             last_line: 0,
+            exclusions: vec![],
+            next_sample_line: 0,
+            next_sample_byte: 0,
+            lines_seen: 0,
         }
     }
 
+    /// Install a cancellation flag for this search.
+    ///
+    /// The haystack is already in memory, so instead of between buffer
+    /// fills the flag is polled between reported matches (or, for the
+    /// line-at-a-time inverted and sampled modes, between lines). Once
+    /// another thread sets it, the search stops and returns the partial
+    /// count accumulated so far; the sink is notified through
+    /// `Sink::cancelled` so those results are clearly marked as partial.
+    ///
+    /// The default is None: the search can't be cancelled.
+    #[allow(dead_code)]
+    pub fn cancel_flag(mut self, flag: Option<Arc<AtomicBool>>) -> Self {
+        self.cancel = flag;
+        self
+    }
+
     /// If enabled, searching will print a 0-based offset of the
     /// matching line (or the actual match if -o is specified) before
     /// printing the line itself.
@@ -59,6 +100,107 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
         self
     }
 
+    /// If enabled, the 1-based byte column of the first match within each
+    /// matching line is computed and handed to the sink. See
+    /// `Searcher::column`.
+    #[allow(dead_code)]
+    pub fn column(mut self, yes: bool) -> Self {
+        self.opts.column = yes;
+        self
+    }
+
+    /// If set, disable binary detection and instead convert every NUL
+    /// byte to the given replacement before searching. This mirrors
+    /// `InputBuffer::convert_binary` for the streaming searcher, and is
+    /// what's needed to emulate GNU grep's default handling of NUL bytes.
+    ///
+    /// Since this searcher can't write to its input (it is typically a
+    /// memory map), conversion lazily copies the haystack into an owned
+    /// buffer. The copy only happens when the input actually contains a
+    /// NUL byte; clean inputs are searched in place as usual.
+    #[allow(dead_code)]
+    pub fn convert_binary(mut self, replacement: Option<u8>) -> Self {
+        self.convert = replacement;
+        self
+    }
+
+    /// If set, also classify the buffer as binary when the proportion of
+    /// unprintable bytes in its prefix exceeds `threshold`, inspecting at
+    /// most `prefix` bytes. This catches binary data with no NUL bytes in
+    /// the sniffed region, like some UTF-16 text or dense bitmap data. See
+    /// `InputBuffer::binary_heuristic`.
+    #[allow(dead_code)]
+    pub fn binary_heuristic(mut self, config: Option<(f64, usize)>) -> Self {
+        self.heuristic = config;
+        self
+    }
+
+    /// Set the number of bytes at the start of the buffer that are sniffed
+    /// for the binary byte. Only this prefix is inspected since the whole
+    /// buffer is available up front and scanning all of it would be too
+    /// costly for large files.
+    ///
+    /// Callers scanning inputs with long text headers before a binary
+    /// payload can extend the region; callers who want minimal overhead can
+    /// shrink it. Setting it to 0 disables binary sniffing entirely.
+    ///
+    /// The default is 10240.
+    #[allow(dead_code)]
+    pub fn binary_sniff_len(mut self, len: usize) -> Self {
+        self.binary_sniff = len;
+        self
+    }
+
+    /// Set a sorted list of byte ranges to exclude from the search.
+    ///
+    /// Matches from lines overlapping an excluded range are never reported,
+    /// so ranges behave as if they were snapped outward to line boundaries.
+    /// Line numbers and byte offsets remain exact, since terminators inside
+    /// excluded ranges are still counted. The ranges must be sorted and
+    /// non-overlapping.
+    #[allow(dead_code)]
+    pub fn exclude_ranges(mut self, ranges: Vec<(usize, usize)>) -> Self {
+        self.exclusions = ranges;
+        self
+    }
+
+    /// Replace this searcher's entire configuration with the options given.
+    pub fn options(mut self, opts: Options) -> Self {
+        self.opts = opts;
+        self
+    }
+
+    /// Replace this searcher's configuration with the named preset given
+    /// by `Options::grep_defaults`. Further settings can be chained on top.
+    #[allow(dead_code)]
+    pub fn grep_defaults(mut self) -> Self {
+        self.opts = Options::grep_defaults();
+        self
+    }
+
+    /// A read-only snapshot of this searcher's full configuration. See
+    /// `Searcher::config`.
+    #[allow(dead_code)]
+    pub fn config(&self) -> &Options {
+        &self.opts
+    }
+
+    /// Replace this searcher's configuration with the named preset given
+    /// by `Options::streaming_defaults`.
+    #[allow(dead_code)]
+    pub fn streaming_defaults(mut self) -> Self {
+        self.opts = Options::streaming_defaults();
+        self
+    }
+
+    /// Replace this searcher's configuration with the named preset given
+    /// by `Options::forensics_defaults`.
+    #[allow(dead_code)]
+    pub fn forensics_defaults(mut self) -> Self {
+        self.opts = Options::forensics_defaults();
+        self
+    }
+
     /// If enabled, searching will print a count instead of each match.
     ///
     /// Disabled by default.
@@ -96,9 +238,39 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
     /// Set the end-of-line byte used by this searcher.
     pub fn eol(mut self, eol: u8) -> Self {
         self.opts.eol = eol;
+        self.opts.crlf = false;
         self
     }
 
+    /// Set the line terminator used by this searcher. See
+    /// `Searcher::line_terminator` for the CRLF trimming semantics.
+    #[allow(dead_code)]
+    pub fn line_terminator(mut self, term: LineTerminator) -> Self {
+        self.opts.eol = term.as_byte();
+        self.opts.crlf = term.is_crlf();
+        self
+    }
+
+    /// When enabled, the buffer prefix is inspected for `\r\n` versus a
+    /// bare terminator before searching, and the effective line terminator
+    /// is configured accordingly. See `Searcher::detect_line_terminator`.
+    #[allow(dead_code)]
+    pub fn detect_line_terminator(mut self, yes: bool) -> Self {
+        self.opts.detect_terminator = yes;
+        self
+    }
+
+    /// The line terminator actually in effect for this search. Detection,
+    /// if requested, only happens once the search runs.
+    #[allow(dead_code)]
+    pub fn effective_line_terminator(&self) -> LineTerminator {
+        if self.opts.crlf {
+            LineTerminator::Crlf
+        } else {
+            LineTerminator::Byte(self.opts.eol)
+        }
+    }
+
     /// If enabled, matching is inverted so that lines that *don't* match the
     /// given pattern are treated as matches.
     pub fn invert_match(mut self, yes: bool) -> Self {
@@ -106,6 +278,52 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
         self
     }
 
+    /// If enabled, a line is only reported when a match begins at its very
+    /// first byte. See `Searcher::anchor_line_start` for the full
+    /// combination rules.
+    #[allow(dead_code)]
+    pub fn anchor_line_start(mut self, yes: bool) -> Self {
+        self.opts.anchor_line_start = yes;
+        self
+    }
+
+    /// If enabled, lines with no content (after stripping the terminator
+    /// and any trailing carriage return) are never reported as matches.
+    /// They still advance line numbers.
+    #[allow(dead_code)]
+    pub fn skip_empty_lines(mut self, yes: bool) -> Self {
+        self.opts.skip_empty_lines = yes;
+        self
+    }
+
+    /// Begin the search at the given absolute byte offset instead of the
+    /// start of the buffer. See `Searcher::start_offset`: byte offsets stay
+    /// absolute, while line numbers restart at the skip point. If the
+    /// offset falls inside a line, the whole containing line is searched.
+    #[allow(dead_code)]
+    pub fn start_offset(mut self, offset: u64) -> Self {
+        self.opts.start_offset = offset;
+        self
+    }
+
+    /// Stop searching at the first non-matching line found after at least
+    /// one matching line, as if the buffer ended there. See
+    /// `Searcher::stop_on_nonmatch`.
+    #[allow(dead_code)]
+    pub fn stop_on_nonmatch(mut self, yes: bool) -> Self {
+        self.opts.stop_on_nonmatch = yes;
+        self
+    }
+
+    /// If set, compute the indentation of each reported matching line (with
+    /// tabs expanded to `tab_stop`) and attach it to the payload handed to
+    /// the sink.
+    #[allow(dead_code)]
+    pub fn report_indent(mut self, tab_stop: Option<usize>) -> Self {
+        self.opts.report_indent = tab_stop;
+        self
+    }
+
     /// If enabled, compute line numbers and prefix each line of output with
     /// them.
     pub fn line_number(mut self, yes: bool) -> Self {
@@ -113,6 +331,32 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
         self
     }
 
+    /// Set the number reported for the first line of the buffer. Values
+    /// below 1 are treated as 1, the default.
+    #[allow(dead_code)]
+    pub fn line_number_start(mut self, start: u64) -> Self {
+        self.opts.line_number_start = cmp::max(1, start);
+        self
+    }
+
+    /// Set the policy for a final line that lacks a terminator. `Ignore`
+    /// drops the partial line; `Flag` searches it but also calls
+    /// `Sink::unterminated`.
+    #[allow(dead_code)]
+    pub fn final_line(mut self, policy: FinalLinePolicy) -> Self {
+        self.opts.final_line = policy;
+        self
+    }
+
+    /// Give up when no match appears within the first `limit` bytes. A
+    /// match found before the threshold turns the limit off for the rest
+    /// of the buffer.
+    #[allow(dead_code)]
+    pub fn quit_after_no_match_within(mut self, limit: Option<u64>) -> Self {
+        self.opts.quit_after_no_match_within = limit;
+        self
+    }
+
     /// Limit the number of matches to the given count.
     ///
     /// The default is None, which corresponds to no limit.
@@ -121,6 +365,59 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
         self
     }
 
+    /// Limit the search to the given number of bytes of the buffer. See
+    /// `Searcher::max_bytes`: the line straddling the boundary is searched
+    /// in full, and the budget is measured from `start_offset`.
+    #[allow(dead_code)]
+    pub fn max_bytes(mut self, count: Option<u64>) -> Self {
+        self.opts.max_bytes = count;
+        self
+    }
+
+    /// If enabled, each individual match is reported to the sink instead
+    /// of its containing line, with the match's own byte span and column.
+    /// Requires a regex-backed matcher; without one, whole lines are
+    /// reported as usual.
+    ///
+    /// Disabled by default.
+    #[allow(dead_code)]
+    pub fn only_matching(mut self, yes: bool) -> Self {
+        self.opts.only_matching = yes;
+        self
+    }
+
+    /// Report at most one match per stride of `n` physical lines.
+    ///
+    /// After a match is reported, further matching lines are suppressed
+    /// until `n` lines have passed. Suppressed matches still count towards
+    /// totals, so counts and max-count termination are unaffected. Sampling
+    /// is ignored for inverted searches.
+    #[allow(dead_code)]
+    pub fn sample_every_lines(mut self, n: Option<u64>) -> Self {
+        self.opts.sample_lines = n;
+        self
+    }
+
+    /// Report at most one match per stride of `n` bytes.
+    ///
+    /// The stride is measured from the start of the last reported matching
+    /// line. Otherwise this behaves like `sample_every_lines`.
+    #[allow(dead_code)]
+    pub fn sample_every_bytes(mut self, n: Option<u64>) -> Self {
+        self.opts.sample_bytes = n;
+        self
+    }
+
+    /// Examine each line with probability `p`, skipping the matcher
+    /// entirely for unexamined lines. See `Searcher::sample_random` for the
+    /// full semantics.
+    #[allow(dead_code)]
+    pub fn sample_random(mut self, p: Option<(f64, u64)>) -> Self {
+        self.opts.sample_random =
+            p.map(|(p, seed)| RandomSample::new(p, seed));
+        self
+    }
+
     /// If enabled, don't show any output and quit searching after the first
     /// match is found.
     pub fn quiet(mut self, yes: bool) -> Self {
@@ -134,39 +431,262 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
         self
     }
 
+    /// Report binary data without giving up on the search. An occurrence
+    /// of `byte` no longer aborts the search: the buffer is searched as
+    /// text and the offset of the first occurrence is reported to the
+    /// sink via `Sink::binary_data`.
+    #[allow(dead_code)]
+    pub fn binary_report(mut self, byte: Option<u8>) -> Self {
+        self.opts.binary_report = byte;
+        self
+    }
+
+    /// If enabled, the buffer is treated as UTF-16LE encoded text and is
+    /// searched natively, without transcoding.
+    ///
+    /// In this mode, the caller is responsible for supplying a matcher that
+    /// was compiled for UTF-16LE bytes. Lines are terminated by the code unit
+    /// pair `\n\0`, line numbers are computed in code units and binary
+    /// detection is disabled, since NUL bytes are expected in UTF-16 text.
+    #[allow(dead_code)]
+    pub fn utf16le(mut self, yes: bool) -> Self {
+        self.opts.utf16le = yes;
+        self
+    }
+
+    /// Returns the 1-based line number of the line containing the given byte
+    /// offset, using this searcher's line terminator configuration.
+    ///
+    /// This is useful for computing the line number of a single match after
+    /// the fact when the search itself ran with line numbers disabled. Note
+    /// that it scans the entire buffer up to `offset`, so its cost is linear
+    /// in `offset`.
+    #[allow(dead_code)]
+    pub fn line_number_at(&self, offset: usize) -> u64 {
+        line_number_at(self.buf, self.opts.eol, self.opts.utf16le, offset)
+    }
+
     #[inline(never)]
+    /// Search for the existence of a match without delivering anything
+    /// to the sink. This mirrors `Searcher::has_match`: only plain
+    /// existence is answered and the line selection refinements are
+    /// ignored.
+    #[allow(dead_code)]
+    pub fn has_match(&self) -> bool {
+        if self.convert.is_some() {
+            if let Some(owned) = self.converted_buf() {
+                return self.grep.is_match(&owned);
+            }
+        } else if self.is_binary() {
+            return false;
+        }
+        self.grep.is_match(self.buf)
+    }
+
+    /// Returns true if the buffer prefix looks binary, by NUL sniffing or,
+    /// when configured, the content heuristic.
+    fn is_binary(&self) -> bool {
+        if self.opts.text || self.opts.utf16le
+            || self.opts.binary_report.is_some() {
+            return false;
+        }
+        let binary_upto = cmp::min(self.binary_sniff, self.buf.len());
+        if is_binary(&self.buf[..binary_upto], true) {
+            return true;
+        }
+        if let Some((threshold, prefix)) = self.heuristic {
+            let upto = cmp::min(prefix, self.buf.len());
+            if binary_heuristic_offset(&self.buf[..upto], threshold)
+                .is_some() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Returns a copy of the haystack with every NUL byte replaced by the
+    /// configured conversion byte, or None if no conversion is needed.
+    fn converted_buf(&self) -> Option<Vec<u8>> {
+        let replacement = self.convert?;
+        let first = memchr(b'\x00', self.buf)?;
+        let mut owned = self.buf.to_vec();
+        for b in owned[first..].iter_mut() {
+            if *b == b'\x00' {
+                *b = replacement;
+            }
+        }
+        Some(owned)
+    }
+
+    /// The end of the searchable region under the byte budget measured
+    /// from `start`. The line straddling the boundary is completed, so a
+    /// partial line is never matched against.
+    fn byte_budget_upto(&self, start: usize) -> usize {
+        let max = match self.opts.max_bytes {
+            None => return self.buf.len(),
+            Some(max) => max,
+        };
+        let limit = cmp::min(
+            (start as u64).saturating_add(max),
+            self.buf.len() as u64) as usize;
+        if limit >= self.buf.len() {
+            return self.buf.len();
+        }
+        let from = limit.saturating_sub(1);
+        if self.opts.utf16le {
+            let mut i = from - (from & 1);
+            while i + 1 < self.buf.len() {
+                if self.buf[i] == self.opts.eol && self.buf[i + 1] == 0 {
+                    return i + 2;
+                }
+                i += 2;
+            }
+            self.buf.len()
+        } else {
+            memchr(self.opts.eol, &self.buf[from..])
+                .map_or(self.buf.len(), |i| from + i + 1)
+        }
+    }
+
     pub fn run(mut self) -> u64 {
-        let binary_upto = cmp::min(10_240, self.buf.len());
-        if !self.opts.text && is_binary(&self.buf[..binary_upto], true) {
+        if self.convert.is_some() {
+            // Conversion replaces binary detection. If the haystack needs
+            // converting, re-run over the owned copy with conversion
+            // disabled so this branch isn't taken again.
+            if let Some(owned) = self.converted_buf() {
+                let searcher = BufferSearcher {
+                    opts: self.opts,
+                    printer: self.printer,
+                    grep: self.grep,
+                    path: self.path,
+                    buf: &owned,
+                    cancel: self.cancel,
+                    convert: None,
+                    heuristic: None,
+                    binary_sniff: self.binary_sniff,
+                    match_line_count: 0,
+                    match_count: None,
+                    line_count: None,
+                    byte_offset: None,
+                    last_line: 0,
+                    exclusions: self.exclusions,
+                    next_sample_line: 0,
+                    next_sample_byte: 0,
+                    lines_seen: 0,
+                };
+                return searcher.run();
+            }
+        } else if self.is_binary() {
             return 0;
         }
+        if self.opts.detect_terminator {
+            if let Some(crlf) = detect_crlf(self.buf, self.opts.eol, false) {
+                self.opts.crlf = crlf;
+            }
+        }
 
+        if let Some(byte) = self.opts.binary_report {
+            if let Some(i) = memchr(byte, self.buf) {
+                self.printer.binary_data(self.path, i as u64);
+            }
+        }
+        let flag_unterminated = match self.opts.final_line {
+            FinalLinePolicy::Match => false,
+            policy => {
+                match self.unterminated_from() {
+                    // POSIX-strict: the partial line is not a line.
+                    Some(from) if policy == FinalLinePolicy::Ignore => {
+                        self.buf = &self.buf[..from];
+                        false
+                    }
+                    Some(_) => true,
+                    None => false,
+                }
+            }
+        };
         self.match_line_count = 0;
-        self.line_count = if self.opts.line_number { Some(0) } else { None };
+        if self.opts.line_number && !self.printer.needs_line_numbers() {
+            // The sink declared it never reads line numbers, so don't pay
+            // for counting them.
+            self.opts.line_number = false;
+        }
+        self.line_count =
+            if self.opts.line_number || self.opts.sample_lines.is_some() {
+                Some(self.opts.line_number_start - 1)
+            } else {
+                None
+            };
         // The memory map searcher uses one contiguous block of bytes, so the
         // offsets given the printer are sufficient to compute the byte offset.
         self.byte_offset = if self.opts.byte_offset { Some(0) } else { None };
         self.match_count = if self.opts.count_matches { Some(0) } else { None };
-        let mut last_end = 0;
-        for m in self.grep.iter(self.buf) {
-            if self.opts.invert_match {
-                self.print_inverted_matches(last_end, m.start());
-            } else {
-                self.print_match(m.start(), m.end());
-            }
-            last_end = m.end();
-            if self.opts.terminate(self.match_line_count) {
-                break;
+        let mut start =
+            cmp::min(self.opts.start_offset as usize, self.buf.len());
+        if self.opts.utf16le {
+            start -= start & 1;
+        } else if start > 0 {
+            // Snap back to the start of the containing line, so an offset
+            // inside a line searches the whole line. The matcher only
+            // reports matches beginning at or after the scan position.
+            start = memrchr(self.opts.eol, &self.buf[..start])
+                .map_or(0, |i| i + 1);
+        }
+        self.last_line = start;
+        let upto = self.byte_budget_upto(start);
+        if self.opts.sample_random.is_some() {
+            self.search_sampled(upto);
+        } else if self.opts.invert_match {
+            self.search_inverted(upto);
+        } else if self.count_only() {
+            self.search_counting(start, upto);
+        } else {
+            let mut mat = Match::default();
+            let mut pos = start;
+            let mut nonmatch_from = start;
+            while self.grep.read_match(&mut mat, &self.buf[..upto], pos) {
+                if self.check_cancel() {
+                    break;
+                }
+                pos = mat.end();
+                let (start, end) = self.match_range(mat.start(), mat.end());
+                if self.give_up(start) {
+                    break;
+                }
+                // On sorted input, a non-matching line after a match ends
+                // the search as if at EOF: any gap before this match, or
+                // a match that fails the anchor, qualifies.
+                if self.opts.stop_on_nonmatch && self.match_line_count > 0
+                    && start > nonmatch_from {
+                    break;
+                }
+                if let Some(skip) = self.exclusion_end(start, end) {
+                    // Skip the rest of the excluded range entirely.
+                    pos = cmp::max(pos, skip);
+                    nonmatch_from = cmp::max(end, skip);
+                    continue;
+                }
+                if !self.line_anchored(start, end) {
+                    if self.opts.stop_on_nonmatch
+                        && self.match_line_count > 0 {
+                        break;
+                    }
+                    continue;
+                }
+                self.print_match(start, end);
+                nonmatch_from = end;
+                if self.opts.terminate(self.match_line_count) {
+                    break;
+                }
             }
         }
-        if self.opts.invert_match && !self.opts.terminate(self.match_line_count) {
-            let upto = self.buf.len();
-            self.print_inverted_matches(last_end, upto);
+        if flag_unterminated {
+            self.printer.unterminated(self.path);
         }
+        self.printer.progress(self.path, upto as u64);
         if self.opts.count && self.match_line_count > 0 {
             self.printer.path_count(self.path, self.match_line_count);
         } else if self.opts.count_matches
-            && self.match_count.map_or(false, |c| c > 0)
+            && self.match_count.is_some_and(|c| c > 0)
         {
             self.printer.path_count(self.path, self.match_count.unwrap());
         }
@@ -179,46 +699,341 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
         self.match_line_count
     }
 
+    /// True when the search needs only the number of matching lines:
+    /// output is suppressed and no configured option examines the span or
+    /// contents of each matching line.
+    #[inline(always)]
+    fn count_only(&self) -> bool {
+        // A matcher built for a different terminator mis-identifies line
+        // boundaries; this searcher can't fail, so the best it can do is
+        // stay off the matcher-driven counting fast path.
+        self.grep.line_terminator().is_none_or(|eol| eol == self.opts.eol)
+            && self.opts.skip_matches()
+            && !self.opts.count_matches
+            && !self.opts.skip_empty_lines
+            && !self.opts.anchor_line_start
+            && !self.opts.utf16le
+            && self.exclusions.is_empty()
+            && self.opts.quit_after_no_match_within.is_none()
+            && !self.opts.stop_on_nonmatch
+    }
+
+    /// A specialized version of the main search loop for counting. Each
+    /// matching line contributes only its end offset, so the work of
+    /// locating line starts and assembling match payloads is skipped.
+    fn search_counting(&mut self, start: usize, upto: usize) {
+        let mut pos = start;
+        while let Some(end) =
+            self.grep.shortest_match(&self.buf[..upto], pos) {
+            if self.check_cancel() {
+                break;
+            }
+            self.match_line_count += 1;
+            pos = end;
+            if self.opts.terminate(self.match_line_count) {
+                break;
+            }
+        }
+    }
+
+    /// Returns true if the search should stop because the position given
+    /// lies past the no-match window and nothing has matched yet.
+    #[inline(always)]
+    fn give_up(&self, pos: usize) -> bool {
+        self.opts.quit_after_no_match_within.is_some_and(|limit| {
+            self.match_line_count == 0 && pos as u64 >= limit
+        })
+    }
+
+    /// Polls the cancellation flag, if any. On cancellation, notifies the
+    /// sink and returns true; the caller stops searching.
+    #[inline(always)]
+    fn check_cancel(&mut self) -> bool {
+        let cancelled = self.cancel.as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed));
+        if cancelled {
+            self.printer.cancelled(self.path);
+        }
+        cancelled
+    }
+
+    /// Returns the start of an unterminated final line, i.e. the
+    /// position just past the last line terminator when the buffer does
+    /// not end in one. Returns `None` for an empty or terminated buffer.
+    fn unterminated_from(&self) -> Option<usize> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        if self.opts.utf16le {
+            let n = self.buf.len() & !1;
+            if n >= 2 && self.buf[n - 2] == self.opts.eol
+                && self.buf[n - 1] == 0 {
+                return None;
+            }
+            let mut i = n;
+            while i >= 2 {
+                if self.buf[i - 2] == self.opts.eol && self.buf[i - 1] == 0 {
+                    return Some(i);
+                }
+                i -= 2;
+            }
+            Some(0)
+        } else {
+            if self.buf[self.buf.len() - 1] == self.opts.eol {
+                return None;
+            }
+            Some(memrchr(self.opts.eol, self.buf).map_or(0, |i| i + 1))
+        }
+    }
+
+    /// Returns the given match range, snapped to code unit boundaries when
+    /// searching UTF-16LE text.
+    ///
+    /// The matcher itself works on bytes, so the line boundaries it reports
+    /// may point at the trailing NUL of a terminator pair. Rounding them up
+    /// to even offsets keeps all other positions aligned on code units.
+    #[inline(always)]
+    fn match_range(&self, mut start: usize, mut end: usize) -> (usize, usize) {
+        if self.opts.utf16le {
+            start += start & 1;
+            end = cmp::min(end + (end & 1), self.buf.len());
+        }
+        (start, end)
+    }
+
+    /// Returns true if the given matching line satisfies the line-start
+    /// anchor. Always true when anchoring is disabled.
+    #[inline(always)]
+    fn line_anchored(&self, start: usize, end: usize) -> bool {
+        !self.opts.anchor_line_start
+            || is_anchored_match(self.grep.regex(), &self.buf[start..end])
+    }
+
+    /// Returns the end of the exclusion range overlapping the given line
+    /// range, if there is one.
+    #[inline(always)]
+    fn exclusion_end(&self, start: usize, end: usize) -> Option<usize> {
+        if self.exclusions.is_empty() {
+            return None;
+        }
+        let i = self.exclusions.partition_point(|&(_, re)| re <= start);
+        match self.exclusions.get(i) {
+            Some(&(rs, re)) if rs < end => Some(re),
+            _ => None,
+        }
+    }
+
     #[inline(always)]
     fn count_individual_matches(&mut self, start: usize, end: usize) {
         if let Some(ref mut count) = self.match_count {
-            for _ in self.grep.regex().find_iter(&self.buf[start..end]) {
-                *count += 1;
+            match self.grep.regex() {
+                Some(re) => {
+                    for _ in re.find_iter(&self.buf[start..end]) {
+                        *count += 1;
+                    }
+                }
+                // Without a regex, individual matches can't be resolved,
+                // so count matching lines instead.
+                None => *count += 1,
             }
         }
     }
 
     #[inline(always)]
     pub fn print_match(&mut self, start: usize, end: usize) {
+        if self.opts.skip_empty_lines
+            && is_empty_line(&self.buf[start..end], self.opts.eol, false) {
+            return;
+        }
         self.match_line_count += 1;
         self.count_individual_matches(start, end);
         if self.opts.skip_matches() {
             return;
         }
         self.count_lines(start);
+        if self.sample_suppressed(start) {
+            return;
+        }
         self.add_line(end);
-        self.printer.matched(
-            self.grep.regex(), self.path, self.buf,
-            start, end, self.line_count, self.byte_offset);
+        let indent = self.opts.report_indent.map(|tab_stop| {
+            indent_of(&self.buf[start..end], tab_stop, false)
+        });
+        let end =
+            if self.opts.crlf {
+                start + trim_crlf(&self.buf[start..end], self.opts.eol, false)
+            } else {
+                end
+            };
+        if self.opts.only_matching && self.grep.regex().is_some() {
+            self.print_submatches(start, end, indent);
+            return;
+        }
+        let column =
+            if self.opts.column {
+                Some(column_of(self.grep.regex(), &self.buf[start..end]))
+            } else {
+                None
+            };
+        self.printer.matched_full(self.path, &SinkMatch {
+            re: self.grep.regex(),
+            buf: self.buf,
+            start,
+            end,
+            offset: start as u64,
+            line_number: self.printed_line_number(),
+            byte_offset: self.byte_offset,
+            column,
+            indent,
+            match_range: None,
+        });
     }
 
+    /// Report each individual match within the line at `start..end` as
+    /// its own event, with the byte offset and column adjusted to the
+    /// match rather than the line.
+    fn print_submatches(&mut self, start: usize, end: usize,
+                        indent: Option<Indent>) {
+        let re = self.grep.regex().unwrap();
+        let line_number = self.printed_line_number();
+        for m in re.find_iter(&self.buf[start..end]) {
+            let column =
+                if self.opts.column {
+                    Some(m.start() as u64 + 1)
+                } else {
+                    None
+                };
+            self.printer.matched_full(self.path, &SinkMatch {
+                re: Some(re),
+                buf: self.buf,
+                start: start + m.start(),
+                end: start + m.end(),
+                offset: (start + m.start()) as u64,
+                line_number,
+                byte_offset: self.byte_offset,
+                column,
+                indent,
+                match_range: Some((0, m.end() - m.start())),
+            });
+        }
+    }
+
+    /// The line number to attach to printed output, if line numbers were
+    /// requested. Line counting may also be enabled internally for
+    /// sampling, in which case the count isn't reported.
+    #[inline(always)]
+    fn printed_line_number(&self) -> Option<u64> {
+        if self.opts.line_number {
+            self.line_count
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if the match starting at the given position should be
+    /// suppressed by sampling. A reported match advances the stride.
+    ///
+    /// `count_lines` must have been called for `start` already, so that the
+    /// current line count is up to date. Inverted searches are never
+    /// sampled.
     #[inline(always)]
-    fn print_inverted_matches(&mut self, start: usize, end: usize) {
+    fn sample_suppressed(&mut self, start: usize) -> bool {
+        if self.opts.sample_lines.is_none()
+            && self.opts.sample_bytes.is_none() {
+            return false;
+        }
+        if self.opts.invert_match {
+            return false;
+        }
+        let abs = start as u64;
+        if self.opts.sample_bytes.is_some() && abs < self.next_sample_byte {
+            return true;
+        }
+        let line = self.line_count.map_or(0, |c| c + 1);
+        if self.opts.sample_lines.is_some() && line < self.next_sample_line {
+            return true;
+        }
+        if let Some(n) = self.opts.sample_bytes {
+            self.next_sample_byte = abs + n;
+        }
+        if let Some(n) = self.opts.sample_lines {
+            self.next_sample_line = line + n;
+        }
+        false
+    }
+
+    /// A specialized path for random sampling. Every line boundary is
+    /// walked individually so that the selection of examined lines can't be
+    /// biased by the matcher skipping ahead to candidates.
+    #[inline(always)]
+    fn search_sampled(&mut self, upto: usize) {
+        let sample = self.opts.sample_random.unwrap();
+        let mut it = IterLines::new(self.opts.eol, self.last_line)
+            .utf16le(self.opts.utf16le);
+        while let Some((start, end)) = it.next(&self.buf[..upto]) {
+            if self.opts.terminate(self.match_line_count) {
+                return;
+            }
+            if self.check_cancel() {
+                return;
+            }
+            if self.give_up(start) {
+                return;
+            }
+            let ordinal = self.lines_seen;
+            self.lines_seen += 1;
+            if sample.examine(ordinal)
+                && self.exclusion_end(start, end).is_none() {
+                let matched = self.grep.is_match(&self.buf[start..end])
+                    && self.line_anchored(start, end);
+                if matched != self.opts.invert_match {
+                    self.print_match(start, end);
+                }
+            }
+        }
+    }
+
+    /// A specialized path for inverted searching that emits each
+    /// non-matching line directly instead of locating matches first, which
+    /// skips all of the match span bookkeeping. Since this searcher doesn't
+    /// support contexts, it applies to every inverted search. Its output is
+    /// identical to the general path.
+    #[inline(always)]
+    fn search_inverted(&mut self, upto: usize) {
         debug_assert!(self.opts.invert_match);
-        let mut it = IterLines::new(self.opts.eol, start);
-        while let Some((s, e)) = it.next(&self.buf[..end]) {
+        let mut it = IterLines::new(self.opts.eol, self.last_line)
+            .utf16le(self.opts.utf16le);
+        while let Some((start, end)) = it.next(&self.buf[..upto]) {
             if self.opts.terminate(self.match_line_count) {
                 return;
             }
-            self.print_match(s, e);
+            if self.check_cancel() {
+                return;
+            }
+            if self.give_up(start) {
+                return;
+            }
+            let matched = self.grep.is_match(&self.buf[start..end])
+                && self.line_anchored(start, end);
+            if matched && self.opts.stop_on_nonmatch
+                && self.match_line_count > 0 {
+                return;
+            }
+            if !matched && self.exclusion_end(start, end).is_none() {
+                self.print_match(start, end);
+            }
         }
     }
 
     #[inline(always)]
     fn count_lines(&mut self, upto: usize) {
         if let Some(ref mut line_count) = self.line_count {
-            *line_count += count_lines(
-                &self.buf[self.last_line..upto], self.opts.eol);
+            let buf = &self.buf[self.last_line..upto];
+            *line_count +=
+                if self.opts.utf16le {
+                    count_lines_utf16le(buf, self.opts.eol)
+                } else {
+                    count_lines(buf, self.opts.eol)
+                };
             self.last_line = upto;
         }
     }
@@ -232,18 +1047,41 @@ impl<'a, W: WriteColor> BufferSearcher<'a, W> {
     }
 }
 
+#[cfg(feature = "bytes")]
+impl<'a, S: Sink, M: Matcher + ?Sized> BufferSearcher<'a, S, M> {
+    /// Create a searcher over a reference-counted `bytes::Bytes` buffer.
+    ///
+    /// The search borrows the buffer without copying it. To also collect
+    /// owned match payloads that reference the buffer instead of copying
+    /// line data, pair this with a `sink::BytesCollector` built from a
+    /// clone of the same `Bytes` handle.
+    #[allow(dead_code)]
+    pub fn search_bytes(
+        printer: &'a mut S,
+        grep: &'a M,
+        path: &'a Path,
+        buf: &'a bytes::Bytes,
+    ) -> BufferSearcher<'a, S, M> {
+        BufferSearcher::new(printer, grep, path, buf)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
 
-    use grep::GrepBuilder;
+    use grep::{DynMatcher, Grep, GrepBuilder};
 
     use printer::Printer;
     use termcolor;
 
+    use search_stream::{FinalLinePolicy, LineTerminator};
+
     use super::BufferSearcher;
 
-    const SHERLOCK: &'static str = "\
+    const SHERLOCK: &str = "\
 For the Doctor Watsons of this world, as opposed to the Sherlock
 Holmeses, success in the province of detective work must always
 be, to a very large extent, the result of luck. Sherlock Holmes
@@ -253,10 +1091,15 @@ and exhibited clearly, with a label attached.\
 ";
 
     fn test_path() -> &'static Path {
-        &Path::new("/baz.rs")
+        Path::new("/baz.rs")
+    }
+
+    fn utf16le(s: &str) -> String {
+        s.chars().flat_map(|c| vec![c, '\0']).collect()
     }
 
-    type TestSearcher<'a> = BufferSearcher<'a, termcolor::NoColor<Vec<u8>>>;
+    type TestSearcher<'a> =
+        BufferSearcher<'a, Printer<termcolor::NoColor<Vec<u8>>>, Grep>;
 
     fn search<F: FnMut(TestSearcher) -> TestSearcher>(
         pat: &str,
@@ -274,6 +1117,125 @@ and exhibited clearly, with a label attached.\
         (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
     }
 
+    #[test]
+    fn stop_on_nonmatch_sorted() {
+        // Matches are contiguous; the first non-matching line after them
+        // ends the search, so the later "a3" is never reported.
+        let (count, out) = search("a", "a1\na2\nb1\na3\n", |s| {
+            s.line_number(true).stop_on_nonmatch(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:a1\n/baz.rs:2:a2\n");
+    }
+
+    #[test]
+    fn stop_on_nonmatch_inverted() {
+        // With inverted matching the roles swap: the first matching line
+        // after a delivered line ends the search.
+        let (count, out) = search("b", "a1\na2\nb1\na3\n", |s| {
+            s.invert_match(true).stop_on_nonmatch(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:a1\n/baz.rs:a2\n");
+    }
+
+    #[test]
+    fn count_fast_path_agrees() {
+        // The counting loop must agree with the general path, including
+        // when several matches land on one line.
+        let hay = "aaa bbb aaa\nccc\naaa\nddd aaa\n";
+        let (fast, out) = search("aaa", hay, |s| s.count(true));
+        let (slow, _) = search("aaa", hay, |s| s);
+        assert_eq!(slow, fast);
+        assert_eq!(3, fast);
+        assert_eq!("/baz.rs:3\n", out);
+
+        // max_count still terminates it.
+        let (count, _) = search(
+            "aaa", hay, |s| s.count(true).max_count(Some(2)));
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn binary_report_keeps_searching() {
+        // By default a NUL aborts the search with no matches; in report
+        // mode the buffer is searched as text.
+        let (count, out) = search("baz", "foo\x00bar\nbaz\n", |s| s);
+        assert_eq!(0, count);
+        assert_eq!("", out);
+
+        let (count, out) = search("baz", "foo\x00bar\nbaz\n", |s| {
+            s.binary_report(Some(0))
+        });
+        assert_eq!(1, count);
+        assert_eq!("/baz.rs:baz\n", out);
+    }
+
+    #[test]
+    fn quit_after_no_match_within_window() {
+        let mut text = "filler line\n".repeat(50);
+        text.push_str("needle\n");
+        let (count, out) = search("needle", &text, |s| {
+            s.quit_after_no_match_within(Some(64))
+        });
+        assert_eq!(0, count);
+        assert!(out.is_empty());
+
+        // A match before the threshold turns the limit off.
+        let early = format!("needle\n{}", text);
+        let (count, _) = search("needle", &early, |s| {
+            s.quit_after_no_match_within(Some(64))
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn final_line_ignored_when_unterminated() {
+        // The slice path agrees with the reader path: POSIX-strict
+        // searching drops SHERLOCK's unterminated final line.
+        let (count, out) = search("label", SHERLOCK, |s| {
+            s.final_line(FinalLinePolicy::Ignore)
+        });
+        assert_eq!(0, count);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn line_number_start_seeds_counter() {
+        let matches = search("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).line_number_start(100)
+        });
+        assert_eq!(2, matches.0);
+        assert!(matches.1.starts_with("/baz.rs:100:"));
+    }
+
+    #[test]
+    fn only_matching() {
+        let (count, out) = search("o+", "foo boo\nbar\n", |s| {
+            s.only_matching(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:oo\n/baz.rs:1:oo\n");
+    }
+
+    #[test]
+    fn cancel_flag_stops_search() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let (count, _) = search("Sherlock", SHERLOCK, |s| {
+            s.cancel_flag(Some(flag.clone()))
+        });
+        assert_eq!(2, count);
+
+        // Once the flag is raised, the search stops before reporting
+        // anything further.
+        flag.store(true, Ordering::SeqCst);
+        let (count, out) = search("Sherlock", SHERLOCK, |s| {
+            s.cancel_flag(Some(flag.clone()))
+        });
+        assert_eq!(0, count);
+        assert!(out.is_empty());
+    }
+
     #[test]
     fn basic_search() {
         let (count, out) = search("Sherlock", SHERLOCK, |s|s);
@@ -284,6 +1246,198 @@ and exhibited clearly, with a label attached.\
 ");
     }
 
+    #[test]
+    fn utf16le_search() {
+        let text = utf16le("foo\nbar\nbaz\n");
+        let (count, out) = search(&utf16le("bar"), &text, |s| {
+            s.utf16le(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:2:b\0a\0r\0\n\0\n");
+    }
+
+    #[test]
+    fn anchor_line_start() {
+        let text = "foo bar\nbar foo\nbar\n";
+        let (count, out) = search("bar", text, |s| {
+            s.anchor_line_start(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:bar foo\n/baz.rs:3:bar\n");
+    }
+
+    #[test]
+    fn anchor_line_start_inverted() {
+        let text = "foo bar\nbar foo\nbar\n";
+        let (count, out) = search("bar", text, |s| {
+            s.anchor_line_start(true).invert_match(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:foo bar\n");
+    }
+
+    #[test]
+    fn crlf_terminator() {
+        let text = "one\r\ntwo\nthree\r\n";
+        let (count, out) = search("t", text, |s| {
+            s.line_terminator(LineTerminator::Crlf).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:two\n/baz.rs:3:three\n");
+    }
+
+    #[test]
+    fn has_match() {
+        let run = |pat: &str| {
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new(pat).build().unwrap();
+            BufferSearcher::new(
+                &mut pp, &grep, test_path(), SHERLOCK.as_bytes())
+                .has_match()
+        };
+        assert!(run("Sherlock"));
+        assert!(!run("zzzzz"));
+    }
+
+    #[test]
+    fn detect_line_terminator() {
+        let text = "one\r\ntwo\r\nthree\r\n";
+        let (count, out) = search("t", text, |s| {
+            s.detect_line_terminator(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:two\n/baz.rs:3:three\n");
+    }
+
+    #[test]
+    fn sample_random() {
+        let text = "aaa\nbbb\naaa\nccc\naaa\n";
+        let (count, out) = search("aaa", text, |s| {
+            s.line_number(true).sample_random(Some((1.0, 42)))
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "/baz.rs:1:aaa\n/baz.rs:3:aaa\n/baz.rs:5:aaa\n");
+        let (count, out) = search("aaa", text, |s| {
+            s.sample_random(Some((0.0, 42)))
+        });
+        assert_eq!(0, count);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn sample_lines() {
+        let text = "aaa\naaa\naaa\naaa\naaa\naaa\n";
+        let (count, out) = search("aaa", text, |s| {
+            s.line_number(true).sample_every_lines(Some(3))
+        });
+        assert_eq!(6, count);
+        assert_eq!(out, "/baz.rs:1:aaa\n/baz.rs:4:aaa\n");
+    }
+
+    #[test]
+    fn sample_bytes() {
+        let text = "aaa\naaa\naaa\naaa\n";
+        let (count, out) = search("aaa", text, |s| {
+            s.byte_offset(true).sample_every_bytes(Some(8))
+        });
+        assert_eq!(4, count);
+        assert_eq!(out, "/baz.rs:0:aaa\n/baz.rs:8:aaa\n");
+    }
+
+    #[test]
+    fn exclude_ranges() {
+        let i = SHERLOCK.find("the result of luck").unwrap();
+        let (count, out) = search("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).exclude_ranges(vec![(i, i + 10)])
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+");
+    }
+
+    #[test]
+    fn exclude_ranges_invert() {
+        let i = SHERLOCK.find("Holmeses").unwrap();
+        let (count, out) = search("Sherlock", SHERLOCK, |s| {
+            s.invert_match(true).exclude_ranges(vec![(i, i + 4)])
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "\
+/baz.rs:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn dyn_matcher() {
+        let (_, expected) = search("Sherlock", SHERLOCK, |s| s.line_number(true));
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let dynm = DynMatcher::new(grep);
+        let count = {
+            let searcher = BufferSearcher::new(
+                &mut pp, &dynm, test_path(), SHERLOCK.as_bytes());
+            searcher.line_number(true).run()
+        };
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(2, count);
+        assert_eq!(expected, out);
+    }
+
+    #[test]
+    fn line_number_at_offset() {
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let searcher = BufferSearcher::new(
+            &mut pp, &grep, test_path(), SHERLOCK.as_bytes());
+        assert_eq!(1, searcher.line_number_at(0));
+        assert_eq!(3, searcher.line_number_at(129));
+        assert_eq!(6, searcher.line_number_at(SHERLOCK.len()));
+    }
+
+    #[test]
+    fn start_offset() {
+        let text = "foo\nbar\nfoo\n";
+        let (count, out) = search("foo", text, |s| {
+            s.start_offset(4).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:8:foo\n");
+        // An offset inside a line searches the whole containing line.
+        let (count, out) = search("bar", text, |s| s.start_offset(5));
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:bar\n");
+        // Inverted searches honour the offset too.
+        let (count, out) = search("foo", text, |s| {
+            s.start_offset(4).invert_match(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:bar\n");
+    }
+
+    #[test]
+    fn max_bytes() {
+        let text = "foo\nbar\nfoo\n";
+        // The budget ends inside the second line, which is still searched
+        // in full; the third line is not.
+        let (count, out) = search("foo|bar", text, |s| {
+            s.max_bytes(Some(5))
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:foo\n/baz.rs:bar\n");
+        // Measured from the starting offset.
+        let (count, out) = search("foo|bar", text, |s| {
+            s.start_offset(4).max_bytes(Some(2))
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:bar\n");
+    }
+
     #[test]
     fn binary() {
         let text = "Sherlock\n\x00Holmes\n";
@@ -293,6 +1447,86 @@ and exhibited clearly, with a label attached.\
     }
 
 
+    #[test]
+    fn convert_binary_filler() {
+        // Without conversion, the NUL byte stops the search as binary.
+        let (count, out) = search("quux", "b\x00ar\nquux\n", |s|s);
+        assert_eq!(0, count);
+        assert_eq!(out, "");
+        // With a filler byte, the match is found.
+        let (count, out) = search("quux", "b\x00ar\nquux\n", |s| {
+            s.convert_binary(Some(b' '))
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:quux\n");
+    }
+
+    #[test]
+    fn convert_binary_terminator() {
+        // Converting to the line terminator splits the binary data into
+        // more lines, so reported line numbers reflect the converted data.
+        let (count, out) = search("quux", "foo\x00bar\nquux\n", |s| {
+            s.convert_binary(Some(b'\n')).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:3:quux\n");
+    }
+
+    #[test]
+    fn convert_binary_has_match() {
+        let run = |pat: &str| {
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new(pat).build().unwrap();
+            BufferSearcher::new(
+                &mut pp, &grep, test_path(), b"b\x00ar\nquux\n")
+                .convert_binary(Some(b' '))
+                .has_match()
+        };
+        assert!(run("quux"));
+        assert!(!run("zzzzz"));
+    }
+
+    #[test]
+    fn binary_heuristic() {
+        // No NUL bytes, but the prefix is mostly control bytes.
+        let text = "\x01\x02\x03\x04\x05\x06\nSherlock\n";
+        let (count, out) = search("Sherlock", text, |s|s);
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:Sherlock\n");
+        let (count, out) = search("Sherlock", text, |s| {
+            s.binary_heuristic(Some((0.3, 1024)))
+        });
+        assert_eq!(0, count);
+        assert_eq!(out, "");
+        // Plain text stays searchable under the same heuristic.
+        let (count, _) = search("Sherlock", SHERLOCK, |s| {
+            s.binary_heuristic(Some((0.3, 1024)))
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn binary_sniff_len() {
+        // The NUL byte sits past a long text header.
+        let mut text = "Sherlock\n".repeat(2000);
+        text.push_str("\x00binary payload\n");
+        // The default prefix misses it entirely.
+        let (count, _) = search("Sherlock", &text, |s|s);
+        assert_eq!(2000, count);
+        // Extending the sniffed region catches it; shrinking it to zero
+        // disables sniffing even when the NUL is at the front.
+        let (count, _) = search("Sherlock", &text, |s| {
+            s.binary_sniff_len(text.len())
+        });
+        assert_eq!(0, count);
+        let (count, out) = search("bar", "\x00foo\nbar\n", |s| {
+            s.binary_sniff_len(0)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:bar\n");
+    }
+
     #[test]
     fn binary_text() {
         let text = "Sherlock\n\x00Holmes\n";
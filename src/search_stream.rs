@@ -5,21 +5,119 @@ using `read` calls and a (roughly) fixed size buffer.
 */
 
 use std::cmp;
+use std::collections::VecDeque;
 use std::error::Error as StdError;
 use std::fmt;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use bytecount;
-use grep::{Grep, Match};
+use grep::{Match, Matcher};
 use memchr::{memchr, memrchr};
-use termcolor::WriteColor;
+use regex::bytes::Regex;
 
-use printer::Printer;
+use sink::{Sink, SinkMatch};
 
 /// The default read size (capacity of input buffer).
 const READ_SIZE: usize = 8 * (1<<10);
 
+/// The smallest capacity that adaptive sizing will suggest.
+const MIN_ADAPTIVE_CAPACITY: usize = 1<<12;
+
+/// The largest capacity that adaptive sizing will suggest, regardless of
+/// the line lengths observed.
+const MAX_ADAPTIVE_CAPACITY: usize = 1<<24;
+
+/// Line length statistics shared by all adaptive input buffers in this
+/// process.
+static BUFFER_STATS: BufferSizeStats = BufferSizeStats {
+    bytes: AtomicUsize::new(0),
+    lines: AtomicUsize::new(0),
+    max_line: AtomicUsize::new(0),
+};
+
+/// Statistics about the line lengths observed by input buffers created with
+/// `InputBuffer::adaptive`.
+///
+/// One instance of these statistics is shared by every adaptive buffer in
+/// the process, so that buffers created (or reset) later benefit from what
+/// earlier searches learned about the corpus.
+pub struct BufferSizeStats {
+    /// The total number of bytes observed.
+    bytes: AtomicUsize,
+    /// The total number of complete lines observed.
+    lines: AtomicUsize,
+    /// A lower bound on the length of the longest line observed. (It is
+    /// measured from the longest unterminated region seen in a buffer, which
+    /// may start after the true beginning of the line.)
+    max_line: AtomicUsize,
+}
+
+impl BufferSizeStats {
+    /// Returns a lower bound on the length, in bytes, of the longest line
+    /// observed so far.
+    #[allow(dead_code)]
+    pub fn max_line_len(&self) -> usize {
+        self.max_line.load(Ordering::Relaxed)
+    }
+
+    /// Returns the typical (mean) line length, in bytes, observed so far,
+    /// or `0` if no complete lines have been observed.
+    #[allow(dead_code)]
+    pub fn typical_line_len(&self) -> usize {
+        let lines = self.lines.load(Ordering::Relaxed);
+        if lines == 0 {
+            return 0;
+        }
+        self.bytes.load(Ordering::Relaxed) / lines
+    }
+
+    /// Returns the buffer capacity suggested by the statistics gathered so
+    /// far.
+    ///
+    /// The suggestion is big enough to hold the longest line observed and a
+    /// healthy batch of typical lines, rounded up to a power of two and
+    /// bounded by a hard cap. Before anything has been observed, this is the
+    /// default capacity.
+    pub fn suggested_capacity(&self) -> usize {
+        let lines = self.lines.load(Ordering::Relaxed);
+        if lines == 0 {
+            return READ_SIZE;
+        }
+        let typical = self.bytes.load(Ordering::Relaxed) / lines;
+        let want = cmp::max(
+            cmp::max(self.max_line_len(), typical.saturating_mul(128)),
+            MIN_ADAPTIVE_CAPACITY,
+        );
+        cmp::min(
+            want.checked_next_power_of_two()
+                .unwrap_or(MAX_ADAPTIVE_CAPACITY),
+            MAX_ADAPTIVE_CAPACITY,
+        )
+    }
+
+    /// Record a read of `bytes` bytes containing `lines` complete lines.
+    fn record_read(&self, bytes: usize, lines: usize) {
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.lines.fetch_add(lines, Ordering::Relaxed);
+    }
+
+    /// Record an unterminated region of `len` bytes, which bounds the length
+    /// of some line from below.
+    fn record_line_len(&self, len: usize) {
+        self.max_line.fetch_max(len, Ordering::Relaxed);
+    }
+}
+
+/// Returns the line length statistics gathered by all adaptive input
+/// buffers in this process.
+#[allow(dead_code)]
+pub fn buffer_size_stats() -> &'static BufferSizeStats {
+    &BUFFER_STATS
+}
+
 /// Error describes errors that can occur while searching.
 #[derive(Debug)]
 pub enum Error {
@@ -27,25 +125,81 @@ pub enum Error {
     Io {
         err: io::Error,
         path: PathBuf,
-    }
+    },
+    /// A Windows sharing violation: another process holds the file with a
+    /// sharing mode that excludes the access we asked for. Retrying later,
+    /// or asking the other program to release the file, usually resolves
+    /// it.
+    SharingViolation {
+        path: PathBuf,
+    },
+    /// The search was cancelled through its cancellation flag. Results
+    /// delivered to the sink before the flag was observed remain valid.
+    Cancelled {
+        path: PathBuf,
+    },
+    /// A per-search override conflicted with the searcher's fixed
+    /// configuration.
+    Config {
+        path: PathBuf,
+        msg: String,
+    },
+    /// A heap limit too small to hold the lines the configured context
+    /// requires. This is detected from the configuration alone, before
+    /// any input is read.
+    HeapLimitTooSmall {
+        path: PathBuf,
+        /// A conservative lower bound on the bytes the configuration
+        /// needs.
+        required: usize,
+        /// The configured heap limit, in bytes.
+        given: usize,
+    },
+    /// A single line (together with any retained context) outgrew the
+    /// configured heap limit.
+    HeapLimit {
+        path: PathBuf,
+        /// The line number of the offending line, when line counting was
+        /// enabled.
+        line_number: Option<u64>,
+        /// The byte offset at which the offending line starts.
+        offset: u64,
+        /// The configured heap limit, in bytes.
+        limit: usize,
+    },
+    /// A line exceeded the configured maximum line length under the
+    /// `Error` policy.
+    LineTooLong {
+        path: PathBuf,
+        /// The line number of the oversized line, when line counting was
+        /// enabled.
+        line_number: Option<u64>,
+        /// The byte offset at which the oversized line starts.
+        offset: u64,
+    },
 }
 
 impl Error {
-    fn from_io<P: AsRef<Path>>(err: io::Error, path: P) -> Error {
-        Error::Io { err: err, path: path.as_ref().to_path_buf() }
+    pub fn from_io<P: AsRef<Path>>(err: io::Error, path: P) -> Error {
+        Error::Io { err, path: path.as_ref().to_path_buf() }
     }
-}
 
-impl StdError for Error {
-    fn description(&self) -> &str {
-        match *self {
-            Error::Io { ref err, .. } => err.description(),
-        }
+    /// Returns true if this error reports a cancelled search.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(*self, Error::Cancelled { .. })
     }
+}
 
-    fn cause(&self) -> Option<&StdError> {
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
             Error::Io { ref err, .. } => Some(err),
+            Error::SharingViolation { .. } => None,
+            Error::Cancelled { .. } => None,
+            Error::Config { .. } => None,
+            Error::HeapLimitTooSmall { .. } => None,
+            Error::HeapLimit { .. } => None,
+            Error::LineTooLong { .. } => None,
         }
     }
 }
@@ -56,17 +210,69 @@ impl fmt::Display for Error {
             Error::Io { ref err, ref path } => {
                 write!(f, "{}: {}", path.display(), err)
             }
+            Error::SharingViolation { ref path } => {
+                write!(
+                    f,
+                    "{}: the file is held by another process with an \
+                     incompatible sharing mode; retry once the other \
+                     process releases it",
+                    path.display())
+            }
+            Error::Cancelled { ref path } => {
+                write!(f, "{}: search cancelled", path.display())
+            }
+            Error::Config { ref path, ref msg } => {
+                write!(f, "{}: {}", path.display(), msg)
+            }
+            Error::HeapLimitTooSmall { ref path, required, given } => {
+                write!(
+                    f,
+                    "{}: heap limit of {} bytes is too small for the \
+                     configured context; at least {} bytes are needed",
+                    path.display(), given, required)
+            }
+            Error::HeapLimit { ref path, line_number, offset, limit } => {
+                match line_number {
+                    Some(n) => write!(
+                        f,
+                        "{}: line {} at offset {} exceeds the heap limit \
+                         of {} bytes",
+                        path.display(), n, offset, limit),
+                    None => write!(
+                        f,
+                        "{}: the line at offset {} exceeds the heap limit \
+                         of {} bytes",
+                        path.display(), offset, limit),
+                }
+            }
+            Error::LineTooLong { ref path, line_number, offset } => {
+                match line_number {
+                    Some(n) => write!(
+                        f,
+                        "{}: line {} (starting at byte offset {}) exceeds \
+                         the maximum line length",
+                        path.display(), n, offset),
+                    None => write!(
+                        f,
+                        "{}: the line starting at byte offset {} exceeds \
+                         the maximum line length",
+                        path.display(), offset),
+                }
+            }
         }
     }
 }
 
-pub struct Searcher<'a, R, W: 'a> {
+pub struct Searcher<'a, R, S: 'a, M: 'a + ?Sized> {
     opts: Options,
+    cancel: Option<Arc<AtomicBool>>,
     inp: &'a mut InputBuffer,
-    printer: &'a mut Printer<W>,
-    grep: &'a Grep,
+    printer: &'a mut S,
+    grep: &'a M,
     path: &'a Path,
     haystack: R,
+    segments: Vec<R>,
+    segment_index: u64,
     match_line_count: u64,
     match_count: Option<u64>,
     line_count: Option<u64>,
@@ -75,48 +281,231 @@ pub struct Searcher<'a, R, W: 'a> {
     last_printed: usize,
     last_line: usize,
     after_context_remaining: usize,
+    context_budget: usize,
+    exclusions: Vec<(u64, u64)>,
+    buf_offset: u64,
+    next_sample_line: u64,
+    next_sample_byte: u64,
+    lines_seen: u64,
+    sampled_lines: u64,
+    skipped_errors: u64,
+    detect_pending: bool,
+    byte_budget_done: bool,
+    nonmatch_done: bool,
+    checkpoint: Option<&'a mut Checkpoint>,
+    para_buf: Vec<u8>,
+    para_first_line: Option<u64>,
+    para_first_offset: u64,
+    para_before: VecDeque<(Vec<u8>, Option<u64>, u64)>,
+    para_after_remaining: usize,
+    para_gap: bool,
+}
+
+/// A line terminator for a searcher: a single byte, or CRLF.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineTerminator {
+    /// Lines end with the single byte given.
+    Byte(u8),
+    /// Lines end with `\r\n`. Lines are still split on `\n`, so a file
+    /// with mixed endings is handled gracefully: a lone `\n` terminates a
+    /// line just as well.
+    Crlf,
+}
+
+#[allow(dead_code)]
+impl LineTerminator {
+    /// The byte that lines are split on.
+    pub fn as_byte(&self) -> u8 {
+        match *self {
+            LineTerminator::Byte(b) => b,
+            LineTerminator::Crlf => b'\n',
+        }
+    }
+
+    /// Returns true if this is the CRLF terminator.
+    pub fn is_crlf(&self) -> bool {
+        *self == LineTerminator::Crlf
+    }
+}
+
+impl Default for LineTerminator {
+    fn default() -> LineTerminator {
+        LineTerminator::Byte(b'\n')
+    }
+}
+
+/// What to do with a line that exceeds the configured maximum length.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LongLinePolicy {
+    /// Silently discard the oversized line. Line numbers and byte offsets
+    /// of subsequent lines are unaffected.
+    Skip,
+    /// Abort the search with an error naming the line number (when line
+    /// counting is enabled) and the byte offset of the oversized line.
+    Error,
+}
+
+/// The policy for allocating memory in an `InputBuffer`.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BufferAllocation {
+    /// Grow the buffer on demand, doubling its size as needed. This is
+    /// the default.
+    OnDemand,
+    /// Reserve the given number of bytes up front and grow on demand
+    /// past that. Useful to avoid growth stalls mid-search.
+    Eager(usize),
+    /// Never allocate beyond the initial capacity. A line that does not
+    /// fit in the buffer surfaces as a read error.
+    Fixed,
+}
+
+/// How to treat a final line that lacks a terminator.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FinalLinePolicy {
+    /// Search the partial final line like any other line. This is the
+    /// default.
+    Match,
+    /// POSIX-strict: a final line with no terminator is not a line, so
+    /// ignore it.
+    Ignore,
+    /// Search the line, but tell the sink it was unterminated via
+    /// `Sink::unterminated`.
+    Flag,
 }
 
 /// Options for configuring search.
-#[derive(Clone)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Options {
     pub after_context: usize,
+    pub anchor_line_start: bool,
     pub before_context: usize,
+    pub best_effort: bool,
+    pub binary_report: Option<u8>,
     pub byte_offset: bool,
+    pub column: bool,
+    pub context_byte_limit: Option<usize>,
     pub count: bool,
     pub count_matches: bool,
+    pub crlf: bool,
+    pub detect_terminator: bool,
     pub files_with_matches: bool,
     pub files_without_matches: bool,
+    pub final_line: FinalLinePolicy,
+    pub heap_limit: Option<usize>,
     pub eol: u8,
     pub invert_match: bool,
     pub line_number: bool,
+    pub line_number_start: u64,
+    pub max_bytes: Option<u64>,
     pub max_count: Option<u64>,
+    pub max_line_len: Option<(usize, LongLinePolicy)>,
+    pub only_matching: bool,
+    pub paragraph: bool,
+    pub passthru: bool,
+    pub peek_back: Option<u64>,
     pub quiet: bool,
+    pub quit_after_no_match_within: Option<u64>,
+    pub record_sep: Option<Vec<u8>>,
+    pub record_size: Option<usize>,
+    pub report_indent: Option<usize>,
+    pub sample_lines: Option<u64>,
+    pub sample_bytes: Option<u64>,
+    pub sample_random: Option<RandomSample>,
+    pub skip_empty_lines: bool,
+    pub skip_increment: u64,
+    pub start_offset: u64,
+    pub stop_on_nonmatch: bool,
+    pub stitch_segments: bool,
     pub text: bool,
+    pub utf16le: bool,
 }
 
 impl Default for Options {
     fn default() -> Options {
         Options {
             after_context: 0,
+            anchor_line_start: false,
             before_context: 0,
+            best_effort: false,
+            binary_report: None,
             byte_offset: false,
+            column: false,
+            context_byte_limit: None,
             count: false,
             count_matches: false,
+            crlf: false,
+            detect_terminator: false,
             files_with_matches: false,
             files_without_matches: false,
+            final_line: FinalLinePolicy::Match,
+            heap_limit: None,
             eol: b'\n',
             invert_match: false,
             line_number: false,
+            line_number_start: 1,
+            max_bytes: None,
             max_count: None,
+            max_line_len: None,
+            only_matching: false,
+            paragraph: false,
+            passthru: false,
+            peek_back: None,
             quiet: false,
+            quit_after_no_match_within: None,
+            record_sep: None,
+            record_size: None,
+            report_indent: None,
+            sample_lines: None,
+            sample_bytes: None,
+            sample_random: None,
+            skip_empty_lines: false,
+            skip_increment: READ_SIZE as u64,
+            start_offset: 0,
+            stop_on_nonmatch: false,
+            stitch_segments: false,
             text: false,
+            utf16le: false,
         }
     }
 
 }
 
 impl Options {
+    /// The settings bundle for CLI-style grepping: line numbers on, binary
+    /// detection on (quit on NUL) and Unix line terminators. Start from
+    /// this and tweak individual settings as needed.
+    pub fn grep_defaults() -> Options {
+        Options {
+            line_number: true,
+            ..Options::default()
+        }
+    }
+
+    /// The settings bundle for long-running streaming consumers (e.g.
+    /// tailing pipes on a server): binary data is searched as text so a
+    /// stray NUL byte can't silently abort the search mid-stream, and line
+    /// numbers are off to keep per-chunk bookkeeping minimal.
+    pub fn streaming_defaults() -> Options {
+        Options {
+            text: true,
+            ..Options::default()
+        }
+    }
+
+    /// The settings bundle for searching raw or binary data (e.g.
+    /// forensics): binary detection off and absolute byte offsets on, since
+    /// line numbers are rarely meaningful in raw data.
+    pub fn forensics_defaults() -> Options {
+        Options {
+            byte_offset: true,
+            text: true,
+            ..Options::default()
+        }
+    }
+
     /// Several options (--quiet, --count, --count-matches, --files-with-matches,
     /// --files-without-match) imply that we shouldn't ever display matches.
     pub fn skip_matches(&self) -> bool {
@@ -135,20 +524,82 @@ impl Options {
         if match_line_count > 0 && self.stop_after_first_match() {
             return true;
         }
-        if self.max_count.map_or(false, |max| match_line_count >= max) {
+        if self.max_count.is_some_and(|max| match_line_count >= max) {
             return true;
         }
         false
     }
 }
 
-impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
+/// A set of per-search overrides for a whitelisted subset of `Options`.
+///
+/// A searcher's input buffer is reusable scratch space whose configuration
+/// (encoding, line terminator, line length limits, binary handling)
+/// outlives any one search, and building a complete `Options` value when
+/// only a couple of settings vary between files is needless ceremony.
+/// Fields left as `None` keep the searcher's configured value; the rest
+/// are applied on top of it for a single search without touching the
+/// buffer configuration.
+///
+/// The line terminator and encoding fields are validated rather than
+/// applied: the shared input buffer was configured for one terminator and
+/// encoding, so an override that disagrees with the searcher's settings is
+/// rejected.
+#[derive(Clone, Debug, Default)]
+pub struct SearchOptions {
+    /// Invert matching.
+    pub invert_match: Option<bool>,
+    /// The number of contextual lines to print before each match.
+    pub before_context: Option<usize>,
+    /// The number of contextual lines to print after each match.
+    pub after_context: Option<usize>,
+    /// The maximum number of matching lines to report. `Some(None)` lifts
+    /// a configured limit.
+    pub max_count: Option<Option<u64>>,
+    /// The input byte budget. `Some(None)` lifts a configured limit.
+    pub max_bytes: Option<Option<u64>>,
+    /// Quit after the first match without producing output.
+    pub quiet: Option<bool>,
+    /// The end-of-line byte. Validated only: a value differing from the
+    /// searcher's configured terminator is rejected.
+    pub eol: Option<u8>,
+    /// UTF-16LE mode. Validated only, like `eol`.
+    pub utf16le: Option<bool>,
+}
+
+/// A record of how far a search consumed its input, for resuming later.
+///
+/// Tailing a growing file is the motivating use: run a search, keep the
+/// checkpoint, and hand it to the next search over the same (reopened or
+/// rewound) input to pick up exactly where the previous one stopped, with
+/// line numbers and byte offsets continuing seamlessly. A default
+/// (zeroed) checkpoint starts from the beginning.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Checkpoint {
+    /// The absolute byte offset of the first byte not yet searched.
+    pub offset: u64,
+    /// The number of lines seen before `offset`.
+    pub lines: u64,
+    /// True if the search consumed its input to the end, rather than
+    /// stopping early at a match limit, a byte budget, binary data or a
+    /// cancellation.
+    pub eof: bool,
+}
+
+impl<'a, R: io::Read, S: Sink, M: Matcher + ?Sized> Searcher<'a, R, S, M> {
     /// Create a new searcher.
     ///
+    /// Construction is infallible: the default configuration is always
+    /// valid, and checks that depend on the combination of options (say,
+    /// a heap limit against the configured before-context) are deferred
+    /// to `run`, which reports them as `Error::Config` before reading
+    /// any input. Nothing a builder method accepts can make a later call
+    /// panic.
+    ///
     /// `inp` is a reusable input buffer that is used as scratch space by this
     /// searcher.
     ///
-    /// `printer` is used to output all results of searching.
+    /// `printer` is any sink used to output all results of searching.
     ///
     /// `grep` is the actual matcher.
     ///
@@ -157,18 +608,21 @@ impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
     /// `haystack` is a reader of text to search.
     pub fn new(
         inp: &'a mut InputBuffer,
-        printer: &'a mut Printer<W>,
-        grep: &'a Grep,
+        printer: &'a mut S,
+        grep: &'a M,
         path: &'a Path,
         haystack: R,
-    ) -> Searcher<'a, R, W> {
+    ) -> Searcher<'a, R, S, M> {
         Searcher {
             opts: Options::default(),
-            inp: inp,
-            printer: printer,
-            grep: grep,
-            path: path,
-            haystack: haystack,
+            cancel: None,
+            inp,
+            printer,
+            grep,
+            path,
+            haystack,
+            segments: vec![],
+            segment_index: 0,
             match_line_count: 0,
             match_count: None,
             line_count: None,
@@ -177,7 +631,116 @@ impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
             last_printed: 0,
             last_line: 0,
             after_context_remaining: 0,
+            context_budget: usize::MAX,
+            exclusions: vec![],
+            buf_offset: 0,
+            next_sample_line: 0,
+            next_sample_byte: 0,
+            lines_seen: 0,
+            sampled_lines: 0,
+            skipped_errors: 0,
+            byte_budget_done: false,
+            nonmatch_done: false,
+            checkpoint: None,
+            para_buf: vec![],
+            para_first_line: None,
+            para_first_offset: 0,
+            para_before: VecDeque::new(),
+            para_after_remaining: 0,
+            para_gap: false,
+            detect_pending: false,
+        }
+    }
+
+    /// Replace this searcher's configuration with the named preset given
+    /// by `Options::grep_defaults`. Further settings can be chained on top.
+    #[allow(dead_code)]
+    pub fn grep_defaults(self) -> Self {
+        self.options(Options::grep_defaults())
+    }
+
+    /// Replace this searcher's configuration with the named preset given
+    /// by `Options::streaming_defaults`.
+    #[allow(dead_code)]
+    pub fn streaming_defaults(self) -> Self {
+        self.options(Options::streaming_defaults())
+    }
+
+    /// Replace this searcher's configuration with the named preset given
+    /// by `Options::forensics_defaults`.
+    #[allow(dead_code)]
+    pub fn forensics_defaults(self) -> Self {
+        self.options(Options::forensics_defaults())
+    }
+
+    /// Replace this searcher's entire configuration with the options given.
+    pub fn options(mut self, opts: Options) -> Self {
+        self.opts = opts;
+        self.inp.eol(self.opts.eol);
+        self.inp.utf16le(self.opts.utf16le);
+        self
+    }
+
+    /// Apply per-search overrides on top of this searcher's configuration.
+    ///
+    /// Fields left as `None` in `overrides` keep their configured values.
+    /// Overrides that would require configuring the shared input buffer
+    /// differently — a different line terminator or encoding — are
+    /// rejected with `Error::Config` instead of being applied.
+    #[allow(dead_code)]
+    pub fn with_overrides(
+        mut self,
+        overrides: &SearchOptions,
+    ) -> Result<Self, Error> {
+        if let Some(eol) = overrides.eol {
+            if eol != self.opts.eol {
+                return Err(Error::Config {
+                    path: self.path.to_path_buf(),
+                    msg: format!(
+                        "cannot override the line terminator ({:?}) for a \
+                         single search",
+                        self.opts.eol as char),
+                });
+            }
+        }
+        if let Some(yes) = overrides.utf16le {
+            if yes != self.opts.utf16le {
+                return Err(Error::Config {
+                    path: self.path.to_path_buf(),
+                    msg: "cannot toggle UTF-16LE mode for a single search"
+                        .to_string(),
+                });
+            }
+        }
+        if let Some(yes) = overrides.invert_match {
+            self.opts.invert_match = yes;
+        }
+        if let Some(count) = overrides.before_context {
+            self.opts.before_context = count;
         }
+        if let Some(count) = overrides.after_context {
+            self.opts.after_context = count;
+        }
+        if let Some(count) = overrides.max_count {
+            self.opts.max_count = count;
+        }
+        if let Some(count) = overrides.max_bytes {
+            self.opts.max_bytes = count;
+        }
+        if let Some(yes) = overrides.quiet {
+            self.opts.quiet = yes;
+        }
+        Ok(self)
+    }
+
+    /// A read-only snapshot of this searcher's full configuration.
+    ///
+    /// `Options` derives `PartialEq`, so callers (a sink deciding how to
+    /// serialize its output, say) can query individual fields or compare
+    /// whole configurations, e.g. against `Options::default()`.
+    #[allow(dead_code)]
+    pub fn config(&self) -> &Options {
+        &self.opts
     }
 
     /// The number of contextual lines to show after each match. The default
@@ -187,6 +750,70 @@ impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
         self
     }
 
+    /// Limit the number of context bytes delivered around each match.
+    ///
+    /// Context settings count lines, so one pathological multi-megabyte
+    /// "line" of context can dwarf the match itself. With a limit, each
+    /// match refreshes a byte budget that its context lines draw down: a
+    /// line that overflows the remainder is truncated (and the sink told
+    /// so via `Sink::context_truncated`), and later lines are skipped
+    /// entirely. Line numbering and separators are unaffected.
+    #[allow(dead_code)]
+    pub fn context_byte_limit(mut self, limit: Option<usize>) -> Self {
+        self.opts.context_byte_limit = limit;
+        self
+    }
+
+    /// If enabled, a line is only reported when a match begins at its very
+    /// first byte, regardless of whether the pattern itself is anchored.
+    ///
+    /// This only affects which lines are selected: the printer still reports
+    /// every match on a selected line when printing individual matches. With
+    /// inverted matching, a line whose matches all begin past the first byte
+    /// counts as a non-match and is reported. A line that starts with (part
+    /// of) its own terminator, as happens for the line feed of a CRLF pair
+    /// when splitting on `\r`, can only be selected if the pattern matches
+    /// that terminator byte. Matchers that aren't backed by a regex report
+    /// whole lines, so for them every matched line is considered anchored.
+    ///
+    /// Disabled by default.
+    #[allow(dead_code)]
+    pub fn anchor_line_start(mut self, yes: bool) -> Self {
+        self.opts.anchor_line_start = yes;
+        self
+    }
+
+    /// If enabled, every line of the input is delivered to the sink:
+    /// matching lines through `matched` and all other lines through
+    /// `context`, with line numbers and byte offsets intact. With
+    /// `invert_match`, the roles swap. Before/after context settings are
+    /// ignored, since passthru subsumes them, and no context separators
+    /// are ever emitted.
+    ///
+    /// Disabled by default.
+    #[allow(dead_code)]
+    pub fn passthru(mut self, yes: bool) -> Self {
+        self.opts.passthru = yes;
+        self
+    }
+
+    /// If enabled, blank-line-delimited paragraphs are the unit of
+    /// matching instead of lines.
+    ///
+    /// Each record is the run of consecutive non-empty lines between blank
+    /// lines; the pattern is tested against the whole record and matching
+    /// records are delivered to the sink as one region. Line numbers refer
+    /// to the first line of the record, byte offsets to its first byte, and
+    /// context settings count records rather than lines. Sampling,
+    /// passthru and the empty-line filter do not apply in this mode.
+    ///
+    /// This is disabled by default.
+    #[allow(dead_code)]
+    pub fn paragraph(mut self, yes: bool) -> Self {
+        self.opts.paragraph = yes;
+        self
+    }
+
     /// The number of contextual lines to show before each match. The default
     /// is zero.
     pub fn before_context(mut self, count: usize) -> Self {
@@ -204,8 +831,36 @@ impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
         self
     }
 
+    /// If enabled, the 1-based byte column of the first match within each
+    /// matching line is computed and handed to the sink. Inverted matches
+    /// have no match to locate, so they report column 1. Callers that
+    /// don't ask pay nothing.
+    ///
+    /// Disabled by default.
+    #[allow(dead_code)]
+    pub fn column(mut self, yes: bool) -> Self {
+        self.opts.column = yes;
+        self
+    }
+
+    /// Set a sorted list of absolute byte ranges to exclude from the search.
+    ///
+    /// Matches from lines overlapping an excluded range are never reported,
+    /// so ranges behave as if they were snapped outward to line boundaries.
+    /// Line numbers and byte offsets remain exact, since excluded bytes
+    /// still pass through the searcher and have their terminators counted.
+    /// The ranges must be sorted and non-overlapping.
+    #[allow(dead_code)]
+    pub fn exclude_ranges(mut self, ranges: Vec<(u64, u64)>) -> Self {
+        self.exclusions = ranges;
+        self
+    }
+
     /// If enabled, searching will print a count instead of each match.
     ///
+    /// The count is always the number of matching *lines*, regardless of the
+    /// granularity at which the printer reports matches.
+    ///
     /// Disabled by default.
     pub fn count(mut self, yes: bool) -> Self {
         self.opts.count = yes;
@@ -215,6 +870,10 @@ impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
     /// If enabled, searching will print the count of individual matches
     /// instead of each match.
     ///
+    /// The count is always the number of individual *matches*, so a line
+    /// with three matches contributes three, regardless of the granularity
+    /// at which the printer reports matches.
+    ///
     /// Disabled by default.
    pub fn count_matches(mut self, yes: bool) -> Self {
         self.opts.count_matches = yes;
@@ -240,6 +899,301 @@ impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
     /// Set the end-of-line byte used by this searcher.
     pub fn eol(mut self, eol: u8) -> Self {
         self.opts.eol = eol;
+        self.opts.crlf = false;
+        self
+    }
+
+    /// Set the line terminator used by this searcher.
+    ///
+    /// This is a richer version of `eol`: `LineTerminator::Crlf` splits
+    /// lines on `\n` exactly like the default, but the line ranges
+    /// reported to the sink have their terminator trimmed, including any
+    /// carriage return preceding it, so consumers never see a stray `\r`.
+    /// Lines ending with a bare `\n` in the same input are trimmed the
+    /// same way, and line counting is unaffected, so files with mixed
+    /// endings produce correct line numbers.
+    #[allow(dead_code)]
+    pub fn line_terminator(mut self, term: LineTerminator) -> Self {
+        self.opts.eol = term.as_byte();
+        self.opts.crlf = term.is_crlf();
+        self
+    }
+
+    /// Set a multi-byte record terminator, such as `\n---\n` between
+    /// YAML documents.
+    ///
+    /// Records take the place of lines: the run of bytes between
+    /// terminator occurrences is matched as one unit and delivered to
+    /// the sink with the terminator stripped. Records are carried in a
+    /// side buffer, so both a record and the terminator itself may span
+    /// read boundaries. Line numbers refer to the first line of the
+    /// record, byte offsets to its first byte, and context settings
+    /// count records rather than lines. Empty records (consecutive
+    /// terminators) are skipped. The input buffer still rolls on the
+    /// single `eol` byte, which this sets to the terminator's final
+    /// byte.
+    ///
+    /// A single-byte terminator is routed to `eol`, keeping the existing
+    /// fast path. `None` or an empty terminator restores line-oriented
+    /// searching.
+    #[allow(dead_code)]
+    pub fn record_terminator(mut self, sep: Option<Vec<u8>>) -> Self {
+        let sep = match sep {
+            Some(sep) => sep,
+            None => {
+                self.opts.record_sep = None;
+                return self;
+            }
+        };
+        if sep.is_empty() {
+            self.opts.record_sep = None;
+            return self;
+        }
+        if sep.len() == 1 {
+            self.opts.record_sep = None;
+            return self.eol(sep[0]);
+        }
+        self.opts.eol = sep[sep.len() - 1];
+        self.opts.crlf = false;
+        self.inp.eol(self.opts.eol);
+        self.opts.record_sep = Some(sep);
+        self
+    }
+
+    /// Set the allocation policy of the underlying input buffer.
+    ///
+    /// By default the buffer grows on demand, doubling whenever a line
+    /// outgrows it. `Eager` reserves its bytes before the search starts;
+    /// `Fixed` never grows, turning a line that doesn't fit into a read
+    /// error regardless of any other limit.
+    #[allow(dead_code)]
+    pub fn buffer_alloc(self, policy: BufferAllocation) -> Self {
+        self.inp.buffer_alloc(policy);
+        self
+    }
+
+    /// Cap the memory the input buffer may use, in bytes.
+    ///
+    /// The buffer normally grows to fit whatever line (or before-context
+    /// region, which rides in the same buffer across rolls and so is
+    /// accounted for automatically) doesn't fit. With a limit, growth
+    /// past it aborts with `Error::HeapLimit`, which names the line
+    /// number (when counting is on) and offset of the line that burst
+    /// the limit. A limit too small to
+    /// possibly hold the configured before-context is rejected up front
+    /// with `Error::Config` when the search starts.
+    #[allow(dead_code)]
+    pub fn heap_limit(mut self, limit: Option<usize>) -> Self {
+        self.opts.heap_limit = limit;
+        self.inp.heap_limit(limit);
+        self
+    }
+
+    /// Split the input into fixed-size records of exactly `size` bytes
+    /// instead of terminator-delimited lines.
+    ///
+    /// Every `size`-byte chunk is matched as one unit, which suits
+    /// fixed-width binary logs. Record ordinals take the place of line
+    /// numbers, byte offsets point at the record's first byte, and
+    /// context settings count records. Records may span read boundaries;
+    /// a short final record at EOF is still searched. `None` or a zero
+    /// size restores line-oriented searching.
+    #[allow(dead_code)]
+    pub fn fixed_record_size(mut self, size: Option<usize>) -> Self {
+        let size = size.filter(|&n| n > 0);
+        self.opts.record_size = size;
+        self.inp.fixed_records(size.is_some());
+        self
+    }
+
+    /// Set the policy for a final line that lacks a terminator.
+    ///
+    /// By default such a line is searched like any other. `Ignore` gives
+    /// POSIX-strict behavior where the partial line is dropped, and
+    /// `Flag` searches it but also calls `Sink::unterminated` so the
+    /// consumer knows the input didn't end in a terminator.
+    #[allow(dead_code)]
+    pub fn final_line(mut self, policy: FinalLinePolicy) -> Self {
+        self.opts.final_line = policy;
+        self.inp.final_line(policy);
+        self
+    }
+
+    /// When enabled, the first filled buffer is inspected for `\r\n`
+    /// versus a bare terminator, and the effective line terminator for the
+    /// rest of this search is configured accordingly. If the first chunk
+    /// contains no terminator at all, the configured terminator stays in
+    /// effect. Detection happens anew for every search.
+    #[allow(dead_code)]
+    pub fn detect_line_terminator(mut self, yes: bool) -> Self {
+        self.opts.detect_terminator = yes;
+        self
+    }
+
+    /// The line terminator actually in effect for this search. Until the
+    /// first chunk of input has been examined, this reports the configured
+    /// terminator.
+    #[allow(dead_code)]
+    pub fn effective_line_terminator(&self) -> LineTerminator {
+        if self.opts.crlf {
+            LineTerminator::Crlf
+        } else {
+            LineTerminator::Byte(self.opts.eol)
+        }
+    }
+
+    /// If enabled, lines with no content are never reported as matches.
+    ///
+    /// A line counts as empty when nothing remains after stripping its
+    /// terminator and any trailing carriage return. Whitespace is content:
+    /// a line of only spaces is not empty. Suppressed lines still advance
+    /// line numbers and may still be printed as context of nearby matches.
+    ///
+    /// This is chiefly useful with inverted matching, where blank lines
+    /// otherwise flood the output.
+    ///
+    /// Disabled by default.
+    #[allow(dead_code)]
+    pub fn skip_empty_lines(mut self, yes: bool) -> Self {
+        self.opts.skip_empty_lines = yes;
+        self
+    }
+
+    /// If enabled, a read error no longer aborts the search.
+    ///
+    /// Instead, the error is reported to the sink along with the absolute
+    /// byte offset at which it occurred, and the searcher continues with
+    /// whatever input remains reachable. For plain readers that means
+    /// giving up on the rest of the stream; `run_seekable` can skip past
+    /// the failing region instead. Line numbers are approximate from the
+    /// first skipped error onwards, and the end-of-search summary reports
+    /// how many errors were skipped.
+    ///
+    /// Disabled by default: a read error fails the search.
+    #[allow(dead_code)]
+    pub fn best_effort(mut self, yes: bool) -> Self {
+        self.opts.best_effort = yes;
+        self
+    }
+
+    /// Install a cancellation flag for this search.
+    ///
+    /// The flag is polled between buffer fills. Once another thread sets
+    /// it, the search stops before its next fill and fails with
+    /// `Error::Cancelled`; the sink is notified through `Sink::cancelled`
+    /// first, so the results delivered up to that point are clearly
+    /// marked as partial. Cancellation is never downgraded to a skipped
+    /// read by best-effort mode.
+    ///
+    /// The default is None: the search can't be cancelled.
+    #[allow(dead_code)]
+    pub fn cancel_flag(mut self, flag: Option<Arc<AtomicBool>>) -> Self {
+        self.cancel = flag;
+        self
+    }
+
+    /// Set the number of bytes `run_seekable` seeks forward when skipping
+    /// past a read error in best-effort mode.
+    #[allow(dead_code)]
+    pub fn skip_increment(mut self, n: u64) -> Self {
+        self.opts.skip_increment = n;
+        self
+    }
+
+    /// Begin the search at the given absolute byte offset instead of the
+    /// start of the input.
+    ///
+    /// This is useful for resuming a search over an append-only input from
+    /// a checkpoint. `run` reads and discards the skipped bytes;
+    /// `run_seekable` seeks past them. Reported byte offsets and exclusion
+    /// ranges remain absolute, but line numbers restart at the skip point,
+    /// so checkpoints are best taken at line boundaries.
+    ///
+    /// The default is 0, which searches the whole input.
+    #[allow(dead_code)]
+    pub fn start_offset(mut self, offset: u64) -> Self {
+        self.opts.start_offset = offset;
+        self
+    }
+
+    /// Stop searching at the first non-matching line found after at least
+    /// one matching line, as if the input ended there. On sorted input,
+    /// where all matches are contiguous, this skips the rest of the file.
+    ///
+    /// The trailing after-context owed to the final match is still
+    /// flushed. With `invert_match` the roles swap: the first matching
+    /// line found after a delivered line ends the search. Lines
+    /// suppressed by an exclusion range neither count as matches nor end
+    /// the search.
+    #[allow(dead_code)]
+    pub fn stop_on_nonmatch(mut self, yes: bool) -> Self {
+        self.opts.stop_on_nonmatch = yes;
+        self
+    }
+
+    /// When searching several readers as one stream (`run_readers`),
+    /// stitch a line split across a segment boundary back together
+    /// instead of ending it at the boundary.
+    ///
+    /// Off by default, which treats each segment's unterminated final
+    /// line as ending at the boundary -- the right behavior when the
+    /// segments are whole files, such as rotated logs.
+    #[allow(dead_code)]
+    pub fn stitch_segments(mut self, yes: bool) -> Self {
+        self.opts.stitch_segments = yes;
+        self
+    }
+
+    /// Resume from, and record into, the checkpoint given.
+    ///
+    /// The values already in the checkpoint seed the search: input is
+    /// skipped to its offset and line numbering continues after its line
+    /// count, so a default checkpoint searches from the start. When the
+    /// search ends — at EOF, at a match limit or byte budget, on binary
+    /// data, or by cancellation — the checkpoint is rewritten with the
+    /// offset of the first byte not yet searched and the number of lines
+    /// seen before it, ready to seed the next search. Line counting is
+    /// kept for the checkpoint's bookkeeping even when line numbers are
+    /// not reported, and the counting fast path is bypassed since it
+    /// does not track consumption precisely.
+    #[allow(dead_code)]
+    pub fn checkpoint(mut self, cp: &'a mut Checkpoint) -> Self {
+        self.opts.start_offset = cp.offset;
+        self.opts.line_number_start = cp.lines + 1;
+        self.checkpoint = Some(cp);
+        self
+    }
+
+    /// If set, compute the indentation of each reported matching line and
+    /// attach it to the payload handed to the sink. `tab_stop` controls how
+    /// tabs are expanded when computing the width.
+    ///
+    /// Disabled by default, in which case no per-line scan happens and the
+    /// sink sees `None`.
+    #[allow(dead_code)]
+    pub fn report_indent(mut self, tab_stop: Option<usize>) -> Self {
+        self.opts.report_indent = tab_stop;
+        self
+    }
+
+    /// If set, `run_seekable` reads up to `before_context` lines preceding
+    /// the reader's starting position, bounded by the given number of
+    /// bytes, so that matches early in a range-restricted search still get
+    /// full before-context. The peeked lines are only ever printed as
+    /// context; they are never searched.
+    ///
+    /// Byte offsets remain true offsets into the underlying input. Line
+    /// numbers are exact when the cap reaches back to the start of the
+    /// input and are otherwise relative to the peeked region (the first
+    /// peeked line may also be cut short by the cap).
+    ///
+    /// For inputs that can't seek, `run` leaves context truncated at the
+    /// range boundary as before and notes as much in the summary.
+    ///
+    /// Disabled by default.
+    #[allow(dead_code)]
+    pub fn peek_back(mut self, cap: Option<u64>) -> Self {
+        self.opts.peek_back = cap;
         self
     }
 
@@ -257,1210 +1211,6913 @@ impl<'a, R: io::Read, W: WriteColor> Searcher<'a, R, W> {
         self
     }
 
+    /// Set the number reported for the first line of the input.
+    ///
+    /// This seeds the line counter, so that searching the tail of a file
+    /// from a known checkpoint reports line numbers relative to the whole
+    /// file. It affects matched lines and context lines alike. Values
+    /// below 1 are treated as 1, the default.
+    #[allow(dead_code)]
+    pub fn line_number_start(mut self, start: u64) -> Self {
+        self.opts.line_number_start = cmp::max(1, start);
+        self
+    }
+
     /// Limit the number of matches to the given count.
     ///
+    /// Once the limit is reached, the search stops reading input
+    /// entirely, not merely stops reporting; only the after-context still
+    /// owed to the final match is read and delivered first. Inverted
+    /// matches count against the limit like any others.
+    ///
     /// The default is None, which corresponds to no limit.
     pub fn max_count(mut self, count: Option<u64>) -> Self {
         self.opts.max_count = count;
         self
     }
 
-    /// If enabled, don't show any output and quit searching after the first
-    /// match is found.
-    pub fn quiet(mut self, yes: bool) -> Self {
-        self.opts.quiet = yes;
+    /// Limit the search to the given number of bytes of input.
+    ///
+    /// Once the budget is consumed the search stops filling the buffer,
+    /// after completing the line that straddles the boundary, so a partial
+    /// line is never matched against. With `start_offset`, the budget is
+    /// measured from the starting offset.
+    ///
+    /// The default is None, which corresponds to no limit.
+    #[allow(dead_code)]
+    pub fn max_bytes(mut self, count: Option<u64>) -> Self {
+        self.opts.max_bytes = count;
         self
     }
 
-    /// If enabled, search binary files as if they were text.
-    pub fn text(mut self, yes: bool) -> Self {
-        self.opts.text = yes;
-        self.inp.text(yes);
+    /// If enabled, each individual match is reported to the sink instead
+    /// of its containing line, with the match's own byte span, absolute
+    /// offset and column. A line with several matches produces several
+    /// events, all carrying the line's number. In paragraph mode the
+    /// record is searched as a whole, so a match may span lines; its line
+    /// number and column refer to the line it starts on. Empty matches
+    /// are reported like any other and cannot loop: the regex engine
+    /// advances past them on its own. Requires a regex-backed matcher;
+    /// without one, whole lines are reported as usual.
+    ///
+    /// Disabled by default.
+    #[allow(dead_code)]
+    pub fn only_matching(mut self, yes: bool) -> Self {
+        self.opts.only_matching = yes;
         self
     }
 
-    /// Execute the search. Results are written to the printer and the total
-    /// number of matches is returned.
-    #[inline(never)]
-    pub fn run(mut self) -> Result<u64, Error> {
-        self.inp.reset();
-        self.match_line_count = 0;
-        self.line_count = if self.opts.line_number { Some(0) } else { None };
-        self.byte_offset = if self.opts.byte_offset { Some(0) } else { None };
-        self.match_count = if self.opts.count_matches { Some(0) } else { None };
-        self.last_match = Match::default();
-        self.after_context_remaining = 0;
-        while !self.terminate() {
+    /// Set a maximum line length, in bytes and including the terminator,
+    /// along with the policy for lines that exceed it.
+    ///
+    /// With `LongLinePolicy::Skip`, oversized lines are silently
+    /// discarded: they are never reported (matching or not), but line
+    /// numbers and byte offsets of the lines around them stay correct.
+    /// With `LongLinePolicy::Error`, the search aborts with an error that
+    /// names the line number (when line counting is enabled) and the byte
+    /// offset of the offending line. Either way, a line too long to
+    /// accumulate within the limit is discarded to its next terminator
+    /// without ever being buffered whole; such discarded bytes bypass
+    /// transcoding and binary detection.
+    ///
+    /// The default is None, which corresponds to no limit.
+    #[allow(dead_code)]
+    pub fn max_line_length(
+        mut self,
+        limit: Option<(usize, LongLinePolicy)>,
+    ) -> Self {
+        self.opts.max_line_len = limit;
+        self.inp.max_line_len(limit);
+        self
+    }
+
+    /// Report at most one match per stride of `n` physical lines.
+    ///
+    /// After a match is reported, further matching lines are suppressed
+    /// until `n` lines have passed. Suppressed matches still count towards
+    /// totals, so counts and max-count termination are unaffected. This is
+    /// useful for estimating match density over very large inputs without
+    /// paying for every match event. Sampling is ignored for inverted
+    /// searches and searches with contexts.
+    #[allow(dead_code)]
+    pub fn sample_every_lines(mut self, n: Option<u64>) -> Self {
+        self.opts.sample_lines = n;
+        self
+    }
+
+    /// Report at most one match per stride of `n` bytes.
+    ///
+    /// The stride is measured from the start of the last reported matching
+    /// line, in absolute input offsets. Otherwise this behaves like
+    /// `sample_every_lines`.
+    #[allow(dead_code)]
+    pub fn sample_every_bytes(mut self, n: Option<u64>) -> Self {
+        self.opts.sample_bytes = n;
+        self
+    }
+
+    /// Examine each line with probability `p`, skipping the matcher
+    /// entirely for unexamined lines.
+    ///
+    /// The decision is a deterministic function of the seed and the line's
+    /// ordinal, so runs over the same input are reproducible. This mode
+    /// always walks line boundaries one at a time instead of letting the
+    /// matcher skip ahead to candidates, so the selection of examined lines
+    /// is unbiased by their content. The end-of-search summary reports the
+    /// examined-line count, matched-line count and seed, from which callers
+    /// can compute match rate estimates. Contexts are not supported in this
+    /// mode.
+    #[allow(dead_code)]
+    pub fn sample_random(mut self, p: Option<(f64, u64)>) -> Self {
+        self.opts.sample_random =
+            p.map(|(p, seed)| RandomSample::new(p, seed));
+        self
+    }
+
+    /// If enabled, don't show any output and quit searching after the first
+    /// match is found.
+    pub fn quiet(mut self, yes: bool) -> Self {
+        self.opts.quiet = yes;
+        self
+    }
+
+    /// Give up when no match appears within the first `limit` bytes.
+    ///
+    /// The search proceeds normally, but once the absolute offset passes
+    /// the threshold with zero matches delivered, it stops as if the
+    /// input had ended. A match found before the threshold turns the
+    /// limit off for the rest of the input. Useful for quickly
+    /// classifying files by their leading window. The threshold is
+    /// checked at buffer-fill granularity, so a match found in the same
+    /// fill that crosses it is still delivered.
+    #[allow(dead_code)]
+    pub fn quit_after_no_match_within(mut self, limit: Option<u64>) -> Self {
+        self.opts.quit_after_no_match_within = limit;
+        self
+    }
+
+    /// If enabled, search binary files as if they were text.
+    pub fn text(mut self, yes: bool) -> Self {
+        self.opts.text = yes;
+        self.inp.text(yes);
+        self
+    }
+
+    /// Report binary data without giving up on the search.
+    ///
+    /// When set, an occurrence of `byte` no longer aborts the search the
+    /// way binary detection does: the input is searched as if it were
+    /// text, and each filled region containing the byte is reported to
+    /// the sink via `Sink::binary_data` with the absolute offset of its
+    /// first occurrence. Useful for labelling output as binary while
+    /// still delivering its matches.
+    #[allow(dead_code)]
+    pub fn binary_report(mut self, byte: Option<u8>) -> Self {
+        self.opts.binary_report = byte;
+        self.inp.text(byte.is_some());
+        self
+    }
+
+    /// If enabled, the haystack is treated as UTF-16LE encoded text and is
+    /// searched natively, without transcoding.
+    ///
+    /// In this mode, the caller is responsible for supplying a matcher that
+    /// was compiled for UTF-16LE bytes. Lines are terminated by the code unit
+    /// pair `\n\0`, line numbers are computed in code units and binary
+    /// detection is disabled, since NUL bytes are expected in UTF-16 text.
+    #[allow(dead_code)]
+    pub fn utf16le(mut self, yes: bool) -> Self {
+        self.opts.utf16le = yes;
+        self.inp.utf16le(yes);
+        self
+    }
+
+    /// Execute the search over this searcher's reader followed by each
+    /// reader in `more`, in order, as one logical stream.
+    ///
+    /// The line counter and absolute byte offsets carry across segment
+    /// boundaries, so a match in a later segment reports its global
+    /// position -- rotated log sets (`app.log.2`, `app.log.1`,
+    /// `app.log`) being the motivating case. The sink is told where each
+    /// segment begins via `Sink::segment`. By default an unterminated
+    /// line at a segment boundary ends there; see `stitch_segments`.
+    #[allow(dead_code)]
+    pub fn run_readers<I>(mut self, more: I) -> Result<u64, Error>
+    where I: IntoIterator<Item = R> {
+        self.segments = more.into_iter().collect();
+        self.segments.reverse();
+        self.inp.expect_more(
+            self.opts.stitch_segments && !self.segments.is_empty());
+        self.printer.segment(self.path, 0, 0);
+        self.run()
+    }
+
+    /// Execute the search. Results are written to the printer and the total
+    /// number of matches is returned.
+    ///
+    /// Configuration problems surface here as `Error::Config`, before any
+    /// input is read; construction and the builder methods themselves
+    /// never fail. Everything else that can go wrong is an I/O or limit
+    /// error from the search itself.
+    #[inline(never)]
+    pub fn run(mut self) -> Result<u64, Error> {
+        self.check_config()?;
+        self.begin();
+        if self.opts.start_offset > 0 {
+            self.skip_to_start()?;
+        }
+        if self.opts.peek_back.is_some() && self.opts.before_context > 0 {
+            debug!(
+                "{}: input is not seekable; before-context is truncated at \
+                 the range start", self.path.display());
+        }
+        let mut eof = false;
+        while !self.terminate() {
             let upto = self.inp.lastnl;
             self.print_after_context(upto);
-            if !self.fill()? {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => {
+                    eof = self.inp.binary_at.is_none()
+                        && !self.byte_budget_done
+                        && !self.give_up();
+                    break;
+                }
+                Err(err) => {
+                    if !self.opts.best_effort || err.is_cancelled() {
+                        self.record_checkpoint(false);
+                        return Err(err);
+                    }
+                    // A plain reader can't skip past the failing region,
+                    // so report the error and give up on the rest of the
+                    // stream.
+                    self.report_read_error(&err);
+                    break;
+                }
+            }
+            self.search_lines();
+        }
+        if self.record_oriented() {
+            self.flush_paragraph();
+        }
+        if self.inp.last_line_unterminated() {
+            self.printer.unterminated(self.path);
+        }
+        self.drain_after_context()?;
+        self.record_checkpoint(eof);
+        Ok(self.finish())
+    }
+
+    /// Discard input up to the configured starting offset. A plain reader
+    /// can't seek, so the skipped bytes are read and thrown away;
+    /// `run_seekable` seeks instead. Skipping stops quietly at EOF.
+    fn skip_to_start(&mut self) -> Result<(), Error> {
+        let mut remaining = self.opts.start_offset;
+        let cap = cmp::min(remaining, READ_SIZE as u64) as usize;
+        let mut scratch = vec![0; cap];
+        while remaining > 0 {
+            let cap = cmp::min(remaining, scratch.len() as u64) as usize;
+            let n = self.haystack.read(&mut scratch[..cap])
+                .map_err(|err| Error::from_io(err, self.path))?;
+            if n == 0 {
                 break;
             }
-            while !self.terminate() && self.inp.pos < self.inp.lastnl {
-                let matched = self.grep.read_match(
-                    &mut self.last_match,
-                    &self.inp.buf[..self.inp.lastnl],
-                    self.inp.pos);
-                if self.opts.invert_match {
-                    let upto =
-                        if matched {
-                            self.last_match.start()
-                        } else {
-                            self.inp.lastnl
-                        };
-                    if upto > self.inp.pos {
-                        let upto_context = self.inp.pos;
-                        self.print_after_context(upto_context);
-                        self.print_before_context(upto_context);
-                        self.print_inverted_matches(upto);
+            remaining -= n as u64;
+        }
+        self.note_skipped(self.opts.start_offset - remaining);
+        Ok(())
+    }
+
+    /// Record that the search starts `skipped` bytes into the input, so
+    /// byte offsets and exclusion ranges stay absolute. Line numbers
+    /// restart at the skip point.
+    fn note_skipped(&mut self, skipped: u64) {
+        self.buf_offset = skipped;
+        self.inp.read_offset = skipped;
+        if let Some(ref mut off) = self.byte_offset {
+            *off = skipped;
+        }
+    }
+
+    /// Search for the existence of a match and return as soon as one is
+    /// found, without delivering anything to the sink. Line counting,
+    /// contexts and all reporting are skipped, and the matcher only has
+    /// to establish that some match exists rather than locate its
+    /// boundaries, so a hit near the start of a huge input returns almost
+    /// immediately. Inverted matching and the other line selection
+    /// refinements are ignored; for those, use `run` with `quiet`, which
+    /// also stops at the first reportable match.
+    #[allow(dead_code)]
+    pub fn has_match(mut self) -> Result<bool, Error> {
+        self.begin();
+        loop {
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => return Ok(false),
+                Err(err) => {
+                    if !self.opts.best_effort || err.is_cancelled() {
+                        return Err(err);
                     }
-                } else if matched {
-                    let start = self.last_match.start();
-                    let end = self.last_match.end();
-                    self.print_after_context(start);
-                    self.print_before_context(start);
-                    self.print_match(start, end);
-                }
-                if matched {
-                    self.inp.pos = self.last_match.end();
-                } else {
-                    self.inp.pos = self.inp.lastnl;
+                    // A plain reader can't skip past the failing region,
+                    // so the rest of the stream is unknowable.
+                    self.report_read_error(&err);
+                    return Ok(false);
                 }
             }
+            if self.grep.is_match(&self.inp.buf[self.inp.pos..self.inp.lastnl])
+            {
+                return Ok(true);
+            }
+            self.inp.pos = self.inp.lastnl;
         }
-        if self.after_context_remaining > 0 {
-            if self.last_printed == self.inp.lastnl {
-                self.fill()?;
+    }
+
+    /// Print any after-context still owed once the main loop is done,
+    /// filling as many times as needed. When a match limit stops the
+    /// search early, the context owed to the final match may not be
+    /// buffered yet, and with small buffers it can span several fills. In
+    /// best-effort mode, a read error here is reported and swallowed like
+    /// any other.
+    fn drain_after_context(&mut self) -> Result<(), Error> {
+        while self.after_context_remaining > 0 {
+            if self.last_printed < self.inp.lastnl {
+                let upto = self.inp.lastnl;
+                self.print_after_context(upto);
+                continue;
             }
+            // A fill can succeed without reading anything when rolled-over
+            // context lines already provide a complete line, so watch the
+            // read offset to tell actual progress from a no-op.
+            let offset = self.inp.read_offset;
+            let more = match self.fill() {
+                Ok(more) => more,
+                Err(err) => {
+                    if !self.opts.best_effort || err.is_cancelled() {
+                        return Err(err);
+                    }
+                    self.report_read_error(&err);
+                    false
+                }
+            };
             let upto = self.inp.lastnl;
             if upto > 0 {
                 self.print_after_context(upto);
             }
-        }
-        if self.match_line_count > 0 {
-            if self.opts.count {
-                self.printer.path_count(self.path, self.match_line_count);
-            } else if self.opts.count_matches {
-                self.printer.path_count(self.path, self.match_count.unwrap());
-            } else if self.opts.files_with_matches {
-                self.printer.path(self.path);
+            if !more
+                || (self.inp.read_offset == offset
+                    && self.last_printed == self.inp.lastnl) {
+                break;
             }
-        } else if self.opts.files_without_matches {
-            self.printer.path(self.path);
         }
-        Ok(self.match_line_count)
+        Ok(())
     }
 
-    #[inline(always)]
-    fn terminate(&self) -> bool {
-        self.opts.terminate(self.match_line_count)
+    /// Report a skipped read error to the sink, attributed to the absolute
+    /// offset of the read that failed.
+    fn report_read_error(&mut self, err: &Error) {
+        self.skipped_errors += 1;
+        if let Error::Io { ref err, .. } = *err {
+            self.printer.read_error(self.path, self.inp.read_offset, err);
+        }
     }
 
-    #[inline(always)]
-    fn fill(&mut self) -> Result<bool, Error> {
-        let keep =
-            if self.opts.before_context > 0 || self.opts.after_context > 0 {
-                let lines = 1 + cmp::max(
-                    self.opts.before_context, self.opts.after_context);
-                start_of_previous_lines(
-                    self.opts.eol,
-                    &self.inp.buf,
-                    self.inp.lastnl.saturating_sub(1),
-                    lines)
-            } else {
-                self.inp.lastnl
-            };
-        if keep < self.last_printed {
-            self.last_printed -= keep;
-        } else {
-            self.last_printed = 0;
+    /// Poll the cancellation flag, if any. On cancellation, the sink is
+    /// notified so partial results are clearly marked, and the search
+    /// fails with a `Cancelled` error.
+    fn check_cancel(&mut self) -> Result<(), Error> {
+        let cancelled = self.cancel.as_ref()
+            .is_some_and(|flag| flag.load(Ordering::Relaxed));
+        if cancelled {
+            self.printer.cancelled(self.path);
+            return Err(Error::Cancelled { path: self.path.to_path_buf() });
         }
-        if keep <= self.last_line {
-            self.last_line -= keep;
-        } else {
-            self.count_lines(keep);
-            self.last_line = 0;
-        }
-        self.count_byte_offset(keep);
-        let ok = self.inp.fill(&mut self.haystack, keep).map_err(|err| {
-            Error::from_io(err, &self.path)
-        })?;
-        Ok(ok)
+        Ok(())
     }
 
-    #[inline(always)]
-    fn print_inverted_matches(&mut self, upto: usize) {
-        debug_assert!(self.opts.invert_match);
-        let mut it = IterLines::new(self.opts.eol, self.inp.pos);
-        while let Some((start, end)) = it.next(&self.inp.buf[..upto]) {
-            if self.terminate() {
-                return;
+    /// Convert this searcher into a push-based feeder.
+    ///
+    /// The haystack given to this searcher is never read from; instead, the
+    /// caller feeds chunks of it into the feeder as they become available.
+    ///
+    /// Configuration problems surface here as in `run`, before the first
+    /// chunk is accepted.
+    #[allow(dead_code)]
+    pub fn feeder(mut self) -> Result<Feeder<'a, R, S, M>, Error> {
+        self.check_config()?;
+        self.begin();
+        Ok(Feeder { searcher: self, done: false })
+    }
+
+    /// Reject option combinations that cannot possibly work before any
+    /// input is read. Currently this covers a heap limit too small to
+    /// hold even minimal lines for the configured before-context, which
+    /// is retained in the buffer across rolls.
+    fn check_config(&self) -> Result<(), Error> {
+        if let Some(eol) = self.grep.line_terminator() {
+            if eol != self.opts.eol {
+                return Err(Error::Config {
+                    path: self.path.to_path_buf(),
+                    msg: format!(
+                        "the matcher was built for line terminator 0x{:02x} \
+                         but the searcher is configured with 0x{:02x}",
+                        eol, self.opts.eol),
+                });
             }
-            self.print_match(start, end);
-            self.inp.pos = end;
         }
+        let limit = match self.opts.heap_limit {
+            None => return Ok(()),
+            Some(limit) => limit,
+        };
+        // Every retained before-context line needs at least one byte plus
+        // a terminator, and the line being searched needs the same.
+        // After-context lines are printed as they are scanned and never
+        // retained, so they add nothing to the bound.
+        let needed = 2 * (1 + self.opts.before_context);
+        if limit < needed {
+            return Err(Error::HeapLimitTooSmall {
+                path: self.path.to_path_buf(),
+                required: needed,
+                given: limit,
+            });
+        }
+        Ok(())
     }
 
-    #[inline(always)]
-    fn print_before_context(&mut self, upto: usize) {
-        if self.opts.skip_matches() || self.opts.before_context == 0 {
-            return;
-        }
-        let start = self.last_printed;
-        let end = upto;
-        if start >= end {
-            return;
+    /// Reset all search state in preparation for a new search.
+    fn begin(&mut self) {
+        self.inp.reset();
+        self.match_line_count = 0;
+        if self.opts.line_number && !self.printer.needs_line_numbers() {
+            // The sink declared it never reads line numbers, so don't pay
+            // for counting them.
+            self.opts.line_number = false;
         }
-        let before_context_start =
-            start + start_of_previous_lines(
-                self.opts.eol,
-                &self.inp.buf[start..],
-                end - start - 1,
-                self.opts.before_context);
-        let mut it = IterLines::new(self.opts.eol, before_context_start);
-        while let Some((s, e)) = it.next(&self.inp.buf[..end]) {
-            self.print_separator(s);
-            self.print_context(s, e);
+        self.line_count = if self.opts.line_number
+            || self.opts.sample_lines.is_some()
+            || self.checkpoint.is_some()
+            {
+                Some(self.opts.line_number_start - 1)
+            } else {
+                None
+            };
+        self.byte_offset = if self.opts.byte_offset { Some(0) } else { None };
+        self.match_count = if self.opts.count_matches { Some(0) } else { None };
+        self.last_match = Match::default();
+        self.after_context_remaining = 0;
+        self.context_budget =
+            self.opts.context_byte_limit.unwrap_or(usize::MAX);
+        self.buf_offset = 0;
+        self.next_sample_line = 0;
+        self.next_sample_byte = 0;
+        self.detect_pending = self.opts.detect_terminator;
+        if self.opts.passthru {
+            // Passthru subsumes contexts: every line is delivered anyway,
+            // so neutralize them rather than track separators for output
+            // that can never appear.
+            self.opts.before_context = 0;
+            self.opts.after_context = 0;
         }
+        self.lines_seen = 0;
+        self.sampled_lines = 0;
+        self.skipped_errors = 0;
+        self.byte_budget_done = false;
+        self.nonmatch_done = false;
+        self.para_buf.clear();
+        self.para_first_line = None;
+        self.para_first_offset = 0;
+        self.para_before.clear();
+        self.para_after_remaining = 0;
+        self.para_gap = false;
     }
 
-    #[inline(always)]
-    fn print_after_context(&mut self, upto: usize) {
-        if self.opts.skip_matches() || self.after_context_remaining == 0 {
-            return;
-        }
-        let start = self.last_printed;
-        let end = upto;
-        let mut it = IterLines::new(self.opts.eol, start);
-        while let Some((s, e)) = it.next(&self.inp.buf[..end]) {
-            self.print_context(s, e);
-            self.after_context_remaining -= 1;
-            if self.after_context_remaining == 0 {
-                break;
+    /// Search all complete lines that are currently buffered.
+    /// The fixed-size analog of `search_lines`: carve the buffered
+    /// region into records of exactly `record_size` bytes, flushing each
+    /// complete one. Records may span read boundaries; the partial
+    /// record rides in the side buffer and a short final record is
+    /// flushed at EOF. Record ordinals stand in for line numbers.
+    fn search_fixed_records(&mut self) {
+        let size = self.opts.record_size.unwrap();
+        let lastnl = self.inp.lastnl;
+        let mut pos = self.inp.pos;
+        while !self.terminate() && pos < lastnl {
+            let take = cmp::min(size - self.para_buf.len(), lastnl - pos);
+            self.carry_record_bytes(pos, pos + take);
+            pos += take;
+            if self.para_buf.len() == size {
+                self.flush_paragraph();
+                if let Some(ref mut line_count) = self.line_count {
+                    *line_count += 1;
+                }
             }
         }
+        self.inp.pos = lastnl;
+        self.last_line = lastnl;
     }
 
-    #[inline(always)]
-    fn print_match(&mut self, start: usize, end: usize) {
-        self.match_line_count += 1;
-        self.count_individual_matches(start, end);
-        if self.opts.skip_matches() {
-            return;
+    /// The record-mode analog of `search_lines`: split the buffered
+    /// region on the configured multi-byte terminator, flushing each
+    /// complete record through the same machinery as paragraph mode. The
+    /// unterminated tail is carried in the record's side buffer, so both
+    /// records and terminators may span read boundaries.
+    fn search_records(&mut self) {
+        let sep = self.opts.record_sep.clone().unwrap();
+        let lastnl = self.inp.lastnl;
+        let mut pos = self.inp.pos;
+        // A terminator may straddle the carried tail and this region, in
+        // which case neither side contains a complete occurrence. Check a
+        // window of `sep.len() - 1` bytes on either side of the junction.
+        if !self.para_buf.is_empty() && !self.terminate() {
+            let tail = cmp::min(sep.len() - 1, self.para_buf.len());
+            let head = cmp::min(sep.len() - 1, lastnl - pos);
+            let mut window = Vec::with_capacity(tail + head);
+            window.extend_from_slice(
+                &self.para_buf[self.para_buf.len() - tail..]);
+            window.extend_from_slice(&self.inp.buf[pos..pos + head]);
+            if let Some(i) = find_bytes(&window, &sep) {
+                if i < tail {
+                    let keep = self.para_buf.len() - (tail - i);
+                    self.para_buf.truncate(keep);
+                    self.flush_paragraph();
+                    pos += i + sep.len() - tail;
+                }
+            }
         }
-        self.print_separator(start);
-        self.count_lines(start);
-        self.add_line(end);
-        self.printer.matched(
-            self.grep.regex(), self.path,
-            &self.inp.buf, start, end, self.line_count, self.byte_offset);
-        self.last_printed = end;
-        self.after_context_remaining = self.opts.after_context;
+        while !self.terminate() {
+            let i = match find_bytes(&self.inp.buf[pos..lastnl], &sep) {
+                Some(i) => i,
+                None => break,
+            };
+            self.carry_record_bytes(pos, pos + i);
+            self.flush_paragraph();
+            pos += i + sep.len();
+        }
+        if pos < lastnl {
+            self.carry_record_bytes(pos, lastnl);
+        }
+        self.inp.pos = lastnl;
     }
 
-    #[inline(always)]
-    fn print_context(&mut self, start: usize, end: usize) {
-        self.count_lines(start);
-        self.add_line(end);
-        self.printer.context(
-            &self.path, &self.inp.buf, start, end,
-            self.line_count, self.byte_offset);
-        self.last_printed = end;
+    /// Append `buf[start..end]` to the pending record, noting the
+    /// record's first line and byte offset if it was empty. The line
+    /// count is brought up to the record's start first so that the
+    /// watermark in `last_line` stays ahead of the rollover logic.
+    fn carry_record_bytes(&mut self, start: usize, end: usize) {
+        if self.para_buf.is_empty() {
+            if self.opts.record_size.is_none() {
+                self.count_lines(start);
+            }
+            self.para_first_line = self.line_count.map(|n| n + 1);
+            self.para_first_offset = self.buf_offset + start as u64;
+        }
+        self.para_buf.extend_from_slice(&self.inp.buf[start..end]);
     }
 
-    #[inline(always)]
-    fn print_separator(&mut self, before: usize) {
-        if self.opts.before_context == 0 && self.opts.after_context == 0 {
-            return;
+    /// The paragraph-mode analog of `search_lines`: walk the complete
+    /// lines in the buffer, folding consecutive non-empty lines into the
+    /// current record and flushing it when a blank line ends it. Records
+    /// can span buffer fills, so their bytes are carried in a side buffer;
+    /// the input buffer itself still rolls at line boundaries.
+    fn search_paragraphs(&mut self) {
+        let lastnl = self.inp.lastnl;
+        let mut it = IterLines::new(self.opts.eol, self.inp.pos)
+            .utf16le(self.opts.utf16le);
+        while !self.terminate() {
+            let (start, end) = match it.next(&self.inp.buf[..lastnl]) {
+                Some(range) => range,
+                None => break,
+            };
+            if is_empty_line(
+                &self.inp.buf[start..end],
+                self.opts.eol,
+                self.opts.utf16le) {
+                self.flush_paragraph();
+            } else {
+                if self.para_buf.is_empty() {
+                    self.para_first_line = self.line_count.map(|n| n + 1);
+                    self.para_first_offset =
+                        self.buf_offset + start as u64;
+                }
+                self.para_buf.extend_from_slice(&self.inp.buf[start..end]);
+            }
+            // `add_line` also advances the `last_line` watermark, so the
+            // rollover logic doesn't count these lines a second time.
+            self.add_line(end);
+            self.inp.pos = end;
         }
-        if !self.printer.has_printed() {
+        self.inp.pos = lastnl;
+    }
+
+    /// Deliver the buffered record, if any. Called when a blank line ends
+    /// the record and once more at the end of the search for a final
+    /// record without a trailing blank line.
+    fn flush_paragraph(&mut self) {
+        if self.para_buf.is_empty() {
             return;
         }
-        if (self.last_printed == 0 && before > 0)
-            || self.last_printed < before {
-            self.printer.context_separate();
+        let matched = self.grep.is_match(&self.para_buf)
+            && (!self.opts.anchor_line_start
+                || is_anchored_match(self.grep.regex(), &self.para_buf));
+        if matched != self.opts.invert_match {
+            self.match_line_count += 1;
+            if let Some(ref mut count) = self.match_count {
+                match self.grep.regex() {
+                    Some(re) => {
+                        for _ in re.find_iter(&self.para_buf) {
+                            *count += 1;
+                        }
+                    }
+                    None => *count += 1,
+                }
+            }
+            if !self.opts.skip_matches() {
+                self.context_budget =
+                    self.opts.context_byte_limit.unwrap_or(usize::MAX);
+                self.print_paragraph_match();
+            }
+            self.para_after_remaining = self.opts.after_context;
+        } else if !self.opts.skip_matches() {
+            if self.para_after_remaining > 0 {
+                self.para_after_remaining -= 1;
+                let line_number = self.reported_para_line();
+                let byte_offset = self.reported_para_offset();
+                let allowed = self.context_allowance(self.para_buf.len());
+                if allowed > 0 {
+                    self.printer.context(
+                        self.path, &self.para_buf, 0, allowed,
+                        line_number, byte_offset);
+                    if allowed < self.para_buf.len() {
+                        self.printer.context_truncated(self.path);
+                    }
+                }
+            } else if self.opts.before_context > 0 {
+                let record = (
+                    self.para_buf.clone(),
+                    self.para_first_line,
+                    self.para_first_offset,
+                );
+                self.para_before.push_back(record);
+                if self.para_before.len() > self.opts.before_context {
+                    self.para_before.pop_front();
+                    self.para_gap = true;
+                }
+            } else {
+                self.para_gap = true;
+            }
         }
+        self.para_buf.clear();
+        self.para_first_line = None;
     }
 
-    #[inline(always)]
-    fn count_byte_offset(&mut self, buf_last_end: usize) {
-        if let Some(ref mut byte_offset) = self.byte_offset {
-            *byte_offset += buf_last_end as u64;
+    /// Print a matching record, preceded by any queued before-context
+    /// records and a separator when records were skipped in between.
+    fn print_paragraph_match(&mut self) {
+        if (self.opts.before_context > 0 || self.opts.after_context > 0)
+            && self.para_gap && self.printer.has_printed() {
+            self.printer.context_separate();
+        }
+        self.para_gap = false;
+        while let Some((buf, first_line, offset)) =
+            self.para_before.pop_front() {
+            let line_number =
+                if self.opts.line_number { first_line } else { None };
+            let byte_offset =
+                if self.opts.byte_offset { Some(offset) } else { None };
+            let allowed = self.context_allowance(buf.len());
+            if allowed > 0 {
+                self.printer.context(
+                    self.path, &buf, 0, allowed, line_number, byte_offset);
+                if allowed < buf.len() {
+                    self.printer.context_truncated(self.path);
+                }
+            }
+        }
+        let indent = self.opts.report_indent.map(|tab_stop| {
+            indent_of(&self.para_buf, tab_stop, self.opts.utf16le)
+        });
+        if self.opts.only_matching && self.grep.regex().is_some() {
+            self.print_paragraph_submatches(indent);
+            return;
         }
+        let line_number = self.reported_para_line();
+        let byte_offset = self.reported_para_offset();
+        let column =
+            if self.opts.column {
+                Some(column_of(self.grep.regex(), &self.para_buf))
+            } else {
+                None
+            };
+        self.printer.matched_full(self.path, &SinkMatch {
+            re: self.grep.regex(),
+            buf: &self.para_buf,
+            start: 0,
+            end: self.para_buf.len(),
+            offset: self.para_first_offset,
+            line_number,
+            byte_offset,
+            column,
+            indent,
+            match_range: None,
+        });
     }
 
-    #[inline(always)]
-    fn count_individual_matches(&mut self, start: usize, end: usize) {
-        if let Some(ref mut count) = self.match_count {
-            for _ in self.grep.regex().find_iter(&self.inp.buf[start..end]) {
-                *count += 1;
-            }
+    /// The only-matching analog of the record report. The record was
+    /// searched as a whole, so a match may span lines; its line number
+    /// and column refer to the line it starts on.
+    fn print_paragraph_submatches(&mut self, indent: Option<Indent>) {
+        let re = self.grep.regex().unwrap();
+        for m in re.find_iter(&self.para_buf) {
+            let line_number =
+                if self.opts.line_number {
+                    self.para_first_line.map(|first| {
+                        first + count_lines(
+                            &self.para_buf[..m.start()], self.opts.eol)
+                    })
+                } else {
+                    None
+                };
+            let byte_offset =
+                if self.opts.byte_offset {
+                    Some(self.para_first_offset)
+                } else {
+                    None
+                };
+            let column =
+                if self.opts.column {
+                    let line_start =
+                        memrchr(self.opts.eol, &self.para_buf[..m.start()])
+                            .map_or(0, |i| i + 1);
+                    Some((m.start() - line_start) as u64 + 1)
+                } else {
+                    None
+                };
+            self.printer.matched_full(self.path, &SinkMatch {
+                re: Some(re),
+                buf: &self.para_buf,
+                start: m.start(),
+                end: m.end(),
+                offset: self.para_first_offset + m.start() as u64,
+                line_number,
+                byte_offset,
+                column,
+                indent,
+                match_range: Some((0, m.end() - m.start())),
+            });
         }
     }
 
     #[inline(always)]
-    fn count_lines(&mut self, upto: usize) {
-        if let Some(ref mut line_count) = self.line_count {
-            *line_count += count_lines(
-                &self.inp.buf[self.last_line..upto], self.opts.eol);
-            self.last_line = upto;
+    fn reported_para_line(&self) -> Option<u64> {
+        if self.opts.line_number {
+            self.para_first_line
+        } else {
+            None
         }
     }
 
     #[inline(always)]
-    fn add_line(&mut self, line_end: usize) {
-        if let Some(ref mut line_count) = self.line_count {
-            *line_count += 1;
-            self.last_line = line_end;
+    fn reported_para_offset(&self) -> Option<u64> {
+        if self.opts.byte_offset {
+            Some(self.para_first_offset)
+        } else {
+            None
         }
     }
-}
 
-/// `InputBuffer` encapsulates the logic of maintaining a ~fixed sized buffer
-/// on which to search. There are three key pieces of complexity:
-///
-/// 1. We must be able to handle lines that are longer than the size of the
-///    buffer. For this reason, the buffer is allowed to expand (and is
-///    therefore not technically fixed). Note that once a buffer expands, it
-///    will never contract.
-/// 2. The contents of the buffer may end with a partial line, so we must keep
-///    track of where the last complete line ends. Namely, the partial line
-///    is only completed on subsequent reads *after* searching up through
-///    the last complete line is done.
-/// 3. When printing the context of a match, the last N lines of the buffer
-///    may need to be rolled over into the next buffer. For example, a match
-///    may occur at the beginning of a buffer, in which case, lines at the end
-///    of the previous contents of the buffer need to be printed.
-///
-/// An `InputBuffer` is designed to be reused and isn't tied to any particular
-/// reader.
-pub struct InputBuffer {
-    /// The number of bytes to attempt to read at a time. Once set, this is
-    /// never changed.
-    read_size: usize,
-    /// The end-of-line terminator used in this buffer.
-    eol: u8,
-    /// A scratch buffer.
-    tmp: Vec<u8>,
-    /// A buffer to read bytes into. All searches are executed directly against
-    /// this buffer and pos/lastnl/end point into it.
-    buf: Vec<u8>,
-    /// The current position in buf. The current position represents where the
-    /// next search should start.
-    pos: usize,
-    /// The position immediately following the last line terminator in buf.
-    /// This may be equal to end.
-    ///
-    /// Searching should never cross this boundary. In particular, the contents
-    /// of the buffer following this position may correspond to *partial* line.
-    /// All contents before this position are complete lines.
-    lastnl: usize,
-    /// The end position of the buffer. Data after this position is not
-    /// specified.
-    end: usize,
-    /// Set to true if and only if no reads have occurred yet.
-    first: bool,
-    /// Set to true if all binary data should be treated as if it were text.
-    text: bool,
-}
-
-impl InputBuffer {
-    /// Create a new buffer with a default capacity.
-    pub fn new() -> InputBuffer {
-        InputBuffer::with_capacity(READ_SIZE)
-    }
-
-    /// Create a new buffer with the capacity given.
-    ///
-    /// The capacity determines the size of each read from the underlying
-    /// reader.
-    ///
-    /// `cap` must be a minimum of `1`.
-    pub fn with_capacity(mut cap: usize) -> InputBuffer {
-        if cap == 0 {
-            cap = 1;
+    fn search_lines(&mut self) {
+        if self.opts.record_size.is_some() {
+            self.search_fixed_records();
+            return;
         }
-        InputBuffer {
-            read_size: cap,
-            eol: b'\n',
-            buf: vec![0; cap],
-            tmp: vec![],
-            pos: 0,
-            lastnl: 0,
-            end: 0,
-            first: true,
-            text: false,
+        if self.opts.record_sep.is_some() {
+            self.search_records();
+            return;
+        }
+        if self.opts.paragraph {
+            self.search_paragraphs();
+            return;
+        }
+        if self.opts.sample_random.is_some() {
+            self.search_lines_sampled();
+            return;
+        }
+        if self.opts.passthru {
+            self.search_lines_passthru();
+            return;
+        }
+        // With inverted matching and no contexts, we can iterate over lines
+        // directly and test each one, which skips all of the match span
+        // bookkeeping below. This is a nice win in the common case where
+        // most lines do not match. The same applies when contexts are
+        // configured but the output is suppressed (counting, quiet, file
+        // lists): context lines are never printed, so none of the context
+        // bookkeeping below can affect the output.
+        if self.opts.invert_match
+            && ((self.opts.before_context == 0
+                 && self.opts.after_context == 0)
+                || self.opts.skip_matches()) {
+            self.search_lines_inverted();
+            return;
+        }
+        // When the output is suppressed and nothing examines the contents
+        // of matching lines, only the count is needed: ask the matcher for
+        // the end of each matching line via `shortest_match` and skip the
+        // span, context and payload bookkeeping entirely.
+        if self.count_only() {
+            self.search_lines_counting();
+            return;
+        }
+        while !self.terminate() && self.inp.pos < self.inp.lastnl {
+            let matched = self.grep.read_match(
+                &mut self.last_match,
+                &self.inp.buf[..self.inp.lastnl],
+                self.inp.pos);
+            if self.opts.invert_match {
+                let mut stopper = false;
+                let upto =
+                    if matched {
+                        let (start, end) = self.match_range();
+                        // A match that fails the anchor doesn't count, so
+                        // its line is part of the inverted output.
+                        if self.line_anchored(start, end) {
+                            stopper = true;
+                            start
+                        } else {
+                            end
+                        }
+                    } else {
+                        self.inp.lastnl
+                    };
+                if upto > self.inp.pos {
+                    let upto_context = self.inp.pos;
+                    self.print_after_context(upto_context);
+                    self.print_before_context(upto_context);
+                    self.print_inverted_matches(upto);
+                }
+                // With inverted matching, a matching line ends a search
+                // over sorted input once something has been delivered.
+                if stopper && self.opts.stop_on_nonmatch
+                    && self.match_line_count > 0 {
+                    self.nonmatch_done = true;
+                    break;
+                }
+            } else if matched {
+                let (start, end) = self.match_range();
+                let anchored = self.line_anchored(start, end);
+                // On sorted input, a non-matching line after a match ends
+                // the search as if at EOF: any gap before this match, or
+                // a match that fails the anchor, qualifies.
+                if self.opts.stop_on_nonmatch && self.match_line_count > 0
+                    && (start > self.inp.pos || !anchored) {
+                    self.nonmatch_done = true;
+                    break;
+                }
+                if anchored && !self.excluded(start, end) {
+                    self.print_after_context(start);
+                    self.print_before_context(start);
+                    self.print_match(start, end);
+                }
+            } else if self.opts.stop_on_nonmatch
+                && self.match_line_count > 0
+                && self.inp.pos < self.inp.lastnl {
+                // The rest of the region holds only non-matching lines.
+                self.nonmatch_done = true;
+            }
+            if matched {
+                self.inp.pos = self.match_range().1;
+            } else {
+                self.inp.pos = self.inp.lastnl;
+            }
         }
     }
 
-    /// Set the end-of-line terminator used by this input buffer.
-    pub fn eol(&mut self, eol: u8) -> &mut Self {
-        self.eol = eol;
-        self
-    }
-
-    /// If enabled, search binary files as if they were text.
-    ///
-    /// Note that this may cause the buffer to load the entire contents of a
-    /// file into memory.
-    pub fn text(&mut self, yes: bool) -> &mut Self {
-        self.text = yes;
-        self
+    /// True when the search needs only the number of matching lines:
+    /// output is suppressed and no configured option examines the span or
+    /// contents of each matching line.
+    #[inline(always)]
+    fn count_only(&self) -> bool {
+        self.checkpoint.is_none()
+            && self.opts.skip_matches()
+            && !self.opts.invert_match
+            && !self.opts.count_matches
+            && !self.opts.skip_empty_lines
+            && !self.opts.anchor_line_start
+            && !self.opts.utf16le
+            && self.exclusions.is_empty()
+            && !matches!(self.opts.max_line_len,
+                         Some((_, LongLinePolicy::Skip)))
+            && !self.opts.stop_on_nonmatch
     }
 
-    /// Resets this buffer so that it may be reused with a new reader.
-    fn reset(&mut self) {
-        self.pos = 0;
-        self.lastnl = 0;
-        self.end = 0;
-        self.first = true;
+    /// A specialized version of `search_lines` for counting. Each matching
+    /// line contributes only its end offset, so the work of locating line
+    /// starts and assembling match payloads is skipped. This is a sizable
+    /// win on match-dense input.
+    fn search_lines_counting(&mut self) {
+        while !self.terminate() && self.inp.pos < self.inp.lastnl {
+            match self.grep.shortest_match(
+                &self.inp.buf[..self.inp.lastnl], self.inp.pos) {
+                None => self.inp.pos = self.inp.lastnl,
+                Some(end) => {
+                    self.match_line_count += 1;
+                    self.inp.pos = end;
+                }
+            }
+        }
     }
 
-    /// Fill the contents of this buffer with the reader given. The reader
-    /// given should be the same in every call to fill unless reset has been
-    /// called.
-    ///
-    /// The bytes in buf[keep_from..end] are rolled over into the beginning
-    /// of the buffer.
-    fn fill<R: io::Read>(
-        &mut self,
-        rdr: &mut R,
-        keep_from: usize,
-    ) -> Result<bool, io::Error> {
-        // Rollover bytes from buf[keep_from..end] and update our various
-        // pointers. N.B. This could be done with the ptr::copy, but I haven't
-        // been able to produce a benchmark that notices a difference in
-        // performance. (Invariably, ptr::copy is seems clearer IMO, but it is
-        // not safe.)
-        self.tmp.clear();
-        self.tmp.extend_from_slice(&self.buf[keep_from..self.end]);
-        self.buf[0..self.tmp.len()].copy_from_slice(&self.tmp);
-        self.pos = self.lastnl - keep_from;
-        self.lastnl = 0;
-        self.end = self.tmp.len();
-        while self.lastnl == 0 {
-            // If our buffer isn't big enough to hold the contents of a full
-            // read, expand it.
-            if self.buf.len() - self.end < self.read_size {
-                let min_len = self.read_size + self.buf.len() - self.end;
-                let new_len = cmp::max(min_len, self.buf.len() * 2);
-                self.buf.resize(new_len, 0);
+    /// A specialized version of `search_lines` for inverted searching
+    /// when no context lines can be printed. It emits each non-matching
+    /// line directly instead of locating matches first. Its output is
+    /// identical to the general path.
+    fn search_lines_inverted(&mut self) {
+        debug_assert!(self.opts.invert_match);
+        let mut it = IterLines::new(self.opts.eol, self.inp.pos)
+            .utf16le(self.opts.utf16le);
+        while !self.terminate() {
+            let (start, end) =
+                match it.next(&self.inp.buf[..self.inp.lastnl]) {
+                    None => break,
+                    Some(range) => range,
+                };
+            let matched = self.grep.is_match(&self.inp.buf[start..end])
+                && self.line_anchored(start, end);
+            if matched && self.opts.stop_on_nonmatch
+                && self.match_line_count > 0 {
+                self.nonmatch_done = true;
+                return;
             }
-            let n = rdr.read(
-                &mut self.buf[self.end..self.end + self.read_size])?;
-            if !self.text {
-                if is_binary(&self.buf[self.end..self.end + n], self.first) {
-                    return Ok(false);
-                }
+            if !matched && !self.excluded(start, end) {
+                self.print_match(start, end);
             }
-            self.first = false;
-            // We assume that reading 0 bytes means we've hit EOF.
-            if n == 0 {
-                // If we've searched everything up to the end of the buffer,
-                // then there's nothing left to do.
-                if self.end - self.pos == 0 {
-                    return Ok(false);
+            self.inp.pos = end;
+        }
+    }
+
+    /// A specialized version of `search_lines` for random sampling. Every
+    /// line boundary is walked individually so that the selection of
+    /// examined lines can't be biased by the matcher skipping ahead to
+    /// candidates; unexamined lines only pay for line splitting.
+    fn search_lines_sampled(&mut self) {
+        let sample = self.opts.sample_random.unwrap();
+        let mut it = IterLines::new(self.opts.eol, self.inp.pos)
+            .utf16le(self.opts.utf16le);
+        while !self.terminate() {
+            let (start, end) =
+                match it.next(&self.inp.buf[..self.inp.lastnl]) {
+                    None => break,
+                    Some(range) => range,
+                };
+            let ordinal = self.lines_seen;
+            self.lines_seen += 1;
+            if sample.examine(ordinal) && !self.excluded(start, end) {
+                self.sampled_lines += 1;
+                let matched = self.grep.is_match(&self.inp.buf[start..end])
+                    && self.line_anchored(start, end);
+                if matched != self.opts.invert_match {
+                    self.print_match(start, end);
                 }
-                // Even if we hit EOF, we might still have to search the
-                // last line if it didn't contain a trailing terminator.
-                self.lastnl = self.end;
-                break;
             }
-            self.lastnl =
-                memrchr(self.eol, &self.buf[self.end..self.end + n])
-                .map(|i| self.end + i + 1)
-                .unwrap_or(0);
-            self.end += n;
+            self.inp.pos = end;
         }
-        Ok(true)
     }
-}
 
-/// Returns true if and only if the given buffer is determined to be "binary"
-/// or otherwise not contain text data that is usefully searchable.
-///
-/// Note that this may return both false positives and false negatives.
-#[inline(always)]
-pub fn is_binary(buf: &[u8], first: bool) -> bool {
-    if first && buf.len() >= 4 && &buf[0..4] == b"%PDF" {
-        return true;
+    /// A specialized version of `search_lines` for passthru mode, where
+    /// every line is delivered to the sink: matching lines through the
+    /// usual match path, all other lines as context. No separators are
+    /// printed since the output has no gaps.
+    fn search_lines_passthru(&mut self) {
+        let mut it = IterLines::new(self.opts.eol, self.inp.pos)
+            .utf16le(self.opts.utf16le);
+        while !self.terminate() {
+            let (start, end) =
+                match it.next(&self.inp.buf[..self.inp.lastnl]) {
+                    None => break,
+                    Some(range) => range,
+                };
+            let matched = self.grep.is_match(&self.inp.buf[start..end])
+                && self.line_anchored(start, end);
+            if matched != self.opts.invert_match
+                && !self.excluded(start, end) {
+                self.print_match(start, end);
+            } else {
+                self.print_context(start, end);
+            }
+            self.inp.pos = end;
+        }
     }
-    memchr(b'\x00', buf).is_some()
-}
 
-/// Count the number of lines in the given buffer.
-#[inline(never)]
-pub fn count_lines(buf: &[u8], eol: u8) -> u64 {
-    bytecount::count(buf, eol) as u64
-}
+    /// Print the end-of-search summary and return the number of matching
+    /// lines.
+    fn finish(&mut self) -> u64 {
+        self.printer.progress(self.path, self.inp.read_offset);
+        if self.skipped_errors > 0 {
+            debug!(
+                "{}: {} read errors skipped; line numbers are approximate",
+                self.path.display(), self.skipped_errors);
+        }
+        if let Some(sample) = self.opts.sample_random {
+            debug!(
+                "{}: random sample (seed {}): examined {} of {} lines, \
+                 {} matched",
+                self.path.display(), sample.seed, self.sampled_lines,
+                self.lines_seen, self.match_line_count);
+        }
+        debug!(
+            "{}: peak buffer capacity: {} bytes ({} retained, {} scratch)",
+            self.path.display(), self.inp.peak, self.inp.buf.len(),
+            self.inp.tmp.capacity());
+        if self.match_line_count > 0 {
+            if self.opts.count {
+                self.printer.path_count(self.path, self.match_line_count);
+            } else if self.opts.count_matches {
+                self.printer.path_count(self.path, self.match_count.unwrap());
+            } else if self.opts.files_with_matches {
+                self.printer.path(self.path);
+            }
+        } else if self.opts.files_without_matches {
+            self.printer.path(self.path);
+        }
+        self.match_line_count
+    }
 
-/// Replaces a with b in buf.
-#[allow(dead_code)]
-fn replace_buf(buf: &mut [u8], a: u8, b: u8) {
-    if a == b {
-        return;
+    #[inline(always)]
+    fn terminate(&self) -> bool {
+        self.nonmatch_done || self.opts.terminate(self.match_line_count)
     }
-    let mut pos = 0;
-    while let Some(i) = memchr(a, &buf[pos..]).map(|i| pos + i) {
-        buf[i] = b;
-        pos = i + 1;
-        while buf.get(pos) == Some(&a) {
-            buf[pos] = b;
-            pos += 1;
+
+    /// Returns the range of the last match, snapped to code unit boundaries
+    /// when searching UTF-16LE text.
+    ///
+    /// The matcher itself works on bytes, so the line boundaries it reports
+    /// may point at the trailing NUL of a terminator pair. Rounding them up
+    /// to even offsets keeps all other positions aligned on code units.
+    #[inline(always)]
+    fn match_range(&self) -> (usize, usize) {
+        let (mut start, mut end) =
+            (self.last_match.start(), self.last_match.end());
+        if self.opts.utf16le {
+            start += start & 1;
+            end = cmp::min(end + (end & 1), self.inp.lastnl);
         }
+        (start, end)
     }
-}
 
-/// An "iterator" over lines in a particular buffer.
-///
-/// Idiomatic Rust would borrow the buffer and use it as internal state to
-/// advance over the positions of each line. We neglect that approach to avoid
-/// the borrow in the search code. (Because the borrow prevents composition
-/// through other mutable methods.)
-pub struct IterLines {
-    eol: u8,
-    pos: usize,
-}
+    /// Returns true if the given matching line satisfies the line-start
+    /// anchor. Always true when anchoring is disabled.
+    #[inline(always)]
+    fn line_anchored(&self, start: usize, end: usize) -> bool {
+        !self.opts.anchor_line_start
+            || is_anchored_match(self.grep.regex(), &self.inp.buf[start..end])
+    }
 
-impl IterLines {
-    /// Creates a new iterator over lines starting at the position given.
-    ///
-    /// The buffer is passed to the `next` method.
+    /// Returns true if the given buffer-relative line range overlaps a
+    /// caller-specified exclusion range.
     #[inline(always)]
-    pub fn new(eol: u8, start: usize) -> IterLines {
-        IterLines {
-            eol: eol,
-            pos: start,
+    fn excluded(&self, start: usize, end: usize) -> bool {
+        if self.exclusions.is_empty() {
+            return false;
         }
+        let s = self.buf_offset + start as u64;
+        let e = self.buf_offset + end as u64;
+        let i = self.exclusions.partition_point(|&(_, re)| re <= s);
+        self.exclusions.get(i).is_some_and(|&(rs, _)| rs < e)
     }
 
-    /// Return the start and end position of the next line in the buffer. The
-    /// buffer given should be the same on every call.
-    ///
-    /// The range returned includes the new line.
+    /// The line number to attach to printed output, if line numbers were
+    /// requested. Line counting may also be enabled internally for
+    /// sampling, in which case the count isn't reported.
     #[inline(always)]
-    pub fn next(&mut self, buf: &[u8]) -> Option<(usize, usize)> {
-        match memchr(self.eol, &buf[self.pos..]) {
-            None => {
-                if self.pos < buf.len() {
-                    let start = self.pos;
-                    self.pos = buf.len();
-                    Some((start, buf.len()))
-                } else {
-                    None
-                }
-            }
-            Some(end) => {
-                let start = self.pos;
-                let end = self.pos + end + 1;
-                self.pos = end;
-                Some((start, end))
-            }
+    fn printed_line_number(&self) -> Option<u64> {
+        if self.opts.line_number {
+            self.line_count
+        } else {
+            None
         }
     }
-}
 
-/// Returns the starting index of the Nth line preceding `end`.
-///
-/// If `buf` is empty, then `0` is returned. If `count` is `0`, then `end` is
-/// returned.
-///
-/// If `end` points at a new line in `buf`, then searching starts as if `end`
-/// pointed immediately before the new line.
-///
-/// The position returned corresponds to the first byte in the given line.
-#[inline(always)]
-fn start_of_previous_lines(
-    eol: u8,
-    buf: &[u8],
-    mut end: usize,
-    mut count: usize,
-) -> usize {
-    // TODO(burntsushi): This function needs to be badly simplified. The case
-    // analysis is impossible to follow.
-    if buf[..end].is_empty() {
-        return 0;
+    /// Returns true if the match starting at the given position should be
+    /// suppressed by sampling. A reported match advances the stride.
+    ///
+    /// `count_lines` must have been called for `start` already, so that the
+    /// current line count is up to date. Inverted searches and searches
+    /// with contexts are never sampled.
+    #[inline(always)]
+    fn sample_suppressed(&mut self, start: usize) -> bool {
+        if self.opts.sample_lines.is_none()
+            && self.opts.sample_bytes.is_none() {
+            return false;
+        }
+        if self.opts.invert_match
+            || self.opts.before_context > 0
+            || self.opts.after_context > 0 {
+            return false;
+        }
+        let abs = self.buf_offset + start as u64;
+        if self.opts.sample_bytes.is_some() && abs < self.next_sample_byte {
+            return true;
+        }
+        let line = self.line_count.map_or(0, |c| c + 1);
+        if self.opts.sample_lines.is_some() && line < self.next_sample_line {
+            return true;
+        }
+        if let Some(n) = self.opts.sample_bytes {
+            self.next_sample_byte = abs + n;
+        }
+        if let Some(n) = self.opts.sample_lines {
+            self.next_sample_line = line + n;
+        }
+        false
     }
-    if count == 0 {
-        return end;
+
+    #[inline(always)]
+    fn fill(&mut self) -> Result<bool, Error> {
+        self.check_cancel()?;
+        if self.byte_budget_done || self.give_up() {
+            return Ok(false);
+        }
+        let keep = self.keep_from();
+        self.roll_counters(keep);
+        let ok = self.inp.fill(&mut self.haystack, keep).map_err(|err| {
+            Error::from_io(err, self.path)
+        })?;
+        if let Some(offset) = self.inp.heap_limit_at {
+            let pos = self.inp.pos;
+            self.count_lines(pos);
+            return Err(Error::HeapLimit {
+                path: self.path.to_path_buf(),
+                line_number: self.line_count.map(|n| n + 1),
+                offset,
+                limit: self.inp.heap_limit.unwrap_or(0),
+            });
+        }
+        if !ok && self.inp.binary_at.is_none() && self.next_segment() {
+            return self.fill();
+        }
+        if ok {
+            self.maybe_detect_terminator();
+            self.clamp_to_byte_budget();
+            self.printer.progress(self.path, self.inp.read_offset);
+            if let Some(byte) = self.opts.binary_report {
+                self.report_binary(byte);
+            }
+        }
+        match self.opts.max_line_len {
+            Some((limit, LongLinePolicy::Error)) => {
+                self.check_line_lengths(limit)?;
+            }
+            // Fold lines the buffer discarded into the counters so the
+            // numbering and offsets of surviving lines stay exact.
+            Some((_, LongLinePolicy::Skip))
+                if self.inp.fill_skipped_lines > 0 => {
+                if let Some(ref mut lc) = self.line_count {
+                    *lc += self.inp.fill_skipped_lines;
+                }
+                if let Some(ref mut off) = self.byte_offset {
+                    *off += self.inp.fill_skipped_bytes;
+                }
+                self.buf_offset += self.inp.fill_skipped_bytes;
+            }
+            Some((_, LongLinePolicy::Skip)) => {}
+            None => {}
+        }
+        Ok(ok)
     }
-    if end == buf.len() {
-        end -= 1;
+
+    /// Move on to the next reader segment, if any. Returns true when a
+    /// new segment was installed and filling should be retried.
+    fn next_segment(&mut self) -> bool {
+        let next = match self.segments.pop() {
+            None => return false,
+            Some(rdr) => rdr,
+        };
+        self.haystack = next;
+        self.segment_index += 1;
+        self.inp.expect_more(
+            self.opts.stitch_segments && !self.segments.is_empty());
+        self.printer.segment(
+            self.path, self.segment_index, self.inp.read_offset);
+        true
     }
-    if buf[end] == eol {
-        if end == 0 {
-            return end + 1;
+
+    /// Clamp the searchable region of the buffer to the byte budget. The
+    /// line straddling the boundary is completed, so a partial line is
+    /// never matched against, and once it has been surfaced every further
+    /// fill reports EOF.
+    fn clamp_to_byte_budget(&mut self) {
+        let max = match self.opts.max_bytes {
+            None => return,
+            Some(max) => max,
+        };
+        let limit = self.opts.start_offset.saturating_add(max);
+        if self.buf_offset + (self.inp.lastnl as u64) < limit {
+            return;
         }
-        end -= 1;
+        let from = (limit.saturating_sub(self.buf_offset) as usize)
+            .saturating_sub(1)
+            .max(self.inp.pos);
+        let lastnl = self.inp.lastnl;
+        self.inp.lastnl =
+            if self.opts.utf16le {
+                let mut found = lastnl;
+                let mut i = from;
+                while i + 1 < lastnl {
+                    if self.inp.buf[i] == self.opts.eol
+                        && self.inp.buf[i + 1] == 0
+                        && (self.buf_offset + i as u64).is_multiple_of(2) {
+                        found = i + 2;
+                        break;
+                    }
+                    i += 1;
+                }
+                found
+            } else {
+                memchr(self.opts.eol, &self.inp.buf[from..lastnl])
+                    .map_or(lastnl, |i| from + i + 1)
+            };
+        self.byte_budget_done = true;
     }
-    while count > 0 {
-        if buf[end] == eol {
-            count -= 1;
-            if count == 0 {
-                return end + 1;
+
+    /// Abort with `Error::LineTooLong` if the buffer flagged an oversized
+    /// line or any freshly buffered line exceeds the limit.
+    fn check_line_lengths(&mut self, limit: usize) -> Result<(), Error> {
+        if let Some(offset) = self.inp.long_line_at {
+            let pos = self.inp.pos;
+            return Err(self.line_too_long(pos, offset));
+        }
+        let mut it = IterLines::new(self.opts.eol, self.inp.pos)
+            .utf16le(self.opts.utf16le);
+        while let Some((start, end)) = it.next(&self.inp.buf[..self.inp.lastnl])
+        {
+            if end - start > limit {
+                let offset = self.buf_offset + start as u64;
+                return Err(self.line_too_long(start, offset));
             }
-            if end == 0 {
-                return end;
+        }
+        Ok(())
+    }
+
+    fn line_too_long(&mut self, start: usize, offset: u64) -> Error {
+        self.count_lines(start);
+        Error::LineTooLong {
+            path: self.path.to_path_buf(),
+            line_number: self.line_count.map(|n| n + 1),
+            offset,
+        }
+    }
+
+    /// Report the first occurrence of the binary marker byte in the
+    /// freshly buffered region, if any. The search itself is unaffected.
+    fn report_binary(&mut self, byte: u8) {
+        let region = &self.inp.buf[self.inp.pos..self.inp.lastnl];
+        if let Some(i) = memchr(byte, region) {
+            let offset = self.buf_offset + (self.inp.pos + i) as u64;
+            self.printer.binary_data(self.path, offset);
+        }
+    }
+
+    /// Examine the first chunk of input for the line terminator style, if
+    /// detection was requested and hasn't happened yet.
+    #[inline(always)]
+    fn maybe_detect_terminator(&mut self) {
+        if !self.detect_pending {
+            return;
+        }
+        self.detect_pending = false;
+        let detected = detect_crlf(
+            &self.inp.buf[..self.inp.end], self.opts.eol, self.opts.utf16le);
+        if let Some(crlf) = detected {
+            self.opts.crlf = crlf;
+        }
+    }
+
+    /// Add the chunk given to the input buffer, rolling over as in `fill`.
+    ///
+    /// Returns false if and only if the chunk was detected as binary.
+    #[allow(dead_code)]
+    #[inline(always)]
+    fn push_chunk(&mut self, chunk: &[u8]) -> bool {
+        let keep = self.keep_from();
+        self.roll_counters(keep);
+        let ok = self.inp.push(chunk, keep);
+        if ok {
+            self.maybe_detect_terminator();
+            self.printer.progress(self.path, self.inp.read_offset);
+        }
+        ok
+    }
+
+    /// Returns true if the search should stop because the region
+    /// searched so far extends past the no-match window without a single
+    /// match having been delivered.
+    #[inline(always)]
+    fn give_up(&self) -> bool {
+        self.opts.quit_after_no_match_within.is_some_and(|limit| {
+            self.match_line_count == 0
+                && self.buf_offset + self.inp.lastnl as u64 >= limit
+        })
+    }
+
+    /// Returns true if the search is record oriented rather than line
+    /// oriented, i.e. matches are delivered via the record side buffer.
+    #[inline(always)]
+    fn record_oriented(&self) -> bool {
+        self.opts.paragraph
+            || self.opts.record_sep.is_some()
+            || self.opts.record_size.is_some()
+    }
+
+    /// Returns the position in the input buffer from which bytes must be
+    /// rolled over into the next buffer contents.
+    #[inline(always)]
+    fn keep_from(&self) -> usize {
+        // Record-oriented contexts are copies held in the side buffer,
+        // so nothing before `lastnl` ever needs re-presenting.
+        if self.record_oriented() {
+            return self.inp.lastnl;
+        }
+        if self.opts.before_context > 0 || self.opts.after_context > 0 {
+            let lines = 1 + cmp::max(
+                self.opts.before_context, self.opts.after_context);
+            if self.opts.utf16le {
+                start_of_previous_lines_utf16le(
+                    self.opts.eol,
+                    &self.inp.buf,
+                    self.inp.lastnl.saturating_sub(1),
+                    lines)
+            } else {
+                start_of_previous_lines(
+                    self.opts.eol,
+                    &self.inp.buf,
+                    self.inp.lastnl.saturating_sub(1),
+                    lines)
             }
-            end -= 1;
-            continue;
+        } else {
+            self.inp.lastnl
         }
-        match memrchr(eol, &buf[..end]) {
-            None => {
-                return 0;
+    }
+
+    /// Adjust all buffer-relative counters for a rollover that keeps the
+    /// bytes from the position given.
+    #[inline(always)]
+    fn roll_counters(&mut self, keep: usize) {
+        if keep < self.last_printed {
+            self.last_printed -= keep;
+        } else {
+            self.last_printed = 0;
+        }
+        if keep <= self.last_line {
+            self.last_line -= keep;
+        } else {
+            self.count_lines(keep);
+            self.last_line = 0;
+        }
+        self.count_byte_offset(keep);
+        self.buf_offset += keep as u64;
+    }
+
+    #[inline(always)]
+    fn print_inverted_matches(&mut self, upto: usize) {
+        debug_assert!(self.opts.invert_match);
+        let mut it = IterLines::new(self.opts.eol, self.inp.pos)
+            .utf16le(self.opts.utf16le);
+        while let Some((start, end)) = it.next(&self.inp.buf[..upto]) {
+            if self.terminate() {
+                return;
             }
-            Some(i) => {
-                count -= 1;
-                end = i;
-                if end == 0 {
-                    if buf[end] == eol && count == 0 {
-                        end += 1;
-                    }
-                    return end;
-                }
-                end -= 1;
+            if !self.excluded(start, end) {
+                self.print_match(start, end);
+            }
+            self.inp.pos = end;
+        }
+    }
+
+    #[inline(always)]
+    fn print_before_context(&mut self, upto: usize) {
+        // Every match event passes through here first, so this is where
+        // the per-match context byte budget is refreshed.
+        self.context_budget =
+            self.opts.context_byte_limit.unwrap_or(usize::MAX);
+        if self.opts.skip_matches() || self.opts.before_context == 0 {
+            return;
+        }
+        let start = self.last_printed;
+        let end = upto;
+        if start >= end {
+            return;
+        }
+        let before_context_start =
+            start + if self.opts.utf16le {
+                start_of_previous_lines_utf16le(
+                    self.opts.eol,
+                    &self.inp.buf[start..],
+                    end - start - 1,
+                    self.opts.before_context)
+            } else {
+                start_of_previous_lines(
+                    self.opts.eol,
+                    &self.inp.buf[start..],
+                    end - start - 1,
+                    self.opts.before_context)
+            };
+        let mut it = IterLines::new(self.opts.eol, before_context_start)
+            .utf16le(self.opts.utf16le);
+        while let Some((s, e)) = it.next(&self.inp.buf[..end]) {
+            self.print_separator(s);
+            self.print_context(s, e);
+        }
+    }
+
+    #[inline(always)]
+    fn print_after_context(&mut self, upto: usize) {
+        if self.opts.skip_matches() || self.after_context_remaining == 0 {
+            return;
+        }
+        let start = self.last_printed;
+        let end = upto;
+        let mut it = IterLines::new(self.opts.eol, start)
+            .utf16le(self.opts.utf16le);
+        while let Some((s, e)) = it.next(&self.inp.buf[..end]) {
+            self.print_context(s, e);
+            self.after_context_remaining -= 1;
+            if self.after_context_remaining == 0 {
+                break;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn print_match(&mut self, start: usize, end: usize) {
+        if self.opts.skip_empty_lines
+            && is_empty_line(
+                &self.inp.buf[start..end], self.opts.eol,
+                self.opts.utf16le) {
+            return;
+        }
+        if let Some((limit, LongLinePolicy::Skip)) = self.opts.max_line_len {
+            if end - start > limit {
+                return;
+            }
+        }
+        self.match_line_count += 1;
+        self.count_individual_matches(start, end);
+        if self.opts.skip_matches() {
+            return;
+        }
+        self.count_lines(start);
+        if self.sample_suppressed(start) {
+            return;
+        }
+        self.print_separator(start);
+        self.add_line(end);
+        let indent = self.opts.report_indent.map(|tab_stop| {
+            indent_of(&self.inp.buf[start..end], tab_stop, self.opts.utf16le)
+        });
+        let pend = self.reported_end(start, end);
+        if self.opts.only_matching && self.grep.regex().is_some() {
+            self.print_submatches(start, pend, indent);
+        } else {
+            let column =
+                if self.opts.column {
+                    Some(column_of(
+                        self.grep.regex(), &self.inp.buf[start..end]))
+                } else {
+                    None
+                };
+            self.printer.matched_full(self.path, &SinkMatch {
+                re: self.grep.regex(),
+                buf: &self.inp.buf,
+                start,
+                end: pend,
+                offset: self.buf_offset + start as u64,
+                line_number: self.printed_line_number(),
+                byte_offset: self.byte_offset,
+                column,
+                indent,
+                match_range: None,
+            });
+        }
+        self.last_printed = end;
+        self.after_context_remaining = self.opts.after_context;
+    }
+
+    /// Report each individual match within the line at `start..pend` as
+    /// its own event, with the byte offset and column adjusted to the
+    /// match rather than the line.
+    fn print_submatches(&mut self, start: usize, pend: usize,
+                        indent: Option<Indent>) {
+        let re = self.grep.regex().unwrap();
+        let line_number = self.printed_line_number();
+        for m in re.find_iter(&self.inp.buf[start..pend]) {
+            let column =
+                if self.opts.column {
+                    Some(m.start() as u64 + 1)
+                } else {
+                    None
+                };
+            self.printer.matched_full(self.path, &SinkMatch {
+                re: Some(re),
+                buf: &self.inp.buf,
+                start: start + m.start(),
+                end: start + m.end(),
+                offset: self.buf_offset + (start + m.start()) as u64,
+                line_number,
+                byte_offset: self.byte_offset,
+                column,
+                indent,
+                match_range: Some((0, m.end() - m.start())),
+            });
+        }
+    }
+
+    /// Charge a context line of `len` bytes against the per-match
+    /// context byte budget. Returns how many of its bytes may be
+    /// delivered: all of them, a truncated prefix, or none at all.
+    #[inline(always)]
+    fn context_allowance(&mut self, len: usize) -> usize {
+        let allowed = cmp::min(len, self.context_budget);
+        self.context_budget -= allowed;
+        allowed
+    }
+
+    #[inline(always)]
+    fn print_context(&mut self, start: usize, end: usize) {
+        self.count_lines(start);
+        self.add_line(end);
+        let pend = self.reported_end(start, end);
+        let allowed = self.context_allowance(pend - start);
+        if allowed > 0 {
+            self.printer.context(
+                self.path, &self.inp.buf, start, start + allowed,
+                self.printed_line_number(), self.byte_offset);
+            if allowed < pend - start {
+                self.printer.context_truncated(self.path);
             }
         }
+        self.last_printed = end;
+    }
+
+    /// The end of the line range to report to the sink. With a CRLF
+    /// terminator, the terminator (and its carriage return) is trimmed.
+    #[inline(always)]
+    fn reported_end(&self, start: usize, end: usize) -> usize {
+        if !self.opts.crlf {
+            return end;
+        }
+        start + trim_crlf(
+            &self.inp.buf[start..end], self.opts.eol, self.opts.utf16le)
+    }
+
+    #[inline(always)]
+    fn print_separator(&mut self, before: usize) {
+        if self.opts.before_context == 0 && self.opts.after_context == 0 {
+            return;
+        }
+        if !self.printer.has_printed() {
+            return;
+        }
+        if (self.last_printed == 0 && before > 0)
+            || self.last_printed < before {
+            self.printer.context_separate();
+        }
+    }
+
+    #[inline(always)]
+    fn count_byte_offset(&mut self, buf_last_end: usize) {
+        if let Some(ref mut byte_offset) = self.byte_offset {
+            *byte_offset += buf_last_end as u64;
+        }
+    }
+
+    #[inline(always)]
+    fn count_individual_matches(&mut self, start: usize, end: usize) {
+        if let Some(ref mut count) = self.match_count {
+            match self.grep.regex() {
+                Some(re) => {
+                    for _ in re.find_iter(&self.inp.buf[start..end]) {
+                        *count += 1;
+                    }
+                }
+                // Without a regex, individual matches can't be resolved,
+                // so count matching lines instead.
+                None => *count += 1,
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn count_lines(&mut self, upto: usize) {
+        if let Some(ref mut line_count) = self.line_count {
+            let buf = &self.inp.buf[self.last_line..upto];
+            *line_count +=
+                if self.opts.utf16le {
+                    count_lines_utf16le(buf, self.opts.eol)
+                } else {
+                    count_lines(buf, self.opts.eol)
+                };
+            self.last_line = upto;
+        }
+    }
+
+    /// Record the current consumption point into the caller's
+    /// checkpoint, if one was supplied. `eof` says whether the input was
+    /// exhausted. Lines are counted lazily, so everything up to the
+    /// consumption point is folded in first; unconsumed bytes sitting in
+    /// the buffer past `pos` are deliberately excluded.
+    fn record_checkpoint(&mut self, eof: bool) {
+        if self.checkpoint.is_none() {
+            return;
+        }
+        let pos = self.inp.pos;
+        if pos > self.last_line {
+            self.count_lines(pos);
+        }
+        let offset = self.buf_offset + pos as u64;
+        let lines = self.line_count.unwrap_or(0);
+        if let Some(ref mut cp) = self.checkpoint {
+            cp.offset = offset;
+            cp.lines = lines;
+            cp.eof = eof;
+        }
+    }
+
+    #[inline(always)]
+    fn add_line(&mut self, line_end: usize) {
+        if let Some(ref mut line_count) = self.line_count {
+            *line_count += 1;
+            self.last_line = line_end;
+        }
+    }
+}
+
+/// A push-based driver for a `Searcher`.
+///
+/// A feeder is useful when the haystack cannot be wrapped up in an
+/// `io::Read`, e.g., when chunks of it arrive from an event loop. Each chunk
+/// is handed to `push`, which searches any complete lines that become
+/// available and carries partial lines over to subsequent pushes. Calling
+/// `finish` searches the final (possibly unterminated) line and prints the
+/// end-of-search summary.
+#[allow(dead_code)]
+impl<'a, R, S, M> Searcher<'a, R, S, M>
+where R: io::Read + io::Seek,
+      S: Sink,
+      M: Matcher + ?Sized,
+{
+    /// Execute the search against a seekable input.
+    ///
+    /// This behaves exactly like `run`, except in best-effort mode, where
+    /// a read error does not give up on the rest of the input: the error is
+    /// reported to the sink and the searcher seeks `skip_increment` bytes
+    /// past the point of failure, resuming the search on the other side.
+    /// Lines spanning a skipped region are searched as one, so line numbers
+    /// and match counts are approximate from the first skip onwards.
+    #[allow(dead_code)]
+    pub fn run_seekable(mut self) -> Result<u64, Error> {
+        self.check_config()?;
+        self.begin();
+        if self.opts.start_offset > 0 {
+            let pos = self.haystack
+                .seek(io::SeekFrom::Start(self.opts.start_offset))
+                .map_err(|err| Error::from_io(err, self.path))?;
+            self.note_skipped(pos);
+        }
+        if let Some(cap) = self.opts.peek_back {
+            self.peek_back_context(cap)?;
+        }
+        let mut eof = false;
+        while !self.terminate() {
+            let upto = self.inp.lastnl;
+            self.print_after_context(upto);
+            match self.fill() {
+                Ok(true) => {}
+                Ok(false) => {
+                    eof = self.inp.binary_at.is_none()
+                        && !self.byte_budget_done
+                        && !self.give_up();
+                    break;
+                }
+                Err(err) => {
+                    if !self.opts.best_effort || err.is_cancelled() {
+                        self.record_checkpoint(false);
+                        return Err(err);
+                    }
+                    self.report_read_error(&err);
+                    if !self.skip_past_error() {
+                        break;
+                    }
+                    continue;
+                }
+            }
+            self.search_lines();
+        }
+        if self.record_oriented() {
+            self.flush_paragraph();
+        }
+        if self.inp.last_line_unterminated() {
+            self.printer.unterminated(self.path);
+        }
+        self.drain_after_context()?;
+        self.record_checkpoint(eof);
+        Ok(self.finish())
+    }
+
+    /// Seek past the region that just failed to read. Returns false if the
+    /// seek itself fails, in which case the search gives up on the rest of
+    /// the input.
+    fn skip_past_error(&mut self) -> bool {
+        let target = self.inp.read_offset + self.opts.skip_increment;
+        match self.haystack.seek(io::SeekFrom::Start(target)) {
+            Ok(_) => {
+                self.inp.read_offset = target;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Read up to `before_context` lines preceding the reader's current
+    /// position and stage them in the input buffer, excluded from matching,
+    /// so the first matches of the search can pull real context from before
+    /// the range start.
+    fn peek_back_context(&mut self, cap: u64) -> Result<(), Error> {
+        if self.opts.before_context == 0 || self.opts.skip_matches() {
+            return Ok(());
+        }
+        let pos = self.haystack.stream_position()
+            .map_err(|err| Error::from_io(err, self.path))?;
+        if pos == 0 || cap == 0 {
+            return Ok(());
+        }
+        let start = pos.saturating_sub(cap);
+        self.haystack.seek(io::SeekFrom::Start(start))
+            .map_err(|err| Error::from_io(err, self.path))?;
+        let mut chunk = vec![0; (pos - start) as usize];
+        self.haystack.read_exact(&mut chunk)
+            .map_err(|err| Error::from_io(err, self.path))?;
+        let from = if self.opts.utf16le {
+            start_of_previous_lines_utf16le(
+                self.opts.eol, &chunk, chunk.len() - 1,
+                self.opts.before_context)
+        } else {
+            start_of_previous_lines(
+                self.opts.eol, &chunk, chunk.len() - 1,
+                self.opts.before_context)
+        };
+        // The peeked lines are part of the buffer but not of the search:
+        // shift any caller exclusions past them and exclude them wholesale.
+        let peeked = (chunk.len() - from) as u64;
+        for r in &mut self.exclusions {
+            r.0 += peeked;
+            r.1 += peeked;
+        }
+        self.exclusions.insert(0, (0, peeked));
+        self.push_chunk(&chunk[from..]);
+        if start == 0 {
+            // The peek reached the start of the input, so line numbers can
+            // be exact: account for the lines before the peeked region.
+            if let Some(ref mut line_count) = self.line_count {
+                *line_count +=
+                    if self.opts.utf16le {
+                        count_lines_utf16le(&chunk[..from], self.opts.eol)
+                    } else {
+                        count_lines(&chunk[..from], self.opts.eol)
+                    };
+            }
+        } else {
+            debug!(
+                "{}: peek-back capped at {} bytes; line numbers are \
+                 relative to the peeked region", self.path.display(), cap);
+        }
+        if let Some(ref mut off) = self.byte_offset {
+            *off = start + from as u64;
+        }
+        Ok(())
+    }
+}
+
+pub struct Feeder<'a, R: 'a, S: 'a, M: 'a + ?Sized> {
+    searcher: Searcher<'a, R, S, M>,
+    done: bool,
+}
+
+#[allow(dead_code)]
+impl<'a, R: io::Read, S: Sink, M: Matcher + ?Sized> Feeder<'a, R, S, M> {
+    /// Feed the next chunk of the haystack to the searcher.
+    ///
+    /// Any matches that are completed by this chunk are written to the
+    /// printer. If the haystack was detected as binary (and binary files
+    /// aren't being searched as text), then this is a no-op.
+    pub fn push(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        if self.done {
+            return Ok(());
+        }
+        if self.searcher.terminate()
+            && self.searcher.after_context_remaining == 0 {
+            self.done = true;
+            return Ok(());
+        }
+        // A previous push may have ended in the middle of a line, in which
+        // case there is nothing new to print context for yet.
+        let upto = self.searcher.inp.lastnl;
+        if upto > 0 {
+            self.searcher.print_after_context(upto);
+        }
+        if !self.searcher.push_chunk(chunk) {
+            self.done = true;
+            return Ok(());
+        }
+        self.searcher.search_lines();
+        Ok(())
+    }
+
+    /// Complete the search.
+    ///
+    /// This searches the final line if it wasn't terminated, flushes a
+    /// pending record along with any pending contextual lines and prints
+    /// the end-of-search summary. The total number of matching lines is
+    /// returned.
+    pub fn finish(mut self) -> Result<u64, Error> {
+        if !self.done {
+            // This call is the feeder's EOF, so a partial final line is
+            // subject to the same policy that `fill` applies at EOF.
+            if self.searcher.inp.end > self.searcher.inp.lastnl {
+                match self.searcher.opts.final_line {
+                    FinalLinePolicy::Ignore => {}
+                    FinalLinePolicy::Flag => {
+                        self.searcher.inp.unterminated = true;
+                        self.searcher.inp.lastnl = self.searcher.inp.end;
+                    }
+                    FinalLinePolicy::Match => {
+                        self.searcher.inp.lastnl = self.searcher.inp.end;
+                    }
+                }
+            }
+            self.searcher.search_lines();
+            if self.searcher.record_oriented() {
+                self.searcher.flush_paragraph();
+            }
+            if self.searcher.inp.last_line_unterminated() {
+                self.searcher.printer.unterminated(self.searcher.path);
+            }
+            if self.searcher.after_context_remaining > 0 {
+                let upto = self.searcher.inp.lastnl;
+                if upto > 0 {
+                    self.searcher.print_after_context(upto);
+                }
+            }
+        }
+        Ok(self.searcher.finish())
+    }
+}
+
+/// `InputBuffer` encapsulates the logic of maintaining a ~fixed sized buffer
+/// on which to search. There are three key pieces of complexity:
+///
+/// 1. We must be able to handle lines that are longer than the size of the
+///    buffer. For this reason, the buffer is allowed to expand (and is
+///    therefore not technically fixed). Note that once a buffer expands, it
+///    will never contract, unless it was created with `adaptive`.
+/// 2. The contents of the buffer may end with a partial line, so we must keep
+///    track of where the last complete line ends. Namely, the partial line
+///    is only completed on subsequent reads *after* searching up through
+///    the last complete line is done.
+/// 3. When printing the context of a match, the last N lines of the buffer
+///    may need to be rolled over into the next buffer. For example, a match
+///    may occur at the beginning of a buffer, in which case, lines at the end
+///    of the previous contents of the buffer need to be printed.
+///
+/// An `InputBuffer` is designed to be reused and isn't tied to any particular
+/// reader.
+pub struct InputBuffer {
+    /// The number of bytes to attempt to read at a time. Once set, this is
+    /// never changed, except by `reset` in adaptive mode.
+    read_size: usize,
+    /// The end-of-line terminator used in this buffer.
+    eol: u8,
+    /// A scratch buffer.
+    tmp: Vec<u8>,
+    /// A buffer to read bytes into. All searches are executed directly against
+    /// this buffer and pos/lastnl/end point into it.
+    buf: Vec<u8>,
+    /// The current position in buf. The current position represents where the
+    /// next search should start.
+    pos: usize,
+    /// The position immediately following the last line terminator in buf.
+    /// This may be equal to end.
+    ///
+    /// Searching should never cross this boundary. In particular, the contents
+    /// of the buffer following this position may correspond to *partial* line.
+    /// All contents before this position are complete lines.
+    lastnl: usize,
+    /// The end position of the buffer. Data after this position is not
+    /// specified.
+    end: usize,
+    /// Set to true if and only if no reads have occurred yet.
+    first: bool,
+    /// Set to true if all binary data should be treated as if it were text.
+    text: bool,
+    /// Set to true if the buffer contains UTF-16LE encoded text, where line
+    /// terminators are the code unit pair `\n\0`.
+    utf16le: bool,
+    /// Set to true if every filled byte is a complete record, i.e. `lastnl`
+    /// tracks the end of the buffer instead of the last line terminator.
+    /// Used for fixed-size records, whose boundaries the caller tracks.
+    fixed: bool,
+    /// The allocation policy applied when a line outgrows the buffer.
+    alloc: BufferAllocation,
+    /// An upper bound on the buffer's size, in bytes. Growth past the
+    /// bound surfaces as a read error.
+    heap_limit: Option<usize>,
+    /// True when the searcher will supply another reader after the
+    /// current one reports EOF, so a partial final line must be held
+    /// back for stitching instead of searched.
+    more_input: bool,
+    /// The policy for a final line that lacks a terminator.
+    final_line: FinalLinePolicy,
+    /// Set to true if the final line lacked a terminator and the policy
+    /// asks for it to be flagged.
+    unterminated: bool,
+    /// Set to true if this buffer records line length statistics and derives
+    /// its capacity from them on reset.
+    adaptive: bool,
+    /// The length of the unterminated line fragment carried over from
+    /// previous reads. Only maintained in adaptive mode.
+    partial: usize,
+    /// If set, then `reset` shrinks the buffer whenever its capacity exceeds
+    /// this multiple of what the previous search actually needed.
+    shrink: Option<usize>,
+    /// A lower bound on the length of the longest line seen since the last
+    /// reset.
+    max_line_est: usize,
+    /// Set to true if reads should be issued with `read_vectored` across
+    /// two slices at a time.
+    vectored: bool,
+    /// If set, then binary detection is replaced by converting every NUL
+    /// byte read into this replacement byte.
+    convert: Option<u8>,
+    /// Set to true if any byte has been converted since the last reset.
+    converted: bool,
+    /// If set, a content-based heuristic (threshold, prefix length) that
+    /// classifies the input as binary when too many unprintable bytes
+    /// appear in the inspected prefix.
+    heuristic: Option<(f64, usize)>,
+    /// The number of context bytes captured on either side of the byte
+    /// that triggers binary detection.
+    preview_context: usize,
+    /// The absolute offset of the byte that triggered binary detection, if
+    /// it fired since the last reset.
+    binary_at: Option<u64>,
+    /// A preview of the input around the point where binary detection last
+    /// fired, if it has.
+    preview: Option<BinaryPreview>,
+    /// The total number of bytes consumed from the reader since the last
+    /// reset, used to report absolute offsets.
+    read_offset: u64,
+    /// The largest capacity this buffer reached since the last reset. This
+    /// is a high-water mark updated at growth points only, so it records the
+    /// peak transient usage of a single search even when a shrink policy
+    /// later releases the memory.
+    peak: usize,
+    /// The maximum line length, if any, and what to do with a line that
+    /// exceeds it.
+    max_line_len: Option<(usize, LongLinePolicy)>,
+    /// The number of oversized lines discarded by the last `fill`.
+    fill_skipped_lines: u64,
+    /// The number of bytes discarded by the last `fill`, terminators
+    /// included.
+    fill_skipped_bytes: u64,
+    /// Set when the last `fill` hit an oversized line under the `Error`
+    /// policy; holds the absolute byte offset of the start of that line.
+    long_line_at: Option<u64>,
+    /// The absolute offset of the start of the line that burst the heap
+    /// limit, if one has since the last fill.
+    heap_limit_at: Option<u64>,
+}
+
+impl InputBuffer {
+    /// Create a new buffer with a default capacity.
+    pub fn new() -> InputBuffer {
+        InputBuffer::with_capacity(READ_SIZE)
+    }
+
+    /// Create a new buffer with the capacity given.
+    ///
+    /// The capacity determines the size of each read from the underlying
+    /// reader.
+    ///
+    /// `cap` is clamped to an effective minimum of `1`. Since the buffer
+    /// grows on demand to hold at least one complete line, even the
+    /// smallest capacity can search arbitrarily long lines; it just costs
+    /// more reads. A capacity can therefore never cause a mid-search
+    /// failure.
+    pub fn with_capacity(mut cap: usize) -> InputBuffer {
+        if cap == 0 {
+            cap = 1;
+        }
+        InputBuffer {
+            read_size: cap,
+            eol: b'\n',
+            buf: vec![0; cap],
+            tmp: vec![],
+            pos: 0,
+            lastnl: 0,
+            end: 0,
+            first: true,
+            text: false,
+            utf16le: false,
+            fixed: false,
+            alloc: BufferAllocation::OnDemand,
+            final_line: FinalLinePolicy::Match,
+            heap_limit: None,
+            more_input: false,
+            unterminated: false,
+            adaptive: false,
+            partial: 0,
+            shrink: None,
+            max_line_est: 0,
+            vectored: false,
+            convert: None,
+            converted: false,
+            heuristic: None,
+            binary_at: None,
+            preview_context: 16,
+            preview: None,
+            read_offset: 0,
+            peak: cap,
+            max_line_len: None,
+            fill_skipped_lines: 0,
+            fill_skipped_bytes: 0,
+            long_line_at: None,
+            heap_limit_at: None,
+        }
+    }
+
+    /// Create a new buffer whose capacity adapts to the line lengths
+    /// observed by all adaptive buffers in this process.
+    ///
+    /// The initial capacity is taken from `buffer_size_stats`, and the
+    /// capacity is re-derived every time this buffer is reset. Unlike a
+    /// fixed size buffer, an adaptive buffer may contract on reset.
+    #[allow(dead_code)]
+    pub fn adaptive() -> InputBuffer {
+        let mut inp =
+            InputBuffer::with_capacity(BUFFER_STATS.suggested_capacity());
+        inp.adaptive = true;
+        inp
+    }
+
+    /// Pre-size this buffer for an input whose total length is known in
+    /// advance, clamped to the configured heap limit.
+    ///
+    /// Growth normally doubles upward from the read size as needed;
+    /// pre-sizing skips the intermediate allocations when the input is
+    /// going to need the room anyway, and raises the read size so the
+    /// input is consumed in as few reads as possible. The reservation
+    /// persists for the lifetime of the buffer.
+    #[allow(dead_code)]
+    pub fn reserve(&mut self, len: usize) -> &mut Self {
+        let len = match self.heap_limit {
+            None => len,
+            Some(limit) => cmp::min(len, limit),
+        };
+        if len > self.buf.len() {
+            self.buf.resize(len, 0);
+            self.peak = cmp::max(self.peak, self.buf.len());
+            self.read_size = len;
+        }
+        self
+    }
+
+    /// Set the end-of-line terminator used by this input buffer.
+    pub fn eol(&mut self, eol: u8) -> &mut Self {
+        self.eol = eol;
+        self
+    }
+
+    /// If enabled, search binary files as if they were text.
+    ///
+    /// Note that this may cause the buffer to load the entire contents of a
+    /// file into memory.
+    pub fn text(&mut self, yes: bool) -> &mut Self {
+        self.text = yes;
+        self
+    }
+
+    /// If enabled, treat the contents of this buffer as UTF-16LE encoded
+    /// text.
+    ///
+    /// In particular, lines are terminated by the code unit pair `\n\0` and
+    /// binary detection is disabled.
+    pub fn utf16le(&mut self, yes: bool) -> &mut Self {
+        self.utf16le = yes;
+        self
+    }
+
+    /// If enabled, treat every filled byte as searchable instead of
+    /// holding back a partial final line. Used for fixed-size records,
+    /// where the caller tracks record boundaries itself.
+    pub fn fixed_records(&mut self, yes: bool) -> &mut Self {
+        self.fixed = yes;
+        self
+    }
+
+    /// Set the policy for a final line that lacks a terminator.
+    pub fn final_line(&mut self, policy: FinalLinePolicy) -> &mut Self {
+        self.final_line = policy;
+        self
+    }
+
+    /// Returns true if the last fill surfaced a final line with no
+    /// terminator and the policy asks for it to be flagged.
+    pub fn last_line_unterminated(&self) -> bool {
+        self.unterminated
+    }
+
+    /// Cap the memory this buffer may use, in bytes. Growth past the
+    /// cap surfaces as a read error. The cap covers everything the
+    /// buffer retains, including any rolled-over before-context region.
+    pub fn heap_limit(&mut self, limit: Option<usize>) -> &mut Self {
+        self.heap_limit = limit;
+        self
+    }
+
+    /// Declare whether more input will follow the current reader's EOF.
+    /// While set, an EOF with a partial final line buffered returns
+    /// without searching it, so the caller can install the next reader
+    /// and the line can be stitched across the boundary.
+    pub fn expect_more(&mut self, yes: bool) -> &mut Self {
+        self.more_input = yes;
+        self
+    }
+
+    /// Set the allocation policy for this buffer.
+    ///
+    /// `Eager` reserves its bytes immediately, so no fill pays for
+    /// growth until a line outgrows the reservation. `Fixed` never
+    /// grows: a line that doesn't fit in the buffer surfaces as a read
+    /// error.
+    pub fn buffer_alloc(&mut self, policy: BufferAllocation) -> &mut Self {
+        self.alloc = policy;
+        if let BufferAllocation::Eager(n) = policy {
+            if self.buf.len() < n {
+                self.buf.resize(n, 0);
+                self.peak = cmp::max(self.peak, self.buf.len());
+            }
+        }
+        self
+    }
+
+    /// Set the maximum line length for this buffer, along with the policy
+    /// for lines that exceed it.
+    pub fn max_line_len(
+        &mut self,
+        limit: Option<(usize, LongLinePolicy)>,
+    ) -> &mut Self {
+        self.max_line_len = limit;
+        self
+    }
+
+    /// If set, disable binary detection and instead convert every NUL byte
+    /// read into the replacement byte given.
+    ///
+    /// The original NUL is never observable by a search; every byte is
+    /// converted before any line is searched or printed. A filler like
+    /// `\xFF` (invalid UTF-8, so it can't create accidental matches in
+    /// text) leaves line boundaries intact. The line terminator may be
+    /// chosen deliberately to split binary data into more lines, but then
+    /// reported line numbers reflect the converted data rather than the
+    /// original input; `converted` reports whether any conversion happened
+    /// so that consumers can qualify their output.
+    ///
+    /// This has no effect on UTF-16LE buffers, where NUL bytes are ordinary
+    /// code unit halves.
+    ///
+    /// This is disabled by default.
+    #[allow(dead_code)]
+    pub fn convert_binary(&mut self, replacement: Option<u8>) -> &mut Self {
+        self.convert = replacement;
+        self
+    }
+
+    /// Returns true if any byte has been converted by `convert_binary`
+    /// since the last reset.
+    #[allow(dead_code)]
+    pub fn converted(&self) -> bool {
+        self.converted
+    }
+
+    /// If set, also classify the input as binary when the proportion of
+    /// unprintable bytes in its prefix exceeds `threshold`. At most
+    /// `prefix` bytes of the input are inspected.
+    ///
+    /// NUL detection misses binary data that happens to contain no NUL
+    /// bytes in the sniffed region, like some UTF-16 text or dense bitmap
+    /// data. The heuristic catches those by counting control bytes (other
+    /// than common whitespace) and DEL; bytes with the high bit set are
+    /// considered printable so that UTF-8 text is never misclassified. See
+    /// `binary_heuristic_offset` for the exact byte classification.
+    ///
+    /// This is disabled by default.
+    #[allow(dead_code)]
+    pub fn binary_heuristic(
+        &mut self,
+        config: Option<(f64, usize)>,
+    ) -> &mut Self {
+        self.heuristic = config;
+        self
+    }
+
+    /// Set the number of context bytes captured on either side of the byte
+    /// that triggers binary detection. The default is 16.
+    #[allow(dead_code)]
+    pub fn preview_context(&mut self, n: usize) -> &mut Self {
+        self.preview_context = n;
+        self
+    }
+
+    /// Returns a preview of the input around the point where binary
+    /// detection last fired, if it has since the last reset.
+    ///
+    /// The snippet is copied out of the buffer when detection fires, so it
+    /// remains valid after the buffer is reused.
+    #[allow(dead_code)]
+    pub fn binary_preview(&self) -> Option<&BinaryPreview> {
+        self.preview.as_ref()
+    }
+
+    /// Returns the absolute offset of the byte that triggered binary
+    /// detection, if it has fired since the last reset.
+    ///
+    /// This lets callers distinguish "no matches" from "matches may exist
+    /// past the binary region." Matches on lines ending strictly before
+    /// this offset are still delivered before the search terminates.
+    #[allow(dead_code)]
+    pub fn binary_byte_offset(&self) -> Option<u64> {
+        self.binary_at
+    }
+
+    /// If enabled, fill this buffer using vectored reads.
+    ///
+    /// Each call to the underlying reader is then given two slices of the
+    /// read size instead of one, which can halve the number of syscalls for
+    /// sources where syscall count dominates (e.g. pipes with small kernel
+    /// buffers). Readers that don't implement `read_vectored` fall back to
+    /// std's default, which reads into the first slice only, so enabling
+    /// this is always safe if not always profitable.
+    ///
+    /// This is disabled by default.
+    #[allow(dead_code)]
+    pub fn vectored(&mut self, yes: bool) -> &mut Self {
+        self.vectored = yes;
+        self
+    }
+
+    /// Set the shrink policy for this buffer.
+    ///
+    /// When set, a search that grows the buffer (say, for one enormous line)
+    /// no longer inflates it for the lifetime of the buffer. Instead, when
+    /// the buffer is reset for a new search, its capacity is compared
+    /// against the longest line seen by the search that just finished. If
+    /// the capacity exceeds `factor` times what that search needed, then the
+    /// buffer shrinks to a size fit for it. The factor provides hysteresis:
+    /// workloads with consistently long lines never shrink and so never pay
+    /// for re-growing.
+    ///
+    /// The buffer never shrinks in the middle of a search.
+    ///
+    /// This is disabled by default.
+    #[allow(dead_code)]
+    pub fn shrink_excess(&mut self, factor: Option<usize>) -> &mut Self {
+        self.shrink = factor;
+        self
+    }
+
+    /// Returns the current capacity of this buffer, in bytes.
+    #[allow(dead_code)]
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns the largest capacity this buffer reached during the current
+    /// (or most recently finished) search, in bytes.
+    ///
+    /// With a shrink policy, this can exceed `capacity` once the next search
+    /// begins: the shrink happens on reset, but the peak records what the
+    /// finished search transiently needed. Call this after a search to size
+    /// heap limits.
+    #[allow(dead_code)]
+    pub fn peak_capacity(&self) -> usize {
+        self.peak
+    }
+
+    /// Manually restore this buffer to its original capacity.
+    ///
+    /// This may only be called in between searches.
+    #[allow(dead_code)]
+    pub fn reset_buffers(&mut self) {
+        self.buf = vec![0; self.read_size];
+        self.max_line_est = 0;
+        self.reset();
+    }
+
+    /// Release memory retained from previous searches immediately.
+    ///
+    /// Like `reset_buffers`, but the scratch buffer — which holds rolled
+    /// over context and converted data, and otherwise keeps its
+    /// high-water mark for the lifetime of this buffer — is freed
+    /// outright as well. The `shrink_excess` policy releases excess
+    /// lazily when the next search starts; this is for long-lived
+    /// buffers, in daemons say, that may not search again for a while
+    /// and should not pin a monster line's worth of memory until then.
+    ///
+    /// This may only be called in between searches.
+    #[allow(dead_code)]
+    pub fn shrink_to_fit(&mut self) {
+        self.tmp = vec![];
+        self.reset_buffers();
+    }
+
+    /// Resets this buffer so that it may be reused with a new reader.
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.lastnl = 0;
+        self.end = 0;
+        self.first = true;
+        self.unterminated = false;
+        self.converted = false;
+        self.binary_at = None;
+        self.preview = None;
+        self.read_offset = 0;
+        self.fill_skipped_lines = 0;
+        self.fill_skipped_bytes = 0;
+        self.long_line_at = None;
+        self.heap_limit_at = None;
+        if let Some(factor) = self.shrink {
+            let needed = cmp::max(
+                self.read_size,
+                self.max_line_est
+                    .checked_next_power_of_two()
+                    .unwrap_or(self.buf.len()),
+            );
+            if self.buf.len() > factor.saturating_mul(needed) {
+                self.buf = vec![0; needed];
+            }
+            if self.tmp.capacity() > factor.saturating_mul(needed) {
+                self.tmp = vec![];
+            }
+        }
+        self.max_line_est = 0;
+        if self.adaptive {
+            self.partial = 0;
+            let cap = BUFFER_STATS.suggested_capacity();
+            if cap != self.buf.len() {
+                self.read_size = cap;
+                self.buf = vec![0; cap];
+            }
+        }
+        self.peak = self.buf.len();
+    }
+
+    /// Fill the contents of this buffer with the reader given. The reader
+    /// given should be the same in every call to fill unless reset has been
+    /// called.
+    ///
+    /// The bytes in buf[keep_from..end] are rolled over into the beginning
+    /// of the buffer.
+    fn fill<R: io::Read>(
+        &mut self,
+        rdr: &mut R,
+        keep_from: usize,
+    ) -> Result<bool, io::Error> {
+        if self.binary_at.is_some() {
+            return Ok(false);
+        }
+        self.rollover(keep_from);
+        self.fill_skipped_lines = 0;
+        self.fill_skipped_bytes = 0;
+        self.long_line_at = None;
+        self.heap_limit_at = None;
+        let read_size =
+            if self.vectored { 2 * self.read_size } else { self.read_size };
+        while self.lastnl == 0 {
+            // If our buffer isn't big enough to hold the contents of a full
+            // read, expand it -- unless the allocation policy forbids
+            // growth, in which case read into the space that's left and
+            // fail once the buffer is exhausted without a complete line.
+            if self.buf.len() - self.end < read_size {
+                if self.alloc == BufferAllocation::Fixed {
+                    if self.buf.len() == self.end {
+                        return Err(io::Error::other(format!(
+                            "line too long to fit in the fixed-size \
+                             buffer ({} bytes)", self.buf.len())));
+                    }
+                } else {
+                    let min_len = read_size + self.buf.len() - self.end;
+                    let mut new_len = cmp::max(min_len, self.buf.len() * 2);
+                    if let Some(limit) = self.heap_limit {
+                        new_len = cmp::max(
+                            cmp::min(new_len, limit), self.buf.len());
+                        if new_len == self.end {
+                            // Flag rather than fail so the searcher can
+                            // attach the line number and offset of the
+                            // line that burst the limit.
+                            self.heap_limit_at = Some(
+                                self.read_offset
+                                - (self.end - self.pos) as u64);
+                            return Ok(true);
+                        }
+                    }
+                    self.buf.resize(new_len, 0);
+                    self.peak = cmp::max(self.peak, self.buf.len());
+                }
+            }
+            let read_size = cmp::min(read_size, self.buf.len() - self.end);
+            let n = if self.vectored && read_size == 2 * self.read_size {
+                let (left, right) = self.buf[self.end..self.end + read_size]
+                    .split_at_mut(self.read_size);
+                rdr.read_vectored(&mut [
+                    io::IoSliceMut::new(left),
+                    io::IoSliceMut::new(right),
+                ])?
+            } else {
+                rdr.read(&mut self.buf[self.end..self.end + read_size])?
+            };
+            if self.adaptive {
+                self.record(n);
+            }
+            self.convert(n);
+            if self.convert.is_none() && !self.text && !self.utf16le {
+                if let Some(off) = binary_offset(
+                    &self.buf[self.end..self.end + n], self.first) {
+                    self.capture_preview(n, off);
+                    return Ok(self.keep_before_binary(off));
+                }
+                if let Some(off) = self.heuristic_offset(n) {
+                    self.capture_preview(n, off);
+                    return Ok(self.keep_before_binary(off));
+                }
+            }
+            self.read_offset += n as u64;
+            self.first = false;
+            // We assume that reading 0 bytes means we've hit EOF.
+            if n == 0 {
+                // If we've searched everything up to the end of the buffer,
+                // then there's nothing left to do.
+                if self.end - self.pos == 0 {
+                    return Ok(false);
+                }
+                // Another reader follows this one: hold the partial tail
+                // back so it can be stitched to the next segment's first
+                // bytes.
+                if self.more_input {
+                    return Ok(false);
+                }
+                // Even if we hit EOF, we might still have to search the
+                // last line if it didn't contain a trailing terminator --
+                // unless the caller asked for POSIX-strict behavior, in
+                // which case the partial line is not a line at all.
+                match self.final_line {
+                    FinalLinePolicy::Ignore => return Ok(false),
+                    FinalLinePolicy::Flag => self.unterminated = true,
+                    FinalLinePolicy::Match => {}
+                }
+                self.lastnl = self.end;
+                break;
+            }
+            self.lastnl =
+                if self.fixed { self.end + n } else { self.find_lastnl(n) };
+            self.end += n;
+            self.max_line_est =
+                cmp::max(self.max_line_est, self.end - self.lastnl);
+            if self.lastnl == 0 {
+                if let Some((limit, policy)) = self.max_line_len {
+                    if self.end - self.pos > limit {
+                        match policy {
+                            LongLinePolicy::Error => {
+                                self.long_line_at = Some(
+                                    self.read_offset
+                                    - (self.end - self.pos) as u64);
+                                return Ok(true);
+                            }
+                            LongLinePolicy::Skip => {
+                                if !self.discard_long_line(rdr)? {
+                                    return Ok(false);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Read and discard input up to and including the next line
+    /// terminator, dropping the bytes already buffered for the oversized
+    /// line first. Any remainder following the terminator is kept as the
+    /// start of the next line. Returns false if EOF arrived before a
+    /// terminator was found. Discarded bytes bypass transcoding and
+    /// binary detection.
+    fn discard_long_line<R: io::Read>(
+        &mut self,
+        rdr: &mut R,
+    ) -> Result<bool, io::Error> {
+        self.fill_skipped_bytes += (self.end - self.pos) as u64;
+        self.end = self.pos;
+        loop {
+            if self.buf.len() - self.end < self.read_size {
+                let new_len = self.read_size + self.end;
+                self.buf.resize(cmp::max(new_len, self.buf.len()), 0);
+            }
+            let n = rdr.read(
+                &mut self.buf[self.end..self.end + self.read_size])?;
+            self.read_offset += n as u64;
+            if n == 0 {
+                self.fill_skipped_lines += 1;
+                return Ok(false);
+            }
+            match self.find_firstnl(self.end, n) {
+                None => {
+                    self.fill_skipped_bytes += n as u64;
+                }
+                Some(term_end) => {
+                    self.fill_skipped_bytes += (term_end - self.end) as u64;
+                    self.fill_skipped_lines += 1;
+                    let rem = self.end + n - term_end;
+                    self.buf.copy_within(term_end..term_end + rem, self.end);
+                    self.lastnl = self.find_lastnl(rem);
+                    self.end += rem;
+                    return Ok(true);
+                }
+            }
+        }
+    }
+
+    /// Returns the position immediately past the first line terminator in
+    /// the `n` bytes starting at `from`, if any.
+    fn find_firstnl(&self, from: usize, n: usize) -> Option<usize> {
+        if self.utf16le {
+            // Terminator pairs sit at even absolute offsets in the
+            // stream; reconstruct the parity from the read offset, since
+            // discarding may leave the buffer itself unaligned.
+            for i in from..from + n.saturating_sub(1) {
+                let abs = self.read_offset - (from + n - i) as u64;
+                if abs.is_multiple_of(2)
+                    && self.buf[i] == self.eol && self.buf[i + 1] == 0 {
+                    return Some(i + 2);
+                }
+            }
+            None
+        } else {
+            memchr(self.eol, &self.buf[from..from + n]).map(|i| from + i + 1)
+        }
+    }
+
+    /// Capture a preview around the detected binary byte at offset `off`
+    /// within the `n` freshly read bytes following `end`. The snippet may
+    /// extend into previously read bytes still present in the buffer.
+    fn capture_preview(&mut self, n: usize, off: usize) {
+        let pos = self.end + off;
+        let start = pos.saturating_sub(self.preview_context);
+        let upto = cmp::min(self.end + n, pos + self.preview_context + 1);
+        self.preview = Some(BinaryPreview {
+            offset: self.read_offset + off as u64,
+            pos: pos - start,
+            bytes: self.buf[start..upto].to_vec(),
+        });
+    }
+
+    /// Convert NUL bytes in the `n` bytes following `end`, if a replacement
+    /// byte is configured.
+    /// Record a binary byte detected at offset `off` within the chunk that
+    /// was just added, and surface any complete lines preceding it so that
+    /// matches before the binary region are still delivered.
+    ///
+    /// Returns true only when such lines exist; the partial line containing
+    /// the binary byte is dropped and every later `fill` or `push` reports
+    /// EOF.
+    fn keep_before_binary(&mut self, off: usize) -> bool {
+        self.binary_at = Some(self.read_offset + off as u64);
+        let lastnl = self.find_lastnl(off);
+        if lastnl == 0 {
+            return false;
+        }
+        self.lastnl = lastnl;
+        self.end = lastnl;
+        true
+    }
+
+    /// Apply the content heuristic to the chunk of length `n` that was
+    /// just added at `self.end`, returning the offset of the first
+    /// unprintable byte within the chunk if it classified as binary.
+    ///
+    /// Only the configured prefix of the whole input is inspected, so
+    /// chunks past it are never examined.
+    fn heuristic_offset(&self, n: usize) -> Option<usize> {
+        let (threshold, prefix) = self.heuristic?;
+        let seen = self.read_offset as usize;
+        if seen >= prefix {
+            return None;
+        }
+        let upto = cmp::min(n, prefix - seen);
+        binary_heuristic_offset(
+            &self.buf[self.end..self.end + upto], threshold)
+    }
+
+    fn convert(&mut self, n: usize) {
+        let replacement = match self.convert {
+            None => return,
+            Some(b) => b,
+        };
+        if self.utf16le {
+            return;
+        }
+        for byte in &mut self.buf[self.end..self.end + n] {
+            if *byte == b'\x00' {
+                *byte = replacement;
+                self.converted = true;
+            }
+        }
+    }
+
+    /// Record line length statistics for the `n` bytes following `end`,
+    /// which have been read but not yet accounted for.
+    fn record(&mut self, n: usize) {
+        let chunk = &self.buf[self.end..self.end + n];
+        let lines = if self.utf16le {
+            count_lines_utf16le(chunk, self.eol)
+        } else {
+            count_lines(chunk, self.eol)
+        };
+        BUFFER_STATS.record_read(n, lines as usize);
+        // The carried-over fragment plus everything up to the first
+        // terminator (or the whole chunk, absent one) bounds the length of
+        // some line from below. This misses lines contained entirely within
+        // one read, which is fine: those fit comfortably already.
+        match memchr(self.eol, chunk) {
+            None => {
+                self.partial += n;
+            }
+            Some(first) => {
+                BUFFER_STATS.record_line_len(self.partial + first);
+                let last = memrchr(self.eol, chunk).unwrap();
+                self.partial = n - (last + 1);
+            }
+        }
+        BUFFER_STATS.record_line_len(self.partial);
+    }
+
+    /// Add a chunk of the haystack to this buffer. The reader given to `fill`
+    /// is never consulted; this is the push-based analog of `fill`.
+    ///
+    /// Returns false if and only if the chunk was detected as binary (and
+    /// binary data isn't being treated as text).
+    #[allow(dead_code)]
+    fn push(&mut self, chunk: &[u8], keep_from: usize) -> bool {
+        if self.binary_at.is_some() {
+            return false;
+        }
+        self.rollover(keep_from);
+        if self.buf.len() - self.end < chunk.len() {
+            let min_len = self.end + chunk.len();
+            let new_len = cmp::max(min_len, self.buf.len() * 2);
+            self.buf.resize(new_len, 0);
+            self.peak = cmp::max(self.peak, self.buf.len());
+        }
+        self.buf[self.end..self.end + chunk.len()].copy_from_slice(chunk);
+        self.convert(chunk.len());
+        if self.convert.is_none() && !self.text && !self.utf16le {
+            if let Some(off) = binary_offset(
+                &self.buf[self.end..self.end + chunk.len()], self.first) {
+                self.capture_preview(chunk.len(), off);
+                return self.keep_before_binary(off);
+            }
+            if let Some(off) = self.heuristic_offset(chunk.len()) {
+                self.capture_preview(chunk.len(), off);
+                return self.keep_before_binary(off);
+            }
+        }
+        self.read_offset += chunk.len() as u64;
+        self.first = false;
+        self.lastnl =
+            if self.fixed {
+                self.end + chunk.len()
+            } else {
+                self.find_lastnl(chunk.len())
+            };
+        self.end += chunk.len();
+        self.max_line_est =
+            cmp::max(self.max_line_est, self.end - self.lastnl);
+        true
+    }
+
+    /// Rollover bytes from buf[keep_from..end] and update our various
+    /// pointers. N.B. This could be done with the ptr::copy, but I haven't
+    /// been able to produce a benchmark that notices a difference in
+    /// performance. (Invariably, ptr::copy is seems clearer IMO, but it is
+    /// not safe.)
+    fn rollover(&mut self, keep_from: usize) {
+        self.tmp.clear();
+        self.tmp.extend_from_slice(&self.buf[keep_from..self.end]);
+        self.buf[0..self.tmp.len()].copy_from_slice(&self.tmp);
+        self.pos -= keep_from;
+        self.lastnl = 0;
+        self.end = self.tmp.len();
+    }
+
+    /// Returns the position immediately following the last line terminator
+    /// in the `n` bytes following `end`, or `0` if there is none.
+    fn find_lastnl(&self, n: usize) -> usize {
+        if self.utf16le {
+            // A read may end in the middle of a code unit, so back up
+            // to an even offset before searching. This guarantees
+            // that a terminator pair straddling the previous read is
+            // still found.
+            let start = self.end - (self.end & 1);
+            find_last_eol_utf16le(
+                self.eol, &self.buf[start..self.end + n])
+            .map(|i| start + i + 2)
+            .unwrap_or(0)
+        } else {
+            memrchr(self.eol, &self.buf[self.end..self.end + n])
+            .map(|i| self.end + i + 1)
+            .unwrap_or(0)
+        }
+    }
+}
+
+/// Returns true if and only if the given buffer is determined to be "binary"
+/// or otherwise not contain text data that is usefully searchable.
+///
+/// Note that this may return both false positives and false negatives.
+#[inline(always)]
+pub fn is_binary(buf: &[u8], first: bool) -> bool {
+    binary_offset(buf, first).is_some()
+}
+
+/// Returns the offset of the byte that makes the given buffer "binary," if
+/// any. This is the byte that `is_binary` detects.
+#[inline(always)]
+pub fn binary_offset(buf: &[u8], first: bool) -> Option<usize> {
+    if first && buf.len() >= 4 && &buf[0..4] == b"%PDF" {
+        return Some(0);
+    }
+    memchr(b'\x00', buf)
+}
+
+/// Applies a content-based binary heuristic to the given buffer: if the
+/// proportion of unprintable bytes exceeds `threshold` (a fraction in
+/// `0..=1`), the offset of the first unprintable byte is returned.
+///
+/// A byte is unprintable if it is a control byte other than common
+/// whitespace (`\t`, `\n`, `\x0B`, `\x0C`, `\r`, `\x1B`) or DEL. Bytes
+/// with the high bit set count as printable, so UTF-8 text is never
+/// misclassified no matter how dense it is.
+#[allow(dead_code)]
+pub fn binary_heuristic_offset(buf: &[u8], threshold: f64) -> Option<usize> {
+    fn unprintable(b: u8) -> bool {
+        (b < 0x20 && !matches!(b, b'\t' | b'\n' | 0x0B | 0x0C | b'\r'
+                                   | 0x1B))
+            || b == 0x7F
+    }
+    if buf.is_empty() {
+        return None;
+    }
+    let count = buf.iter().cloned().filter(|&b| unprintable(b)).count();
+    if (count as f64) <= threshold * (buf.len() as f64) {
+        return None;
+    }
+    buf.iter().position(|&b| unprintable(b))
+}
+
+/// A snippet of bytes captured around the point where binary detection
+/// fired, so that callers can show the user what triggered it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BinaryPreview {
+    offset: u64,
+    pos: usize,
+    bytes: Vec<u8>,
+}
+
+impl BinaryPreview {
+    /// The absolute offset of the detected byte in the input.
+    #[allow(dead_code)]
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// The bytes surrounding the detected byte.
+    #[allow(dead_code)]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The position of the detected byte within `bytes`.
+    #[allow(dead_code)]
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+}
+
+/// Capture a preview of the bytes that make the given slice "binary," with
+/// up to `context` bytes on either side of the detected byte, or `None` if
+/// the slice looks like text. For in-memory slices this is all that's
+/// needed; streaming searches capture their preview in the input buffer
+/// before it is reused.
+#[allow(dead_code)]
+pub fn binary_preview(buf: &[u8], context: usize) -> Option<BinaryPreview> {
+    let off = binary_offset(buf, true)?;
+    let start = off.saturating_sub(context);
+    let end = cmp::min(buf.len(), off + context + 1);
+    Some(BinaryPreview {
+        offset: off as u64,
+        pos: off - start,
+        bytes: buf[start..end].to_vec(),
+    })
+}
+
+/// Configuration for random line sampling.
+///
+/// Whether a line is examined is a deterministic function of the seed and
+/// the line's ordinal, so a given configuration always selects the same
+/// lines from the same input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RandomSample {
+    /// The probability of examining a line, as a fraction of `u32::MAX`.
+    threshold: u32,
+    /// The seed mixed into the per-line hash.
+    pub seed: u64,
+}
+
+impl RandomSample {
+    /// Create a sampling configuration that examines each line with
+    /// probability `p` (clamped to `0.0..=1.0`).
+    pub fn new(p: f64, seed: u64) -> RandomSample {
+        let threshold = (p.clamp(0.0, 1.0) * u32::MAX as f64) as u32;
+        RandomSample { threshold, seed }
+    }
+
+    /// Returns true if the line with the given ordinal is examined.
+    #[inline(always)]
+    pub fn examine(&self, ordinal: u64) -> bool {
+        (splitmix64(self.seed ^ ordinal) >> 32) as u32 <= self.threshold
+    }
+}
+
+/// The finalizer of the splitmix64 generator, used as a cheap avalanching
+/// hash for sampling decisions.
+#[inline(always)]
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// The indentation of a reported matching line.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Indent {
+    /// The number of leading space and tab characters.
+    pub chars: usize,
+    /// The width of the leading whitespace with tabs expanded to the
+    /// requested tab stop.
+    pub width: usize,
+}
+
+/// Computes the indentation of the line given. A tab advances the width to
+/// the next multiple of `tab_stop` (clamped to a minimum of 1).
+pub fn indent_of(line: &[u8], tab_stop: usize, utf16le: bool) -> Indent {
+    let tab_stop = cmp::max(1, tab_stop);
+    let step = if utf16le { 2 } else { 1 };
+    let (mut chars, mut width, mut i) = (0, 0, 0);
+    while i + step <= line.len() {
+        if utf16le && line[i + 1] != 0 {
+            break;
+        }
+        match line[i] {
+            b' ' => width += 1,
+            b'\t' => width += tab_stop - (width % tab_stop),
+            _ => break,
+        }
+        chars += 1;
+        i += step;
+    }
+    Indent { chars, width }
+}
+
+/// Returns the length of the line given with its terminator trimmed,
+/// along with any carriage return preceding the terminator. This is how
+/// CRLF-aware reporting removes the `\r`: it is part of the terminator,
+/// not of the line.
+pub fn trim_crlf(line: &[u8], eol: u8, utf16le: bool) -> usize {
+    let mut len = line.len();
+    if utf16le {
+        if line[..len].ends_with(&[eol, 0]) {
+            len -= 2;
+            if line[..len].ends_with(&[b'\r', 0]) {
+                len -= 2;
+            }
+        }
+    } else if len > 0 && line[len - 1] == eol {
+        len -= 1;
+        if len > 0 && line[len - 1] == b'\r' {
+            len -= 1;
+        }
+    }
+    len
+}
+
+/// Inspects the buffer given for its first line terminator and reports
+/// whether that terminator is preceded by a carriage return. Returns
+/// `None` when the buffer contains no terminator at all, in which case a
+/// caller doing detection should stick with its configured default.
+pub fn detect_crlf(buf: &[u8], eol: u8, utf16le: bool) -> Option<bool> {
+    if utf16le {
+        let mut i = 0;
+        while i + 1 < buf.len() {
+            if buf[i] == eol && buf[i + 1] == 0 {
+                return Some(
+                    i >= 2 && buf[i - 2] == b'\r' && buf[i - 1] == 0);
+            }
+            i += 2;
+        }
+        None
+    } else {
+        memchr(eol, buf).map(|i| i > 0 && buf[i - 1] == b'\r')
+    }
+}
+
+/// Returns the 1-based byte column of the first match of the regex given
+/// within the line. Lines reported without a locatable match (inverted
+/// matching, or a matcher with no regex) report column 1.
+pub fn column_of(re: Option<&Regex>, line: &[u8]) -> u64 {
+    re.and_then(|re| re.find(line)).map_or(1, |m| m.start() as u64 + 1)
+}
+
+/// Returns true if the leftmost match of the regex given begins at the very
+/// first byte of the line given.
+///
+/// The line must already be known to contain a match. Without a regex,
+/// individual match positions can't be resolved, so the whole line counts as
+/// the match and is anchored by definition.
+pub fn is_anchored_match(re: Option<&Regex>, line: &[u8]) -> bool {
+    match re {
+        Some(re) => re.find(line).is_some_and(|m| m.start() == 0),
+        None => true,
+    }
+}
+
+/// Returns true if the line given has no content after stripping its
+/// terminator and any trailing carriage return. Whitespace is content, so a
+/// line of only spaces is not empty.
+pub fn is_empty_line(mut line: &[u8], eol: u8, utf16le: bool) -> bool {
+    if utf16le {
+        if line.ends_with(&[eol, 0]) {
+            line = &line[..line.len() - 2];
+        }
+        if line.ends_with(&[b'\r', 0]) {
+            line = &line[..line.len() - 2];
+        }
+    } else {
+        if line.last() == Some(&eol) {
+            line = &line[..line.len() - 1];
+        }
+        if line.last() == Some(&b'\r') {
+            line = &line[..line.len() - 1];
+        }
+    }
+    line.is_empty()
+}
+
+/// Count the number of lines in the given buffer.
+#[inline(never)]
+pub fn count_lines(buf: &[u8], eol: u8) -> u64 {
+    bytecount::count(buf, eol) as u64
+}
+
+/// Count the number of lines in the given UTF-16LE encoded buffer.
+///
+/// `buf` must begin on an even code unit boundary.
+///
+/// A line terminator is the code unit `eol` (the byte pair `eol\0` at an
+/// even offset). In the style of `bytecount`, code units are compared a
+/// 64-bit word -- four units -- at a time, branch-free, instead of
+/// hunting for candidate bytes one terminator at a time. That keeps the
+/// cost flat even when the buffer is dense with terminators or with
+/// `eol` bytes at odd offsets, both of which degrade a candidate scan.
+#[inline(never)]
+pub fn count_lines_utf16le(buf: &[u8], eol: u8) -> u64 {
+    const LO: u64 = 0x0001_0001_0001_0001;
+    const HI: u64 = 0x8000_8000_8000_8000;
+    let pat = (eol as u64).wrapping_mul(LO);
+    let mut count = 0u64;
+    let mut chunks = buf.chunks_exact(8);
+    for chunk in chunks.by_ref() {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(chunk);
+        // A code unit equal to `eol` becomes a zero 16-bit lane; the
+        // subtract-and-mask trick lights the high bit of each zero lane.
+        let x = u64::from_le_bytes(word) ^ pat;
+        count += (x.wrapping_sub(LO) & !x & HI).count_ones() as u64;
+    }
+    for pair in chunks.remainder().chunks_exact(2) {
+        if pair[0] == eol && pair[1] == 0 {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// The scan loop behind `find_bytes`, which has already rejected empty
+/// and oversized needles.
+#[inline(always)]
+fn find_bytes_inner(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    let limit = haystack.len() - needle.len() + 1;
+    let mut pos = 0;
+    while pos < limit {
+        let i = match memchr(needle[0], &haystack[pos..limit]) {
+            Some(i) => pos + i,
+            None => return None,
+        };
+        if haystack[i..i + needle.len()] == *needle {
+            return Some(i);
+        }
+        pos = i + 1;
+    }
+    None
+}
+
+/// Returns the index of the first occurrence of `needle` in `haystack`,
+/// using `memchr` on the needle's first byte to skip ahead. Intended for
+/// short needles like record terminators.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return None;
+    }
+    find_bytes_inner(haystack, needle)
+}
+
+/// Returns the index of the first code unit of the first UTF-16LE line
+/// terminator in `buf`, which is the code unit pair `eol\0`.
+///
+/// `buf` must begin on an even code unit boundary.
+#[inline(always)]
+fn find_eol_utf16le(eol: u8, buf: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    while let Some(i) = memchr(eol, &buf[pos..]).map(|i| pos + i) {
+        if i & 1 == 0 && buf.get(i + 1) == Some(&0) {
+            return Some(i);
+        }
+        pos = i + 1;
+    }
+    None
+}
+
+/// Returns the index of the first code unit of the last UTF-16LE line
+/// terminator in `buf`, which is the code unit pair `eol\0`.
+///
+/// `buf` must begin on an even code unit boundary.
+#[inline(always)]
+fn find_last_eol_utf16le(eol: u8, buf: &[u8]) -> Option<usize> {
+    let mut end = buf.len();
+    while let Some(i) = memrchr(eol, &buf[..end]) {
+        if i & 1 == 0 && buf.get(i + 1) == Some(&0) {
+            return Some(i);
+        }
+        end = i;
+    }
+    None
+}
+
+/// Returns the 1-based line number of the line containing `offset` in `buf`.
+///
+/// This is a lazy alternative to tracking line numbers during a search: it
+/// scans all of `buf[..offset]` counting line terminators, so its cost is
+/// linear in `offset`. It is intended for callers that search with line
+/// numbers disabled but occasionally need the line number of a single match
+/// after the fact. This requires the original input, so it is only possible
+/// when searching a slice (e.g., a memory map), not a plain reader.
+#[allow(dead_code)]
+pub fn line_number_at(buf: &[u8], eol: u8, utf16le: bool, offset: usize) -> u64 {
+    let upto = cmp::min(offset, buf.len());
+    let count =
+        if utf16le {
+            count_lines_utf16le(&buf[..upto], eol)
+        } else {
+            count_lines(&buf[..upto], eol)
+        };
+    count + 1
+}
+
+/// Replaces a with b in buf.
+#[allow(dead_code)]
+fn replace_buf(buf: &mut [u8], a: u8, b: u8) {
+    if a == b {
+        return;
+    }
+    let mut pos = 0;
+    while let Some(i) = memchr(a, &buf[pos..]).map(|i| pos + i) {
+        buf[i] = b;
+        pos = i + 1;
+        while buf.get(pos) == Some(&a) {
+            buf[pos] = b;
+            pos += 1;
+        }
+    }
+}
+
+/// An "iterator" over lines in a particular buffer.
+///
+/// Idiomatic Rust would borrow the buffer and use it as internal state to
+/// advance over the positions of each line. We neglect that approach to avoid
+/// the borrow in the search code. (Because the borrow prevents composition
+/// through other mutable methods.)
+pub struct IterLines {
+    eol: u8,
+    utf16le: bool,
+    pos: usize,
+}
+
+impl IterLines {
+    /// Creates a new iterator over lines starting at the position given.
+    ///
+    /// The buffer is passed to the `next` method.
+    #[inline(always)]
+    pub fn new(eol: u8, start: usize) -> IterLines {
+        IterLines {
+            eol,
+            utf16le: false,
+            pos: start,
+        }
+    }
+
+    /// If enabled, treat the buffer as UTF-16LE encoded text, where lines are
+    /// terminated by the code unit pair `\n\0`.
+    #[inline(always)]
+    pub fn utf16le(mut self, yes: bool) -> IterLines {
+        self.utf16le = yes;
+        self
+    }
+
+    /// Return the start and end position of the next line in the buffer. The
+    /// buffer given should be the same on every call.
+    ///
+    /// The range returned includes the new line.
+    #[inline(always)]
+    pub fn next(&mut self, buf: &[u8]) -> Option<(usize, usize)> {
+        let next_eol =
+            if self.utf16le {
+                find_eol_utf16le(self.eol, &buf[self.pos..])
+                    .map(|i| (i, i + 2))
+            } else {
+                memchr(self.eol, &buf[self.pos..]).map(|i| (i, i + 1))
+            };
+        match next_eol {
+            None => {
+                if self.pos < buf.len() {
+                    let start = self.pos;
+                    self.pos = buf.len();
+                    Some((start, buf.len()))
+                } else {
+                    None
+                }
+            }
+            Some((_, end)) => {
+                let start = self.pos;
+                let end = self.pos + end;
+                self.pos = end;
+                Some((start, end))
+            }
+        }
+    }
+}
+
+/// Returns the starting index of the Nth line preceding `end`.
+///
+/// If `buf` is empty, then `0` is returned. If `count` is `0`, then `end` is
+/// returned.
+///
+/// If `end` points at a new line in `buf`, then searching starts as if `end`
+/// pointed immediately before the new line.
+///
+/// The position returned corresponds to the first byte in the given line.
+#[inline(always)]
+fn start_of_previous_lines(
+    eol: u8,
+    buf: &[u8],
+    mut end: usize,
+    mut count: usize,
+) -> usize {
+    // TODO(burntsushi): This function needs to be badly simplified. The case
+    // analysis is impossible to follow.
+    if buf[..end].is_empty() {
+        return 0;
+    }
+    if count == 0 {
+        return end;
+    }
+    if end == buf.len() {
+        end -= 1;
+    }
+    if buf[end] == eol {
+        if end == 0 {
+            return end + 1;
+        }
+        end -= 1;
+    }
+    while count > 0 {
+        if buf[end] == eol {
+            count -= 1;
+            if count == 0 {
+                return end + 1;
+            }
+            if end == 0 {
+                return end;
+            }
+            end -= 1;
+            continue;
+        }
+        match memrchr(eol, &buf[..end]) {
+            None => {
+                return 0;
+            }
+            Some(i) => {
+                count -= 1;
+                end = i;
+                if end == 0 {
+                    if buf[end] == eol && count == 0 {
+                        end += 1;
+                    }
+                    return end;
+                }
+                end -= 1;
+            }
+        }
+    }
+    end + 2
+}
+
+/// Returns the starting index of the Nth line preceding `end` in a UTF-16LE
+/// encoded buffer, where lines are terminated by the code unit pair `eol\0`.
+///
+/// This is the UTF-16LE analog of `start_of_previous_lines`. `buf` must begin
+/// on an even code unit boundary; `end` is rounded down to one.
+#[inline(always)]
+fn start_of_previous_lines_utf16le(
+    eol: u8,
+    buf: &[u8],
+    mut end: usize,
+    mut count: usize,
+) -> usize {
+    if buf[..end].is_empty() {
+        return 0;
+    }
+    if count == 0 {
+        return end;
+    }
+    // Work on whole code units only. A trailing lone byte (from a read that
+    // split a code unit) is ignored.
+    end -= end & 1;
+    let last = (buf.len() - (buf.len() & 1)).saturating_sub(2);
+    if end > last {
+        end = last;
+    }
+    if buf.get(end) == Some(&eol) && buf.get(end + 1) == Some(&0) {
+        if end == 0 {
+            return end + 2;
+        }
+        end -= 2;
+    }
+    while count > 0 {
+        match find_last_eol_utf16le(eol, &buf[..end + 2]) {
+            None => {
+                return 0;
+            }
+            Some(i) => {
+                count -= 1;
+                if count == 0 {
+                    return i + 2;
+                }
+                if i == 0 {
+                    return 0;
+                }
+                end = i - 2;
+            }
+        }
+    }
+    end + 2
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::cmp;
+    use std::io;
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use grep::{Grep, GrepBuilder, Matcher};
+    use printer::{Printer, ReportGranularity};
+    use termcolor;
+
+    use super::{
+        BufferAllocation, BufferSizeStats, Checkpoint, Error,
+        FinalLinePolicy, InputBuffer, LineTerminator, LongLinePolicy,
+        Options, READ_SIZE, SearchOptions, Searcher, buffer_size_stats,
+        line_number_at, start_of_previous_lines,
+        start_of_previous_lines_utf16le,
+    };
+
+    const SHERLOCK: &str = "\
+For the Doctor Watsons of this world, as opposed to the Sherlock
+Holmeses, success in the province of detective work must always
+be, to a very large extent, the result of luck. Sherlock Holmes
+can extract a clew from a wisp of straw or a flake of cigar ash;
+but Doctor Watson has to have it taken out for him and dusted,
+and exhibited clearly, with a label attached.\
+";
+
+    const CODE: &str = "\
+extern crate snap;
+
+use std::io;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    // Wrap the stdin reader in a Snappy reader.
+    let mut rdr = snap::Reader::new(stdin.lock());
+    let mut wtr = stdout.lock();
+    io::copy(&mut rdr, &mut wtr).expect(\"I/O operation failed\");
+}
+";
+
+    fn hay(s: &str) -> io::Cursor<Vec<u8>> {
+        io::Cursor::new(s.to_string().into_bytes())
+    }
+
+    fn utf16le(s: &str) -> String {
+        s.chars().flat_map(|c| vec![c, '\0']).collect()
+    }
+
+    fn test_path() -> &'static Path {
+        Path::new("/baz.rs")
+    }
+
+    type TestSearcher<'a> = Searcher<
+        'a,
+        io::Cursor<Vec<u8>>,
+        Printer<termcolor::NoColor<Vec<u8>>>,
+        Grep,
+    >;
+
+    fn search_smallcap<F: FnMut(TestSearcher) -> TestSearcher>(
+        pat: &str,
+        haystack: &str,
+        mut map: F,
+    ) -> (u64, String) {
+        let mut inp = InputBuffer::with_capacity(1);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(haystack));
+            map(searcher).run().unwrap()
+        };
+        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
+    }
+
+    fn search_segments<F: FnMut(TestSearcher) -> TestSearcher>(
+        pat: &str,
+        segments: &[&str],
+        mut map: F,
+    ) -> (u64, String) {
+        let mut inp = InputBuffer::with_capacity(1);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(segments[0]));
+            let more: Vec<_> =
+                segments[1..].iter().map(|s| hay(s)).collect();
+            map(searcher).run_readers(more).unwrap()
+        };
+        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
+    }
+
+    fn search<F: FnMut(TestSearcher) -> TestSearcher>(
+        pat: &str,
+        haystack: &str,
+        mut map: F,
+    ) -> (u64, String) {
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(haystack));
+            map(searcher).run().unwrap()
+        };
+        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
+    }
+
+    fn search_with_printer<F, G>(
+        pat: &str,
+        haystack: &str,
+        mut pmap: F,
+        mut smap: G,
+    ) -> (u64, String)
+    where F: FnMut(Printer<termcolor::NoColor<Vec<u8>>>)
+            -> Printer<termcolor::NoColor<Vec<u8>>>,
+          G: FnMut(TestSearcher) -> TestSearcher,
+    {
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = pmap(Printer::new(outbuf).with_filename(true));
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(haystack));
+            smap(searcher).run().unwrap()
+        };
+        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
+    }
+
+    fn search_feeder<F: FnMut(TestSearcher) -> TestSearcher>(
+        chunk_size: usize,
+        pat: &str,
+        haystack: &str,
+        mut map: F,
+    ) -> (u64, String) {
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(""));
+            let mut feed = map(searcher).feeder().unwrap();
+            for chunk in haystack.as_bytes().chunks(chunk_size) {
+                feed.push(chunk).unwrap();
+            }
+            feed.finish().unwrap()
+        };
+        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
+    }
+
+    #[test]
+    fn previous_lines() {
+        let eol = b'\n';
+        let text = SHERLOCK.as_bytes();
+        assert_eq!(366, text.len());
+
+        assert_eq!(0, start_of_previous_lines(eol, text, 366, 100));
+        assert_eq!(366, start_of_previous_lines(eol, text, 366, 0));
+
+        assert_eq!(321, start_of_previous_lines(eol, text, 366, 1));
+        assert_eq!(321, start_of_previous_lines(eol, text, 365, 1));
+        assert_eq!(321, start_of_previous_lines(eol, text, 364, 1));
+        assert_eq!(321, start_of_previous_lines(eol, text, 322, 1));
+        assert_eq!(321, start_of_previous_lines(eol, text, 321, 1));
+        assert_eq!(258, start_of_previous_lines(eol, text, 320, 1));
+
+        assert_eq!(258, start_of_previous_lines(eol, text, 366, 2));
+        assert_eq!(258, start_of_previous_lines(eol, text, 365, 2));
+        assert_eq!(258, start_of_previous_lines(eol, text, 364, 2));
+        assert_eq!(258, start_of_previous_lines(eol, text, 322, 2));
+        assert_eq!(258, start_of_previous_lines(eol, text, 321, 2));
+        assert_eq!(193, start_of_previous_lines(eol, text, 320, 2));
+
+        assert_eq!(65, start_of_previous_lines(eol, text, 66, 1));
+        assert_eq!(0, start_of_previous_lines(eol, text, 66, 2));
+        assert_eq!(64, start_of_previous_lines(eol, text, 64, 0));
+        assert_eq!(0, start_of_previous_lines(eol, text, 64, 1));
+        assert_eq!(0, start_of_previous_lines(eol, text, 64, 2));
+
+        assert_eq!(0, start_of_previous_lines(eol, text, 0, 2));
+        assert_eq!(0, start_of_previous_lines(eol, text, 0, 1));
+    }
+
+    #[test]
+    fn previous_lines_short() {
+        let eol = b'\n';
+        let text = &b"a\nb\nc\nd\ne\nf\n"[..];
+        assert_eq!(12, text.len());
+
+        assert_eq!(10, start_of_previous_lines(eol, text, 12, 1));
+        assert_eq!(8, start_of_previous_lines(eol, text, 12, 2));
+        assert_eq!(6, start_of_previous_lines(eol, text, 12, 3));
+        assert_eq!(4, start_of_previous_lines(eol, text, 12, 4));
+        assert_eq!(2, start_of_previous_lines(eol, text, 12, 5));
+        assert_eq!(0, start_of_previous_lines(eol, text, 12, 6));
+        assert_eq!(0, start_of_previous_lines(eol, text, 12, 7));
+        assert_eq!(10, start_of_previous_lines(eol, text, 11, 1));
+        assert_eq!(8, start_of_previous_lines(eol, text, 11, 2));
+        assert_eq!(6, start_of_previous_lines(eol, text, 11, 3));
+        assert_eq!(4, start_of_previous_lines(eol, text, 11, 4));
+        assert_eq!(2, start_of_previous_lines(eol, text, 11, 5));
+        assert_eq!(0, start_of_previous_lines(eol, text, 11, 6));
+        assert_eq!(0, start_of_previous_lines(eol, text, 11, 7));
+        assert_eq!(10, start_of_previous_lines(eol, text, 10, 1));
+        assert_eq!(8, start_of_previous_lines(eol, text, 10, 2));
+        assert_eq!(6, start_of_previous_lines(eol, text, 10, 3));
+        assert_eq!(4, start_of_previous_lines(eol, text, 10, 4));
+        assert_eq!(2, start_of_previous_lines(eol, text, 10, 5));
+        assert_eq!(0, start_of_previous_lines(eol, text, 10, 6));
+        assert_eq!(0, start_of_previous_lines(eol, text, 10, 7));
+
+        assert_eq!(8, start_of_previous_lines(eol, text, 9, 1));
+        assert_eq!(8, start_of_previous_lines(eol, text, 8, 1));
+
+        assert_eq!(6, start_of_previous_lines(eol, text, 7, 1));
+        assert_eq!(6, start_of_previous_lines(eol, text, 6, 1));
+
+        assert_eq!(4, start_of_previous_lines(eol, text, 5, 1));
+        assert_eq!(4, start_of_previous_lines(eol, text, 4, 1));
+
+        assert_eq!(2, start_of_previous_lines(eol, text, 3, 1));
+        assert_eq!(2, start_of_previous_lines(eol, text, 2, 1));
+
+        assert_eq!(0, start_of_previous_lines(eol, text, 1, 1));
+        assert_eq!(0, start_of_previous_lines(eol, text, 0, 1));
+    }
+
+    #[test]
+    fn previous_lines_empty() {
+        let eol = b'\n';
+        let text = &b"\n\n\nd\ne\nf\n"[..];
+        assert_eq!(9, text.len());
+
+        assert_eq!(7, start_of_previous_lines(eol, text, 9, 1));
+        assert_eq!(5, start_of_previous_lines(eol, text, 9, 2));
+        assert_eq!(3, start_of_previous_lines(eol, text, 9, 3));
+        assert_eq!(2, start_of_previous_lines(eol, text, 9, 4));
+        assert_eq!(1, start_of_previous_lines(eol, text, 9, 5));
+        assert_eq!(0, start_of_previous_lines(eol, text, 9, 6));
+        assert_eq!(0, start_of_previous_lines(eol, text, 9, 7));
+
+        let text = &b"a\n\n\nd\ne\nf\n"[..];
+        assert_eq!(10, text.len());
+
+        assert_eq!(8, start_of_previous_lines(eol, text, 10, 1));
+        assert_eq!(6, start_of_previous_lines(eol, text, 10, 2));
+        assert_eq!(4, start_of_previous_lines(eol, text, 10, 3));
+        assert_eq!(3, start_of_previous_lines(eol, text, 10, 4));
+        assert_eq!(2, start_of_previous_lines(eol, text, 10, 5));
+        assert_eq!(0, start_of_previous_lines(eol, text, 10, 6));
+        assert_eq!(0, start_of_previous_lines(eol, text, 10, 7));
+    }
+
+    #[test]
+    fn previous_lines_utf16le() {
+        let eol = b'\n';
+        let text16 = utf16le("a\nb\nc\nd\n");
+        let text = text16.as_bytes();
+        assert_eq!(16, text.len());
+
+        assert_eq!(16, start_of_previous_lines_utf16le(eol, text, 16, 0));
+        assert_eq!(12, start_of_previous_lines_utf16le(eol, text, 16, 1));
+        assert_eq!(8, start_of_previous_lines_utf16le(eol, text, 16, 2));
+        assert_eq!(4, start_of_previous_lines_utf16le(eol, text, 16, 3));
+        assert_eq!(0, start_of_previous_lines_utf16le(eol, text, 16, 4));
+        assert_eq!(0, start_of_previous_lines_utf16le(eol, text, 16, 5));
+
+        assert_eq!(12, start_of_previous_lines_utf16le(eol, text, 15, 1));
+        assert_eq!(12, start_of_previous_lines_utf16le(eol, text, 13, 1));
+        assert_eq!(8, start_of_previous_lines_utf16le(eol, text, 13, 2));
+
+        assert_eq!(0, start_of_previous_lines_utf16le(eol, text, 0, 1));
+    }
+
+    #[test]
+    fn line_number_at_offset() {
+        let text = SHERLOCK.as_bytes();
+        assert_eq!(1, line_number_at(text, b'\n', false, 0));
+        assert_eq!(1, line_number_at(text, b'\n', false, 64));
+        assert_eq!(2, line_number_at(text, b'\n', false, 65));
+        assert_eq!(5, line_number_at(text, b'\n', false, 300));
+        assert_eq!(6, line_number_at(text, b'\n', false, text.len()));
+        // Offsets past the end saturate at the last line.
+        assert_eq!(6, line_number_at(text, b'\n', false, text.len() + 100));
+
+        let text16 = utf16le("foo\nbar\nbaz\n");
+        let text = text16.as_bytes();
+        assert_eq!(1, line_number_at(text, b'\n', true, 0));
+        assert_eq!(1, line_number_at(text, b'\n', true, 7));
+        assert_eq!(2, line_number_at(text, b'\n', true, 8));
+        assert_eq!(3, line_number_at(text, b'\n', true, 16));
+    }
+
+    #[test]
+    fn basic_search1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s|s);
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn utf16le_search1() {
+        let text = utf16le("foo\nbar\nbaz\n");
+        let (count, out) = search_smallcap(
+            &utf16le("bar"), &text, |s| s.utf16le(true));
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:b\0a\0r\0\n\0\n");
+    }
+
+    #[test]
+    fn utf16le_line_numbers() {
+        let text = utf16le("foo\nbar\nbaz\n");
+        let (count, out) = search_smallcap(&utf16le("ba"), &text, |s| {
+            s.utf16le(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:2:b\0a\0r\0\n\0\n/baz.rs:3:b\0a\0z\0\n\0\n");
+    }
+
+    #[test]
+    fn utf16le_invert_match() {
+        let text = utf16le("foo\nbar\nbaz\n");
+        let (count, out) = search_smallcap(&utf16le("bar"), &text, |s| {
+            s.utf16le(true).invert_match(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:f\0o\0o\0\n\0\n/baz.rs:3:b\0a\0z\0\n\0\n");
+    }
+
+    #[test]
+    fn utf16le_count() {
+        let text = utf16le("foo\nbar\nbaz\n");
+        let (count, out) = search_smallcap(&utf16le("ba"), &text, |s| {
+            s.utf16le(true).count(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2\n");
+    }
+
+    #[test]
+    fn utf16le_before_context() {
+        let text = utf16le("foo\nbar\nbaz\nquux\n");
+        let (count, out) = search_smallcap(&utf16le("baz"), &text, |s| {
+            s.utf16le(true).line_number(true).before_context(1)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs-2-b\0a\0r\0\n\0\n/baz.rs:3:b\0a\0z\0\n\0\n");
+    }
+
+    #[test]
+    fn utf16le_after_context() {
+        let text = utf16le("foo\nbar\nbaz\n");
+        let (count, out) = search_smallcap(&utf16le("bar"), &text, |s| {
+            s.utf16le(true).line_number(true).after_context(1)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:2:b\0a\0r\0\n\0\n/baz.rs-3-b\0a\0z\0\n\0\n");
+    }
+
+    #[test]
+    fn utf16le_column_numbers() {
+        let text = utf16le("foo\nbar\n");
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf)
+            .with_filename(true)
+            .column(true)
+            .utf16le(true);
+        let grep = GrepBuilder::new(&utf16le("ar")).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(&text));
+            searcher.utf16le(true).run().unwrap()
+        };
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:2:b\0a\0r\0\n\0\n");
+    }
+
+    #[test]
+    fn granularity_per_line() {
+        // One event per matching line, no matter how many matches it has.
+        let text = "aaa bbb aaa\nccc\naaa\n";
+        let (count, out) = search_with_printer(
+            "aaa", text,
+            |p| p.granularity(ReportGranularity::PerLine),
+            |s| s.line_number(true));
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:aaa bbb aaa\n/baz.rs:3:aaa\n");
+    }
+
+    #[test]
+    fn granularity_per_match() {
+        // One event per match: the first line produces two events, the
+        // second none and the third one.
+        let text = "aaa bbb aaa\nccc\naaa\n";
+        let (count, out) = search_with_printer(
+            "aaa", text,
+            |p| p.granularity(ReportGranularity::PerMatch),
+            |s| s.line_number(true));
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:aaa bbb aaa
+/baz.rs:1:aaa bbb aaa
+/baz.rs:3:aaa
+");
+    }
+
+    #[test]
+    fn granularity_only_matching_per_line() {
+        // An explicit granularity takes precedence over the one implied by
+        // only_matching, reporting just the first match on each line.
+        let text = "aaa bbb aaa\nccc\naaa\n";
+        let (count, out) = search_with_printer(
+            "aaa", text,
+            |p| p.only_matching(true)
+                 .granularity(ReportGranularity::PerLine),
+            |s| s.line_number(true));
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:aaa\n/baz.rs:3:aaa\n");
+    }
+
+    #[test]
+    fn granularity_count_matches() {
+        // count_matches counts individual matches under either granularity.
+        let text = "aaa bbb aaa\nccc\naaa\n";
+        for &granularity in &[
+            ReportGranularity::PerLine,
+            ReportGranularity::PerMatch,
+        ] {
+            let (_, out) = search_with_printer(
+                "aaa", text,
+                |p| p.granularity(granularity),
+                |s| s.count_matches(true));
+            assert_eq!(out, "/baz.rs:3\n");
+        }
+    }
+
+    #[test]
+    fn feeder_basic() {
+        let (count, out) = search_feeder(7, "Sherlock", SHERLOCK, |s|s);
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn feeder_differential() {
+        // A feeder must produce exactly the same results as running an
+        // equivalent search over the concatenated chunks, regardless of how
+        // the haystack is chunked up.
+        fn check<F: FnMut(TestSearcher) -> TestSearcher + Copy>(
+            pat: &str,
+            haystack: &str,
+            map: F,
+        ) {
+            let expected = search(pat, haystack, map);
+            for &size in &[1, 2, 3, 7, 64, 4096] {
+                let got = search_feeder(size, pat, haystack, map);
+                assert_eq!(expected, got, "chunk size: {}", size);
+            }
+        }
+        check("Sherlock", SHERLOCK, |s| s);
+        check("Sherlock", SHERLOCK, |s| s.line_number(true));
+        check("Sherlock", SHERLOCK, |s| {
+            s.invert_match(true).line_number(true)
+        });
+        check("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).before_context(2).after_context(1)
+        });
+        check("Doctor", SHERLOCK, |s| {
+            s.line_number(true).after_context(2).max_count(Some(2))
+        });
+        check("Sherlock", SHERLOCK, |s| s.count(true));
+        check("the", SHERLOCK, |s| s.count_matches(true));
+        check("Sherlock", SHERLOCK, |s| s.byte_offset(true));
+        check("Sherlock", SHERLOCK, |s| s.files_with_matches(true));
+        check("zzzz", SHERLOCK, |s| s.files_without_matches(true));
+        check("and exhibited clearly", SHERLOCK, |s| s.line_number(true));
+        check("stdin", CODE, |s| s.line_number(true).before_context(2));
+        // Record-oriented modes must flush a final record that no
+        // trailing separator closes off.
+        let para = "foo\nbar\n\nbaz\nquux\n\nfoo\n";
+        check("quux", para, |s| s.paragraph(true).line_number(true));
+        check("foo", para, |s| s.paragraph(true).line_number(true));
+        check("bar", "foo\n\nbar", |s| s.paragraph(true));
+        check("cd", "ab--cd--ef", |s| {
+            s.record_terminator(Some(b"--".to_vec()))
+        });
+        check("ef", "ab--cd--ef", |s| {
+            s.record_terminator(Some(b"--".to_vec())).line_number(true)
+        });
+        check("x", "foobarbazquux", |s| {
+            s.fixed_record_size(Some(4)).line_number(true)
+        });
+    }
+
+    #[test]
+    fn feeder_final_line_policies() {
+        // EOF for a feeder is the `finish` call; the final-line policy
+        // must be applied there exactly as the pull-based drivers apply
+        // it at EOF. SHERLOCK has no trailing newline and only its final
+        // line contains "label".
+        for &policy in &[
+            FinalLinePolicy::Match,
+            FinalLinePolicy::Ignore,
+            FinalLinePolicy::Flag,
+        ] {
+            let expected = search("label", SHERLOCK, |s| {
+                s.final_line(policy)
+            });
+            let got = search_feeder(7, "label", SHERLOCK, |s| {
+                s.final_line(policy)
+            });
+            assert_eq!(expected, got, "{:?}", policy);
+        }
+
+        // With `Flag`, the sink hears about the missing terminator.
+        use sink::Sink;
+
+        struct UnterminatedRecorder {
+            calls: usize,
+        }
+
+        impl Sink for UnterminatedRecorder {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&::regex::bytes::Regex>, _: P,
+                _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>, _: Option<u64>,
+                _: Option<super::Indent>,
+            ) {
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn unterminated<P: AsRef<Path>>(&mut self, _: P) {
+                self.calls += 1;
+            }
+            fn has_printed(&self) -> bool {
+                false
+            }
+        }
+
+        let mut inp = InputBuffer::with_capacity(4096);
+        let mut sink = UnterminatedRecorder { calls: 0 };
+        let grep = GrepBuilder::new("label").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut sink, &grep, test_path(), hay(""));
+            let mut feed = searcher
+                .final_line(FinalLinePolicy::Flag)
+                .feeder()
+                .unwrap();
+            feed.push(SHERLOCK.as_bytes()).unwrap();
+            feed.finish().unwrap();
+        }
+        assert_eq!(1, sink.calls);
+    }
+
+    #[test]
+    fn feeder_checks_config() {
+        // The push-based entry point validates its configuration like
+        // `run`, before the first chunk is accepted.
+        let mut inp = InputBuffer::new();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(""));
+            let err = searcher
+                .before_context(1000)
+                .heap_limit(Some(64))
+                .feeder()
+                .err()
+                .expect("config error");
+            assert!(matches!(err, Error::HeapLimitTooSmall { .. }));
+        }
+        let grep = GrepBuilder::new("Sherlock")
+            .line_terminator(b'\x00')
+            .build()
+            .unwrap();
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay(""));
+        let err = searcher.feeder().err().expect("config error");
+        assert!(err.to_string().contains("line terminator"));
+    }
+
+    #[test]
+    fn feeder_binary() {
+        // Binary detection happens per chunk, so only compare against a
+        // streaming search with the same read granularity.
+        let text = "Sherlock\n\x00Holmes\n";
+        let expected = search("Sherlock|Holmes", text, |s|s);
+        let got = search_feeder(4096, "Sherlock|Holmes", text, |s|s);
+        assert_eq!(expected, got);
+        assert_eq!(1, got.0);
+
+        let expected = search("Sherlock|Holmes", text, |s| s.text(true));
+        let got = search_feeder(4096, "Sherlock|Holmes", text, |s| {
+            s.text(true)
+        });
+        assert_eq!(expected, got);
+        assert_eq!(2, got.0);
+    }
+
+    #[test]
+    fn max_bytes() {
+        let text = "foo\nbar\nfoo\n";
+        // The budget ends inside the second line, which is still searched
+        // in full; the third line is never read.
+        let (count, out) = search("foo|bar", text, |s| s.max_bytes(Some(5)));
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:foo\n/baz.rs:bar\n");
+        let (count, out) =
+            search_smallcap("foo|bar", text, |s| s.max_bytes(Some(5)));
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:foo\n/baz.rs:bar\n");
+        // Measured from the starting offset.
+        let (count, out) = search("foo|bar", text, |s| {
+            s.start_offset(4).max_bytes(Some(2))
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:bar\n");
+    }
+
+    #[test]
+    fn start_offset() {
+        let text = "foo\nbar\nfoo\n";
+        let (count, out) = search("foo", text, |s| {
+            s.start_offset(4).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:8:foo\n");
+        // Small capacities skip across several reads.
+        let (count, out) = search_smallcap("foo", text, |s| {
+            s.start_offset(4).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:8:foo\n");
+        // An offset past EOF simply finds nothing.
+        let (count, out) = search("foo", text, |s| s.start_offset(100));
+        assert_eq!(0, count);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn binary() {
+        // Matches on lines before the binary byte are still delivered.
+        let text = "Sherlock\n\x00Holmes\n";
+        let (count, out) = search("Sherlock|Holmes", text, |s|s);
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:Sherlock\n");
+    }
+
+    fn search_binary_offset(
+        cap: usize,
+        pat: &str,
+        haystack: &str,
+    ) -> (u64, String, Option<u64>) {
+        let mut inp = InputBuffer::with_capacity(cap);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(haystack));
+            searcher.run().unwrap()
+        };
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        (count, out, inp.binary_byte_offset())
+    }
+
+    #[test]
+    fn binary_byte_offset_mid_buffer() {
+        // The binary byte lands in the middle of the first buffer fill;
+        // both complete lines before it are still searched.
+        let (count, out, offset) =
+            search_binary_offset(4096, "Sherlock", "Sherlock\nfoo\n\x00Holmes\n");
+        assert_eq!((1, Some(13)), (count, offset));
+        assert_eq!(out, "/baz.rs:Sherlock\n");
+    }
+
+    #[test]
+    fn binary_byte_offset_buffer_boundary() {
+        // With a one byte read size, the binary byte arrives in its own
+        // chunk after earlier fills already delivered the match.
+        let (count, out, offset) =
+            search_binary_offset(1, "ab", "ab\n\x00cd\n");
+        assert_eq!((1, Some(3)), (count, offset));
+        assert_eq!(out, "/baz.rs:ab\n");
+    }
+
+    #[test]
+    fn binary_byte_offset_before_first_terminator() {
+        // No complete line precedes the binary byte, so nothing is
+        // reported, but the offset still distinguishes this from a clean
+        // no-match search.
+        let (count, out, offset) =
+            search_binary_offset(4096, "Sherlock", "\x00Sherlock\n");
+        assert_eq!((0, Some(0)), (count, offset));
+        assert_eq!(out, "");
+        let (_, _, offset) = search_binary_offset(4096, "zzz", "Sherlock\n");
+        assert_eq!(None, offset);
+    }
+
+    #[test]
+    fn binary_text() {
+        let text = "Sherlock\n\x00Holmes\n";
+        let (count, out) = search("Sherlock|Holmes", text, |s| s.text(true));
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:Sherlock\n/baz.rs:\x00Holmes\n");
+    }
+
+    #[test]
+    fn line_numbers() {
+        let (count, out) = search_smallcap(
+            "Sherlock", SHERLOCK, |s| s.line_number(true));
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn count() {
+        let (count, out) = search_smallcap(
+            "Sherlock", SHERLOCK, |s| s.count(true));
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2\n");
+    }
+
+    #[test]
+    fn byte_offset() {
+        let (_, out) = search_smallcap(
+            "Sherlock", SHERLOCK, |s| s.byte_offset(true));
+        assert_eq!(out, "\
+/baz.rs:0:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:129:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn byte_offset_with_before_context() {
+        let (_, out) = search_smallcap("dusted", SHERLOCK, |s| {
+            s.line_number(true).byte_offset(true).before_context(2)
+        });
+        assert_eq!(out, "\
+/baz.rs-3-129-be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-4-193-can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:5:258:but Doctor Watson has to have it taken out for him and dusted,
+");
+    }
+
+    #[test]
+    fn byte_offset_inverted() {
+        let (_, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.invert_match(true).byte_offset(true)
+        });
+        assert_eq!(out, "\
+/baz.rs:65:Holmeses, success in the province of detective work must always
+/baz.rs:193:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:258:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:321:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn count_matches() {
+        let (_, out) = search_smallcap(
+            "the", SHERLOCK, |s| s.count_matches(true));
+        assert_eq!(out, "/baz.rs:4\n");
+    }
+
+    #[test]
+    fn files_with_matches() {
+        let (count, out) = search_smallcap(
+            "Sherlock", SHERLOCK, |s| s.files_with_matches(true));
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs\n");
+    }
+
+    #[test]
+    fn files_without_matches() {
+        let (count, out) = search_smallcap(
+            "zzzz", SHERLOCK, |s| s.files_without_matches(true));
+        assert_eq!(0, count);
+        assert_eq!(out, "/baz.rs\n");
+    }
+
+    #[test]
+    fn max_count() {
+        let (count, out) = search_smallcap(
+            "Sherlock", SHERLOCK, |s| s.max_count(Some(1)));
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+");
+    }
+
+    #[test]
+    fn invert_match_max_count() {
+        let (count, out) = search(
+            "zzzz", SHERLOCK, |s| s.invert_match(true).max_count(Some(1)));
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+");
+    }
+
+    #[test]
+    fn utf16_column_numbers() {
+        // "Шерлок " is 13 bytes but 7 UTF-16 code units.
+        let text = "Шерлок Холмс\n";
+        let (count, out) = search_with_printer(
+            "Холмс", text,
+            |p| p.column(true).utf16_columns(true),
+            |s| s);
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:8:Шерлок Холмс\n");
+    }
+
+    #[test]
+    fn sample_lines() {
+        let text = "aaa\naaa\naaa\naaa\naaa\naaa\n";
+        let (count, out) = search_smallcap("aaa", text, |s| {
+            s.line_number(true).sample_every_lines(Some(3))
+        });
+        assert_eq!(6, count);
+        assert_eq!(out, "/baz.rs:1:aaa\n/baz.rs:4:aaa\n");
+    }
+
+    #[test]
+    fn sample_bytes() {
+        let text = "aaa\naaa\naaa\naaa\n";
+        let (count, out) = search_smallcap("aaa", text, |s| {
+            s.byte_offset(true).sample_every_bytes(Some(8))
+        });
+        assert_eq!(4, count);
+        assert_eq!(out, "/baz.rs:0:aaa\n/baz.rs:8:aaa\n");
+    }
+
+    #[test]
+    fn sample_lines_count() {
+        let (count, out) = search("aaa", "aaa\naaa\naaa\n", |s| {
+            s.count(true).sample_every_lines(Some(2))
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "/baz.rs:3\n");
+    }
+
+    #[test]
+    fn sample_random_full_probability() {
+        // p = 1 examines every line, so the output matches a full scan.
+        let text = "aaa\nbbb\naaa\nccc\naaa\n";
+        let (count, out) = search("aaa", text, |s| {
+            s.line_number(true).sample_random(Some((1.0, 42)))
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "/baz.rs:1:aaa\n/baz.rs:3:aaa\n/baz.rs:5:aaa\n");
+    }
+
+    #[test]
+    fn sample_random_zero_probability() {
+        let (count, out) = search("aaa", "aaa\naaa\naaa\n", |s| {
+            s.sample_random(Some((0.0, 42)))
+        });
+        assert_eq!(0, count);
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn sample_random_deterministic() {
+        // The same seed always selects the same lines, and a fair coin
+        // selects some but not all of them.
+        let text: String =
+            (0..200).map(|i| format!("aaa {}\n", i)).collect();
+        let run = || search("aaa", &text, |s| {
+            s.line_number(true).sample_random(Some((0.5, 42)))
+        });
+        let (count1, out1) = run();
+        let (count2, out2) = run();
+        assert_eq!(count1, count2);
+        assert_eq!(out1, out2);
+        assert!(count1 > 0 && count1 < 200, "{}", count1);
+    }
+
+    #[test]
+    fn sample_random_chunked() {
+        // Line ordinals keep counting across buffer refills, so a tiny
+        // buffer selects the same lines as one big one.
+        let text: String =
+            (0..50).map(|i| format!("aaa {}\n", i)).collect();
+        let big = search("aaa", &text, |s| {
+            s.line_number(true).sample_random(Some((0.5, 7)))
+        });
+        let small = search_smallcap("aaa", &text, |s| {
+            s.line_number(true).sample_random(Some((0.5, 7)))
+        });
+        assert_eq!(big, small);
+    }
+
+    #[test]
+    fn sample_random_inverted() {
+        let (count, out) = search("zzz", "aaa\nbbb\n", |s| {
+            s.invert_match(true).line_number(true)
+                .sample_random(Some((1.0, 42)))
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:aaa\n/baz.rs:2:bbb\n");
+    }
+
+    #[test]
+    fn exclude_ranges() {
+        let i = SHERLOCK.find("the result of luck").unwrap() as u64;
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).exclude_ranges(vec![(i, i + 10)])
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+");
+    }
+
+    #[test]
+    fn exclude_ranges_byte_offset() {
+        let i = SHERLOCK.find("For the Doctor").unwrap() as u64;
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.byte_offset(true).exclude_ranges(vec![(i, i + 1)])
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:129:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn exclude_ranges_invert() {
+        let i = SHERLOCK.find("Holmeses").unwrap() as u64;
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.invert_match(true).exclude_ranges(vec![(i, i + 4)])
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "\
+/baz.rs:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn invert_match() {
+        let (count, out) = search_smallcap(
+            "Sherlock", SHERLOCK, |s| s.invert_match(true));
+        assert_eq!(4, count);
+        assert_eq!(out, "\
+/baz.rs:Holmeses, success in the province of detective work must always
+/baz.rs:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn invert_match_line_numbers() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.invert_match(true).line_number(true)
+        });
+        assert_eq!(4, count);
+        assert_eq!(out, "\
+/baz.rs:2:Holmeses, success in the province of detective work must always
+/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:6:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn invert_match_count() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.invert_match(true).count(true)
+        });
+        assert_eq!(4, count);
+        assert_eq!(out, "/baz.rs:4\n");
+    }
+
+    #[test]
+    fn skip_empty_lines_inverted() {
+        // Leading, trailing and consecutive empty lines are all suppressed,
+        // but line numbering still accounts for them.
+        let text = "\n\nfoo\n\nbar\n\n\n";
+        let (count, out) = search("zzz", text, |s| {
+            s.invert_match(true).skip_empty_lines(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:3:foo\n/baz.rs:5:bar\n");
+
+        // The same input on a tiny buffer must behave identically.
+        let (count2, out2) = search_smallcap("zzz", text, |s| {
+            s.invert_match(true).skip_empty_lines(true).line_number(true)
+        });
+        assert_eq!((count, out), (count2, out2));
+    }
+
+    #[test]
+    fn skip_empty_lines_empty_pattern() {
+        // A pattern that matches the empty string matches every line, but
+        // the empty lines stay suppressed.
+        let (count, out) = search(".*", "a\n\nb\n", |s| {
+            s.skip_empty_lines(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:a\n/baz.rs:3:b\n");
+    }
+
+    #[test]
+    fn skip_empty_lines_crlf() {
+        // A lone carriage return before the terminator still counts as
+        // empty.
+        let (count, out) = search(".*", "a\r\n\r\nb\r\n", |s| {
+            s.skip_empty_lines(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:a\r\n/baz.rs:3:b\r\n");
+    }
+
+    #[test]
+    fn skip_empty_lines_whitespace_is_content() {
+        // Whitespace-only lines are not empty and are still reported.
+        let (count, out) = search(".*", "  \nx\n", |s| {
+            s.skip_empty_lines(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:  \n/baz.rs:2:x\n");
+    }
+
+    #[test]
+    fn skip_empty_lines_utf16le() {
+        let text = utf16le("foo\n\nbaz\n");
+        let (count, out) = search_smallcap(&utf16le("zzz"), &text, |s| {
+            s.utf16le(true).invert_match(true)
+                .skip_empty_lines(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:f\0o\0o\0\n\0\n/baz.rs:3:b\0a\0z\0\n\0\n");
+    }
+
+    #[test]
+    fn paragraph_basic() {
+        // The whole record prints when any of its lines match, and the
+        // reported line number is the record's first line.
+        let text = "foo\nbar\n\nbaz\nquux\n\nwat\n";
+        let (count, out) = search("bar", text, |s| {
+            s.paragraph(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:foo\nbar\n");
+
+        // Records that straddle buffer fills must behave identically.
+        let (count2, out2) = search_smallcap("bar", text, |s| {
+            s.paragraph(true).line_number(true)
+        });
+        assert_eq!((count, out), (count2, out2));
+    }
+
+    #[test]
+    fn paragraph_final_record() {
+        // The last record needs no trailing blank line.
+        let text = "foo\nbar\n\nbaz\nquux\n\nfoo\n";
+        let (count, out) = search("foo", text, |s| {
+            s.paragraph(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:foo\nbar\n/baz.rs:7:foo\n");
+    }
+
+    #[test]
+    fn paragraph_inverted() {
+        let text = "foo\nbar\n\nbaz\nquux\n\nwat\n";
+        let (count, out) = search("bar", text, |s| {
+            s.paragraph(true).invert_match(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:4:baz\nquux\n/baz.rs:7:wat\n");
+    }
+
+    #[test]
+    fn paragraph_context() {
+        // Contexts count records, not lines.
+        let text = "a\n\nb\n\nMATCH\n\nc\n\nd\n";
+        let (count, out) = search("MATCH", text, |s| {
+            s.paragraph(true).before_context(1).after_context(1)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs-b\n/baz.rs:MATCH\n/baz.rs-c\n");
+    }
+
+    #[test]
+    fn paragraph_byte_offset() {
+        let (count, out) = search("baz", "foo\n\nbaz\n", |s| {
+            s.paragraph(true).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:5:baz\n");
+    }
+
+    #[test]
+    fn paragraph_count_matches() {
+        // --count-matches still counts individual pattern occurrences.
+        let (count, out) = search("o", "foo\nboo\n\nbar\n", |s| {
+            s.paragraph(true).count_matches(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:4\n");
+    }
+
+    #[test]
+    fn record_terminator_basic() {
+        // Records are split on the full terminator, which is stripped
+        // from what the printer sees.
+        let text = "foo\n---\nbar\n---\nbaz\n";
+        let (count, out) = search("bar", text, |s| {
+            s.record_terminator(Some(b"\n---\n".to_vec())).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:3:bar\n");
+    }
+
+    #[test]
+    fn record_terminator_final_record() {
+        // The trailing record needs no terminator.
+        let text = "foo\n---\nbar\n---\nbaz";
+        let (count, out) = search("ba.", text, |s| {
+            s.record_terminator(Some(b"\n---\n".to_vec())).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:3:bar\n/baz.rs:5:baz\n");
+    }
+
+    #[test]
+    fn record_terminator_straddles_reads() {
+        // Terminators split across buffer fills must still be found.
+        let text = "alpha one\nalpha two\n---\nbeta\n---\ngamma\n";
+        let want = search("beta", text, |s| {
+            s.record_terminator(Some(b"\n---\n".to_vec())).line_number(true)
+        });
+        assert_eq!(want, (1, "/baz.rs:4:beta\n".to_string()));
+        let got = search_smallcap("beta", text, |s| {
+            s.record_terminator(Some(b"\n---\n".to_vec())).line_number(true)
+        });
+        assert_eq!(want, got);
+    }
+
+    #[test]
+    fn record_terminator_single_byte() {
+        // A one-byte terminator routes to the existing `eol` fast path.
+        let want = search("b", "a:b:c", |s| s.eol(b':'));
+        let got = search("b", "a:b:c", |s| {
+            s.record_terminator(Some(b":".to_vec()))
+        });
+        assert_eq!(want, got);
+        assert_eq!(1, got.0);
+    }
+
+    #[test]
+    fn record_terminator_context() {
+        // Contexts count records, not lines.
+        let text = "one\n--\ntwo\n--\nthree\n--\nfour\n--\nfive\n";
+        let (count, out) = search("three", text, |s| {
+            s.record_terminator(Some(b"\n--\n".to_vec()))
+                .before_context(1)
+                .after_context(1)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs-two\n/baz.rs:three\n/baz.rs-four\n");
+    }
+
+    #[test]
+    fn record_terminator_byte_offset() {
+        let (count, out) = search("baz", "foo\n---\nbaz\n", |s| {
+            s.record_terminator(Some(b"\n---\n".to_vec())).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:8:baz\n");
+    }
+
+    #[test]
+    fn fixed_record_basic() {
+        // Every N bytes form one record; ordinals replace line numbers.
+        let (count, out) = search("bb", "aaaabbbbcccc", |s| {
+            s.fixed_record_size(Some(4)).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:2:bbbb\n");
+    }
+
+    #[test]
+    fn fixed_record_short_final() {
+        // A short final record at EOF is still searched.
+        let (count, out) = search("cc", "aaaabbbbcc", |s| {
+            s.fixed_record_size(Some(4)).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:3:cc\n");
+    }
+
+    #[test]
+    fn fixed_record_straddles_reads() {
+        // Records split across buffer fills must behave identically.
+        let text = "alpha-bravo-charlie-delta-echo--";
+        let want = search("char", text, |s| {
+            s.fixed_record_size(Some(8)).line_number(true)
+        });
+        assert_eq!(want, (1, "/baz.rs:2:avo-char\n".to_string()));
+        let got = search_smallcap("char", text, |s| {
+            s.fixed_record_size(Some(8)).line_number(true)
+        });
+        assert_eq!(want, got);
+    }
+
+    #[test]
+    fn fixed_record_byte_offset() {
+        let (count, out) = search("cc", "aaaabbbbcccc", |s| {
+            s.fixed_record_size(Some(4)).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:8:cccc\n");
+    }
+
+    #[test]
+    fn buffer_alloc_fixed_errors() {
+        // A line that outgrows a fixed-size buffer aborts the search
+        // with a read error instead of growing the buffer.
+        let result = search_smallcap_err("Sherlock", SHERLOCK, |s| {
+            s.buffer_alloc(BufferAllocation::Fixed)
+        });
+        match result {
+            Err(Error::Io { .. }) => {}
+            r => panic!("expected I/O error, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn buffer_alloc_policies_agree() {
+        // Eager and fixed allocation change when memory is allocated,
+        // never what is found.
+        let want = search("Sherlock", SHERLOCK, |s| s);
+        let eager = search("Sherlock", SHERLOCK, |s| {
+            s.buffer_alloc(BufferAllocation::Eager(1 << 16))
+        });
+        assert_eq!(want, eager);
+        let fixed = search("Sherlock", SHERLOCK, |s| {
+            s.buffer_alloc(BufferAllocation::Fixed)
+        });
+        assert_eq!(want, fixed);
+    }
+
+    #[test]
+    fn line_number_start_seeds_counter() {
+        // Matched lines and context lines are both numbered relative to
+        // the configured starting line.
+        let (count, out) = search("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).line_number_start(100).after_context(1)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:100:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-101-Holmeses, success in the province of detective work must always
+/baz.rs:102:be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-103-can extract a clew from a wisp of straw or a flake of cigar ash;
+");
+    }
+
+    #[test]
+    fn final_line_ignored_when_unterminated() {
+        // SHERLOCK has no trailing newline, and only its final line
+        // contains "label". POSIX-strict searching drops that line.
+        let (count, _) = search("label", SHERLOCK, |s| s);
+        assert_eq!(1, count);
+        let (count, out) = search("label", SHERLOCK, |s| {
+            s.final_line(FinalLinePolicy::Ignore)
+        });
+        assert_eq!(0, count);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn context_byte_limit_truncates() {
+        // Each match refreshes a 10-byte context budget, so each context
+        // line is cut to its first nine bytes.
+        let (count, out) = search("Sherlock", SHERLOCK, |s| {
+            s.after_context(1).context_byte_limit(Some(9))
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-Holmeses,
+/baz.rs:be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-can extra
+");
+    }
+
+    #[test]
+    fn context_byte_limit_skips() {
+        // A zero budget suppresses context lines entirely while leaving
+        // the matches themselves alone.
+        let (count, out) = search("Sherlock", SHERLOCK, |s| {
+            s.before_context(1).after_context(1).context_byte_limit(Some(0))
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn quit_after_no_match_within_window() {
+        // The needle sits past the window, so the search gives up first.
+        let mut text = "filler line\n".repeat(50);
+        text.push_str("needle\n");
+        let (count, out) = search_smallcap("needle", &text, |s| {
+            s.quit_after_no_match_within(Some(64))
+        });
+        assert_eq!(0, count);
+        assert!(out.is_empty());
+
+        // Without the window, the same search finds it.
+        let (count, _) = search_smallcap("needle", &text, |s| s);
+        assert_eq!(1, count);
+    }
+
+    #[test]
+    fn quit_window_disarmed_by_early_match() {
+        // A match before the threshold turns the limit off, so a second
+        // match far past the window is still found.
+        let mut text = String::from("needle\n");
+        text.push_str(&"filler line\n".repeat(50));
+        text.push_str("needle\n");
+        let (count, _) = search_smallcap("needle", &text, |s| {
+            s.quit_after_no_match_within(Some(64))
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn heap_limit_covers_context() {
+        // A before-context region that cannot fit in the limit is
+        // rejected before any input is read.
+        let result = search_err("Sherlock", SHERLOCK, |s| {
+            s.before_context(1000).heap_limit(Some(64))
+        });
+        match result {
+            Err(Error::HeapLimitTooSmall { required, given, .. }) => {
+                assert_eq!(64, given);
+                assert!(required > given);
+            }
+            r => panic!("expected heap limit error, got {:?}", r),
+        }
+        // The message names both numbers so the fix is obvious.
+        let msg = search_err("Sherlock", SHERLOCK, |s| {
+            s.before_context(1000).heap_limit(Some(64))
+        }).unwrap_err().to_string();
+        assert!(msg.contains("64"));
+        assert!(msg.contains("2002"));
+    }
+
+    #[test]
+    fn heap_limit_stops_growth() {
+        // The buffer starts tiny and may not grow past the limit, so a
+        // line longer than it surfaces as a read error mid-search.
+        let long = format!("short\n{}\n", "x".repeat(256));
+        let result = search_smallcap_err("x", &long, |s| {
+            s.line_number(true).heap_limit(Some(64))
+        });
+        match result {
+            Err(Error::HeapLimit { line_number, offset, limit, .. }) => {
+                assert_eq!(Some(2), line_number);
+                assert_eq!(6, offset);
+                assert_eq!(64, limit);
+            }
+            r => panic!("expected heap limit error, got {:?}", r),
+        }
+
+        // Input that fits is unaffected.
+        let (count, _) = search("Sherlock", SHERLOCK, |s| {
+            s.heap_limit(Some(1 << 20))
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn count_fast_path_agrees() {
+        // The counting loop must agree with the general path, including
+        // when several matches land on one line and across buffer rolls.
+        let hay = "aaa bbb aaa\nccc\naaa\nddd aaa\n";
+        let (fast, out) = search_smallcap("aaa", hay, |s| s.count(true));
+        let (slow, _) = search_smallcap("aaa", hay, |s| s);
+        assert_eq!(slow, fast);
+        assert_eq!(3, fast);
+        assert_eq!("/baz.rs:3\n", out);
+
+        // max_count still terminates it.
+        let (count, _) = search_smallcap(
+            "aaa", hay, |s| s.count(true).max_count(Some(2)));
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn stop_on_nonmatch_sorted() {
+        // Matches are contiguous; the first non-matching line after them
+        // ends the search, so the later "a3" is never reported.
+        let hay = "a1\na2\nb1\na3\n";
+        let (count, out) = search_smallcap("a", hay, |s| {
+            s.line_number(true).stop_on_nonmatch(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:a1\n/baz.rs:2:a2\n");
+    }
+
+    #[test]
+    fn stop_on_nonmatch_flushes_after_context() {
+        // The after-context owed to the final match still gets flushed.
+        let hay = "a1\na2\nb1\nb2\na3\n";
+        let (count, out) = search_smallcap("a", hay, |s| {
+            s.after_context(1).stop_on_nonmatch(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:a1\n/baz.rs:a2\n/baz.rs-b1\n");
+    }
+
+    #[test]
+    fn stop_on_nonmatch_inverted() {
+        // With inverted matching the roles swap: the first matching line
+        // after a delivered line ends the search.
+        let hay = "a1\na2\nb1\na3\n";
+        let (count, out) = search_smallcap("b", hay, |s| {
+            s.invert_match(true).stop_on_nonmatch(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:a1\n/baz.rs:a2\n");
+    }
+
+    #[test]
+    fn run_readers_continuous_numbering() {
+        // Line numbers carry across segment boundaries, as for rotated
+        // logs searched oldest to newest.
+        let segments = &["a\nfoo\n", "b\n", "foo\nc\n"];
+        let (count, out) = search_segments("foo", segments, |s| {
+            s.line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:foo\n/baz.rs:4:foo\n");
+    }
+
+    #[test]
+    fn run_readers_continuous_offsets() {
+        // Byte offsets are absolute over the whole logical stream.
+        let segments = &["a\nfoo\n", "b\n", "foo\nc\n"];
+        let (count, out) = search_segments("foo", segments, |s| {
+            s.byte_offset(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:foo\n/baz.rs:8:foo\n");
+    }
+
+    #[test]
+    fn run_readers_stitches_split_lines() {
+        // A line split across a boundary is two lines by default and one
+        // stitched line when requested.
+        let segments = &["fo", "o\nbar\n"];
+        let (count, _) = search_segments("foo", segments, |s| s);
+        assert_eq!(0, count);
+
+        let (count, out) = search_segments("foo", segments, |s| {
+            s.stitch_segments(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:foo\n");
+    }
+
+    #[test]
+    fn config_snapshot() {
+        let mut inp = InputBuffer::new();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let grep = GrepBuilder::new("x").build().unwrap();
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay(""));
+        let searcher = searcher.line_number(true).heap_limit(Some(64));
+        assert!(searcher.config().line_number);
+        assert_eq!(Some(64), searcher.config().heap_limit);
+        assert_ne!(*searcher.config(), Options::default());
+    }
+
+    #[test]
+    fn dyn_matcher_searches() {
+        // A searcher over a bare trait object matcher behaves like the
+        // monomorphized one.
+        let mut inp = InputBuffer::new();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let matcher: &dyn Matcher = &grep;
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, matcher, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap()
+        };
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn mismatched_line_terminator_rejected() {
+        let mut inp = InputBuffer::new();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock")
+            .line_terminator(b'\x00')
+            .build()
+            .unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            let err = searcher.run().unwrap_err();
+            assert!(err.to_string().contains("line terminator"));
+        }
+        // Configuring the searcher for the same terminator proceeds: the
+        // entire haystack is one NUL-terminated "line".
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+        assert_eq!(1, searcher.eol(b'\x00').run().unwrap());
+    }
+
+    #[test]
+    fn checkpoint_resumes_seamlessly() {
+        // The reference: one uninterrupted numbered search.
+        let (_, want) = search("Sherlock", SHERLOCK, |s| s.line_number(true));
+
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let mut cp = Checkpoint::default();
+        // First pass stops at the first match; the checkpoint records
+        // the consumption point even though the buffer holds more.
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher
+                .line_number(true)
+                .max_count(Some(1))
+                .checkpoint(&mut cp)
+                .run()
+                .unwrap();
+        }
+        assert!(!cp.eof);
+        assert_eq!(1, cp.lines);
+        assert_eq!(SHERLOCK.find("Holmeses").unwrap() as u64, cp.offset);
+        // The second pass picks up where the first stopped.
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher.line_number(true).checkpoint(&mut cp).run().unwrap();
+        }
+        assert!(cp.eof);
+        assert_eq!(SHERLOCK.len() as u64, cp.offset);
+        // The final line has no terminator, so it is not yet a complete
+        // line: if the input later grows, its continuation keeps this
+        // line's number.
+        assert_eq!(5, cp.lines);
+        let got = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(want, got);
+    }
+
+    #[test]
+    fn checkpoint_counts_without_line_numbers() {
+        // Line counting is kept for the checkpoint even when line
+        // numbers are not reported.
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let mut cp = Checkpoint::default();
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+        searcher.count(true).checkpoint(&mut cp).run().unwrap();
+        assert!(cp.eof);
+        assert_eq!(5, cp.lines);
+        assert_eq!(SHERLOCK.len() as u64, cp.offset);
+    }
+
+    #[test]
+    fn checkpoint_recorded_by_seekable_search() {
+        // `run_seekable` must leave the caller's checkpoint as current
+        // as `run` does, or a resume would replay the region it already
+        // searched.
+        let (_, want) = search("Sherlock", SHERLOCK, |s| s.line_number(true));
+
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let mut cp = Checkpoint::default();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher
+                .line_number(true)
+                .max_count(Some(1))
+                .checkpoint(&mut cp)
+                .run_seekable()
+                .unwrap();
+        }
+        assert!(!cp.eof);
+        assert_eq!(1, cp.lines);
+        assert_eq!(SHERLOCK.find("Holmeses").unwrap() as u64, cp.offset);
+        // The resume seeks to the recorded offset instead of replaying.
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher
+                .line_number(true)
+                .checkpoint(&mut cp)
+                .run_seekable()
+                .unwrap();
+        }
+        assert!(cp.eof);
+        assert_eq!(SHERLOCK.len() as u64, cp.offset);
+        assert_eq!(5, cp.lines);
+        let got = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(want, got);
+    }
+
+    /// A reader that raises its cancellation flag as a side effect of
+    /// every read, simulating another thread cancelling mid-search.
+    struct CancelAfterRead<R> {
+        rdr: R,
+        flag: Arc<AtomicBool>,
+    }
+
+    impl<R: io::Read> io::Read for CancelAfterRead<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.flag.store(true, Ordering::SeqCst);
+            self.rdr.read(buf)
+        }
+    }
+
+    #[test]
+    fn cancel_flag_stops_search() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut inp = InputBuffer::with_capacity(1);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let haystack = CancelAfterRead {
+            rdr: hay(SHERLOCK),
+            flag: flag.clone(),
+        };
+        let result = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), haystack)
+            .cancel_flag(Some(flag))
+            .run();
+        match result {
+            Err(Error::Cancelled { .. }) => {}
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("expected cancellation"),
+        }
+        // Everything delivered before the flag was observed sticks.
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+");
+    }
+
+    #[test]
+    fn cancel_flag_not_downgraded_by_best_effort() {
+        let flag = Arc::new(AtomicBool::new(true));
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let result = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK))
+            .best_effort(true)
+            .cancel_flag(Some(flag))
+            .run();
+        assert!(result.is_err_and(|err| err.is_cancelled()));
+    }
+
+    #[test]
+    fn overrides_apply() {
+        let ov = SearchOptions {
+            invert_match: Some(true),
+            max_count: Some(Some(1)),
+            ..SearchOptions::default()
+        };
+        let (count, out) = search("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).with_overrides(&ov).unwrap()
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:2:Holmeses, success in the province of detective work must always
+");
+    }
+
+    #[test]
+    fn overrides_lift_limit() {
+        let ov = SearchOptions {
+            max_count: Some(None),
+            ..SearchOptions::default()
+        };
+        let (count, _) = search("Sherlock", SHERLOCK, |s| {
+            s.max_count(Some(1)).with_overrides(&ov).unwrap()
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn overrides_reject_buffer_config() {
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+
+        let ov = SearchOptions {
+            eol: Some(b'\x00'),
+            ..SearchOptions::default()
+        };
+        match Searcher::new(&mut inp, &mut pp, &grep, test_path(),
+                            hay(SHERLOCK)).with_overrides(&ov) {
+            Err(Error::Config { .. }) => {}
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("expected a config error"),
+        }
+
+        // An override that agrees with the configuration is a no-op.
+        let agree = SearchOptions {
+            eol: Some(b'\n'),
+            ..SearchOptions::default()
+        };
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+        assert!(searcher.with_overrides(&agree).is_ok());
+    }
+
+    #[test]
+    fn anchor_line_start_basic() {
+        let text = "foo bar\nbar foo\nbar\n";
+        let (count, out) = search("bar", text, |s| {
+            s.anchor_line_start(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:bar foo\n/baz.rs:3:bar\n");
+    }
+
+    #[test]
+    fn anchor_line_start_empty_lines() {
+        // An empty line is anchored whenever the pattern matches the empty
+        // string.
+        let text = "\nfoo\n\n";
+        let (count, out) = search(".*", text, |s| {
+            s.anchor_line_start(true).line_number(true)
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "/baz.rs:1:\n/baz.rs:2:foo\n/baz.rs:3:\n");
+        let (count, out) = search("foo", text, |s| {
+            s.anchor_line_start(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:2:foo\n");
+    }
+
+    #[test]
+    fn anchor_line_start_whole_line() {
+        // A pattern anchored on both ends is unaffected.
+        let text = "bar\nxbar\nbar \n";
+        let (count, out) = search("^bar$", text, |s| {
+            s.anchor_line_start(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:bar\n");
+    }
+
+    #[test]
+    fn anchor_line_start_only_matching() {
+        // Anchoring selects lines; the printer still reports every match on
+        // a selected line.
+        let text = "bar foo bar\nfoo bar\n";
+        let (count, out) = search_with_printer(
+            "bar", text,
+            |p| p.only_matching(true),
+            |s| s.anchor_line_start(true).line_number(true));
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:bar\n/baz.rs:1:bar\n");
+    }
+
+    #[test]
+    fn anchor_line_start_inverted() {
+        // A line whose matches all begin past the first byte counts as a
+        // non-match.
+        let text = "foo bar\nbar foo\nbar\n";
+        let (count, out) = search("bar", text, |s| {
+            s.anchor_line_start(true).invert_match(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:foo bar\n");
+    }
+
+    #[test]
+    fn anchor_line_start_inverted_context() {
+        // Same, on the general inverted path that supports contexts.
+        let text = "one bar\ntwo\nbar\n";
+        let (count, out) = search("bar", text, |s| {
+            s.anchor_line_start(true).invert_match(true)
+                .line_number(true).before_context(1)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:one bar\n/baz.rs:2:two\n");
+    }
+
+    #[test]
+    fn anchor_line_start_crlf_split() {
+        // Splitting on a bare carriage return leaves the line feed of each
+        // CRLF pair at the start of the next line, so nothing after the
+        // first line can be anchored unless the pattern matches that line
+        // feed.
+        let text = "foo\r\nbar\r\nbaz";
+        let run = |pat: &str| {
+            let mut inp = InputBuffer::with_capacity(4096);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new(pat)
+                .line_terminator(b'\r').build().unwrap();
+            Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), text.as_bytes())
+                .eol(b'\r').anchor_line_start(true).run().unwrap()
+        };
+        assert_eq!(1, run("foo"));
+        assert_eq!(0, run("bar"));
+        // The line feed is ordinary line content here, so a pattern that
+        // matches it can anchor.
+        assert_eq!(1, run("\nbar"));
+    }
+
+    #[test]
+    fn crlf_terminator_trims_carriage_return() {
+        let text = "foo\r\nbar\r\nbaz\r\n";
+        let (count, out) = search("ba", text, |s| {
+            s.line_terminator(LineTerminator::Crlf).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:bar\n/baz.rs:3:baz\n");
+    }
+
+    #[test]
+    fn crlf_terminator_mixed_endings() {
+        // Lines are still split on the line feed, so mixing bare `\n`
+        // endings into the same file keeps line numbers correct and trims
+        // both styles of terminator.
+        let text = "one\r\ntwo\nthree\r\nfour";
+        let (count, out) = search("t", text, |s| {
+            s.line_terminator(LineTerminator::Crlf).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:two\n/baz.rs:3:three\n");
+        // A final line without any terminator is reported as is.
+        let (count, out) = search("four", text, |s| {
+            s.line_terminator(LineTerminator::Crlf).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:4:four\n");
+    }
+
+    #[test]
+    fn crlf_terminator_context() {
+        let text = "one\r\ntwo\r\nthree\r\n";
+        let (count, out) = search("two", text, |s| {
+            s.line_terminator(LineTerminator::Crlf).line_number(true)
+                .before_context(1).after_context(1)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs-1-one
+/baz.rs:2:two
+/baz.rs-3-three
+");
+    }
+
+    #[test]
+    fn crlf_terminator_byte_offsets() {
+        // Trimming only affects the reported line range. Byte offsets
+        // still describe the underlying input, carriage returns included.
+        let text = "one\r\ntwo\r\n";
+        let (count, out) = search("two", text, |s| {
+            s.line_terminator(LineTerminator::Crlf).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:5:two\n");
+    }
+
+    #[test]
+    fn detect_line_terminator_crlf() {
+        let text = "foo\r\nbar\r\nbaz\r\n";
+        let (count, out) = search("ba", text, |s| {
+            s.detect_line_terminator(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:bar\n/baz.rs:3:baz\n");
+        // The same input searched through a tiny buffer still detects the
+        // terminator from the first fill.
+        let (count, out) = search_smallcap("ba", text, |s| {
+            s.detect_line_terminator(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:2:bar\n/baz.rs:3:baz\n");
+    }
+
+    #[test]
+    fn detect_line_terminator_unix_overrides_crlf() {
+        // Detection replaces a configured CRLF terminator when the first
+        // chunk shows plain line feeds.
+        let text = "foo\nbar\n";
+        let (count, out) = search("bar", text, |s| {
+            s.line_terminator(LineTerminator::Crlf)
+                .detect_line_terminator(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:2:bar\n");
+    }
+
+    #[test]
+    fn detect_crlf_prefix() {
+        use super::detect_crlf;
+
+        assert_eq!(Some(true), detect_crlf(b"abc\r\ndef", b'\n', false));
+        assert_eq!(Some(false), detect_crlf(b"abc\ndef\r\n", b'\n', false));
+        assert_eq!(None, detect_crlf(b"abcdef", b'\n', false));
+        assert_eq!(None, detect_crlf(b"", b'\n', false));
+        assert_eq!(
+            Some(true),
+            detect_crlf(b"a\0\r\0\n\0b\0", b'\n', true));
+        assert_eq!(
+            Some(false),
+            detect_crlf(b"a\0\n\0b\0", b'\n', true));
+        assert_eq!(None, detect_crlf(b"a\0b\0", b'\n', true));
+    }
+
+    #[test]
+    fn passthru() {
+        let text = "one\ntwo\nthree\n";
+        let (count, out) = search("two", text, |s| {
+            s.passthru(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs-1-one
+/baz.rs:2:two
+/baz.rs-3-three
+");
+    }
+
+    #[test]
+    fn passthru_inverted() {
+        let text = "one\ntwo\nthree\n";
+        let (count, out) = search("two", text, |s| {
+            s.passthru(true).invert_match(true).line_number(true)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:one
+/baz.rs-2-two
+/baz.rs:3:three
+");
+    }
+
+    #[test]
+    fn passthru_byte_offset() {
+        let text = "one\ntwo\n";
+        let (count, out) = search("two", text, |s| {
+            s.passthru(true).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs-0-one\n/baz.rs:4:two\n");
+    }
+
+    #[test]
+    fn passthru_subsumes_context() {
+        // Context settings are ignored and no separators appear: the
+        // output already covers every line.
+        let text = "one\ntwo\nthree\nfour\nfive\n";
+        let (count, out) = search("three", text, |s| {
+            s.passthru(true).line_number(true)
+                .before_context(1).after_context(1)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs-1-one
+/baz.rs-2-two
+/baz.rs:3:three
+/baz.rs-4-four
+/baz.rs-5-five
+");
+    }
+
+    #[test]
+    fn max_count_stops_reading() {
+        // Once the limit is hit, no further input is read. This reader
+        // serves its data and then fails, so a full scan errors out while
+        // a limited one never reaches the failure.
+        let run = |max: Option<u64>| {
+            let rdr = TruncatedReader {
+                data: io::Cursor::new(
+                    b"match\nfiller\nfiller\nfiller\n".to_vec()),
+            };
+            let mut inp = InputBuffer::with_capacity(8);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new("match").build().unwrap();
+            Searcher::new(&mut inp, &mut pp, &grep, test_path(), rdr)
+                .max_count(max).run()
+        };
+        assert!(run(None).is_err());
+        assert_eq!(1, run(Some(1)).unwrap());
+    }
+
+    #[test]
+    fn max_count_after_context_drained() {
+        // The after-context owed to the final match is still delivered,
+        // even when it spans several fills of a tiny buffer.
+        let text = "match\na\nb\nc\nmatch\nd\n";
+        let (count, out) = search_smallcap("match", text, |s| {
+            s.line_number(true).after_context(3).max_count(Some(1))
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:1:match
+/baz.rs-2-a
+/baz.rs-3-b
+/baz.rs-4-c
+");
+    }
+
+    #[test]
+    fn max_count_inverted() {
+        let text = "one\ntwo\nmatch\nthree\nfour\n";
+        let (count, out) = search("match", text, |s| {
+            s.invert_match(true).line_number(true).max_count(Some(2))
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "/baz.rs:1:one\n/baz.rs:2:two\n");
+    }
+
+    #[test]
+    fn has_match() {
+        let run = |pat: &str| {
+            let mut inp = InputBuffer::with_capacity(4096);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new(pat).build().unwrap();
+            Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(),
+                io::Cursor::new(SHERLOCK.to_string().into_bytes()))
+                .has_match().unwrap()
+        };
+        assert!(run("Sherlock"));
+        assert!(!run("zzzzz"));
+    }
+
+    #[test]
+    fn has_match_stops_reading() {
+        // The reader fails once its data is exhausted, so an early hit
+        // must return before the stream is consumed while a miss has to
+        // scan everything and trips over the failure.
+        let run = |pat: &str| {
+            let rdr = TruncatedReader {
+                data: io::Cursor::new(
+                    b"match\nfiller\nfiller\nfiller\n".to_vec()),
+            };
+            let mut inp = InputBuffer::with_capacity(8);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new(pat).build().unwrap();
+            Searcher::new(&mut inp, &mut pp, &grep, test_path(), rdr)
+                .has_match()
+        };
+        assert!(run("match").unwrap());
+        assert!(run("zzzzz").is_err());
+    }
+
+    #[test]
+    fn max_line_length_skip() {
+        // The oversized line is discarded whether it fits in one fill or
+        // has to be dropped mid-accumulation, and line numbers stay
+        // correct either way.
+        let long = "x".repeat(64);
+        let text = format!("foo\n{} match {}\nbar match\n", long, long);
+        let (count, out) = search("match", &text, |s| {
+            s.line_number(true)
+                .max_line_length(Some((20, LongLinePolicy::Skip)))
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:3:bar match\n");
+        let (count, out) = search_smallcap("match", &text, |s| {
+            s.line_number(true)
+                .max_line_length(Some((20, LongLinePolicy::Skip)))
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:3:bar match\n");
+    }
+
+    #[test]
+    fn max_line_length_skip_byte_offset() {
+        let long = "x".repeat(64);
+        let text = format!("foo\n{}\nbar\n", long);
+        let (count, out) = search_smallcap("bar", &text, |s| {
+            s.byte_offset(true)
+                .max_line_length(Some((20, LongLinePolicy::Skip)))
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, format!("/baz.rs:{}:bar\n", 4 + 64 + 1));
+    }
+
+    #[test]
+    fn max_line_length_error() {
+        let long = "x".repeat(64);
+        let text = format!("foo\n{}\nbar\n", long);
+        let check = |err: Error| {
+            match err {
+                Error::LineTooLong { line_number, offset, .. } => {
+                    assert_eq!(Some(2), line_number);
+                    assert_eq!(4, offset);
+                }
+                err => panic!("unexpected error: {}", err),
+            }
+        };
+        check(search_err("bar", &text, |s| {
+            s.line_number(true)
+                .max_line_length(Some((20, LongLinePolicy::Error)))
+        }).unwrap_err());
+        check(search_smallcap_err("bar", &text, |s| {
+            s.line_number(true)
+                .max_line_length(Some((20, LongLinePolicy::Error)))
+        }).unwrap_err());
+    }
+
+    fn search_err<F>(
+        pat: &str,
+        haystack: &str,
+        mut map: F,
+    ) -> Result<u64, Error>
+        where F: FnMut(TestSearcher) -> TestSearcher
+    {
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let hay = io::Cursor::new(haystack.to_string().into_bytes());
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay);
+        map(searcher).run()
+    }
+
+    fn search_smallcap_err<F>(
+        pat: &str,
+        haystack: &str,
+        mut map: F,
+    ) -> Result<u64, Error>
+        where F: FnMut(TestSearcher) -> TestSearcher
+    {
+        let mut inp = InputBuffer::with_capacity(1);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let hay = io::Cursor::new(haystack.to_string().into_bytes());
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay);
+        map(searcher).run()
+    }
+
+    #[test]
+    fn before_context_one1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).before_context(1)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-2-Holmeses, success in the province of detective work must always
+/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn before_context_invert_one1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).before_context(1).invert_match(true)
+        });
+        assert_eq!(4, count);
+        assert_eq!(out, "\
+/baz.rs-1-For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:2:Holmeses, success in the province of detective work must always
+/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:6:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn before_context_invert_one2() {
+        let (count, out) = search_smallcap(" a ", SHERLOCK, |s| {
+            s.line_number(true).before_context(1).invert_match(true)
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:2:Holmeses, success in the province of detective work must always
+--
+/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+");
+    }
+
+    #[test]
+    fn before_context_two1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).before_context(2)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-2-Holmeses, success in the province of detective work must always
+/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn before_context_two2() {
+        let (count, out) = search_smallcap("dusted", SHERLOCK, |s| {
+            s.line_number(true).before_context(2)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+");
+    }
+
+    #[test]
+    fn before_context_two3() {
+        let (count, out) = search_smallcap(
+            "success|attached", SHERLOCK, |s| {
+                s.line_number(true).before_context(2)
+            });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs-1-For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:2:Holmeses, success in the province of detective work must always
+--
+/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:6:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn before_context_two4() {
+        let (count, out) = search("stdin", CODE, |s| {
+            s.line_number(true).before_context(2)
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "\
+/baz.rs-4-
+/baz.rs-5-fn main() {
+/baz.rs:6:    let stdin = io::stdin();
+/baz.rs-7-    let stdout = io::stdout();
+/baz.rs-8-
+/baz.rs:9:    // Wrap the stdin reader in a Snappy reader.
+/baz.rs:10:    let mut rdr = snap::Reader::new(stdin.lock());
+");
+    }
+
+    #[test]
+    fn before_context_two5() {
+        let (count, out) = search("stdout", CODE, |s| {
+            s.line_number(true).before_context(2)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs-5-fn main() {
+/baz.rs-6-    let stdin = io::stdin();
+/baz.rs:7:    let stdout = io::stdout();
+--
+/baz.rs-9-    // Wrap the stdin reader in a Snappy reader.
+/baz.rs-10-    let mut rdr = snap::Reader::new(stdin.lock());
+/baz.rs:11:    let mut wtr = stdout.lock();
+");
+    }
+
+    #[test]
+    fn before_context_three1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+                s.line_number(true).before_context(3)
+            });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-2-Holmeses, success in the province of detective work must always
+/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
+
+    #[test]
+    fn after_context_one1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).after_context(1)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-2-Holmeses, success in the province of detective work must always
+/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
+");
+    }
+
+    #[test]
+    fn after_context_invert_one1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).after_context(1).invert_match(true)
+        });
+        assert_eq!(4, count);
+        assert_eq!(out, "\
+/baz.rs:2:Holmeses, success in the province of detective work must always
+/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs:6:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn after_context_invert_one2() {
+        let (count, out) = search_smallcap(" a ", SHERLOCK, |s| {
+            s.line_number(true).after_context(1).invert_match(true)
+        });
+        assert_eq!(3, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:2:Holmeses, success in the province of detective work must always
+/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
+--
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs-6-and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn after_context_invert_one_max_count_two() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true)
+             .invert_match(true)
+             .after_context(1)
+             .max_count(Some(2))
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:2:Holmeses, success in the province of detective work must always
+/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
+");
+    }
+
+    #[test]
+    fn after_context_two1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).after_context(2)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-2-Holmeses, success in the province of detective work must always
+/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
+");
+    }
+
+    #[test]
+    fn after_context_two2() {
+        let (count, out) = search_smallcap("dusted", SHERLOCK, |s| {
+            s.line_number(true).after_context(2)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs-6-and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn after_context_two3() {
+        let (count, out) = search_smallcap(
+            "success|attached", SHERLOCK, |s| {
+                s.line_number(true).after_context(2)
+            });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:2:Holmeses, success in the province of detective work must always
+/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
+--
+/baz.rs:6:and exhibited clearly, with a label attached.
+");
+    }
+
+    #[test]
+    fn after_context_two_max_count_two() {
+        let (count, out) = search_smallcap(
+            "Doctor", SHERLOCK, |s| {
+                s.line_number(true).after_context(2).max_count(Some(2))
+            });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-2-Holmeses, success in the province of detective work must always
+/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
+--
+/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs-6-and exhibited clearly, with a label attached.
+");
     }
-    end + 2
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io;
-    use std::path::Path;
+    #[test]
+    fn after_context_three1() {
+        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
+            s.line_number(true).after_context(3)
+        });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs-2-Holmeses, success in the province of detective work must always
+/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
+/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
+/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
+/baz.rs-6-and exhibited clearly, with a label attached.
+");
+    }
 
-    use grep::GrepBuilder;
-    use printer::Printer;
-    use termcolor;
+    #[test]
+    fn before_after_context_two1() {
+        let (count, out) = search(
+            r"fn main|let mut rdr", CODE, |s| {
+                s.line_number(true).after_context(2).before_context(2)
+            });
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs-3-use std::io;
+/baz.rs-4-
+/baz.rs:5:fn main() {
+/baz.rs-6-    let stdin = io::stdin();
+/baz.rs-7-    let stdout = io::stdout();
+/baz.rs-8-
+/baz.rs-9-    // Wrap the stdin reader in a Snappy reader.
+/baz.rs:10:    let mut rdr = snap::Reader::new(stdin.lock());
+/baz.rs-11-    let mut wtr = stdout.lock();
+/baz.rs-12-    io::copy(&mut rdr, &mut wtr).expect(\"I/O operation failed\");
+");
+    }
 
-    use super::{InputBuffer, Searcher, start_of_previous_lines};
+    #[test]
+    fn adaptive_search() {
+        let mut inp = InputBuffer::adaptive();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap()
+        };
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(2, count);
+        assert_eq!(out, "\
+/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:be, to a very large extent, the result of luck. Sherlock Holmes
+");
+    }
 
-    const SHERLOCK: &'static str = "\
-For the Doctor Watsons of this world, as opposed to the Sherlock
-Holmeses, success in the province of detective work must always
-be, to a very large extent, the result of luck. Sherlock Holmes
-can extract a clew from a wisp of straw or a flake of cigar ash;
-but Doctor Watson has to have it taken out for him and dusted,
-and exhibited clearly, with a label attached.\
-";
+    #[test]
+    fn adaptive_records_line_lengths() {
+        // The statistics are global, so other tests (and the other assert in
+        // this one) may run concurrently. Everything recorded is monotone,
+        // which keeps these assertions safe.
+        let long = format!("{}\n", "x".repeat(300));
+        let mut inp = InputBuffer::adaptive();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("x").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(&long));
+            searcher.run().unwrap();
+        }
+        let stats = buffer_size_stats();
+        assert!(stats.max_line_len() >= 300);
+        assert!(stats.typical_line_len() > 0);
+    }
 
-    const CODE: &'static str = "\
-extern crate snap;
+    #[test]
+    fn adaptive_suggested_capacity() {
+        use std::sync::atomic::AtomicUsize;
 
-use std::io;
+        let stats = BufferSizeStats {
+            bytes: AtomicUsize::new(0),
+            lines: AtomicUsize::new(0),
+            max_line: AtomicUsize::new(0),
+        };
+        // Nothing observed yet: stick with the default.
+        assert_eq!(stats.suggested_capacity(), super::READ_SIZE);
 
-fn main() {
-    let stdin = io::stdin();
-    let stdout = io::stdout();
+        // Short lines: a batch of typical lines, with a floor.
+        stats.record_read(5_000, 100);
+        assert_eq!(stats.typical_line_len(), 50);
+        assert_eq!(stats.suggested_capacity(), 8192);
 
-    // Wrap the stdin reader in a Snappy reader.
-    let mut rdr = snap::Reader::new(stdin.lock());
-    let mut wtr = stdout.lock();
-    io::copy(&mut rdr, &mut wtr).expect(\"I/O operation failed\");
-}
-";
+        // One monster line dominates, rounded up to a power of two.
+        stats.record_line_len(5 << 20);
+        assert_eq!(stats.max_line_len(), 5 << 20);
+        assert_eq!(stats.suggested_capacity(), 8 << 20);
 
-    fn hay(s: &str) -> io::Cursor<Vec<u8>> {
-        io::Cursor::new(s.to_string().into_bytes())
+        // An even bigger one hits the hard cap.
+        stats.record_line_len(100 << 20);
+        assert_eq!(stats.suggested_capacity(), super::MAX_ADAPTIVE_CAPACITY);
     }
 
-    fn test_path() -> &'static Path {
-        &Path::new("/baz.rs")
+    #[test]
+    fn shrink_after_huge_line() {
+        let huge = format!("{}\n", "x".repeat(1 << 20));
+        let mut inp = InputBuffer::with_capacity(4096);
+        inp.shrink_excess(Some(4));
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("x").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(&huge));
+            searcher.run().unwrap();
+        }
+        assert!(inp.capacity() >= 1 << 20);
+        // The next search saw the huge line, so its reset must not shrink.
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap();
+        }
+        assert!(inp.capacity() >= 1 << 20);
+        // But the search after that saw only short lines, so now the
+        // capacity is excessive and the buffer shrinks. (The search itself
+        // may then grow the buffer again by a read's worth to make room for
+        // rolled over context, hence the inequality.)
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap();
+        }
+        assert!(inp.capacity() <= 2 * 4096);
     }
 
-    type TestSearcher<'a> = Searcher<
-        'a,
-        io::Cursor<Vec<u8>>,
-        termcolor::NoColor<Vec<u8>>,
-    >;
-
-    fn search_smallcap<F: FnMut(TestSearcher) -> TestSearcher>(
-        pat: &str,
-        haystack: &str,
-        mut map: F,
-    ) -> (u64, String) {
-        let mut inp = InputBuffer::with_capacity(1);
+    #[test]
+    fn peak_capacity_survives_shrink() {
+        // One long line amid short ones grows the buffer transiently. With
+        // a shrink policy, later searches release the memory, but the peak
+        // still reports what the search that saw the long line needed.
+        let mixed = format!("short\n{}\nshort\n", "x".repeat(1 << 20));
+        let mut inp = InputBuffer::with_capacity(4096);
+        inp.shrink_excess(Some(4));
         let outbuf = termcolor::NoColor::new(vec![]);
         let mut pp = Printer::new(outbuf).with_filename(true);
-        let grep = GrepBuilder::new(pat).build().unwrap();
-        let count = {
+        let grep = GrepBuilder::new("x").build().unwrap();
+        {
             let searcher = Searcher::new(
-                &mut inp, &mut pp, &grep, test_path(), hay(haystack));
-            map(searcher).run().unwrap()
-        };
-        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
+                &mut inp, &mut pp, &grep, test_path(), hay(&mixed));
+            searcher.run().unwrap();
+        }
+        let peak = inp.peak_capacity();
+        assert!(peak >= 1 << 20);
+        // Two short searches later the buffer has shrunk, and the new peak
+        // reflects only what those searches needed.
+        for _ in 0..2 {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap();
+        }
+        assert!(inp.capacity() < peak);
+        assert!(inp.peak_capacity() < peak);
+        assert_eq!(inp.peak_capacity(), inp.capacity());
     }
 
-    fn search<F: FnMut(TestSearcher) -> TestSearcher>(
-        pat: &str,
-        haystack: &str,
-        mut map: F,
-    ) -> (u64, String) {
+    #[test]
+    fn shrink_to_fit_releases_memory() {
+        let huge = format!("{}\n", "x".repeat(1 << 20));
         let mut inp = InputBuffer::with_capacity(4096);
         let outbuf = termcolor::NoColor::new(vec![]);
         let mut pp = Printer::new(outbuf).with_filename(true);
-        let grep = GrepBuilder::new(pat).build().unwrap();
-        let count = {
+        let grep = GrepBuilder::new("x").build().unwrap();
+        {
             let searcher = Searcher::new(
-                &mut inp, &mut pp, &grep, test_path(), hay(haystack));
-            map(searcher).run().unwrap()
-        };
-        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
+                &mut inp, &mut pp, &grep, test_path(), hay(&huge));
+            searcher.run().unwrap();
+        }
+        // Without a shrink policy the monster line's capacity is pinned
+        // until the caller explicitly lets go of it.
+        assert!(inp.capacity() >= 1 << 20);
+        inp.shrink_to_fit();
+        assert_eq!(4096, inp.capacity());
+        // The buffer is still fully usable afterwards.
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+        assert_eq!(2, searcher.run().unwrap());
     }
 
     #[test]
-    fn previous_lines() {
-        let eol = b'\n';
-        let text = SHERLOCK.as_bytes();
-        assert_eq!(366, text.len());
+    fn no_shrink_without_policy() {
+        let huge = format!("{}\n", "x".repeat(1 << 20));
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("x").build().unwrap();
+        for _ in 0..3 {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(&huge));
+            searcher.run().unwrap();
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap();
+        }
+        assert!(inp.capacity() >= 1 << 20);
+    }
 
-        assert_eq!(0, start_of_previous_lines(eol, text, 366, 100));
-        assert_eq!(366, start_of_previous_lines(eol, text, 366, 0));
+    #[test]
+    fn shrink_manual_reset() {
+        let huge = format!("{}\n", "x".repeat(1 << 20));
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("x").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(&huge));
+            searcher.run().unwrap();
+        }
+        assert!(inp.capacity() >= 1 << 20);
+        inp.reset_buffers();
+        assert_eq!(inp.capacity(), 4096);
+    }
 
-        assert_eq!(321, start_of_previous_lines(eol, text, 366, 1));
-        assert_eq!(321, start_of_previous_lines(eol, text, 365, 1));
-        assert_eq!(321, start_of_previous_lines(eol, text, 364, 1));
-        assert_eq!(321, start_of_previous_lines(eol, text, 322, 1));
-        assert_eq!(321, start_of_previous_lines(eol, text, 321, 1));
-        assert_eq!(258, start_of_previous_lines(eol, text, 320, 1));
+    struct CountingReader {
+        data: io::Cursor<Vec<u8>>,
+        reads: Rc<Cell<usize>>,
+    }
 
-        assert_eq!(258, start_of_previous_lines(eol, text, 366, 2));
-        assert_eq!(258, start_of_previous_lines(eol, text, 365, 2));
-        assert_eq!(258, start_of_previous_lines(eol, text, 364, 2));
-        assert_eq!(258, start_of_previous_lines(eol, text, 322, 2));
-        assert_eq!(258, start_of_previous_lines(eol, text, 321, 2));
-        assert_eq!(193, start_of_previous_lines(eol, text, 320, 2));
+    impl io::Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.data.read(buf)
+        }
 
-        assert_eq!(65, start_of_previous_lines(eol, text, 66, 1));
-        assert_eq!(0, start_of_previous_lines(eol, text, 66, 2));
-        assert_eq!(64, start_of_previous_lines(eol, text, 64, 0));
-        assert_eq!(0, start_of_previous_lines(eol, text, 64, 1));
-        assert_eq!(0, start_of_previous_lines(eol, text, 64, 2));
+        fn read_vectored(
+            &mut self,
+            bufs: &mut [io::IoSliceMut],
+        ) -> io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            let mut total = 0;
+            for buf in bufs {
+                let n = self.data.read(buf)?;
+                total += n;
+                if n < buf.len() {
+                    break;
+                }
+            }
+            Ok(total)
+        }
+    }
 
-        assert_eq!(0, start_of_previous_lines(eol, text, 0, 2));
-        assert_eq!(0, start_of_previous_lines(eol, text, 0, 1));
+    fn search_counting_reads(vectored: bool) -> (usize, String) {
+        let reads = Rc::new(Cell::new(0));
+        let rdr = CountingReader {
+            data: io::Cursor::new(SHERLOCK.to_string().into_bytes()),
+            reads: reads.clone(),
+        };
+        let mut inp = InputBuffer::with_capacity(8);
+        inp.vectored(vectored);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), rdr);
+            searcher.run().unwrap();
+        }
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        (reads.get(), out)
     }
 
     #[test]
-    fn previous_lines_short() {
-        let eol = b'\n';
-        let text = &b"a\nb\nc\nd\ne\nf\n"[..];
-        assert_eq!(12, text.len());
-
-        assert_eq!(10, start_of_previous_lines(eol, text, 12, 1));
-        assert_eq!(8, start_of_previous_lines(eol, text, 12, 2));
-        assert_eq!(6, start_of_previous_lines(eol, text, 12, 3));
-        assert_eq!(4, start_of_previous_lines(eol, text, 12, 4));
-        assert_eq!(2, start_of_previous_lines(eol, text, 12, 5));
-        assert_eq!(0, start_of_previous_lines(eol, text, 12, 6));
-        assert_eq!(0, start_of_previous_lines(eol, text, 12, 7));
-        assert_eq!(10, start_of_previous_lines(eol, text, 11, 1));
-        assert_eq!(8, start_of_previous_lines(eol, text, 11, 2));
-        assert_eq!(6, start_of_previous_lines(eol, text, 11, 3));
-        assert_eq!(4, start_of_previous_lines(eol, text, 11, 4));
-        assert_eq!(2, start_of_previous_lines(eol, text, 11, 5));
-        assert_eq!(0, start_of_previous_lines(eol, text, 11, 6));
-        assert_eq!(0, start_of_previous_lines(eol, text, 11, 7));
-        assert_eq!(10, start_of_previous_lines(eol, text, 10, 1));
-        assert_eq!(8, start_of_previous_lines(eol, text, 10, 2));
-        assert_eq!(6, start_of_previous_lines(eol, text, 10, 3));
-        assert_eq!(4, start_of_previous_lines(eol, text, 10, 4));
-        assert_eq!(2, start_of_previous_lines(eol, text, 10, 5));
-        assert_eq!(0, start_of_previous_lines(eol, text, 10, 6));
-        assert_eq!(0, start_of_previous_lines(eol, text, 10, 7));
-
-        assert_eq!(8, start_of_previous_lines(eol, text, 9, 1));
-        assert_eq!(8, start_of_previous_lines(eol, text, 8, 1));
-
-        assert_eq!(6, start_of_previous_lines(eol, text, 7, 1));
-        assert_eq!(6, start_of_previous_lines(eol, text, 6, 1));
-
-        assert_eq!(4, start_of_previous_lines(eol, text, 5, 1));
-        assert_eq!(4, start_of_previous_lines(eol, text, 4, 1));
+    fn vectored_reads() {
+        let (scalar_reads, scalar_out) = search_counting_reads(false);
+        let (vectored_reads, vectored_out) = search_counting_reads(true);
+        assert_eq!(scalar_out, vectored_out);
+        assert!(vectored_reads < scalar_reads,
+                "expected fewer than {} reads, got {}",
+                scalar_reads, vectored_reads);
+    }
 
-        assert_eq!(2, start_of_previous_lines(eol, text, 3, 1));
-        assert_eq!(2, start_of_previous_lines(eol, text, 2, 1));
+    /// A reader that returns everything it has and then fails every
+    /// subsequent read, like a stream cut off by a transport error.
+    struct TruncatedReader {
+        data: io::Cursor<Vec<u8>>,
+    }
 
-        assert_eq!(0, start_of_previous_lines(eol, text, 1, 1));
-        assert_eq!(0, start_of_previous_lines(eol, text, 0, 1));
+    impl io::Read for TruncatedReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let pos = self.data.position();
+            if pos == self.data.get_ref().len() as u64 {
+                return Err(io::Error::other("bad sector"));
+            }
+            self.data.read(buf)
+        }
     }
 
-    #[test]
-    fn previous_lines_empty() {
-        let eol = b'\n';
-        let text = &b"\n\n\nd\ne\nf\n"[..];
-        assert_eq!(9, text.len());
+    /// A seekable reader with a range of unreadable bytes in the middle.
+    /// Reads stop short of the bad region and fail inside it; seeking works
+    /// everywhere.
+    struct BadRegionReader {
+        data: io::Cursor<Vec<u8>>,
+        bad: ::std::ops::Range<u64>,
+    }
 
-        assert_eq!(7, start_of_previous_lines(eol, text, 9, 1));
-        assert_eq!(5, start_of_previous_lines(eol, text, 9, 2));
-        assert_eq!(3, start_of_previous_lines(eol, text, 9, 3));
-        assert_eq!(2, start_of_previous_lines(eol, text, 9, 4));
-        assert_eq!(1, start_of_previous_lines(eol, text, 9, 5));
-        assert_eq!(0, start_of_previous_lines(eol, text, 9, 6));
-        assert_eq!(0, start_of_previous_lines(eol, text, 9, 7));
+    impl io::Read for BadRegionReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let pos = self.data.position();
+            if self.bad.contains(&pos) {
+                return Err(io::Error::other("bad sector"));
+            }
+            let len = if pos < self.bad.start {
+                cmp::min(buf.len() as u64, self.bad.start - pos) as usize
+            } else {
+                buf.len()
+            };
+            self.data.read(&mut buf[..len])
+        }
+    }
 
-        let text = &b"a\n\n\nd\ne\nf\n"[..];
-        assert_eq!(10, text.len());
+    impl io::Seek for BadRegionReader {
+        fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+            self.data.seek(pos)
+        }
+    }
 
-        assert_eq!(8, start_of_previous_lines(eol, text, 10, 1));
-        assert_eq!(6, start_of_previous_lines(eol, text, 10, 2));
-        assert_eq!(4, start_of_previous_lines(eol, text, 10, 3));
-        assert_eq!(3, start_of_previous_lines(eol, text, 10, 4));
-        assert_eq!(2, start_of_previous_lines(eol, text, 10, 5));
-        assert_eq!(0, start_of_previous_lines(eol, text, 10, 6));
-        assert_eq!(0, start_of_previous_lines(eol, text, 10, 7));
+    fn peek_search<F>(pos: u64, pat: &str, map: F) -> (u64, String)
+        where F: FnMut(TestSearcher) -> TestSearcher
+    {
+        let mut map = map;
+        let mut cur = io::Cursor::new(
+            b"one\ntwo\nthree\nfour\nfive\n".to_vec());
+        cur.set_position(pos);
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), cur);
+            map(searcher).run_seekable().unwrap()
+        };
+        (count, String::from_utf8(pp.into_inner().into_inner()).unwrap())
     }
 
     #[test]
-    fn basic_search1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s|s);
-        assert_eq!(2, count);
+    fn peek_back_full_context() {
+        // The range starts at "three"; both context lines live before it.
+        let (count, out) = peek_search(8, "three", |s| {
+            s.before_context(2).peek_back(Some(1024)).line_number(true)
+        });
+        assert_eq!(1, count);
         assert_eq!(out, "\
-/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs:be, to a very large extent, the result of luck. Sherlock Holmes
-");
+/baz.rs-1-one\n/baz.rs-2-two\n/baz.rs:3:three\n");
     }
 
     #[test]
-    fn binary() {
-        let text = "Sherlock\n\x00Holmes\n";
-        let (count, out) = search("Sherlock|Holmes", text, |s|s);
-        assert_eq!(0, count);
-        assert_eq!(out, "");
+    fn peek_back_true_offsets() {
+        let (count, out) = peek_search(8, "three", |s| {
+            s.before_context(2).peek_back(Some(1024)).byte_offset(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs-0-one\n/baz.rs-4-two\n/baz.rs:8:three\n");
     }
 
     #[test]
-    fn binary_text() {
-        let text = "Sherlock\n\x00Holmes\n";
-        let (count, out) = search("Sherlock|Holmes", text, |s| s.text(true));
-        assert_eq!(2, count);
-        assert_eq!(out, "/baz.rs:Sherlock\n/baz.rs:\x00Holmes\n");
+    fn peek_back_capped() {
+        // A 6 byte cap reaches back into the middle of "one": the cut
+        // line is still offered as context, and line numbers are relative
+        // to the peeked region.
+        let (count, out) = peek_search(8, "three", |s| {
+            s.before_context(2).peek_back(Some(6)).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "\
+/baz.rs-1-e\n/baz.rs-2-two\n/baz.rs:3:three\n");
     }
 
     #[test]
-    fn line_numbers() {
-        let (count, out) = search_smallcap(
-            "Sherlock", SHERLOCK, |s| s.line_number(true));
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
-");
+    fn peek_back_region_never_matches() {
+        // The pattern only occurs before the range start, so nothing is
+        // reported: peeked lines are context material, not search input.
+        let (count, out) = peek_search(8, "two", |s| {
+            s.before_context(1).peek_back(Some(1024)).line_number(true)
+        });
+        assert_eq!(0, count);
+        assert_eq!(out, "");
     }
 
     #[test]
-    fn count() {
-        let (count, out) = search_smallcap(
-            "Sherlock", SHERLOCK, |s| s.count(true));
-        assert_eq!(2, count);
-        assert_eq!(out, "/baz.rs:2\n");
+    fn peek_back_ignored_without_context() {
+        let (count, out) = peek_search(8, "three", |s| {
+            s.peek_back(Some(1024)).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:three\n");
     }
 
     #[test]
-    fn byte_offset() {
-        let (_, out) = search_smallcap(
-            "Sherlock", SHERLOCK, |s| s.byte_offset(true));
-        assert_eq!(out, "\
-/baz.rs:0:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs:129:be, to a very large extent, the result of luck. Sherlock Holmes
-");
+    fn indent_of_widths() {
+        use super::{Indent, indent_of};
+
+        assert_eq!(Indent { chars: 0, width: 0 }, indent_of(b"x", 8, false));
+        assert_eq!(Indent { chars: 2, width: 2 }, indent_of(b"  x", 8, false));
+        assert_eq!(Indent { chars: 1, width: 8 }, indent_of(b"\tx", 8, false));
+        // A tab after spaces advances to the next stop, not by a fixed
+        // width.
+        assert_eq!(
+            Indent { chars: 3, width: 8 }, indent_of(b"  \tx", 8, false));
+        assert_eq!(
+            Indent { chars: 2, width: 8 }, indent_of(b"\t\tx", 4, false));
+        // A whitespace-only line is all indentation.
+        assert_eq!(Indent { chars: 2, width: 2 }, indent_of(b"  ", 8, false));
+        // UTF-16LE indentation is measured in code units.
+        assert_eq!(
+            Indent { chars: 1, width: 4 },
+            indent_of(b"\t\x00x\x00", 4, true));
     }
 
     #[test]
-    fn byte_offset_with_before_context() {
-        let (_, out) = search_smallcap("dusted", SHERLOCK, |s| {
-            s.line_number(true).byte_offset(true).before_context(2)
-        });
-        assert_eq!(out, "\
-/baz.rs-3-129-be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs-4-193-can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:5:258:but Doctor Watson has to have it taken out for him and dusted,
-");
+    fn best_effort_default_fails_fast() {
+        let rdr = TruncatedReader {
+            data: io::Cursor::new(b"foo\nbar\n".to_vec()),
+        };
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(".").build().unwrap();
+        let searcher = Searcher::new(
+            &mut inp, &mut pp, &grep, test_path(), rdr);
+        assert!(searcher.run().is_err());
     }
 
     #[test]
-    fn byte_offset_inverted() {
-        let (_, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.invert_match(true).byte_offset(true)
-        });
-        assert_eq!(out, "\
-/baz.rs:65:Holmeses, success in the province of detective work must always
-/baz.rs:193:can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:258:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs:321:and exhibited clearly, with a label attached.
-");
+    fn best_effort_stream_gives_up_gracefully() {
+        // A plain reader can't seek past the failure, so best-effort mode
+        // reports what was found before the error instead of failing.
+        let rdr = TruncatedReader {
+            data: io::Cursor::new(b"foo\nbar\n".to_vec()),
+        };
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(".").build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), rdr);
+            searcher.best_effort(true).run().unwrap()
+        };
+        assert_eq!(2, count);
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(out, "/baz.rs:foo\n/baz.rs:bar\n");
     }
 
     #[test]
-    fn count_matches() {
-        let (_, out) = search_smallcap(
-            "the", SHERLOCK, |s| s.count_matches(true));
-        assert_eq!(out, "/baz.rs:4\n");
+    fn best_effort_seekable_skips_bad_region() {
+        let mut data = b"aaa\nbbb\n".to_vec();
+        data.extend_from_slice(&[b'X'; 8]);
+        data.extend_from_slice(b"ccc\n");
+        let rdr = BadRegionReader {
+            data: io::Cursor::new(data),
+            bad: 8..16,
+        };
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(r"\w").build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), rdr);
+            searcher.best_effort(true).skip_increment(8)
+                .line_number(true).run_seekable().unwrap()
+        };
+        // The line after the skipped region is still found. Its number
+        // doesn't account for anything inside the region, which is exactly
+        // the documented approximation.
+        assert_eq!(3, count);
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        assert_eq!(out, "/baz.rs:1:aaa\n/baz.rs:2:bbb\n/baz.rs:3:ccc\n");
     }
 
     #[test]
-    fn files_with_matches() {
-        let (count, out) = search_smallcap(
-            "Sherlock", SHERLOCK, |s| s.files_with_matches(true));
-        assert_eq!(1, count);
-        assert_eq!(out, "/baz.rs\n");
+    fn best_effort_reports_to_sink() {
+        use sink::Sink;
+
+        struct ErrorRecorder {
+            errors: Vec<(u64, String)>,
+        }
+
+        impl Sink for ErrorRecorder {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&::regex::bytes::Regex>, _: P,
+                _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>, _: Option<u64>,
+                _: Option<super::Indent>,
+            ) {
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn read_error<P: AsRef<Path>>(
+                &mut self, _: P, offset: u64, err: &io::Error,
+            ) {
+                self.errors.push((offset, err.to_string()));
+            }
+            fn has_printed(&self) -> bool {
+                !self.errors.is_empty()
+            }
+        }
+
+        let rdr = TruncatedReader {
+            data: io::Cursor::new(b"foo\nbar\n".to_vec()),
+        };
+        let mut inp = InputBuffer::with_capacity(4096);
+        let mut sink = ErrorRecorder { errors: vec![] };
+        let grep = GrepBuilder::new(".").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut sink, &grep, test_path(), rdr);
+            searcher.best_effort(true).run().unwrap();
+        }
+        assert_eq!(vec![(8, "bad sector".to_string())], sink.errors);
     }
 
     #[test]
-    fn files_without_matches() {
-        let (count, out) = search_smallcap(
-            "zzzz", SHERLOCK, |s| s.files_without_matches(true));
-        assert_eq!(0, count);
-        assert_eq!(out, "/baz.rs\n");
+    fn progress_reported_to_sink() {
+        use sink::Sink;
+
+        struct ProgressRecorder {
+            calls: Vec<u64>,
+        }
+
+        impl Sink for ProgressRecorder {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&::regex::bytes::Regex>, _: P,
+                _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>, _: Option<u64>,
+                _: Option<super::Indent>,
+            ) {
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn progress<P: AsRef<Path>>(&mut self, _: P, bytes: u64) {
+                self.calls.push(bytes);
+            }
+            fn has_printed(&self) -> bool {
+                false
+            }
+        }
+
+        let mut inp = InputBuffer::with_capacity(4096);
+        let mut sink = ProgressRecorder { calls: vec![] };
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut sink, &grep, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap();
+        }
+        // The input is smaller than one buffer, so every fill (including
+        // the one that surfaces the unterminated final line) and the final
+        // report all see the full length.
+        let len = SHERLOCK.len() as u64;
+        assert!(sink.calls.len() >= 2);
+        assert!(sink.calls.iter().all(|&bytes| bytes == len));
+
+        // Tiny fills report increasing offsets, still ending at the total.
+        let mut inp = InputBuffer::with_capacity(1);
+        let mut sink = ProgressRecorder { calls: vec![] };
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut sink, &grep, test_path(), hay(SHERLOCK));
+            searcher.run().unwrap();
+        }
+        assert!(sink.calls.len() > 2);
+        assert!(sink.calls.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(Some(&len), sink.calls.last());
     }
 
     #[test]
-    fn max_count() {
-        let (count, out) = search_smallcap(
-            "Sherlock", SHERLOCK, |s| s.max_count(Some(1)));
-        assert_eq!(1, count);
-        assert_eq!(out, "\
-/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
-");
+    fn count_lines_utf16le_word_at_a_time() {
+        use super::count_lines_utf16le;
+
+        // A scalar reference implementation to compare against.
+        fn naive(buf: &[u8], eol: u8) -> u64 {
+            buf.chunks_exact(2)
+                .filter(|pair| pair[0] == eol && pair[1] == 0)
+                .count() as u64
+        }
+
+        // Terminators straddling word boundaries, `\n` bytes at odd
+        // offsets (inside other code units), a lone trailing byte and
+        // terminator-dense input must all agree with the reference.
+        let cases: Vec<Vec<u8>> = vec![
+            vec![],
+            utf16le("\n").into_bytes(),
+            utf16le("foo\nbar\nbaz\n").into_bytes(),
+            utf16le("\n\n\n\n\n\n\n\n\n").into_bytes(),
+            "\u{0a0a}\u{0a01}".encode_utf16()
+                .flat_map(|u| u.to_le_bytes())
+                .collect(),
+            b"f\0o\0\n".to_vec(),
+        ];
+        for case in &cases {
+            assert_eq!(
+                naive(case, b'\n'),
+                count_lines_utf16le(case, b'\n'),
+                "diverged on {:?}", case);
+        }
     }
 
     #[test]
-    fn invert_match_max_count() {
-        let (count, out) = search(
-            "zzzz", SHERLOCK, |s| s.invert_match(true).max_count(Some(1)));
-        assert_eq!(1, count);
-        assert_eq!(out, "\
-/baz.rs:For the Doctor Watsons of this world, as opposed to the Sherlock
-");
+    #[ignore]
+    fn bench_count_lines_utf16le() {
+        // Not a real benchmark harness, but enough to demonstrate the
+        // word-at-a-time win over a candidate scan on millions of short
+        // lines. Run with: cargo test --release -- --ignored bench
+        use std::time::Instant;
+
+        use super::{count_lines_utf16le, find_eol_utf16le};
+
+        fn candidate_scan(buf: &[u8], eol: u8) -> u64 {
+            let mut count = 0;
+            let mut pos = 0;
+            while let Some(i) = find_eol_utf16le(eol, &buf[pos..]) {
+                count += 1;
+                pos += i + 2;
+            }
+            count
+        }
+
+        let line = utf16le("0123456789abcde\n").into_bytes();
+        let mut haystack = Vec::with_capacity(line.len() * 2_000_000);
+        for _ in 0..2_000_000 {
+            haystack.extend_from_slice(&line);
+        }
+
+        let start = Instant::now();
+        let expected = candidate_scan(&haystack, b'\n');
+        let scan = start.elapsed();
+
+        let start = Instant::now();
+        let got = count_lines_utf16le(&haystack, b'\n');
+        let simd = start.elapsed();
+
+        assert_eq!(expected, got);
+        println!("candidate scan: {:?}, word-at-a-time: {:?}", scan, simd);
     }
 
     #[test]
-    fn invert_match() {
-        let (count, out) = search_smallcap(
-            "Sherlock", SHERLOCK, |s| s.invert_match(true));
-        assert_eq!(4, count);
-        assert_eq!(out, "\
-/baz.rs:Holmeses, success in the province of detective work must always
-/baz.rs:can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs:and exhibited clearly, with a label attached.
-");
+    fn only_matching_basic() {
+        let (count, out) = search("o+", "foo boo\nbar\n", |s| {
+            s.only_matching(true).line_number(true)
+        });
+        assert_eq!(1, count);
+        assert_eq!(out, "/baz.rs:1:oo\n/baz.rs:1:oo\n");
+
+        let (count2, out2) = search_smallcap("o+", "foo boo\nbar\n", |s| {
+            s.only_matching(true).line_number(true)
+        });
+        assert_eq!((count, out), (count2, out2));
     }
 
     #[test]
-    fn invert_match_line_numbers() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.invert_match(true).line_number(true)
-        });
-        assert_eq!(4, count);
-        assert_eq!(out, "\
-/baz.rs:2:Holmeses, success in the province of detective work must always
-/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs:6:and exhibited clearly, with a label attached.
-");
+    fn only_matching_spans() {
+        use sink::Sink;
+
+        type Span = (Vec<u8>, Option<u64>, Option<u64>, Option<u64>);
+
+        #[derive(Default)]
+        struct SpanRecorder {
+            matches: Vec<Span>,
+        }
+
+        impl Sink for SpanRecorder {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&::regex::bytes::Regex>, _: P,
+                buf: &[u8], start: usize, end: usize,
+                line_number: Option<u64>, byte_offset: Option<u64>,
+                column: Option<u64>, _: Option<super::Indent>,
+            ) {
+                self.matches.push((
+                    buf[start..end].to_vec(), line_number,
+                    byte_offset.map(|off| off + start as u64), column,
+                ));
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn has_printed(&self) -> bool {
+                !self.matches.is_empty()
+            }
+        }
+
+        let mut inp = InputBuffer::with_capacity(4096);
+        let mut sink = SpanRecorder::default();
+        let grep = GrepBuilder::new("o+").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut sink, &grep, test_path(),
+                hay("foo boo\nbar\nboo\n"));
+            searcher.only_matching(true).line_number(true)
+                .byte_offset(true).column(true).run().unwrap();
+        }
+        assert_eq!(sink.matches, vec![
+            (b"oo".to_vec(), Some(1), Some(1), Some(2)),
+            (b"oo".to_vec(), Some(1), Some(5), Some(6)),
+            (b"oo".to_vec(), Some(3), Some(13), Some(2)),
+        ]);
     }
 
     #[test]
-    fn invert_match_count() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.invert_match(true).count(true)
+    fn only_matching_empty_matches_terminate() {
+        // A pattern that can match the empty string must not loop; the
+        // regex engine advances past empty matches on its own.
+        let (count, out) = search("x*", "ab\n", |s| {
+            s.only_matching(true)
         });
-        assert_eq!(4, count);
-        assert_eq!(out, "/baz.rs:4\n");
+        assert_eq!(1, count);
+        assert!(!out.is_empty());
     }
 
     #[test]
-    fn before_context_one1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true).before_context(1)
+    fn only_matching_paragraph() {
+        // In paragraph mode each match is attributed to the line of the
+        // record it starts on, not the record's first line.
+        let text = "foo\nbar\n\nbaz\n";
+        let (count, out) = search("ba.", text, |s| {
+            s.paragraph(true).only_matching(true).line_number(true)
         });
         assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs-2-Holmeses, success in the province of detective work must always
-/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
-");
+        assert_eq!(out, "/baz.rs:2:bar\n/baz.rs:4:baz\n");
     }
 
     #[test]
-    fn before_context_invert_one1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true).before_context(1).invert_match(true)
-        });
-        assert_eq!(4, count);
-        assert_eq!(out, "\
-/baz.rs-1-For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs:2:Holmeses, success in the province of detective work must always
-/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs:6:and exhibited clearly, with a label attached.
-");
+    fn invert_context_count() {
+        // Counting suppresses all context output, so context settings must
+        // not change the result (and the searcher takes a faster path).
+        for &(before, after) in &[(0, 0), (1, 0), (0, 1), (2, 3)] {
+            let (count, out) = search(
+                "Sherlock", SHERLOCK, |s| {
+                    s.invert_match(true).count(true)
+                        .before_context(before).after_context(after)
+                });
+            assert_eq!(4, count);
+            assert_eq!(out, "/baz.rs:4\n");
+        }
     }
 
     #[test]
-    fn before_context_invert_one2() {
-        let (count, out) = search_smallcap(" a ", SHERLOCK, |s| {
-            s.line_number(true).before_context(1).invert_match(true)
-        });
-        assert_eq!(3, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs:2:Holmeses, success in the province of detective work must always
---
-/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-");
+    fn invert_context_differential() {
+        // The invert+context state machine must produce identical output
+        // regardless of how reads chunk the input.
+        for before in 0..3 {
+            for after in 0..3 {
+                let big = search(
+                    "Watson", SHERLOCK, |s| {
+                        s.invert_match(true).line_number(true)
+                            .before_context(before).after_context(after)
+                    });
+                let small = search_smallcap(
+                    "Watson", SHERLOCK, |s| {
+                        s.invert_match(true).line_number(true)
+                            .before_context(before).after_context(after)
+                    });
+                assert_eq!(big, small,
+                           "output diverged for -B{} -A{}", before, after);
+            }
+        }
     }
 
     #[test]
-    fn before_context_two1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true).before_context(2)
-        });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs-2-Holmeses, success in the province of detective work must always
-/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
-");
+    fn tiny_capacity_boundaries() {
+        // A capacity at or near the floor must produce the same output as
+        // a comfortable one, for both byte-oriented and UTF-16LE searches.
+        let expected = search("Sherlock", SHERLOCK, |s| s.line_number(true));
+        for &cap in &[0, 1, 2, 3, 4095, 4096, 4097] {
+            let mut inp = InputBuffer::with_capacity(cap);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new("Sherlock").build().unwrap();
+            let count = {
+                let searcher = Searcher::new(
+                    &mut inp, &mut pp, &grep, test_path(), hay(SHERLOCK));
+                searcher.line_number(true).run().unwrap()
+            };
+            let out =
+                String::from_utf8(pp.into_inner().into_inner()).unwrap();
+            assert_eq!(expected, (count, out), "diverged at capacity {}", cap);
+        }
     }
 
     #[test]
-    fn before_context_two2() {
-        let (count, out) = search_smallcap("dusted", SHERLOCK, |s| {
-            s.line_number(true).before_context(2)
-        });
-        assert_eq!(1, count);
-        assert_eq!(out, "\
-/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-");
+    fn tiny_capacity_utf16le() {
+        // Capacities smaller than a UTF-16 code unit force reads that split
+        // terminator pairs, which must still be handled.
+        let haystack = utf16le(SHERLOCK);
+        let expected = search(
+            "Sherlock", &haystack, |s| s.utf16le(true).line_number(true));
+        for &cap in &[0, 1, 2, 3] {
+            let mut inp = InputBuffer::with_capacity(cap);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new("Sherlock").build().unwrap();
+            let count = {
+                let searcher = Searcher::new(
+                    &mut inp, &mut pp, &grep, test_path(), hay(&haystack));
+                searcher.utf16le(true).line_number(true).run().unwrap()
+            };
+            let out =
+                String::from_utf8(pp.into_inner().into_inner()).unwrap();
+            assert_eq!(expected, (count, out), "diverged at capacity {}", cap);
+        }
     }
 
-    #[test]
-    fn before_context_two3() {
-        let (count, out) = search_smallcap(
-            "success|attached", SHERLOCK, |s| {
-                s.line_number(true).before_context(2)
-            });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs-1-For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs:2:Holmeses, success in the province of detective work must always
---
-/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs:6:and exhibited clearly, with a label attached.
-");
+    fn search_convert(
+        replacement: Option<u8>,
+        pat: &str,
+        haystack: &str,
+    ) -> (u64, String, bool) {
+        let mut inp = InputBuffer::with_capacity(4096);
+        inp.convert_binary(replacement);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(haystack));
+            searcher.line_number(true).run().unwrap()
+        };
+        let out = String::from_utf8(pp.into_inner().into_inner()).unwrap();
+        (count, out, inp.converted())
     }
 
     #[test]
-    fn before_context_two4() {
-        let (count, out) = search("stdin", CODE, |s| {
-            s.line_number(true).before_context(2)
-        });
-        assert_eq!(3, count);
-        assert_eq!(out, "\
-/baz.rs-4-
-/baz.rs-5-fn main() {
-/baz.rs:6:    let stdin = io::stdin();
-/baz.rs-7-    let stdout = io::stdout();
-/baz.rs-8-
-/baz.rs:9:    // Wrap the stdin reader in a Snappy reader.
-/baz.rs:10:    let mut rdr = snap::Reader::new(stdin.lock());
-");
+    fn binary_heuristic() {
+        let run = |config: Option<(f64, usize)>, hay2: &str| {
+            let mut inp = InputBuffer::with_capacity(4096);
+            inp.binary_heuristic(config);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf).with_filename(true);
+            let grep = GrepBuilder::new("Sherlock").build().unwrap();
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(), hay(hay2));
+            searcher.run().unwrap()
+        };
+        // No NUL bytes, but the prefix is mostly control bytes.
+        let text = "\x01\x02\x03\x04\x05\x06\nSherlock\n";
+        assert_eq!(1, run(None, text));
+        assert_eq!(0, run(Some((0.3, 1024)), text));
+        // Plain text stays searchable under the same heuristic.
+        assert_eq!(2, run(Some((0.3, 1024)), SHERLOCK));
+        // A short inspected prefix never sees the control bytes.
+        assert_eq!(1, run(Some((0.3, 0)), text));
     }
 
     #[test]
-    fn before_context_two5() {
-        let (count, out) = search("stdout", CODE, |s| {
-            s.line_number(true).before_context(2)
-        });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs-5-fn main() {
-/baz.rs-6-    let stdin = io::stdin();
-/baz.rs:7:    let stdout = io::stdout();
---
-/baz.rs-9-    // Wrap the stdin reader in a Snappy reader.
-/baz.rs-10-    let mut rdr = snap::Reader::new(stdin.lock());
-/baz.rs:11:    let mut wtr = stdout.lock();
-");
+    fn binary_heuristic_offset_unit() {
+        let f = super::binary_heuristic_offset;
+        assert_eq!(Some(1), f(b"a\x01\x02\x03", 0.3));
+        assert_eq!(None, f(b"abcdefgh\x01", 0.3));
+        assert_eq!(None, f(b"", 0.3));
+        // Whitespace and high-bit bytes are printable.
+        assert_eq!(None, f(b"\t\r\n\xFF\xFE", 0.0));
     }
 
     #[test]
-    fn before_context_three1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-                s.line_number(true).before_context(3)
-            });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs-2-Holmeses, success in the province of detective work must always
-/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
-");
+    fn convert_binary_filler() {
+        // Without conversion, the NUL byte stops the search as binary.
+        let (count, out, converted) =
+            search_convert(None, "quux", "b\x00ar\nquux\n");
+        assert_eq!((0, false), (count, converted));
+        assert_eq!(out, "");
+        // A filler replacement keeps line boundaries (and numbers) intact.
+        let (count, out, converted) =
+            search_convert(Some(b' '), "quux", "b\x00ar\nquux\n");
+        assert_eq!((1, true), (count, converted));
+        assert_eq!(out, "/baz.rs:2:quux\n");
     }
 
     #[test]
-    fn after_context_one1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true).after_context(1)
-        });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs-2-Holmeses, success in the province of detective work must always
-/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
-");
+    fn convert_binary_replacement_searched() {
+        // The replacement byte, not the NUL, is what the search observes.
+        let (count, out, converted) =
+            search_convert(Some(b' '), "b ar", "b\x00ar\n");
+        assert_eq!((1, true), (count, converted));
+        assert_eq!(out, "/baz.rs:1:b ar\n");
     }
 
     #[test]
-    fn after_context_invert_one1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true).after_context(1).invert_match(true)
-        });
-        assert_eq!(4, count);
-        assert_eq!(out, "\
-/baz.rs:2:Holmeses, success in the province of detective work must always
-/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs:6:and exhibited clearly, with a label attached.
-");
+    fn convert_binary_terminator_inflates_line_numbers() {
+        // Converting into the terminator splits lines, so reported line
+        // numbers reflect the converted data. `converted` lets consumers
+        // flag that.
+        let (count, out, converted) =
+            search_convert(Some(b'\n'), "quux", "a\x00b\nquux\n");
+        assert_eq!((1, true), (count, converted));
+        assert_eq!(out, "/baz.rs:3:quux\n");
+    }
+
+    fn search_binary(haystack: &[u8]) -> (u64, InputBuffer) {
+        let mut inp = InputBuffer::with_capacity(4096);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("a").build().unwrap();
+        let count = {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(),
+                io::Cursor::new(haystack.to_vec()));
+            searcher.run().unwrap()
+        };
+        (count, inp)
     }
 
     #[test]
-    fn after_context_invert_one2() {
-        let (count, out) = search_smallcap(" a ", SHERLOCK, |s| {
-            s.line_number(true).after_context(1).invert_match(true)
-        });
-        assert_eq!(3, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs:2:Holmeses, success in the province of detective work must always
-/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
---
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs-6-and exhibited clearly, with a label attached.
-");
+    fn binary_preview_offset_zero() {
+        let (count, inp) = search_binary(b"\x00abc\n");
+        assert_eq!(0, count);
+        let preview = inp.binary_preview().unwrap();
+        assert_eq!(0, preview.offset());
+        assert_eq!(0, preview.pos());
+        assert_eq!(b"\x00abc\n", preview.bytes());
     }
 
     #[test]
-    fn after_context_invert_one_max_count_two() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true)
-             .invert_match(true)
-             .after_context(1)
-             .max_count(Some(2))
-        });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:2:Holmeses, success in the province of detective work must always
-/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs:4:can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
-");
+    fn binary_preview_near_end() {
+        let (count, inp) = search_binary(b"abcdef\n123\x00");
+        // The complete line before the binary byte is still delivered.
+        assert_eq!(1, count);
+        let preview = inp.binary_preview().unwrap();
+        assert_eq!(10, preview.offset());
+        assert_eq!(10, preview.pos());
+        assert_eq!(b"abcdef\n123\x00", preview.bytes());
     }
 
     #[test]
-    fn after_context_two1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true).after_context(2)
-        });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs-2-Holmeses, success in the province of detective work must always
-/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
-");
+    fn binary_preview_across_reads() {
+        // A capacity of 1 forces the detected byte into its own read. The
+        // absolute offset must account for everything read before it, and
+        // the snippet is limited to what the buffer still holds (lines
+        // already searched have been rolled out).
+        let mut haystack = b"0123456789\n".to_vec();
+        haystack.push(0);
+        let mut inp = InputBuffer::with_capacity(1);
+        inp.preview_context(4);
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf).with_filename(true);
+        let grep = GrepBuilder::new("a").build().unwrap();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut pp, &grep, test_path(),
+                io::Cursor::new(haystack));
+            searcher.run().unwrap();
+        }
+        let preview = inp.binary_preview().unwrap();
+        assert_eq!(11, preview.offset());
+        assert_eq!(b"\x00", preview.bytes());
+        assert_eq!(0, preview.pos());
     }
 
     #[test]
-    fn after_context_two2() {
-        let (count, out) = search_smallcap("dusted", SHERLOCK, |s| {
-            s.line_number(true).after_context(2)
-        });
-        assert_eq!(1, count);
-        assert_eq!(out, "\
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs-6-and exhibited clearly, with a label attached.
-");
+    fn binary_preview_slice() {
+        let preview = super::binary_preview(b"ab\x00cd", 1).unwrap();
+        assert_eq!(2, preview.offset());
+        assert_eq!(1, preview.pos());
+        assert_eq!(b"b\x00c", preview.bytes());
+        assert!(super::binary_preview(b"abcd", 1).is_none());
+        // The PDF heuristic reports the header itself.
+        let preview = super::binary_preview(b"%PDF-1.7 etc", 4).unwrap();
+        assert_eq!(0, preview.offset());
+        assert_eq!(b"%PDF-", preview.bytes());
     }
 
     #[test]
-    fn after_context_two3() {
-        let (count, out) = search_smallcap(
-            "success|attached", SHERLOCK, |s| {
-                s.line_number(true).after_context(2)
-            });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:2:Holmeses, success in the province of detective work must always
-/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
---
-/baz.rs:6:and exhibited clearly, with a label attached.
-");
+    fn preset_grep_defaults() {
+        assert_eq!(Options::grep_defaults(), Options {
+            after_context: 0,
+            anchor_line_start: false,
+            before_context: 0,
+            best_effort: false,
+            binary_report: None,
+            byte_offset: false,
+            column: false,
+            context_byte_limit: None,
+            count: false,
+            count_matches: false,
+            crlf: false,
+            detect_terminator: false,
+            files_with_matches: false,
+            files_without_matches: false,
+            final_line: FinalLinePolicy::Match,
+            heap_limit: None,
+            eol: b'\n',
+            invert_match: false,
+            line_number: true,
+            line_number_start: 1,
+            max_bytes: None,
+            max_count: None,
+            max_line_len: None,
+            only_matching: false,
+            paragraph: false,
+            passthru: false,
+            peek_back: None,
+            quiet: false,
+            quit_after_no_match_within: None,
+            record_sep: None,
+            record_size: None,
+            report_indent: None,
+            sample_lines: None,
+            sample_bytes: None,
+            sample_random: None,
+            skip_empty_lines: false,
+            skip_increment: READ_SIZE as u64,
+            start_offset: 0,
+            stop_on_nonmatch: false,
+            stitch_segments: false,
+            text: false,
+            utf16le: false,
+        });
     }
 
     #[test]
-    fn after_context_two_max_count_two() {
-        let (count, out) = search_smallcap(
-            "Doctor", SHERLOCK, |s| {
-                s.line_number(true).after_context(2).max_count(Some(2))
-            });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs-2-Holmeses, success in the province of detective work must always
-/baz.rs-3-be, to a very large extent, the result of luck. Sherlock Holmes
---
-/baz.rs:5:but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs-6-and exhibited clearly, with a label attached.
-");
+    fn preset_streaming_defaults() {
+        assert_eq!(Options::streaming_defaults(), Options {
+            after_context: 0,
+            anchor_line_start: false,
+            before_context: 0,
+            best_effort: false,
+            binary_report: None,
+            byte_offset: false,
+            column: false,
+            context_byte_limit: None,
+            count: false,
+            count_matches: false,
+            crlf: false,
+            detect_terminator: false,
+            files_with_matches: false,
+            files_without_matches: false,
+            final_line: FinalLinePolicy::Match,
+            heap_limit: None,
+            eol: b'\n',
+            invert_match: false,
+            line_number: false,
+            line_number_start: 1,
+            max_bytes: None,
+            max_count: None,
+            max_line_len: None,
+            only_matching: false,
+            paragraph: false,
+            passthru: false,
+            peek_back: None,
+            quiet: false,
+            quit_after_no_match_within: None,
+            record_sep: None,
+            record_size: None,
+            report_indent: None,
+            sample_lines: None,
+            sample_bytes: None,
+            sample_random: None,
+            skip_empty_lines: false,
+            skip_increment: READ_SIZE as u64,
+            start_offset: 0,
+            stop_on_nonmatch: false,
+            stitch_segments: false,
+            text: true,
+            utf16le: false,
+        });
     }
 
     #[test]
-    fn after_context_three1() {
-        let (count, out) = search_smallcap("Sherlock", SHERLOCK, |s| {
-            s.line_number(true).after_context(3)
+    fn preset_forensics_defaults() {
+        assert_eq!(Options::forensics_defaults(), Options {
+            after_context: 0,
+            anchor_line_start: false,
+            before_context: 0,
+            best_effort: false,
+            binary_report: None,
+            byte_offset: true,
+            column: false,
+            context_byte_limit: None,
+            count: false,
+            count_matches: false,
+            crlf: false,
+            detect_terminator: false,
+            files_with_matches: false,
+            files_without_matches: false,
+            final_line: FinalLinePolicy::Match,
+            heap_limit: None,
+            eol: b'\n',
+            invert_match: false,
+            line_number: false,
+            line_number_start: 1,
+            max_bytes: None,
+            max_count: None,
+            max_line_len: None,
+            only_matching: false,
+            paragraph: false,
+            passthru: false,
+            peek_back: None,
+            quiet: false,
+            quit_after_no_match_within: None,
+            record_sep: None,
+            record_size: None,
+            report_indent: None,
+            sample_lines: None,
+            sample_bytes: None,
+            sample_random: None,
+            skip_empty_lines: false,
+            skip_increment: READ_SIZE as u64,
+            start_offset: 0,
+            stop_on_nonmatch: false,
+            stitch_segments: false,
+            text: true,
+            utf16le: false,
         });
-        assert_eq!(2, count);
-        assert_eq!(out, "\
-/baz.rs:1:For the Doctor Watsons of this world, as opposed to the Sherlock
-/baz.rs-2-Holmeses, success in the province of detective work must always
-/baz.rs:3:be, to a very large extent, the result of luck. Sherlock Holmes
-/baz.rs-4-can extract a clew from a wisp of straw or a flake of cigar ash;
-/baz.rs-5-but Doctor Watson has to have it taken out for him and dusted,
-/baz.rs-6-and exhibited clearly, with a label attached.
-");
     }
 
     #[test]
-    fn before_after_context_two1() {
+    fn preset_tweakable() {
         let (count, out) = search(
-            r"fn main|let mut rdr", CODE, |s| {
-                s.line_number(true).after_context(2).before_context(2)
-            });
+            "Sherlock", SHERLOCK,
+            |s| s.grep_defaults().line_number(false).byte_offset(true));
         assert_eq!(2, count);
         assert_eq!(out, "\
-/baz.rs-3-use std::io;
-/baz.rs-4-
-/baz.rs:5:fn main() {
-/baz.rs-6-    let stdin = io::stdin();
-/baz.rs-7-    let stdout = io::stdout();
-/baz.rs-8-
-/baz.rs-9-    // Wrap the stdin reader in a Snappy reader.
-/baz.rs:10:    let mut rdr = snap::Reader::new(stdin.lock());
-/baz.rs-11-    let mut wtr = stdout.lock();
-/baz.rs-12-    io::copy(&mut rdr, &mut wtr).expect(\"I/O operation failed\");
+/baz.rs:0:For the Doctor Watsons of this world, as opposed to the Sherlock
+/baz.rs:129:be, to a very large extent, the result of luck. Sherlock Holmes
 ");
     }
 }
@@ -0,0 +1,2890 @@
+/*!
+The `sink` module defines a trait for consumers of search results, along with
+a small set of combinators for composing them.
+
+A sink receives every event emitted by a searcher: matching lines, contextual
+lines, context separators, paths and counts. `Printer` is the principal
+implementation, but the combinators in this module make it possible to
+compose behaviors without writing a bespoke wrapper every time.
+
+Note that sinks cannot stop a search; termination is always controlled by the
+searcher's own options (e.g., `quiet` or `max_count`). Similarly, sinks do
+not return errors, so there is no error-mapping combinator.
+*/
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::time::Duration;
+use std::vec;
+
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
+use regex::bytes::Regex;
+#[cfg(feature = "colored-sink")]
+use termcolor::{Color, ColorSpec};
+use termcolor::WriteColor;
+
+use grep::Grep;
+use printer::Printer;
+use search_buffer::BufferSearcher;
+use search_stream::{Error, Indent, InputBuffer, Options, Searcher};
+
+/// The full payload reported with a single matched line (or record, in
+/// the paragraph and fixed-record modes), as handed to
+/// `Sink::matched_full`.
+///
+/// Everything `Sink::matched` reports positionally is here, plus what
+/// the drivers always know but the positional form only reports when
+/// configured: the absolute byte offset of the reported bytes, and the
+/// range of the actual match within them when the driver has located
+/// one. This lets printers and JSON emitters outside this crate report
+/// positions without re-deriving them.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub struct SinkMatch<'a> {
+    /// The regex that produced the match, when the matcher exposes one.
+    pub re: Option<&'a Regex>,
+    /// The buffer holding the reported bytes.
+    pub buf: &'a [u8],
+    /// The start of the reported line or record within `buf`.
+    pub start: usize,
+    /// The end of the reported bytes, terminator included when present.
+    pub end: usize,
+    /// The absolute byte offset of `buf[start]` within the input. Unlike
+    /// `byte_offset`, this is always populated, whether or not offset
+    /// reporting was requested, and it stays exact across buffer
+    /// rollovers.
+    pub offset: u64,
+    /// The line number of the first reported line, if line numbers were
+    /// enabled.
+    pub line_number: Option<u64>,
+    /// The byte offset as configured for reporting; present only when
+    /// the searcher was asked to report it.
+    pub byte_offset: Option<u64>,
+    /// The 1-based byte column of the first match within the line, if
+    /// the searcher was asked to report it.
+    pub column: Option<u64>,
+    /// The indentation of the line, if the searcher was asked to report
+    /// it.
+    pub indent: Option<Indent>,
+    /// The range of the actual match within `buf[start..end]`, when the
+    /// driver has located one: in only-matching mode the reported bytes
+    /// are exactly the match, so this spans them. Whole-line reports
+    /// leave it `None`, since the matchers locate matching lines rather
+    /// than spans; sinks that need the span can derive it from `re`.
+    pub match_range: Option<(usize, usize)>,
+}
+
+/// A trait for things that can receive search events from a searcher.
+pub trait Sink {
+    /// Called for each matching line found by a search. `buf[start..end]`
+    /// corresponds to the bytes of the line (including its terminator, if
+    /// present). `column` is the 1-based byte column of the first match
+    /// within the line and `indent` is the line's leading-whitespace
+    /// measurement; each is present only when the searcher was asked to
+    /// report it.
+    #[allow(clippy::too_many_arguments)]
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        column: Option<u64>,
+        indent: Option<Indent>,
+    );
+
+    /// Called for each matching line, with the full `SinkMatch` payload.
+    ///
+    /// The searchers always report through this method. The default
+    /// forwards to `matched`, so existing sinks keep working unchanged;
+    /// sinks that want the always-present absolute offset or the located
+    /// match range override this one instead.
+    fn matched_full<P: AsRef<Path>>(&mut self, path: P, mat: &SinkMatch) {
+        self.matched(
+            mat.re, path, mat.buf, mat.start, mat.end, mat.line_number,
+            mat.byte_offset, mat.column, mat.indent);
+    }
+
+    /// Called for each contextual (non-matching) line printed around a
+    /// match.
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+    );
+
+    /// Called between non-contiguous runs of contextual lines.
+    fn context_separate(&mut self);
+
+    /// Called with the file path when only paths are reported (e.g., for
+    /// files-with-matches).
+    fn path<P: AsRef<Path>>(&mut self, path: P);
+
+    /// Called with the file path and a count when counts are reported.
+    fn path_count<P: AsRef<Path>>(&mut self, path: P, count: u64);
+
+    /// Called when a best-effort search skips a read error. `offset` is
+    /// the absolute byte offset at which the failing read started.
+    ///
+    /// The default implementation does nothing, since most consumers only
+    /// care about the matches that were still found.
+    fn read_error<P: AsRef<Path>>(
+        &mut self,
+        _path: P,
+        _offset: u64,
+        _err: &io::Error,
+    ) {
+    }
+
+    /// Called periodically with the absolute number of input bytes
+    /// consumed so far: roughly once per buffer fill during a streaming
+    /// search, and once with the final count when a search completes. The
+    /// count includes bytes that were read but produced no reportable
+    /// lines, so it is suitable for driving a progress display.
+    ///
+    /// The default implementation does nothing.
+    fn progress<P: AsRef<Path>>(&mut self, _path: P, _bytes: u64) {
+    }
+
+    /// Called when a search stops early because its cancellation flag was
+    /// set. Everything delivered before this call remains valid, but the
+    /// results are partial.
+    ///
+    /// The default implementation does nothing.
+    fn cancelled<P: AsRef<Path>>(&mut self, _path: P) {
+    }
+
+    /// Called at the start of each segment when several readers are
+    /// searched as one logical stream, with the segment's ordinal and
+    /// the absolute byte offset at which it begins. Lets output
+    /// attribute a match's global position back to an individual file.
+    ///
+    /// The default implementation does nothing.
+    fn segment<P: AsRef<Path>>(&mut self, _path: P, _index: u64,
+                               _offset: u64) {
+    }
+
+    /// Called when binary reporting is enabled and the marker byte was
+    /// seen, with the absolute offset of its first occurrence in the
+    /// region just buffered. The search continues normally; this only
+    /// labels the results as coming from binary data.
+    ///
+    /// The default implementation does nothing.
+    fn binary_data<P: AsRef<Path>>(&mut self, _path: P, _offset: u64) {
+    }
+
+    /// Called when a context line was truncated to fit the configured
+    /// context byte budget. The truncated line was just delivered via
+    /// `context`.
+    ///
+    /// The default implementation does nothing.
+    fn context_truncated<P: AsRef<Path>>(&mut self, _path: P) {
+    }
+
+    /// Called when the final line of the input had no trailing line
+    /// terminator and the searcher was configured to flag that. The
+    /// line itself is still delivered through the usual events.
+    ///
+    /// The default implementation does nothing.
+    fn unterminated<P: AsRef<Path>>(&mut self, _path: P) {
+    }
+
+    /// Returns true if this sink reads the line numbers given to it.
+    ///
+    /// The searcher queries this once at the start of a search. A sink
+    /// that returns false -- say, one that only counts or only checks
+    /// for existence -- lets the searcher skip line counting entirely,
+    /// even when it was enabled by the builder, which is a measurable
+    /// saving on large inputs. Events then carry `None` for their line
+    /// numbers.
+    ///
+    /// The default implementation returns true.
+    fn needs_line_numbers(&self) -> bool {
+        true
+    }
+
+    /// Returns true if and only if this sink has received at least one
+    /// event.
+    fn has_printed(&self) -> bool;
+}
+
+impl<W: WriteColor> Sink for Printer<W> {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        _column: Option<u64>,
+        _indent: Option<Indent>,
+    ) {
+        Printer::matched(
+            self, re, path, buf, start, end, line_number, byte_offset);
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+    ) {
+        Printer::context(
+            self, path, buf, start, end, line_number, byte_offset);
+    }
+
+    fn context_separate(&mut self) {
+        Printer::context_separate(self);
+    }
+
+    fn path<P: AsRef<Path>>(&mut self, path: P) {
+        Printer::path(self, path);
+    }
+
+    fn path_count<P: AsRef<Path>>(&mut self, path: P, count: u64) {
+        Printer::path_count(self, path, count);
+    }
+
+    fn has_printed(&self) -> bool {
+        Printer::has_printed(self)
+    }
+}
+
+/// A sink that duplicates every event to two sinks.
+///
+/// Since sinks cannot terminate a search, every event is always forwarded to
+/// both sinks, in order.
+#[allow(dead_code)]
+pub struct Tee<A, B>(pub A, pub B);
+
+impl<A: Sink, B: Sink> Sink for Tee<A, B> {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        column: Option<u64>,
+        indent: Option<Indent>,
+    ) {
+        self.0.matched(
+            re, path.as_ref(), buf, start, end, line_number, byte_offset,
+            column, indent);
+        self.1.matched(
+            re, path.as_ref(), buf, start, end, line_number, byte_offset,
+            column, indent);
+    }
+
+    fn matched_full<P: AsRef<Path>>(&mut self, path: P, mat: &SinkMatch) {
+        self.0.matched_full(path.as_ref(), mat);
+        self.1.matched_full(path.as_ref(), mat);
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+    ) {
+        self.0.context(
+            path.as_ref(), buf, start, end, line_number, byte_offset);
+        self.1.context(
+            path.as_ref(), buf, start, end, line_number, byte_offset);
+    }
+
+    fn context_separate(&mut self) {
+        self.0.context_separate();
+        self.1.context_separate();
+    }
+
+    fn read_error<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        offset: u64,
+        err: &io::Error,
+    ) {
+        self.0.read_error(path.as_ref(), offset, err);
+        self.1.read_error(path.as_ref(), offset, err);
+    }
+
+    fn progress<P: AsRef<Path>>(&mut self, path: P, bytes: u64) {
+        self.0.progress(path.as_ref(), bytes);
+        self.1.progress(path.as_ref(), bytes);
+    }
+
+    fn cancelled<P: AsRef<Path>>(&mut self, path: P) {
+        self.0.cancelled(path.as_ref());
+        self.1.cancelled(path.as_ref());
+    }
+
+    fn unterminated<P: AsRef<Path>>(&mut self, path: P) {
+        self.0.unterminated(path.as_ref());
+        self.1.unterminated(path.as_ref());
+    }
+
+    fn context_truncated<P: AsRef<Path>>(&mut self, path: P) {
+        self.0.context_truncated(path.as_ref());
+        self.1.context_truncated(path.as_ref());
+    }
+
+    fn binary_data<P: AsRef<Path>>(&mut self, path: P, offset: u64) {
+        self.0.binary_data(path.as_ref(), offset);
+        self.1.binary_data(path.as_ref(), offset);
+    }
+
+    fn segment<P: AsRef<Path>>(&mut self, path: P, index: u64,
+                               offset: u64) {
+        self.0.segment(path.as_ref(), index, offset);
+        self.1.segment(path.as_ref(), index, offset);
+    }
+
+    fn path<P: AsRef<Path>>(&mut self, path: P) {
+        self.0.path(path.as_ref());
+        self.1.path(path.as_ref());
+    }
+
+    fn path_count<P: AsRef<Path>>(&mut self, path: P, count: u64) {
+        self.0.path_count(path.as_ref(), count);
+        self.1.path_count(path.as_ref(), count);
+    }
+
+    fn needs_line_numbers(&self) -> bool {
+        self.0.needs_line_numbers() || self.1.needs_line_numbers()
+    }
+
+    fn has_printed(&self) -> bool {
+        self.0.has_printed() || self.1.has_printed()
+    }
+}
+
+/// A sink that drops matched events failing a predicate.
+///
+/// The predicate is called with the file path and the bytes of the matching
+/// line. All other events—contextual lines, separators, paths and counts—are
+/// forwarded unchanged, so any counts reported by the searcher still reflect
+/// the unfiltered search.
+#[allow(dead_code)]
+pub struct Filter<F, S> {
+    pred: F,
+    sink: S,
+}
+
+#[allow(dead_code)]
+impl<F, S> Filter<F, S> {
+    /// Create a new filtering sink with the predicate given.
+    pub fn new(pred: F, sink: S) -> Filter<F, S> {
+        Filter { pred, sink }
+    }
+
+    /// Unwrap this combinator, returning the underlying sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<F, S> Sink for Filter<F, S>
+    where F: FnMut(&Path, &[u8]) -> bool,
+          S: Sink
+{
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        column: Option<u64>,
+        indent: Option<Indent>,
+    ) {
+        if (self.pred)(path.as_ref(), &buf[start..end]) {
+            self.sink.matched(
+                re, path, buf, start, end, line_number, byte_offset, column,
+                indent);
+        }
+    }
+
+    fn matched_full<P: AsRef<Path>>(&mut self, path: P, mat: &SinkMatch) {
+        if (self.pred)(path.as_ref(), &mat.buf[mat.start..mat.end]) {
+            self.sink.matched_full(path, mat);
+        }
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+    ) {
+        self.sink.context(path, buf, start, end, line_number, byte_offset);
+    }
+
+    fn context_separate(&mut self) {
+        self.sink.context_separate();
+    }
+
+    fn read_error<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        offset: u64,
+        err: &io::Error,
+    ) {
+        self.sink.read_error(path, offset, err);
+    }
+
+    fn progress<P: AsRef<Path>>(&mut self, path: P, bytes: u64) {
+        self.sink.progress(path, bytes);
+    }
+
+    fn cancelled<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.cancelled(path);
+    }
+
+    fn unterminated<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.unterminated(path);
+    }
+
+    fn context_truncated<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.context_truncated(path);
+    }
+
+    fn binary_data<P: AsRef<Path>>(&mut self, path: P, offset: u64) {
+        self.sink.binary_data(path, offset);
+    }
+
+    fn segment<P: AsRef<Path>>(&mut self, path: P, index: u64,
+                               offset: u64) {
+        self.sink.segment(path, index, offset);
+    }
+
+    fn path<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.path(path);
+    }
+
+    fn path_count<P: AsRef<Path>>(&mut self, path: P, count: u64) {
+        self.sink.path_count(path, count);
+    }
+
+    fn needs_line_numbers(&self) -> bool {
+        self.sink.needs_line_numbers()
+    }
+
+    fn has_printed(&self) -> bool {
+        self.sink.has_printed()
+    }
+}
+
+/// A sink that transforms the bytes of each matching line before forwarding
+/// it.
+///
+/// Note that the forwarded event refers to the transformed bytes, so
+/// positions within the line (e.g., for column numbers) are relative to the
+/// transformed line, not the original.
+#[allow(dead_code)]
+pub struct Map<F, S> {
+    fun: F,
+    sink: S,
+}
+
+#[allow(dead_code)]
+impl<F, S> Map<F, S> {
+    /// Create a new mapping sink with the transformation given.
+    pub fn new(fun: F, sink: S) -> Map<F, S> {
+        Map { fun, sink }
+    }
+
+    /// Unwrap this combinator, returning the underlying sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+}
+
+impl<F, S> Sink for Map<F, S>
+    where F: FnMut(&[u8]) -> Vec<u8>,
+          S: Sink
+{
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        column: Option<u64>,
+        indent: Option<Indent>,
+    ) {
+        let line = (self.fun)(&buf[start..end]);
+        self.sink.matched(
+            re, path, &line, 0, line.len(), line_number, byte_offset,
+            column, indent);
+    }
+
+    fn matched_full<P: AsRef<Path>>(&mut self, path: P, mat: &SinkMatch) {
+        let line = (self.fun)(&mat.buf[mat.start..mat.end]);
+        self.sink.matched_full(path, &SinkMatch {
+            buf: &line,
+            start: 0,
+            end: line.len(),
+            // The transformation may move the match, so its range no
+            // longer applies to the delivered bytes.
+            match_range: None,
+            ..*mat
+        });
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+    ) {
+        self.sink.context(path, buf, start, end, line_number, byte_offset);
+    }
+
+    fn context_separate(&mut self) {
+        self.sink.context_separate();
+    }
+
+    fn read_error<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        offset: u64,
+        err: &io::Error,
+    ) {
+        self.sink.read_error(path, offset, err);
+    }
+
+    fn progress<P: AsRef<Path>>(&mut self, path: P, bytes: u64) {
+        self.sink.progress(path, bytes);
+    }
+
+    fn cancelled<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.cancelled(path);
+    }
+
+    fn unterminated<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.unterminated(path);
+    }
+
+    fn context_truncated<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.context_truncated(path);
+    }
+
+    fn binary_data<P: AsRef<Path>>(&mut self, path: P, offset: u64) {
+        self.sink.binary_data(path, offset);
+    }
+
+    fn segment<P: AsRef<Path>>(&mut self, path: P, index: u64,
+                               offset: u64) {
+        self.sink.segment(path, index, offset);
+    }
+
+    fn path<P: AsRef<Path>>(&mut self, path: P) {
+        self.sink.path(path);
+    }
+
+    fn path_count<P: AsRef<Path>>(&mut self, path: P, count: u64) {
+        self.sink.path_count(path, count);
+    }
+
+    fn needs_line_numbers(&self) -> bool {
+        self.sink.needs_line_numbers()
+    }
+
+    fn has_printed(&self) -> bool {
+        self.sink.has_printed()
+    }
+}
+
+/// An owned record of a single matching line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MatchRecord {
+    /// The path of the searched input.
+    pub path: PathBuf,
+    /// The line number of the match, if line numbers were enabled.
+    pub line_number: Option<u64>,
+    /// The byte offset of the start of the line, if offsets were enabled.
+    pub byte_offset: Option<u64>,
+    /// The 1-based byte column of the first match within `line`, if the
+    /// searcher was asked to report it.
+    pub column: Option<u64>,
+    /// The bytes of the matching line, including its terminator if present.
+    pub line: Vec<u8>,
+    /// The spans of the individual matches within `line`.
+    pub submatches: Vec<(usize, usize)>,
+    /// The indentation of the line, if the searcher was asked to report it.
+    pub indent: Option<Indent>,
+}
+
+/// A sink that collects every matching line into an owned `MatchRecord`.
+#[derive(Debug, Default)]
+pub struct Collector {
+    records: Vec<MatchRecord>,
+    printed: bool,
+}
+
+impl Collector {
+    /// Create a new collector with no records.
+    #[allow(dead_code)]
+    pub fn new() -> Collector {
+        Collector::default()
+    }
+
+    /// Return the records collected so far.
+    #[allow(dead_code)]
+    pub fn records(&self) -> &[MatchRecord] {
+        &self.records
+    }
+
+    /// Consume this collector and return the collected records.
+    #[allow(dead_code)]
+    pub fn into_records(self) -> Vec<MatchRecord> {
+        self.records
+    }
+}
+
+impl Sink for Collector {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        column: Option<u64>,
+        indent: Option<Indent>,
+    ) {
+        self.printed = true;
+        let line = &buf[start..end];
+        let submatches = re
+            .map(|re| {
+                re.find_iter(line).map(|m| (m.start(), m.end())).collect()
+            })
+            .unwrap_or_default();
+        self.records.push(MatchRecord {
+            path: path.as_ref().to_path_buf(),
+            line_number,
+            byte_offset,
+            column,
+            line: line.to_vec(),
+            submatches,
+            indent,
+        });
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _: P,
+        _: &[u8],
+        _: usize,
+        _: usize,
+        _: Option<u64>,
+        _: Option<u64>,
+    ) {
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+
+    fn has_printed(&self) -> bool {
+        self.printed
+    }
+}
+
+/// Search a single file and return all matching lines as owned records.
+///
+/// This is sugar over `Searcher` with a `Collector` sink, for scripts and
+/// one-off tools:
+///
+/// ```ignore
+/// let grep = GrepBuilder::new("pattern").build()?;
+/// let records = search_path_collect(
+///     &grep, Path::new("some/file"), &Options::grep_defaults())?;
+/// for record in records {
+///     println!("{:?}:{:?}", record.line_number, record.line);
+/// }
+/// ```
+#[allow(dead_code)]
+pub fn search_path_collect(
+    grep: &Grep,
+    path: &Path,
+    opts: &Options,
+) -> Result<Vec<MatchRecord>, Error> {
+    search_path_collect_with(grep, path, opts, &OpenOptions::new())
+}
+
+/// Like `search_path_collect`, but with control over how the file is
+/// opened.
+#[allow(dead_code)]
+pub fn search_path_collect_with(
+    grep: &Grep,
+    path: &Path,
+    opts: &Options,
+    open_opts: &OpenOptions,
+) -> Result<Vec<MatchRecord>, Error> {
+    let file = open_opts.open(path)?;
+    let mut inp = InputBuffer::new();
+    let mut collector = Collector::new();
+    {
+        let searcher =
+            Searcher::new(&mut inp, &mut collector, grep, path, file)
+                .options(opts.clone());
+        searcher.run()?;
+    }
+    Ok(collector.into_records())
+}
+
+/// An iterator over the matches of a single search.
+///
+/// Yields one owned `MatchRecord` per matching line, in order, followed
+/// by at most one `Err` if the underlying search failed partway through.
+/// The records are owned, so the iterator borrows nothing from the
+/// searcher or its input and can be collected or stored freely.
+#[derive(Debug)]
+pub struct Matches {
+    records: vec::IntoIter<MatchRecord>,
+    err: Option<Error>,
+}
+
+impl Iterator for Matches {
+    type Item = Result<MatchRecord, Error>;
+
+    fn next(&mut self) -> Option<Result<MatchRecord, Error>> {
+        match self.records.next() {
+            Some(rec) => Some(Ok(rec)),
+            None => self.err.take().map(Err),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lo, hi) = self.records.size_hint();
+        let extra = if self.err.is_some() { 1 } else { 0 };
+        (lo + extra, hi.map(|hi| hi + extra))
+    }
+}
+
+/// Search an in-memory buffer and iterate over its matches.
+///
+/// This is sugar over `BufferSearcher` with a `Collector` sink, for
+/// callers that prefer a plain loop to the sink callback model. The
+/// buffer search itself cannot fail, so the iterator never yields an
+/// `Err`; the item type is shared with `search_reader_iter`, which can.
+#[allow(dead_code)]
+pub fn search_slice_iter(
+    grep: &Grep,
+    path: &Path,
+    buf: &[u8],
+    opts: &Options,
+) -> Matches {
+    let mut collector = Collector::new();
+    {
+        let searcher = BufferSearcher::new(&mut collector, grep, path, buf)
+            .options(opts.clone());
+        searcher.run();
+    }
+    Matches {
+        records: collector.into_records().into_iter(),
+        err: None,
+    }
+}
+
+/// Search a reader and iterate over its matches.
+///
+/// Like `search_slice_iter`, but driving the streaming searcher over any
+/// `io::Read`. If the search fails partway through, the records reported
+/// before the failure are still yielded and the error follows them as
+/// the final item.
+#[allow(dead_code)]
+pub fn search_reader_iter<R: io::Read>(
+    grep: &Grep,
+    path: &Path,
+    rdr: R,
+    opts: &Options,
+) -> Matches {
+    let mut inp = InputBuffer::new();
+    let mut collector = Collector::new();
+    let err = {
+        let searcher =
+            Searcher::new(&mut inp, &mut collector, grep, path, rdr)
+                .options(opts.clone());
+        searcher.run().err()
+    };
+    Matches {
+        records: collector.into_records().into_iter(),
+        err,
+    }
+}
+
+/// A sink for the counting entry points below. It discards everything
+/// except the per-path count, so no line content is ever copied.
+#[derive(Default)]
+struct CountSink {
+    count: u64,
+    printed: bool,
+}
+
+impl Sink for CountSink {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        _: Option<&Regex>,
+        _: P,
+        _: &[u8],
+        _: usize,
+        _: usize,
+        _: Option<u64>,
+        _: Option<u64>,
+        _: Option<u64>,
+        _: Option<Indent>,
+    ) {
+        self.printed = true;
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _: P,
+        _: &[u8],
+        _: usize,
+        _: usize,
+        _: Option<u64>,
+        _: Option<u64>,
+    ) {
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _: P, count: u64) {
+        self.printed = true;
+        self.count = count;
+    }
+
+    fn has_printed(&self) -> bool {
+        self.printed
+    }
+}
+
+/// Count the lines matched by `grep` in `rdr`.
+///
+/// This forces count mode, so the searcher's count-only fast path kicks
+/// in when the rest of the configuration permits it and no line content
+/// is ever handed to a sink. All other options are respected, so with
+/// `invert_match` set this counts the lines that do not match. In-memory
+/// input can be wrapped in an `io::Cursor`.
+#[allow(dead_code)]
+pub fn count_lines<R: io::Read>(
+    grep: &Grep,
+    path: &Path,
+    rdr: R,
+    opts: &Options,
+) -> Result<u64, Error> {
+    let mut opts = opts.clone();
+    opts.count = true;
+    opts.count_matches = false;
+    let mut inp = InputBuffer::new();
+    let mut sink = CountSink::default();
+    let searcher =
+        Searcher::new(&mut inp, &mut sink, grep, path, rdr).options(opts);
+    searcher.run()
+}
+
+/// Count the individual matches found by `grep` in `rdr`.
+///
+/// Unlike `count_lines`, a line that matches several times contributes
+/// each of its matches, regardless of the configured report granularity.
+#[allow(dead_code)]
+pub fn count_matches<R: io::Read>(
+    grep: &Grep,
+    path: &Path,
+    rdr: R,
+    opts: &Options,
+) -> Result<u64, Error> {
+    let mut opts = opts.clone();
+    opts.count = false;
+    opts.count_matches = true;
+    let mut inp = InputBuffer::new();
+    let mut sink = CountSink::default();
+    {
+        let searcher =
+            Searcher::new(&mut inp, &mut sink, grep, path, rdr).options(opts);
+        searcher.run()?;
+    }
+    Ok(sink.count)
+}
+
+/// The first matching line of a search, as returned by `find_first`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FirstMatch {
+    /// The absolute byte offset of the start of the line.
+    pub byte_offset: u64,
+    /// The line number of the match, if line numbers were enabled.
+    pub line_number: Option<u64>,
+    /// The bytes of the matching line, including its terminator if
+    /// present.
+    pub line: Vec<u8>,
+}
+
+/// A sink that keeps only the first matching line, for `find_first`.
+#[derive(Default)]
+struct FirstSink {
+    hit: Option<FirstMatch>,
+}
+
+impl Sink for FirstSink {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        _: Option<&Regex>,
+        _: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        _: Option<u64>,
+        _: Option<Indent>,
+    ) {
+        if self.hit.is_none() {
+            self.hit = Some(FirstMatch {
+                byte_offset: byte_offset.unwrap_or(0),
+                line_number,
+                line: buf[start..end].to_vec(),
+            });
+        }
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _: P,
+        _: &[u8],
+        _: usize,
+        _: usize,
+        _: Option<u64>,
+        _: Option<u64>,
+    ) {
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+
+    fn has_printed(&self) -> bool {
+        self.hit.is_some()
+    }
+}
+
+/// Find the first line matched by `grep` in `rdr`, or `None` if nothing
+/// matches.
+///
+/// This forces a match limit of one, so the searcher's early-termination
+/// machinery stops reading input as soon as the hit is delivered rather
+/// than scanning to EOF. Byte offsets are always computed; line numbers
+/// follow the caller's configuration, as do inversion and the other
+/// options.
+#[allow(dead_code)]
+pub fn find_first<R: io::Read>(
+    grep: &Grep,
+    path: &Path,
+    rdr: R,
+    opts: &Options,
+) -> Result<Option<FirstMatch>, Error> {
+    let mut opts = opts.clone();
+    opts.max_count = Some(1);
+    opts.byte_offset = true;
+    opts.count = false;
+    opts.count_matches = false;
+    opts.files_with_matches = false;
+    opts.files_without_matches = false;
+    opts.quiet = false;
+    let mut inp = InputBuffer::new();
+    let mut sink = FirstSink::default();
+    {
+        let searcher =
+            Searcher::new(&mut inp, &mut sink, grep, path, rdr).options(opts);
+        searcher.run()?;
+    }
+    Ok(sink.hit)
+}
+
+/// Options controlling how the path-based entry points open their input.
+///
+/// The defaults match `File::open`: read access, no retries and, on
+/// Windows, the standard sharing mode that permits concurrent reads.
+/// Windows builds can additionally relax or restrict the sharing mode and
+/// retry opens that fail with a sharing violation. Note that handing an
+/// opened file to a memory-map-based searcher extends the effective
+/// lifetime of its sharing restrictions to the lifetime of the map, not
+/// just of the handle.
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    retries: u32,
+    backoff: Duration,
+    #[cfg(windows)]
+    share_mode: Option<u32>,
+}
+
+impl Default for OpenOptions {
+    fn default() -> OpenOptions {
+        OpenOptions::new()
+    }
+}
+
+impl OpenOptions {
+    /// Create options equivalent to a plain `File::open`.
+    #[allow(dead_code)]
+    pub fn new() -> OpenOptions {
+        OpenOptions {
+            retries: 0,
+            backoff: Duration::from_millis(10),
+            #[cfg(windows)]
+            share_mode: None,
+        }
+    }
+
+    /// Retry opens that fail with a sharing violation up to `retries`
+    /// times, doubling the delay between attempts starting from `backoff`.
+    /// Once the retries are exhausted, the open fails with
+    /// `Error::SharingViolation`. Sharing violations don't occur on other
+    /// platforms, so this has no effect there.
+    #[allow(dead_code)]
+    pub fn retry_sharing_violation(
+        mut self,
+        retries: u32,
+        backoff: Duration,
+    ) -> OpenOptions {
+        self.retries = retries;
+        self.backoff = backoff;
+        self
+    }
+
+    /// Set the Windows sharing mode to request, as a combination of the
+    /// `FILE_SHARE_READ` (`0x1`), `FILE_SHARE_WRITE` (`0x2`) and
+    /// `FILE_SHARE_DELETE` (`0x4`) bits. A more permissive mode keeps a
+    /// long scan from blocking other programs that want the file.
+    #[cfg(windows)]
+    #[allow(dead_code)]
+    pub fn share_mode(mut self, mode: u32) -> OpenOptions {
+        self.share_mode = Some(mode);
+        self
+    }
+
+    /// Open the file given, applying the retry policy.
+    #[allow(dead_code)]
+    fn open(&self, path: &Path) -> Result<File, Error> {
+        let mut backoff = self.backoff;
+        let mut retries = self.retries;
+        loop {
+            match self.open_once(path) {
+                Ok(file) => return Ok(file),
+                Err(ref err) if is_sharing_violation(err) => {
+                    if retries == 0 {
+                        return Err(Error::SharingViolation {
+                            path: path.to_path_buf(),
+                        });
+                    }
+                    retries -= 1;
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                }
+                Err(err) => return Err(Error::from_io(err, path)),
+            }
+        }
+    }
+
+    #[allow(dead_code)]
+    fn open_once(&self, path: &Path) -> io::Result<File> {
+        let mut opts = fs::OpenOptions::new();
+        opts.read(true);
+        #[cfg(windows)]
+        {
+            use std::os::windows::fs::OpenOptionsExt;
+            if let Some(mode) = self.share_mode {
+                opts.share_mode(mode);
+            }
+        }
+        opts.open(path)
+    }
+}
+
+/// Returns true if the error given is a Windows sharing violation
+/// (`ERROR_SHARING_VIOLATION`). Always false elsewhere.
+#[allow(dead_code)]
+fn is_sharing_violation(err: &io::Error) -> bool {
+    cfg!(windows) && err.raw_os_error() == Some(32)
+}
+
+/// A single owned search event, as forwarded by a `Channel` sink.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChannelEvent {
+    /// A matching line.
+    Match(MatchRecord),
+    /// A contextual (non-matching) line printed around a match.
+    Context {
+        /// The path of the searched input.
+        path: PathBuf,
+        /// The bytes of the line, including its terminator if present.
+        line: Vec<u8>,
+        /// The line number, if line numbers were enabled.
+        line_number: Option<u64>,
+        /// The byte offset of the start of the line, if offsets were
+        /// enabled.
+        byte_offset: Option<u64>,
+    },
+    /// A separator between non-contiguous runs of contextual lines.
+    ContextSeparator,
+    /// A path-only report (e.g., for files-with-matches).
+    Path(PathBuf),
+    /// A path with a count.
+    PathCount(PathBuf, u64),
+    /// A read error skipped by a best-effort search. This carries the
+    /// error's message, since `io::Error` can't be cloned into an owned
+    /// event.
+    ReadError {
+        /// The path of the searched input.
+        path: PathBuf,
+        /// The absolute byte offset at which the failing read started.
+        offset: u64,
+        /// The error's display message.
+        message: String,
+    },
+}
+
+/// A sink that forwards every event over a bounded mpsc channel.
+///
+/// This is the glue for pipelined applications that produce search events on
+/// one thread and consume them on another: each event is converted into an
+/// owned `ChannelEvent` and sent over a `SyncSender`, whose bound provides
+/// natural backpressure when the consumer falls behind. A disconnected
+/// receiver is a clean stop signal, not an error: the sink simply stops
+/// forwarding and discards the remaining events. Since sinks can't terminate
+/// a search, callers that want to cut the search itself short can poll
+/// `disconnected` between files.
+///
+/// ```ignore
+/// let (tx, rx) = mpsc::sync_channel(128);
+/// let handle = thread::spawn(move || {
+///     // The search runs on its own thread...
+///     let mut sink = Channel::new(tx);
+///     let mut inp = InputBuffer::new();
+///     Searcher::new(&mut inp, &mut sink, &grep, path, file).run()
+/// });
+/// // ...while the UI thread renders events as they arrive. The loop ends
+/// // when the search finishes and the sender is dropped.
+/// for event in rx {
+///     match event {
+///         ChannelEvent::Match(record) => ui.render_match(record),
+///         ChannelEvent::PathCount(path, count) => ui.render_count(path, count),
+///         _ => {}
+///     }
+/// }
+/// handle.join().unwrap()?;
+/// ```
+#[allow(dead_code)]
+pub struct Channel {
+    tx: SyncSender<ChannelEvent>,
+    connected: bool,
+    printed: bool,
+}
+
+#[allow(dead_code)]
+impl Channel {
+    /// Create a new sink forwarding events to the given sender.
+    pub fn new(tx: SyncSender<ChannelEvent>) -> Channel {
+        Channel { tx, connected: true, printed: false }
+    }
+
+    /// Returns true if the receiving end has disconnected. Once this is
+    /// true, all further events are discarded.
+    pub fn disconnected(&self) -> bool {
+        !self.connected
+    }
+
+    /// Send the event given, blocking while the channel is full. If the
+    /// receiver has disconnected, the event is discarded and the sink
+    /// stops forwarding.
+    fn send(&mut self, event: ChannelEvent) {
+        if self.connected && self.tx.send(event).is_err() {
+            self.connected = false;
+        }
+    }
+}
+
+impl Sink for Channel {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+        column: Option<u64>,
+        indent: Option<Indent>,
+    ) {
+        self.printed = true;
+        let line = &buf[start..end];
+        let submatches = re
+            .map(|re| {
+                re.find_iter(line).map(|m| (m.start(), m.end())).collect()
+            })
+            .unwrap_or_default();
+        self.send(ChannelEvent::Match(MatchRecord {
+            path: path.as_ref().to_path_buf(),
+            line_number,
+            byte_offset,
+            column,
+            line: line.to_vec(),
+            submatches,
+            indent,
+        }));
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        byte_offset: Option<u64>,
+    ) {
+        self.printed = true;
+        self.send(ChannelEvent::Context {
+            path: path.as_ref().to_path_buf(),
+            line: buf[start..end].to_vec(),
+            line_number,
+            byte_offset,
+        });
+    }
+
+    fn context_separate(&mut self) {
+        self.send(ChannelEvent::ContextSeparator);
+    }
+
+    fn path<P: AsRef<Path>>(&mut self, path: P) {
+        self.printed = true;
+        self.send(ChannelEvent::Path(path.as_ref().to_path_buf()));
+    }
+
+    fn path_count<P: AsRef<Path>>(&mut self, path: P, count: u64) {
+        self.printed = true;
+        self.send(
+            ChannelEvent::PathCount(path.as_ref().to_path_buf(), count));
+    }
+
+    fn read_error<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        offset: u64,
+        err: &io::Error,
+    ) {
+        self.send(ChannelEvent::ReadError {
+            path: path.as_ref().to_path_buf(),
+            offset,
+            message: err.to_string(),
+        });
+    }
+
+    fn has_printed(&self) -> bool {
+        self.printed
+    }
+}
+
+/// A growable bitset of matching line numbers.
+///
+/// This is the cheapest way to answer "which lines matched?": one bit per
+/// line, no line data and no events. Use it as a sink on a searcher with
+/// line numbers enabled; matches reported without a line number are
+/// ignored. With inverted matching the set is the complement of the
+/// matching lines, so diff tooling gets both views from the same type.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[allow(dead_code)]
+pub struct LineSet {
+    blocks: Vec<u64>,
+    count: usize,
+}
+
+#[allow(dead_code)]
+impl LineSet {
+    /// Create a new empty set.
+    pub fn new() -> LineSet {
+        LineSet::default()
+    }
+
+    /// Add the (1-based) line number given to this set.
+    pub fn insert(&mut self, line: u64) {
+        debug_assert!(line > 0);
+        let (block, bit) = (((line - 1) / 64) as usize, (line - 1) % 64);
+        if block >= self.blocks.len() {
+            self.blocks.resize(block + 1, 0);
+        }
+        if self.blocks[block] & (1 << bit) == 0 {
+            self.blocks[block] |= 1 << bit;
+            self.count += 1;
+        }
+    }
+
+    /// Returns true if the line number given is in this set.
+    pub fn contains(&self, line: u64) -> bool {
+        if line == 0 {
+            return false;
+        }
+        let (block, bit) = (((line - 1) / 64) as usize, (line - 1) % 64);
+        self.blocks.get(block).is_some_and(|&b| b & (1 << bit) != 0)
+    }
+
+    /// Returns the number of lines in this set.
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns true if this set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Returns the number of lines in this set that are `<= line`.
+    pub fn rank(&self, line: u64) -> usize {
+        let (block, bit) = ((line / 64) as usize, line % 64);
+        let mut rank = 0;
+        for (i, &b) in self.blocks.iter().enumerate() {
+            if i < block {
+                rank += b.count_ones() as usize;
+            } else if i == block {
+                rank += (b & !(!0u64 << bit)).count_ones() as usize;
+            } else {
+                break;
+            }
+        }
+        rank
+    }
+
+    /// Returns the `k`-th smallest line in this set, starting at
+    /// `select(0)` for the smallest, or `None` if fewer than `k + 1` lines
+    /// are set.
+    pub fn select(&self, k: usize) -> Option<u64> {
+        self.iter().nth(k)
+    }
+
+    /// Returns an iterator over the lines in this set, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item=u64> + '_ {
+        self.blocks.iter().enumerate().flat_map(|(i, &b)| {
+            (0..64).filter(move |bit| b & (1 << bit) != 0)
+                   .map(move |bit| i as u64 * 64 + bit + 1)
+        })
+    }
+}
+
+impl Sink for LineSet {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        _re: Option<&Regex>,
+        _path: P,
+        _buf: &[u8],
+        _start: usize,
+        _end: usize,
+        line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+        _column: Option<u64>,
+        _indent: Option<Indent>,
+    ) {
+        if let Some(line) = line_number {
+            self.insert(line);
+        }
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _: P,
+        _: &[u8],
+        _: usize,
+        _: usize,
+        _: Option<u64>,
+        _: Option<u64>,
+    ) {
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+
+    fn has_printed(&self) -> bool {
+        !self.is_empty()
+    }
+}
+
+/// A sink that writes the input back out with every match masked.
+///
+/// This is meant for searches where every line is reported as a match
+/// (e.g., with a passthru pattern such as `pat|^`): matching lines are
+/// written with each non-empty match replaced by the mask, and all other
+/// bytes -- including the original line terminators, or the lack of one on
+/// the final line -- are written verbatim, so the output differs from the
+/// input only at match positions. Zero-length matches (such as the `^` of a
+/// passthru pattern) are never masked. Without the underlying regex the
+/// position of a match within its line is unknown, so the entire line is
+/// masked.
+///
+/// As with `Printer`, write errors are ignored.
+#[allow(dead_code)]
+pub struct Redact<W: io::Write> {
+    wtr: W,
+    mask: Vec<u8>,
+    printed: bool,
+}
+
+#[allow(dead_code)]
+impl<W: io::Write> Redact<W> {
+    /// Create a new redacting sink that writes to `wtr`, replacing every
+    /// match with `mask`.
+    pub fn new(wtr: W, mask: Vec<u8>) -> Redact<W> {
+        Redact { wtr, mask, printed: false }
+    }
+
+    /// Unwrap this sink, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+}
+
+impl<W: io::Write> Sink for Redact<W> {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        _path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        _line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+        _column: Option<u64>,
+        _indent: Option<Indent>,
+    ) {
+        self.printed = true;
+        let line = &buf[start..end];
+        let re = match re {
+            Some(re) => re,
+            None => {
+                let eol = line.ends_with(b"\n");
+                let upto = line.len() - if eol { 1 } else { 0 };
+                let _ = self.wtr.write_all(&self.mask);
+                let _ = self.wtr.write_all(&line[upto..]);
+                return;
+            }
+        };
+        let mut last = 0;
+        for m in re.find_iter(line) {
+            if m.start() == m.end() {
+                continue;
+            }
+            let _ = self.wtr.write_all(&line[last..m.start()]);
+            let _ = self.wtr.write_all(&self.mask);
+            last = m.end();
+        }
+        let _ = self.wtr.write_all(&line[last..]);
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        _line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+    ) {
+        self.printed = true;
+        let _ = self.wtr.write_all(&buf[start..end]);
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _path: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _path: P, _count: u64) {}
+
+    fn has_printed(&self) -> bool {
+        self.printed
+    }
+}
+
+/// A reusable template expansion for producing lines with their matches
+/// replaced.
+///
+/// The template may refer to capture groups of each match: `$1` (or
+/// `${1}`) for numbered groups, `${name}` for named ones, and `$$` for a
+/// literal `$`. The expansion reuses one internal scratch buffer across
+/// lines, so a sink that holds a `Replacer` performs no per-line
+/// allocation, and no replacement work happens at all for lines it never
+/// asks about.
+#[allow(dead_code)]
+pub struct Replacer {
+    template: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+#[allow(dead_code)]
+impl Replacer {
+    /// Create a new replacer with the template given.
+    pub fn new(template: Vec<u8>) -> Replacer {
+        Replacer { template, scratch: vec![] }
+    }
+
+    /// Produce `line` with every match of `re` replaced by the template.
+    ///
+    /// The result borrows an internal scratch buffer that the next call
+    /// reuses. A line without matches is returned unchanged, without
+    /// copying. Zero-length matches (such as the `^` of a passthru
+    /// pattern) are never replaced, exactly as `Redact` never masks them.
+    /// Without the regex, match positions within the line are unknown, so
+    /// the line is also returned unchanged.
+    pub fn replace<'a>(
+        &'a mut self,
+        re: Option<&Regex>,
+        line: &'a [u8],
+    ) -> &'a [u8] {
+        let re = match re {
+            Some(re) => re,
+            None => return line,
+        };
+        self.scratch.clear();
+        let mut last = 0;
+        for caps in re.captures_iter(line) {
+            let m = caps.get(0).unwrap();
+            if m.start() == m.end() {
+                continue;
+            }
+            self.scratch.extend_from_slice(&line[last..m.start()]);
+            caps.expand(&self.template, &mut self.scratch);
+            last = m.end();
+        }
+        if last == 0 {
+            return line;
+        }
+        self.scratch.extend_from_slice(&line[last..]);
+        &self.scratch
+    }
+}
+
+/// A sink that writes the input back out with every match replaced by a
+/// template, like sed.
+///
+/// This is meant for searches where every line is reported as a match
+/// (e.g., with a passthru pattern such as `pat|^`): matching lines are
+/// written with each match expanded through a `Replacer` template, and
+/// all other bytes -- including the original line terminators, or the
+/// lack of one on the final line -- are written verbatim. Whatever unit
+/// the searcher reports is rewritten whole, so this works for
+/// line-oriented and paragraph searches alike.
+///
+/// As with `Printer`, write errors are ignored.
+#[allow(dead_code)]
+pub struct Replace<W: io::Write> {
+    wtr: W,
+    replacer: Replacer,
+    printed: bool,
+}
+
+#[allow(dead_code)]
+impl<W: io::Write> Replace<W> {
+    /// Create a new replacing sink that writes to `wtr`, expanding every
+    /// match through `template`.
+    pub fn new(wtr: W, template: Vec<u8>) -> Replace<W> {
+        Replace { wtr, replacer: Replacer::new(template), printed: false }
+    }
+
+    /// Unwrap this sink, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+}
+
+impl<W: io::Write> Sink for Replace<W> {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        _path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        _line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+        _column: Option<u64>,
+        _indent: Option<Indent>,
+    ) {
+        self.printed = true;
+        let line = self.replacer.replace(re, &buf[start..end]);
+        let _ = self.wtr.write_all(line);
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        _line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+    ) {
+        self.printed = true;
+        let _ = self.wtr.write_all(&buf[start..end]);
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _path: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _path: P, _count: u64) {}
+
+    fn has_printed(&self) -> bool {
+        self.printed
+    }
+}
+
+/// A sink that writes matching lines with the matched spans colored.
+///
+/// The spans come from the pattern the searcher hands to the sink, so each
+/// line is scanned for offsets exactly once here; the match decision itself
+/// is never re-made. Lines are written as raw bytes and spans address byte
+/// ranges, so non-UTF-8 input is highlighted correctly. When the writer does
+/// not support color, lines are written unstyled.
+///
+/// When the searcher provides no pattern (e.g., for inverted matches), the
+/// entire line (sans terminator) is treated as a single span.
+///
+/// As with `Printer`, write errors are ignored.
+#[cfg(feature = "colored-sink")]
+#[allow(dead_code)]
+pub struct Colored<W: WriteColor> {
+    wtr: W,
+    spec: ColorSpec,
+    printed: bool,
+}
+
+#[cfg(feature = "colored-sink")]
+#[allow(dead_code)]
+impl<W: WriteColor> Colored<W> {
+    /// Create a new coloring sink that writes to `wtr`, highlighting
+    /// matched spans in red.
+    pub fn new(wtr: W) -> Colored<W> {
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(Color::Red));
+        Colored { wtr, spec, printed: false }
+    }
+
+    /// Set the color spec used for matched spans.
+    pub fn color_spec(mut self, spec: ColorSpec) -> Colored<W> {
+        self.spec = spec;
+        self
+    }
+
+    /// Unwrap this sink, returning the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.wtr
+    }
+}
+
+#[cfg(feature = "colored-sink")]
+impl<W: WriteColor> Sink for Colored<W> {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        re: Option<&Regex>,
+        _path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        _line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+        _column: Option<u64>,
+        _indent: Option<Indent>,
+    ) {
+        self.printed = true;
+        let line = &buf[start..end];
+        if !self.wtr.supports_color() {
+            let _ = self.wtr.write_all(line);
+            return;
+        }
+        let spans: Vec<(usize, usize)> = match re {
+            Some(re) => {
+                re.find_iter(line)
+                    .map(|m| (m.start(), m.end()))
+                    .filter(|&(s, e)| s != e)
+                    .collect()
+            }
+            None => {
+                let eol = line.ends_with(b"\n");
+                let upto = line.len() - if eol { 1 } else { 0 };
+                if upto == 0 { vec![] } else { vec![(0, upto)] }
+            }
+        };
+        let mut last = 0;
+        for (s, e) in spans {
+            let _ = self.wtr.write_all(&line[last..s]);
+            let _ = self.wtr.set_color(&self.spec);
+            let _ = self.wtr.write_all(&line[s..e]);
+            let _ = self.wtr.reset();
+            last = e;
+        }
+        let _ = self.wtr.write_all(&line[last..]);
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _path: P,
+        buf: &[u8],
+        start: usize,
+        end: usize,
+        _line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+    ) {
+        self.printed = true;
+        let _ = self.wtr.write_all(&buf[start..end]);
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _path: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _path: P, _count: u64) {}
+
+    fn has_printed(&self) -> bool {
+        self.printed
+    }
+}
+
+/// An owned matching line backed by a reference-counted `Bytes` slice of
+/// the haystack, so collecting matches doesn't copy line data.
+#[cfg(feature = "bytes")]
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct BytesMatch {
+    /// The bytes of the matching line (including its terminator, if
+    /// present), sharing the haystack's allocation.
+    pub line: Bytes,
+    /// The start offset of the line in the haystack.
+    pub start: usize,
+    /// The end offset of the line in the haystack.
+    pub end: usize,
+    /// The line number of the match, if line numbers were enabled.
+    pub line_number: Option<u64>,
+}
+
+/// A sink that collects matching lines as cheap `Bytes` slices into the
+/// haystack instead of copying them.
+///
+/// This is intended for slice searches over a `Bytes` buffer (see
+/// `BufferSearcher::search_bytes`), where the offsets reported to the sink
+/// are absolute offsets into that buffer. The collected matches share the
+/// haystack's allocation, so they can be sent across threads or channels
+/// without duplicating it. Events other than matches are ignored.
+#[cfg(feature = "bytes")]
+#[allow(dead_code)]
+pub struct BytesCollector {
+    buf: Bytes,
+    matches: Vec<BytesMatch>,
+}
+
+#[cfg(feature = "bytes")]
+#[allow(dead_code)]
+impl BytesCollector {
+    /// Create a new collector over the haystack given.
+    ///
+    /// `Bytes` are reference counted, so cloning the haystack handle for
+    /// the collector is cheap.
+    pub fn new(buf: Bytes) -> BytesCollector {
+        BytesCollector { buf, matches: vec![] }
+    }
+
+    /// Returns the matches collected so far.
+    pub fn matches(&self) -> &[BytesMatch] {
+        &self.matches
+    }
+
+    /// Consume this collector, returning the collected matches.
+    pub fn into_matches(self) -> Vec<BytesMatch> {
+        self.matches
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Sink for BytesCollector {
+    fn matched<P: AsRef<Path>>(
+        &mut self,
+        _re: Option<&Regex>,
+        _path: P,
+        _buf: &[u8],
+        start: usize,
+        end: usize,
+        line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+        _column: Option<u64>,
+        _indent: Option<Indent>,
+    ) {
+        self.matches.push(BytesMatch {
+            line: self.buf.slice(start..end),
+            start,
+            end,
+            line_number,
+        });
+    }
+
+    fn context<P: AsRef<Path>>(
+        &mut self,
+        _path: P,
+        _buf: &[u8],
+        _start: usize,
+        _end: usize,
+        _line_number: Option<u64>,
+        _byte_offset: Option<u64>,
+    ) {
+    }
+
+    fn context_separate(&mut self) {}
+
+    fn path<P: AsRef<Path>>(&mut self, _path: P) {}
+
+    fn path_count<P: AsRef<Path>>(&mut self, _path: P, _count: u64) {}
+
+    fn has_printed(&self) -> bool {
+        !self.matches.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    use grep::{Grep, GrepBuilder};
+    use regex::bytes::Regex;
+
+    use search_stream::{FinalLinePolicy, Indent, InputBuffer, Searcher};
+
+    use super::{Collector, Filter, Map, Sink, Tee};
+
+    const SHERLOCK: &str = "\
+For the Doctor Watsons of this world, as opposed to the Sherlock
+Holmeses, success in the province of detective work must always
+be, to a very large extent, the result of luck. Sherlock Holmes
+can extract a clew from a wisp of straw or a flake of cigar ash;
+but Doctor Watson has to have it taken out for him and dusted,
+and exhibited clearly, with a label attached.\
+";
+
+    /// A single event received by a `Recorder`.
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    enum Event {
+        Matched {
+            path: PathBuf,
+            line: Vec<u8>,
+            line_number: Option<u64>,
+        },
+        Context {
+            path: PathBuf,
+            line: Vec<u8>,
+            line_number: Option<u64>,
+        },
+        ContextSeparate,
+        Path(PathBuf),
+        PathCount(PathBuf, u64),
+    }
+
+    /// A sink that records every event it receives, so that sequences of
+    /// events can be compared across combinators.
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<Event>,
+    }
+
+    impl Sink for Recorder {
+        fn matched<P: AsRef<Path>>(
+            &mut self,
+            _re: Option<&Regex>,
+            path: P,
+            buf: &[u8],
+            start: usize,
+            end: usize,
+            line_number: Option<u64>,
+            _byte_offset: Option<u64>,
+            _column: Option<u64>,
+            _indent: Option<Indent>,
+        ) {
+            self.events.push(Event::Matched {
+                path: path.as_ref().to_path_buf(),
+                line: buf[start..end].to_vec(),
+                line_number,
+            });
+        }
+
+        fn context<P: AsRef<Path>>(
+            &mut self,
+            path: P,
+            buf: &[u8],
+            start: usize,
+            end: usize,
+            line_number: Option<u64>,
+            _byte_offset: Option<u64>,
+        ) {
+            self.events.push(Event::Context {
+                path: path.as_ref().to_path_buf(),
+                line: buf[start..end].to_vec(),
+                line_number,
+            });
+        }
+
+        fn context_separate(&mut self) {
+            self.events.push(Event::ContextSeparate);
+        }
+
+        fn path<P: AsRef<Path>>(&mut self, path: P) {
+            self.events.push(Event::Path(path.as_ref().to_path_buf()));
+        }
+
+        fn path_count<P: AsRef<Path>>(&mut self, path: P, count: u64) {
+            self.events.push(
+                Event::PathCount(path.as_ref().to_path_buf(), count));
+        }
+
+        fn has_printed(&self) -> bool {
+            !self.events.is_empty()
+        }
+    }
+
+    type TestSearcher<'a, S> = Searcher<'a, io::Cursor<Vec<u8>>, S, Grep>;
+
+    fn search<S, F>(pat: &str, haystack: &str, sink: &mut S, mut map: F)
+        where S: Sink,
+              F: FnMut(TestSearcher<S>) -> TestSearcher<S>
+    {
+        let mut inp = InputBuffer::with_capacity(4096);
+        let grep = GrepBuilder::new(pat).build().unwrap();
+        let hay = io::Cursor::new(haystack.to_string().into_bytes());
+        let searcher = Searcher::new(
+            &mut inp, sink, &grep, Path::new("/baz.rs"), hay);
+        map(searcher).run().unwrap();
+    }
+
+    #[test]
+    fn unterminated_final_line_flagged() {
+        #[derive(Default)]
+        struct Flagged {
+            matches: u64,
+            unterminated: bool,
+        }
+
+        impl Sink for Flagged {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&Regex>, _: P, _: &[u8],
+                _: usize, _: usize, _: Option<u64>,
+                _: Option<u64>, _: Option<u64>, _: Option<Indent>,
+            ) {
+                self.matches += 1;
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn unterminated<P: AsRef<Path>>(&mut self, _: P) {
+                self.unterminated = true;
+            }
+            fn has_printed(&self) -> bool {
+                self.matches > 0
+            }
+        }
+
+        // SHERLOCK has no trailing newline: the final line is still
+        // searched, and the sink is told it was unterminated.
+        let mut sink = Flagged::default();
+        search("label", SHERLOCK, &mut sink, |s| {
+            s.final_line(FinalLinePolicy::Flag)
+        });
+        assert_eq!(1, sink.matches);
+        assert!(sink.unterminated);
+
+        // Terminated input never flags.
+        let mut sink = Flagged::default();
+        search("foo", "foo\n", &mut sink, |s| {
+            s.final_line(FinalLinePolicy::Flag)
+        });
+        assert_eq!(1, sink.matches);
+        assert!(!sink.unterminated);
+    }
+
+    #[test]
+    fn segment_boundaries_reported() {
+        #[derive(Default)]
+        struct Segments {
+            matches: u64,
+            bounds: Vec<(u64, u64)>,
+        }
+
+        impl Sink for Segments {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&Regex>, _: P, _: &[u8],
+                _: usize, _: usize, _: Option<u64>,
+                _: Option<u64>, _: Option<u64>, _: Option<Indent>,
+            ) {
+                self.matches += 1;
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn segment<P: AsRef<Path>>(&mut self, _: P, index: u64,
+                                       offset: u64) {
+                self.bounds.push((index, offset));
+            }
+            fn has_printed(&self) -> bool {
+                self.matches > 0
+            }
+        }
+
+        let mut inp = InputBuffer::with_capacity(4096);
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut sink = Segments::default();
+        {
+            let first = io::Cursor::new(b"a\nfoo\n".to_vec());
+            let rest = vec![
+                io::Cursor::new(b"b\n".to_vec()),
+                io::Cursor::new(b"foo\nc\n".to_vec()),
+            ];
+            let searcher = Searcher::new(
+                &mut inp, &mut sink, &grep, Path::new("/baz.rs"), first);
+            searcher.run_readers(rest).unwrap();
+        }
+        assert_eq!(2, sink.matches);
+        // One notification per segment, at its absolute start offset.
+        assert_eq!(vec![(0, 0), (1, 6), (2, 8)], sink.bounds);
+    }
+
+    #[test]
+    fn binary_report_keeps_searching() {
+        #[derive(Default)]
+        struct Binary {
+            matches: u64,
+            offsets: Vec<u64>,
+        }
+
+        impl Sink for Binary {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&Regex>, _: P, _: &[u8],
+                _: usize, _: usize, _: Option<u64>,
+                _: Option<u64>, _: Option<u64>, _: Option<Indent>,
+            ) {
+                self.matches += 1;
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn binary_data<P: AsRef<Path>>(&mut self, _: P, offset: u64) {
+                self.offsets.push(offset);
+            }
+            fn has_printed(&self) -> bool {
+                self.matches > 0
+            }
+        }
+
+        let haystack = "foo\x00bar\nbaz\n";
+
+        // By default a NUL aborts the search with no matches.
+        let mut sink = Binary::default();
+        search("baz", haystack, &mut sink, |s| s);
+        assert_eq!(0, sink.matches);
+        assert!(sink.offsets.is_empty());
+
+        // In report mode the match is delivered and the NUL's offset
+        // recorded.
+        let mut sink = Binary::default();
+        search("baz", haystack, &mut sink, |s| s.binary_report(Some(0)));
+        assert_eq!(1, sink.matches);
+        assert_eq!(vec![3], sink.offsets);
+    }
+
+    #[test]
+    fn context_truncated_flagged() {
+        #[derive(Default)]
+        struct Truncations {
+            matches: u64,
+            context_bytes: usize,
+            truncated: u64,
+        }
+
+        impl Sink for Truncations {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&Regex>, _: P, _: &[u8],
+                _: usize, _: usize, _: Option<u64>,
+                _: Option<u64>, _: Option<u64>, _: Option<Indent>,
+            ) {
+                self.matches += 1;
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], start: usize, end: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+                self.context_bytes += end - start;
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn context_truncated<P: AsRef<Path>>(&mut self, _: P) {
+                self.truncated += 1;
+            }
+            fn has_printed(&self) -> bool {
+                self.matches > 0
+            }
+        }
+
+        // Two matches, each with one context line cut to the 10-byte
+        // budget and flagged as truncated.
+        let mut sink = Truncations::default();
+        search("Sherlock", SHERLOCK, &mut sink, |s| {
+            s.after_context(1).context_byte_limit(Some(10))
+        });
+        assert_eq!(2, sink.matches);
+        assert_eq!(20, sink.context_bytes);
+        assert_eq!(2, sink.truncated);
+    }
+
+    #[test]
+    fn needs_line_numbers_skips_counting() {
+        #[derive(Default)]
+        struct CountOnly {
+            matches: u64,
+            line_numbers: Vec<Option<u64>>,
+        }
+
+        impl Sink for CountOnly {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&Regex>, _: P, _: &[u8],
+                _: usize, _: usize, line_number: Option<u64>,
+                _: Option<u64>, _: Option<u64>, _: Option<Indent>,
+            ) {
+                self.matches += 1;
+                self.line_numbers.push(line_number);
+            }
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+            fn context_separate(&mut self) {}
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+            fn needs_line_numbers(&self) -> bool {
+                false
+            }
+            fn has_printed(&self) -> bool {
+                self.matches > 0
+            }
+        }
+
+        // Even with line numbers enabled by the builder, the sink's
+        // declaration wins and the events carry None.
+        let mut sink = CountOnly::default();
+        search("Sherlock", SHERLOCK, &mut sink, |s| s.line_number(true));
+        assert_eq!(2, sink.matches);
+        assert_eq!(vec![None, None], sink.line_numbers);
+
+        // Behind a tee, the other side may still want them, so counting
+        // stays on and both sides see real numbers.
+        let mut tee = Tee(CountOnly::default(), Recorder::default());
+        search("Sherlock", SHERLOCK, &mut tee, |s| s.line_number(true));
+        assert_eq!(vec![Some(1), Some(3)], tee.0.line_numbers);
+    }
+
+    #[test]
+    fn tee_equivalence() {
+        // Both sides of a tee must see exactly the same event sequence as a
+        // sink receiving the events directly.
+        let mut direct = Recorder::default();
+        search("Sherlock", SHERLOCK, &mut direct, |s| {
+            s.line_number(true).before_context(1).after_context(1)
+        });
+
+        let mut tee = Tee(Recorder::default(), Recorder::default());
+        search("Sherlock", SHERLOCK, &mut tee, |s| {
+            s.line_number(true).before_context(1).after_context(1)
+        });
+
+        assert!(!direct.events.is_empty());
+        assert_eq!(direct.events, tee.0.events);
+        assert_eq!(direct.events, tee.1.events);
+    }
+
+    #[test]
+    fn tee_equivalence_counts() {
+        let mut direct = Recorder::default();
+        search("Sherlock", SHERLOCK, &mut direct, |s| s.count(true));
+
+        let mut tee = Tee(Recorder::default(), Recorder::default());
+        search("Sherlock", SHERLOCK, &mut tee, |s| s.count(true));
+
+        assert_eq!(direct.events, tee.0.events);
+        assert_eq!(direct.events, tee.1.events);
+    }
+
+    #[test]
+    fn filter_drops_matches() {
+        let mut filter = Filter::new(
+            |_: &Path, line: &[u8]| !line.starts_with(b"For"),
+            Recorder::default());
+        search("Sherlock", SHERLOCK, &mut filter, |s| s.line_number(true));
+
+        let events = filter.into_inner().events;
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            Event::Matched { line_number, .. } => {
+                assert_eq!(Some(3), line_number);
+            }
+            ref event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn filter_keeps_counts() {
+        // Counts reported by the searcher reflect the unfiltered search,
+        // even when every matched event is dropped.
+        let mut filter = Filter::new(
+            |_: &Path, _: &[u8]| false, Recorder::default());
+        search("Sherlock", SHERLOCK, &mut filter, |s| s.count(true));
+
+        let events = filter.into_inner().events;
+        assert_eq!(
+            vec![Event::PathCount(PathBuf::from("/baz.rs"), 2)],
+            events);
+    }
+
+    #[test]
+    fn map_transforms_lines() {
+        let mut map = Map::new(
+            |line: &[u8]| line.to_ascii_uppercase(), Recorder::default());
+        search("Holmeses", SHERLOCK, &mut map, |s|s);
+
+        let events = map.into_inner().events;
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            Event::Matched { ref line, .. } => {
+                assert!(line.starts_with(b"HOLMESES"));
+            }
+            ref event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn channel_forwards_events() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        use super::{Channel, ChannelEvent};
+
+        // A one-slot channel with a threaded consumer exercises the
+        // backpressure path.
+        let (tx, rx) = mpsc::sync_channel(1);
+        let handle = thread::spawn(move || rx.iter().collect::<Vec<_>>());
+        let mut sink = Channel::new(tx);
+        search("Doctor", SHERLOCK, &mut sink, |s| {
+            s.line_number(true).before_context(1)
+        });
+        assert!(sink.has_printed());
+        assert!(!sink.disconnected());
+        drop(sink);
+
+        let events = handle.join().unwrap();
+        assert_eq!(4, events.len());
+        match events[0] {
+            ChannelEvent::Match(ref r) => {
+                assert_eq!(Some(1), r.line_number);
+                assert!(r.line.starts_with(b"For the Doctor"));
+                assert_eq!(vec![(8, 14)], r.submatches);
+            }
+            ref event => panic!("unexpected event: {:?}", event),
+        }
+        assert_eq!(ChannelEvent::ContextSeparator, events[1]);
+        match events[2] {
+            ChannelEvent::Context { ref line, line_number, .. } => {
+                assert_eq!(Some(4), line_number);
+                assert!(line.starts_with(b"can extract"));
+            }
+            ref event => panic!("unexpected event: {:?}", event),
+        }
+        match events[3] {
+            ChannelEvent::Match(ref r) => assert_eq!(Some(5), r.line_number),
+            ref event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn channel_disconnected_receiver_stops_cleanly() {
+        use std::sync::mpsc;
+
+        use super::Channel;
+
+        // A receiver that goes away mustn't fail the search; the sink just
+        // stops forwarding.
+        let (tx, rx) = mpsc::sync_channel(1);
+        drop(rx);
+        let mut sink = Channel::new(tx);
+        search("Sherlock", SHERLOCK, &mut sink, |s| s.line_number(true));
+        assert!(sink.disconnected());
+        assert!(sink.has_printed());
+    }
+
+    #[test]
+    fn redact_round_trip() {
+        // With a passthru pattern, every line is reported and the output
+        // must differ from the input only at match positions. SHERLOCK has
+        // no trailing terminator, which must survive the round trip.
+        let mut sink = super::Redact::new(vec![], b"[REDACTED]".to_vec());
+        search("Sherlock|^", SHERLOCK, &mut sink, |s| s);
+        let out = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(out, SHERLOCK.replace("Sherlock", "[REDACTED]"));
+    }
+
+    #[test]
+    fn redact_multiple_per_line() {
+        let text = "aaa bbb aaa\nccc\naaa";
+        let mut sink = super::Redact::new(vec![], b"X".to_vec());
+        search("aaa|^", text, &mut sink, |s| s);
+        let out = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(out, "X bbb X\nccc\nX");
+    }
+
+    #[test]
+    fn replacer_expands_captures() {
+        use regex::bytes::Regex;
+
+        let re = Regex::new(r"(\w+)@(\w+)").unwrap();
+        let mut replacer = super::Replacer::new(b"$2@$1".to_vec());
+        let out = replacer.replace(Some(&re), b"a@b c@d\n").to_vec();
+        assert_eq!(out, b"b@a d@c\n".to_vec());
+        // The scratch buffer is reused; a line without matches comes back
+        // unchanged and uncopied.
+        let out = replacer.replace(Some(&re), b"nothing\n").to_vec();
+        assert_eq!(out, b"nothing\n".to_vec());
+    }
+
+    #[test]
+    fn replace_round_trip() {
+        // With a passthru pattern, the output is a full rewrite of the
+        // input. SHERLOCK has no trailing terminator, which must survive.
+        let mut sink = super::Replace::new(vec![], b"${1}LOCK".to_vec());
+        search("(Sher)lock|^", SHERLOCK, &mut sink, |s| s);
+        let out = String::from_utf8(sink.into_inner()).unwrap();
+        assert_eq!(out, SHERLOCK.replace("Sherlock", "SherLOCK"));
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn bytes_collector() {
+        use bytes::Bytes;
+        use search_buffer::BufferSearcher;
+        use super::BytesCollector;
+
+        let hay = Bytes::from_static(b"aaa\nbbb\nabc\n");
+        let mut sink = BytesCollector::new(hay.clone());
+        let grep = GrepBuilder::new("b").build().unwrap();
+        let count = {
+            let searcher = BufferSearcher::search_bytes(
+                &mut sink, &grep, Path::new("/baz.rs"), &hay);
+            searcher.line_number(true).run()
+        };
+        assert_eq!(2, count);
+        let matches = sink.into_matches();
+        assert_eq!(2, matches.len());
+        assert_eq!(&matches[0].line[..], b"bbb\n");
+        assert_eq!((4, 8, Some(2)),
+                   (matches[0].start, matches[0].end, matches[0].line_number));
+        assert_eq!(&matches[1].line[..], b"abc\n");
+        assert_eq!(Some(3), matches[1].line_number);
+    }
+
+    /// Build the expected output of a `Colored` sink by performing the same
+    /// writer operations by hand. Parts flagged `true` are highlighted.
+    #[cfg(feature = "colored-sink")]
+    fn colored_expected(parts: &[(bool, &[u8])]) -> Vec<u8> {
+        use std::io::Write;
+        use termcolor::{Ansi, Color, ColorSpec, WriteColor};
+
+        let mut wtr = Ansi::new(vec![]);
+        let mut spec = ColorSpec::new();
+        spec.set_fg(Some(Color::Red));
+        for &(highlight, bytes) in parts {
+            if highlight {
+                wtr.set_color(&spec).unwrap();
+                wtr.write_all(bytes).unwrap();
+                wtr.reset().unwrap();
+            } else {
+                wtr.write_all(bytes).unwrap();
+            }
+        }
+        wtr.into_inner()
+    }
+
+    #[cfg(feature = "colored-sink")]
+    #[test]
+    fn colored_spans() {
+        use termcolor::Ansi;
+        use super::Colored;
+
+        // Spans at the start and end of a line, and several per line.
+        let mut sink = Colored::new(Ansi::new(vec![]));
+        search("b", "abc\nxxx\nbxb\n", &mut sink, |s| s);
+        let expected = colored_expected(&[
+            (false, b"a"), (true, b"b"), (false, b"c\n"),
+            (true, b"b"), (false, b"x"), (true, b"b"), (false, b"\n"),
+        ]);
+        assert_eq!(expected, sink.into_inner().into_inner());
+    }
+
+    #[cfg(feature = "colored-sink")]
+    #[test]
+    fn colored_fallback_no_color() {
+        use termcolor::NoColor;
+        use super::Colored;
+
+        // A writer without color support gets the raw lines.
+        let mut sink = Colored::new(NoColor::new(vec![]));
+        search("b", "abc\nxxx\nbxb\n", &mut sink, |s| s);
+        assert_eq!(b"abc\nbxb\n".to_vec(), sink.into_inner().into_inner());
+    }
+
+    #[cfg(feature = "colored-sink")]
+    #[test]
+    fn colored_non_utf8() {
+        use termcolor::Ansi;
+        use super::Colored;
+
+        // Spans address byte ranges, so invalid UTF-8 around a match is
+        // written through untouched.
+        let re = Regex::new("b").unwrap();
+        let buf = b"\xFFb\xFE\n";
+        let mut sink = Colored::new(Ansi::new(vec![]));
+        sink.matched(
+            Some(&re), Path::new("/baz.rs"), buf, 0, buf.len(), None, None,
+            None, None);
+        let expected = colored_expected(&[
+            (false, b"\xFF"), (true, b"b"), (false, b"\xFE\n"),
+        ]);
+        assert_eq!(expected, sink.into_inner().into_inner());
+    }
+
+    #[cfg(feature = "colored-sink")]
+    #[test]
+    fn colored_no_pattern_whole_line() {
+        use termcolor::Ansi;
+        use super::Colored;
+
+        // Without a pattern (e.g., inverted matches), the whole line minus
+        // its terminator is one span.
+        let mut sink = Colored::new(Ansi::new(vec![]));
+        sink.matched(
+            None, Path::new("/baz.rs"), b"abc\n", 0, 4, None, None, None,
+            None);
+        let expected = colored_expected(&[(true, b"abc"), (false, b"\n")]);
+        assert_eq!(expected, sink.into_inner().into_inner());
+    }
+
+    #[test]
+    fn line_set_rank_select() {
+        use super::LineSet;
+
+        let mut set = LineSet::new();
+        for &line in &[1, 3, 64, 65, 200] {
+            set.insert(line);
+        }
+        set.insert(3); // duplicates are fine
+        assert_eq!(5, set.len());
+        assert!(set.contains(64) && !set.contains(2));
+        assert_eq!(vec![1, 3, 64, 65, 200], set.iter().collect::<Vec<_>>());
+        assert_eq!(0, set.rank(0));
+        assert_eq!(2, set.rank(3));
+        assert_eq!(3, set.rank(64));
+        assert_eq!(5, set.rank(1000));
+        assert_eq!(Some(1), set.select(0));
+        assert_eq!(Some(200), set.select(4));
+        assert_eq!(None, set.select(5));
+    }
+
+    #[test]
+    fn line_set_matches_event_api() {
+        use super::LineSet;
+
+        // The set must agree, line for line, with the line numbers the
+        // event-based API reports.
+        let mut set = LineSet::new();
+        search("Sherlock", SHERLOCK, &mut set, |s| s.line_number(true));
+        let mut recorder = Recorder::default();
+        search("Sherlock", SHERLOCK, &mut recorder, |s| s.line_number(true));
+        let expected: Vec<u64> = recorder
+            .events
+            .iter()
+            .filter_map(|ev| match *ev {
+                Event::Matched { line_number, .. } => line_number,
+                _ => None,
+            })
+            .collect();
+        assert_eq!(expected, set.iter().collect::<Vec<_>>());
+
+        // Inverted matching yields the complement.
+        let mut inverted = LineSet::new();
+        search("Sherlock", SHERLOCK, &mut inverted, |s| {
+            s.line_number(true).invert_match(true)
+        });
+        for line in 1..7 {
+            assert_ne!(set.contains(line), inverted.contains(line));
+        }
+        assert_eq!(6, set.len() + inverted.len());
+    }
+
+    #[test]
+    fn collector_reports_indent() {
+        use super::Collector;
+
+        let hay = "foo\n  bar\n\tbaz\n\t  quux\n";
+        let mut collector = Collector::new();
+        search("b|q|f", hay, &mut collector, |s| s.report_indent(Some(8)));
+        let indents: Vec<_> = collector
+            .records()
+            .iter()
+            .map(|r| r.indent.unwrap())
+            .collect();
+        assert_eq!(indents, vec![
+            Indent { chars: 0, width: 0 },
+            Indent { chars: 2, width: 2 },
+            Indent { chars: 1, width: 8 },
+            Indent { chars: 3, width: 10 },
+        ]);
+
+        // A different tab stop changes only the expanded widths.
+        let mut collector = Collector::new();
+        search("b|q|f", hay, &mut collector, |s| s.report_indent(Some(4)));
+        let widths: Vec<_> = collector
+            .records()
+            .iter()
+            .map(|r| r.indent.unwrap().width)
+            .collect();
+        assert_eq!(widths, vec![0, 2, 4, 6]);
+
+        // Without the option there is no per-line scan and no payload.
+        let mut collector = Collector::new();
+        search("b|q|f", hay, &mut collector, |s| s);
+        assert!(collector.records().iter().all(|r| r.indent.is_none()));
+    }
+
+    #[test]
+    fn collect_column() {
+        use super::Collector;
+
+        let mut collector = Collector::new();
+        search("bar", "foo bar\nbar\n", &mut collector, |s| s.column(true));
+        let records = collector.records();
+        assert_eq!(2, records.len());
+        assert_eq!(Some(5), records[0].column);
+        assert_eq!(Some(1), records[1].column);
+
+        // Columns are only reported on request.
+        let mut collector = Collector::new();
+        search("bar", "foo bar\n", &mut collector, |s| s);
+        assert_eq!(None, collector.records()[0].column);
+
+        // Inverted matches have no match to locate and report column 1.
+        let mut collector = Collector::new();
+        search("bar", "foo\nfoo bar\n", &mut collector, |s| {
+            s.invert_match(true).column(true)
+        });
+        let records = collector.records();
+        assert_eq!(1, records.len());
+        assert_eq!(Some(1), records[0].column);
+    }
+
+    #[test]
+    fn collect_convenience_equivalent() {
+        use std::fs;
+        use std::io::Write;
+
+        use search_stream::Options;
+
+        use super::search_path_collect;
+
+        // A unique scratch file, so concurrent test runs don't collide
+        // on a fixed /tmp name or leave litter behind.
+        let dir = ::std::env::current_exe().unwrap()
+            .parent().expect("executable's directory")
+            .join("rg-sink-tests")
+            .join(format!("{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("collect-haystack");
+        let mut f = fs::File::create(&path).unwrap();
+        f.write_all(SHERLOCK.as_bytes()).unwrap();
+        drop(f);
+
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let records =
+            search_path_collect(&grep, &path, &Options::grep_defaults())
+                .unwrap();
+
+        // The convenience function must agree with driving the pieces by
+        // hand.
+        let mut inp = InputBuffer::new();
+        let mut collector = Collector::new();
+        {
+            let searcher = Searcher::new(
+                &mut inp, &mut collector, &grep, &path,
+                io::Cursor::new(SHERLOCK.to_string().into_bytes()));
+            searcher.line_number(true).run().unwrap();
+        }
+        assert_eq!(records, collector.into_records());
+
+        // Custom open options with the default policy must behave exactly
+        // like the plain entry point.
+        let with = super::search_path_collect_with(
+            &grep, &path, &Options::grep_defaults(),
+            &super::OpenOptions::new().retry_sharing_violation(
+                2, ::std::time::Duration::from_millis(1)))
+            .unwrap();
+        assert_eq!(records, with);
+
+        assert_eq!(2, records.len());
+        assert_eq!(Some(1), records[0].line_number);
+        assert_eq!(Some(3), records[1].line_number);
+        assert!(records[0].line.starts_with(b"For the Doctor Watsons"));
+        assert_eq!(vec![(56, 64)], records[0].submatches);
+    }
+
+    #[test]
+    fn iter_entry_points() {
+        use search_stream::Options;
+
+        use super::{search_reader_iter, search_slice_iter};
+
+        let grep = GrepBuilder::new("Sherlock").build().unwrap();
+        let path = Path::new("/baz.rs");
+        let opts = Options::grep_defaults();
+
+        // The slice and reader variants agree on the same input.
+        let slice: Vec<_> =
+            search_slice_iter(&grep, path, SHERLOCK.as_bytes(), &opts)
+                .collect::<Result<_, _>>()
+                .unwrap();
+        let rdr = io::Cursor::new(SHERLOCK.to_string().into_bytes());
+        let stream: Vec<_> = search_reader_iter(&grep, path, rdr, &opts)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(slice, stream);
+        assert_eq!(2, slice.len());
+        assert_eq!(Some(1), slice[0].line_number);
+        assert!(slice[1].line.starts_with(b"be, to a very large extent"));
+
+        // A failing reader surfaces the error as the final item.
+        struct FailRead;
+        impl io::Read for FailRead {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("boom"))
+            }
+        }
+        let mut it = search_reader_iter(&grep, path, FailRead, &opts);
+        assert!(it.next().unwrap().is_err());
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn count_entry_points() {
+        use search_stream::Options;
+
+        use super::{count_lines, count_matches};
+
+        let grep = GrepBuilder::new("the").build().unwrap();
+        let path = Path::new("/baz.rs");
+        let opts = Options::grep_defaults();
+        let rdr = || io::Cursor::new(SHERLOCK.to_string().into_bytes());
+
+        // Three lines match, one of them twice.
+        assert_eq!(3, count_lines(&grep, path, rdr(), &opts).unwrap());
+        assert_eq!(4, count_matches(&grep, path, rdr(), &opts).unwrap());
+
+        // Inverting counts the lines that do not match.
+        let mut inverted = opts.clone();
+        inverted.invert_match = true;
+        assert_eq!(3, count_lines(&grep, path, rdr(), &inverted).unwrap());
+    }
+
+    #[test]
+    fn find_first_entry_point() {
+        use std::io::Read;
+
+        use search_stream::Options;
+
+        use super::find_first;
+
+        let grep = GrepBuilder::new("clearly").build().unwrap();
+        let path = Path::new("/baz.rs");
+        let opts = Options::grep_defaults();
+
+        let rdr = io::Cursor::new(SHERLOCK.to_string().into_bytes());
+        let hit = find_first(&grep, path, rdr, &opts).unwrap().unwrap();
+        assert_eq!(
+            SHERLOCK.find("and exhibited").unwrap() as u64,
+            hit.byte_offset);
+        assert_eq!(Some(6), hit.line_number);
+        assert_eq!(b"and exhibited clearly, with a label attached.".to_vec(),
+                   hit.line);
+
+        // No match yields None rather than an error.
+        let rdr = io::Cursor::new(SHERLOCK.to_string().into_bytes());
+        let grep2 = GrepBuilder::new("zzzz").build().unwrap();
+        assert_eq!(None, find_first(&grep2, path, rdr, &opts).unwrap());
+
+        // The search stops at the first hit instead of reading to EOF:
+        // the poisoned tail of this reader is never reached.
+        struct FailRead;
+        impl io::Read for FailRead {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("read past the first match"))
+            }
+        }
+        let mut hay = SHERLOCK.to_string().into_bytes();
+        hay.resize(hay.len() + 1, b'\n');
+        hay.resize(64 * 1024, b'x');
+        let grep3 = GrepBuilder::new("Watsons").build().unwrap();
+        let hit = find_first(
+            &grep3, path, io::Cursor::new(hay).chain(FailRead), &opts);
+        assert_eq!(Some(1), hit.unwrap().unwrap().line_number);
+    }
+
+    #[test]
+    fn matched_full_payload() {
+        use super::SinkMatch;
+
+        #[derive(Default)]
+        struct Rich {
+            offsets: Vec<u64>,
+            lines: Vec<Option<u64>>,
+            ranges: Vec<Option<(usize, usize)>>,
+            texts: Vec<Vec<u8>>,
+            printed: bool,
+        }
+
+        impl Sink for Rich {
+            fn matched<P: AsRef<Path>>(
+                &mut self, _: Option<&Regex>, _: P, _: &[u8],
+                _: usize, _: usize, _: Option<u64>,
+                _: Option<u64>, _: Option<u64>, _: Option<Indent>,
+            ) {
+                unreachable!("the searchers report through matched_full");
+            }
+
+            fn matched_full<P: AsRef<Path>>(
+                &mut self, _: P, mat: &SinkMatch,
+            ) {
+                self.printed = true;
+                self.offsets.push(mat.offset);
+                self.lines.push(mat.line_number);
+                self.ranges.push(mat.match_range);
+                self.texts.push(mat.buf[mat.start..mat.end].to_vec());
+            }
+
+            fn context<P: AsRef<Path>>(
+                &mut self, _: P, _: &[u8], _: usize, _: usize,
+                _: Option<u64>, _: Option<u64>,
+            ) {
+            }
+
+            fn context_separate(&mut self) {}
+
+            fn path<P: AsRef<Path>>(&mut self, _: P) {}
+
+            fn path_count<P: AsRef<Path>>(&mut self, _: P, _: u64) {}
+
+            fn has_printed(&self) -> bool {
+                self.printed
+            }
+        }
+
+        // Needles placed past the buffer capacity force a rollover; the
+        // absolute offsets must stay exact across it, with no offset
+        // reporting configured.
+        let mut hay = "filler\n".repeat(700);
+        hay.push_str("the needle\n");
+        hay.push_str(&"filler\n".repeat(3));
+        hay.push_str("the needle again\n");
+        let mut sink = Rich::default();
+        search("needle", &hay, &mut sink, |s| s.line_number(true));
+        assert_eq!(vec![4900, 4932], sink.offsets);
+        assert_eq!(vec![Some(701), Some(705)], sink.lines);
+        assert_eq!(vec![None, None], sink.ranges);
+        assert_eq!(b"the needle\n".to_vec(), sink.texts[0]);
+
+        // In only-matching mode the reported bytes are exactly the
+        // match, and the range spans them.
+        let mut sink = Rich::default();
+        search("needle", &hay, &mut sink, |s| s.only_matching(true));
+        assert_eq!(vec![4904, 4936], sink.offsets);
+        assert_eq!(vec![Some((0, 6)), Some((0, 6))], sink.ranges);
+        assert_eq!(b"needle".to_vec(), sink.texts[0]);
+
+        // The slice driver agrees with the streaming one.
+        use search_buffer::BufferSearcher;
+        let grep = GrepBuilder::new("needle").build().unwrap();
+        let mut sink = Rich::default();
+        {
+            let searcher = BufferSearcher::new(
+                &mut sink, &grep, Path::new("/baz.rs"), hay.as_bytes());
+            searcher.line_number(true).run();
+        }
+        assert_eq!(vec![4900, 4932], sink.offsets);
+        assert_eq!(vec![Some(701), Some(705)], sink.lines);
+    }
+}
@@ -37,7 +37,7 @@ pub fn unescape(s: &str) -> Vec<u8> {
             }
             HexFirst => {
                 match c {
-                    '0'...'9' | 'A'...'F' | 'a'...'f' => {
+                    '0'..='9' | 'A'..='F' | 'a'..='f' => {
                         state = HexSecond(c);
                     }
                     c => {
@@ -48,7 +48,7 @@ pub fn unescape(s: &str) -> Vec<u8> {
             }
             HexSecond(first) => {
                 match c {
-                    '0'...'9' | 'A'...'F' | 'a'...'f' => {
+                    '0'..='9' | 'A'..='F' | 'a'..='f' => {
                         let ordinal = format!("{}{}", first, c);
                         let byte = u8::from_str_radix(&ordinal, 16).unwrap();
                         bytes.push(byte);
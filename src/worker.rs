@@ -1,14 +1,21 @@
-use std::fs::File;
+use std::cmp;
+use std::error;
+use std::fmt;
+use std::mem;
+use std::fs::{File, Metadata};
 use std::io;
-use std::path::Path;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use encoding_rs::Encoding;
 use grep::Grep;
 use ignore::DirEntry;
 use memmap::Mmap;
+use same_file::Handle;
 use termcolor::WriteColor;
 
-use decoder::DecodeReader;
+use decoder::{self, DecodeReader, EncodingDetection};
 use decompressor::{self, DecompressionReader};
 use pathutil::strip_prefix;
 use printer::Printer;
@@ -22,15 +29,255 @@ pub enum Work {
     DirEntry(DirEntry),
 }
 
+/// A per-file decision about whether to search with a memory map.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MmapDecision {
+    /// Search this file through a memory map.
+    Map,
+    /// Search this file with regular read calls.
+    Read,
+    /// Defer to the worker's configured default.
+    Auto,
+}
+
+/// A callback consulted per file to decide whether to use a memory map.
+///
+/// This lets callers encode knowledge the built-in configuration can't
+/// have, e.g. forcing reads on a slow network mount or forcing maps on a
+/// tmpfs.
+pub type MmapPolicy =
+    Box<dyn Fn(&Path, &Metadata) -> MmapDecision + Send + Sync>;
+
+/// The stored form of `MmapPolicy`. Policies are held behind an `Arc` so
+/// that cloned workers consult the same one.
+type SharedMmapPolicy =
+    Arc<dyn Fn(&Path, &Metadata) -> MmapDecision + Send + Sync>;
+
+/// A search core that a worker can be pinned to.
+///
+/// Normally the worker chooses between these heuristically (configuration,
+/// mmap policy, input type); naming them explicitly gives differential
+/// debugging and benchmarking a way to pin the choice.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SearchStrategy {
+    /// The streaming line searcher, reading the input incrementally.
+    Stream,
+    /// The buffer searcher over a memory map of the whole input.
+    Mmap,
+}
+
+/// An error returned when a forced search strategy can't be honored for a
+/// particular input.
+#[derive(Debug)]
+pub struct ForcedStrategyError {
+    path: PathBuf,
+    strategy: SearchStrategy,
+    reason: &'static str,
+}
+
+impl ForcedStrategyError {
+    fn new(
+        path: &Path,
+        strategy: SearchStrategy,
+        reason: &'static str,
+    ) -> ForcedStrategyError {
+        ForcedStrategyError {
+            path: path.to_path_buf(),
+            strategy,
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for ForcedStrategyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: the forced {:?} strategy is not possible for this input: {}",
+            self.path.display(), self.strategy, self.reason)
+    }
+}
+
+impl error::Error for ForcedStrategyError {
+    fn description(&self) -> &str {
+        "forced search strategy not possible"
+    }
+}
+
+/// An error returned when the worker's configuration requires a memory
+/// mapped input, but the input could not be mapped.
+#[derive(Debug)]
+pub struct MmapUnavailableError {
+    path: PathBuf,
+}
+
+impl MmapUnavailableError {
+    fn new(path: &Path) -> MmapUnavailableError {
+        MmapUnavailableError { path: path.to_path_buf() }
+    }
+}
+
+impl fmt::Display for MmapUnavailableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: the configuration requires a memory mapped input, \
+             but this input cannot be memory mapped",
+            self.path.display())
+    }
+}
+
+impl error::Error for MmapUnavailableError {
+    fn description(&self) -> &str {
+        "input cannot be memory mapped"
+    }
+}
+
+/// An owned, immutable mapping of a file produced by an `MmapProvider`.
+///
+/// The handle owns whatever resource backs the bytes and keeps it alive for
+/// as long as a search borrows them.
+pub struct MappedSlice(Box<dyn Deref<Target = [u8]> + Send + Sync>);
+
+impl MappedSlice {
+    /// Create a new mapping handle from anything that dereferences to a
+    /// byte slice.
+    #[allow(dead_code)]
+    pub fn new<T>(map: T) -> MappedSlice
+        where T: Deref<Target = [u8]> + Send + Sync + 'static
+    {
+        MappedSlice(Box::new(map))
+    }
+}
+
+impl Deref for MappedSlice {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A provider of memory maps for the worker's file search path.
+///
+/// The default provider maps files with the platform's ordinary memory map
+/// facility. Applications with their own mmap management (huge pages,
+/// pre-faulted maps) can inject an implementation via
+/// `WorkerBuilder::mmap_provider`; tests can inject fake maps without
+/// touching the filesystem.
+pub trait MmapProvider {
+    /// Map the given file, or return `None` if the file can't (or
+    /// shouldn't) be mapped, in which case the worker falls back to a
+    /// streaming read.
+    fn map(&self, file: &File) -> io::Result<Option<MappedSlice>>;
+}
+
+/// The default memory map provider, which maps files directly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultMmapProvider;
+
+impl MmapProvider for DefaultMmapProvider {
+    #[cfg(not(unix))]
+    fn map(&self, file: &File) -> io::Result<Option<MappedSlice>> {
+        Ok(Some(MappedSlice::new(mmap_readonly(file)?)))
+    }
+
+    #[cfg(unix)]
+    fn map(&self, file: &File) -> io::Result<Option<MappedSlice>> {
+        use libc::{EOVERFLOW, ENODEV, ENOMEM};
+
+        let err = match mmap_readonly(file) {
+            Ok(mmap) => return Ok(Some(MappedSlice::new(mmap))),
+            Err(err) => err,
+        };
+        let code = err.raw_os_error();
+        if code == Some(EOVERFLOW)
+            || code == Some(ENODEV)
+            || code == Some(ENOMEM)
+        {
+            return Ok(None);
+        }
+        Err(err)
+    }
+}
+
+/// Reusable scratch state for searches.
+///
+/// Every worker owns one of these, but high-throughput callers that build
+/// short-lived workers (because configuration varies per request) can own
+/// the scratch themselves and pass it to `Worker::run_with_scratch`, so
+/// buffer reuse survives across differently-configured workers.
+pub struct SearchScratch {
+    inpbuf: InputBuffer,
+    decodebuf: Vec<u8>,
+}
+
+impl SearchScratch {
+    /// Create fresh scratch state with default capacities.
+    pub fn new() -> SearchScratch {
+        SearchScratch {
+            inpbuf: InputBuffer::new(),
+            decodebuf: vec![0; 8 * (1<<10)],
+        }
+    }
+
+    /// Create fresh scratch state whose input buffer starts at the
+    /// capacity given. The capacity only sets the size of each read; the
+    /// buffer still grows on demand to hold at least one complete line.
+    pub fn with_capacity(cap: usize) -> SearchScratch {
+        SearchScratch {
+            inpbuf: InputBuffer::with_capacity(cap),
+            decodebuf: vec![0; 8 * (1<<10)],
+        }
+    }
+
+    /// Create fresh scratch state sized and prepared for the options
+    /// given.
+    fn for_options(opts: &Options) -> SearchScratch {
+        let mut scratch = match opts.buffer_capacity {
+            Some(cap) => SearchScratch::with_capacity(cap),
+            None => SearchScratch::new(),
+        };
+        scratch.prepare(opts.eol);
+        scratch
+    }
+
+    /// Validate this scratch for use by a worker with the given line
+    /// terminator, growing undersized buffers as needed.
+    fn prepare(&mut self, eol: u8) {
+        if self.decodebuf.len() < 8 * (1<<10) {
+            self.decodebuf.resize(8 * (1<<10), 0);
+        }
+        self.inpbuf.eol(eol);
+    }
+}
+
+impl Default for SearchScratch {
+    fn default() -> SearchScratch {
+        SearchScratch::new()
+    }
+}
+
 pub struct WorkerBuilder {
     grep: Grep,
     opts: Options,
+    mmap_provider: Arc<dyn MmapProvider + Send + Sync>,
+    mmap_policy: Option<SharedMmapPolicy>,
 }
 
 #[derive(Clone, Debug)]
 struct Options {
     mmap: bool,
+    mmap_always: bool,
+    mmap_required: bool,
+    mmap_min_size: Option<u64>,
+    mmap_max_size: Option<u64>,
+    low_cache: bool,
     encoding: Option<&'static Encoding>,
+    encoding_detection: EncodingDetection,
+    bom_sniffing: bool,
     after_context: usize,
     before_context: usize,
     byte_offset: bool,
@@ -42,8 +289,10 @@ struct Options {
     invert_match: bool,
     line_number: bool,
     max_count: Option<u64>,
+    buffer_capacity: Option<usize>,
     no_messages: bool,
     quiet: bool,
+    strategy: Option<SearchStrategy>,
     text: bool,
     search_zip_files: bool
 }
@@ -52,7 +301,14 @@ impl Default for Options {
     fn default() -> Options {
         Options {
             mmap: false,
+            mmap_always: false,
+            mmap_required: false,
+            mmap_min_size: None,
+            mmap_max_size: None,
+            low_cache: false,
             encoding: None,
+            encoding_detection: EncodingDetection::default(),
+            bom_sniffing: true,
             after_context: 0,
             before_context: 0,
             byte_offset: false,
@@ -64,8 +320,10 @@ impl Default for Options {
             invert_match: false,
             line_number: false,
             max_count: None,
+            buffer_capacity: None,
             no_messages: false,
             quiet: false,
+            strategy: None,
             text: false,
             search_zip_files: false,
         }
@@ -79,20 +337,22 @@ impl WorkerBuilder {
     /// are numerous additional options that can be configured on this builder.
     pub fn new(grep: Grep) -> WorkerBuilder {
         WorkerBuilder {
-            grep: grep,
+            grep,
             opts: Options::default(),
+            mmap_provider: Arc::new(DefaultMmapProvider),
+            mmap_policy: None,
         }
     }
 
     /// Create the worker from this builder.
     pub fn build(self) -> Worker {
-        let mut inpbuf = InputBuffer::new();
-        inpbuf.eol(self.opts.eol);
         Worker {
             grep: self.grep,
-            inpbuf: inpbuf,
-            decodebuf: vec![0; 8 * (1<<10)],
+            scratch: SearchScratch::for_options(&self.opts),
             opts: self.opts,
+            mmap_provider: self.mmap_provider,
+            mmap_policy: self.mmap_policy,
+            mmap_fallback: None,
         }
     }
 
@@ -146,6 +406,25 @@ impl WorkerBuilder {
         self
     }
 
+    /// Set the strategy used to detect the encoding of each file.
+    ///
+    /// This has no effect on files whose encoding was explicitly set with
+    /// `encoding`.
+    pub fn encoding_detection(mut self, detection: EncodingDetection) -> Self {
+        self.opts.encoding_detection = detection;
+        self
+    }
+
+    /// Whether a BOM at the start of a file is used to select an encoding
+    /// and stripped from the searched data. See `DecodeReader::bom_sniffing`.
+    ///
+    /// This is enabled by default.
+    #[allow(dead_code)]
+    pub fn bom_sniffing(mut self, yes: bool) -> Self {
+        self.opts.bom_sniffing = yes;
+        self
+    }
+
     /// If enabled, searching will print the path instead of each match.
     ///
     /// Disabled by default.
@@ -190,12 +469,119 @@ impl WorkerBuilder {
         self
     }
 
+    /// Set the initial capacity of the worker's input buffer, in bytes.
+    ///
+    /// A large capacity cuts syscall counts on high-throughput pipes
+    /// without imposing any ceiling: the buffer still grows on demand to
+    /// hold at least one complete line. Workers run with caller-owned
+    /// scratch (`run_with_scratch`) use that scratch's capacity instead.
+    ///
+    /// The default is None, which uses the standard read size.
+    #[allow(dead_code)]
+    pub fn buffer_capacity(mut self, cap: Option<usize>) -> Self {
+        self.opts.buffer_capacity = cap;
+        self
+    }
+
     /// If enabled, try to use memory maps for searching if possible.
     pub fn mmap(mut self, yes: bool) -> Self {
         self.opts.mmap = yes;
         self
     }
 
+    /// If enabled, always use memory maps for file-backed searches,
+    /// bypassing the size and platform heuristics and any per-file
+    /// policy. When mapping genuinely fails (the provider declines, the
+    /// file is empty, or the input needs the streaming transcoder), the
+    /// search falls back to incremental reads and the reason is made
+    /// available through `Worker::last_mmap_fallback`; with
+    /// `mmap_required` also set, such failures are errors instead.
+    #[allow(dead_code)]
+    pub fn mmap_always(mut self, yes: bool) -> Self {
+        self.opts.mmap_always = yes;
+        self
+    }
+
+    /// Skip memory maps for files smaller than the given size, in bytes,
+    /// even when maps are enabled. For small files a plain read is
+    /// cheaper than the page table churn of mapping. This only trims the
+    /// automatic decision; forced strategies, `mmap_always` and explicit
+    /// policy decisions are unaffected.
+    ///
+    /// The default is None, which imposes no minimum.
+    #[allow(dead_code)]
+    pub fn mmap_min_file_size(mut self, size: Option<u64>) -> Self {
+        self.opts.mmap_min_size = size;
+        self
+    }
+
+    /// Skip memory maps for files larger than the given size, in bytes,
+    /// even when maps are enabled. Mapping enormous files can exhaust
+    /// address space on 32-bit targets. Like the minimum, this only trims
+    /// the automatic decision.
+    ///
+    /// The default is None, which imposes no maximum.
+    #[allow(dead_code)]
+    pub fn mmap_max_file_size(mut self, size: Option<u64>) -> Self {
+        self.opts.mmap_max_size = size;
+        self
+    }
+
+    /// If enabled, advise the kernel after searching each file that its
+    /// pages are no longer needed, so that bulk scans don't evict the rest
+    /// of the system from the page cache.
+    ///
+    /// This is a pure hint (errors are ignored, and platforms other than
+    /// Linux ignore it entirely). It trades the cost of re-reading files
+    /// that are searched again soon for friendliness to everything else on
+    /// the machine.
+    ///
+    /// This is disabled by default.
+    #[allow(dead_code)]
+    pub fn low_cache(mut self, yes: bool) -> Self {
+        self.opts.low_cache = yes;
+        self
+    }
+
+    /// Set a callback consulted per file to decide whether to use a memory
+    /// map, overriding the `mmap` setting for that file.
+    ///
+    /// The callback may force a map, force regular reads or defer to the
+    /// configured default with `MmapDecision::Auto`. The decision taken for
+    /// each file is logged at debug level so a policy can be verified.
+    #[allow(dead_code)]
+    pub fn mmap_policy(mut self, policy: MmapPolicy) -> Self {
+        self.mmap_policy = Some(policy.into());
+        self
+    }
+
+    /// If enabled, an input that cannot be memory mapped is reported as an
+    /// error instead of silently falling back to a streaming read.
+    ///
+    /// This is useful with a custom `mmap_provider` whose maps are the only
+    /// viable way to read the input. It only applies to inputs that truly
+    /// can't be mapped (pipes, or files the provider declines); fallbacks
+    /// for transcoding still have a map available and are unaffected. This
+    /// has no effect unless memory maps are enabled with `mmap`.
+    #[allow(dead_code)]
+    pub fn mmap_required(mut self, yes: bool) -> Self {
+        self.opts.mmap_required = yes;
+        self
+    }
+
+    /// Set the provider used to create memory maps when they are enabled.
+    ///
+    /// The default provider maps files directly. This has no effect unless
+    /// memory maps are enabled with `mmap`.
+    #[allow(dead_code)]
+    pub fn mmap_provider(
+        mut self,
+        provider: Box<dyn MmapProvider + Send + Sync>,
+    ) -> Self {
+        self.mmap_provider = provider.into();
+        self
+    }
+
     /// If enabled, error messages are suppressed.
     ///
     /// This is disabled by default.
@@ -211,6 +597,25 @@ impl WorkerBuilder {
         self
     }
 
+    /// Force the worker to use the given search core for every input,
+    /// instead of choosing one heuristically.
+    ///
+    /// This is meant for differential debugging and benchmarking, where
+    /// "same bug under `Stream` but not `Mmap`?" needs a definite answer.
+    /// `Stream` is possible for every input. When `Mmap` is forced, inputs
+    /// that would normally fall back to streaming (pipes, empty files,
+    /// inputs the provider can't map, inputs that need transcoding) fail
+    /// with `ForcedStrategyError` instead of silently switching cores. The
+    /// strategy report notes that the choice was forced.
+    #[allow(dead_code)]
+    pub fn force_strategy(
+        mut self,
+        strategy: Option<SearchStrategy>,
+    ) -> Self {
+        self.opts.strategy = strategy;
+        self
+    }
+
     /// If enabled, search binary files as if they were text.
     pub fn text(mut self, yes: bool) -> Self {
         self.opts.text = yes;
@@ -228,9 +633,30 @@ impl WorkerBuilder {
 /// streaming search or memory map search as appropriate.
 pub struct Worker {
     grep: Grep,
-    inpbuf: InputBuffer,
-    decodebuf: Vec<u8>,
+    scratch: SearchScratch,
     opts: Options,
+    mmap_provider: Arc<dyn MmapProvider + Send + Sync>,
+    mmap_policy: Option<SharedMmapPolicy>,
+    mmap_fallback: Option<&'static str>,
+}
+
+/// Clones share this worker's configuration — the matcher, the options
+/// and the mmap provider and policy — but get fresh, empty scratch
+/// buffers and their own fallback diagnostics, so cloning is cheap no
+/// matter how much scratch space the original has accumulated. Building
+/// one configured worker and handing a clone to each thread of a pool is
+/// the intended use; each clone searches independently.
+impl Clone for Worker {
+    fn clone(&self) -> Worker {
+        Worker {
+            grep: self.grep.clone(),
+            scratch: SearchScratch::for_options(&self.opts),
+            opts: self.opts.clone(),
+            mmap_provider: self.mmap_provider.clone(),
+            mmap_policy: self.mmap_policy.clone(),
+            mmap_fallback: None,
+        }
+    }
 }
 
 impl Worker {
@@ -243,11 +669,7 @@ impl Worker {
         work: Work,
     ) -> u64 {
         let result = match work {
-            Work::Stdin => {
-                let stdin = io::stdin();
-                let stdin = stdin.lock();
-                self.search(printer, Path::new("<stdin>"), stdin)
-            }
+            Work::Stdin => self.run_stdin(printer),
             Work::DirEntry(dent) => {
                 let mut path = dent.path();
                 if self.opts.search_zip_files
@@ -272,11 +694,15 @@ impl Worker {
                     if let Some(p) = strip_prefix("./", path) {
                         path = p;
                     }
-                    if self.opts.mmap {
-                        self.search_mmap(printer, path, &file)
+                    let result = if self.use_mmap(path, &file, None) {
+                        self.search_mmap(printer, path, &file, None)
                     } else {
-                        self.search(printer, path, file)
+                        self.search(printer, path, &file)
+                    };
+                    if self.opts.low_cache {
+                        advise_dontneed(&file);
                     }
+                    result
                 }
             }
         };
@@ -293,6 +719,270 @@ impl Worker {
         }
     }
 
+    /// Like `run`, but uses the given caller-owned scratch state instead of
+    /// this worker's own, so buffers can be reused across workers. The
+    /// scratch is validated first and grown if its capacities are too
+    /// small.
+    #[allow(dead_code)]
+    pub fn run_with_scratch<W: WriteColor>(
+        &mut self,
+        printer: &mut Printer<W>,
+        work: Work,
+        scratch: &mut SearchScratch,
+    ) -> u64 {
+        scratch.prepare(self.opts.eol);
+        mem::swap(&mut self.scratch, scratch);
+        let count = self.run(printer, work);
+        mem::swap(&mut self.scratch, scratch);
+        count
+    }
+
+    /// Search standard input, picking a strategy based on what it actually
+    /// is. A redirected regular file is searched like any other file
+    /// (including with a memory map when enabled, and returning instantly
+    /// when empty); anything else (a pipe, a terminal) is streamed.
+    fn run_stdin<W: WriteColor>(
+        &mut self,
+        printer: &mut Printer<W>,
+    ) -> Result<u64> {
+        let path = Path::new("<stdin>");
+        if let Some(handle) = stdin_as_file() {
+            debug!("stdin: redirected from a regular file");
+            let file = handle.as_file();
+            return if self.use_mmap(path, file, None) {
+                self.search_mmap(printer, path, file, None)
+            } else {
+                self.search(printer, path, file)
+            };
+        }
+        if self.opts.strategy == Some(SearchStrategy::Mmap) {
+            return Err(ForcedStrategyError::new(
+                path, SearchStrategy::Mmap,
+                "stdin is not redirected from a regular file").into());
+        }
+        if self.opts.mmap && self.opts.mmap_required {
+            return Err(MmapUnavailableError::new(path).into());
+        }
+        debug!("stdin: pipe; streaming");
+        let stdin = io::stdin();
+        let stdin = stdin.lock();
+        self.search(printer, path, stdin)
+    }
+
+    /// Search the file at the given path, opening it and picking a
+    /// strategy the same way `run` does, but reporting open errors to the
+    /// caller instead of printing them.
+    ///
+    /// This is the entry point for callers that hold a bare path rather
+    /// than a directory walk entry. Non-regular files (FIFOs, `/proc`
+    /// entries whose metadata reports zero bytes) fall back to
+    /// incremental reads even when memory maps are enabled.
+    #[allow(dead_code)]
+    pub fn search_path<P: AsRef<Path>, W: WriteColor>(
+        &mut self,
+        printer: &mut Printer<W>,
+        path: P,
+    ) -> Result<u64> {
+        let mut path = path.as_ref();
+        if self.opts.search_zip_files && decompressor::is_compressed(path) {
+            return match DecompressionReader::from_path(path) {
+                Some(reader) => self.search(printer, path, reader),
+                None => Ok(0),
+            };
+        }
+        let file = File::open(path).map_err(|err| {
+            io::Error::other(format!("{}: {}", path.display(), err))
+        })?;
+        if let Some(p) = strip_prefix("./", path) {
+            path = p;
+        }
+        let result = if self.use_mmap(path, &file, None) {
+            self.search_mmap(printer, path, &file, None)
+        } else {
+            self.search(printer, path, &file)
+        };
+        if self.opts.low_cache {
+            advise_dontneed(&file);
+        }
+        result
+    }
+
+    /// Search the given pre-opened file.
+    ///
+    /// The strategy is chosen heuristically as in `run`, but on the
+    /// incremental path the file's metadata additionally pre-sizes the
+    /// input buffer, so a file bigger than one read is consumed without
+    /// repeated buffer growth. Zero-size metadata (FIFOs, `/proc`
+    /// entries) is no basis for sizing and keeps the default growth.
+    #[allow(dead_code)]
+    pub fn search_file<W: WriteColor>(
+        &mut self,
+        printer: &mut Printer<W>,
+        path: &Path,
+        file: &File,
+    ) -> Result<u64> {
+        let md = file.metadata()?;
+        if self.use_mmap(path, file, Some(&md)) {
+            return self.search_mmap(printer, path, file, Some(&md));
+        }
+        if md.len() > 0 {
+            self.scratch.inpbuf.reserve(md.len() as usize);
+        }
+        self.search(printer, path, file)
+    }
+
+    /// Search a caller-provided memory map (or any other file-backed
+    /// slice) with the slice driver, without creating a mapping of its
+    /// own.
+    ///
+    /// Applications that already map their files for other reasons can
+    /// reuse those mappings here. Binary detection sniffs the same
+    /// prefix as the internal mmap path. Inputs that require transcoding
+    /// (a configured encoding, a BOM, or auto-detected UTF-16) can't be
+    /// searched in place, since transcoding exists only on the streaming
+    /// path; those return an error instead of silently searching the raw
+    /// bytes.
+    ///
+    /// Unlike the reader-based entry points, slice searches need none of
+    /// the worker's scratch buffers, so this takes a shared reference: a
+    /// single worker can serve an in-memory corpus from many threads at
+    /// once (`Worker` is `Sync`), with no cloning required.
+    #[allow(dead_code)]
+    pub fn search_map<W: WriteColor>(
+        &self,
+        printer: &mut Printer<W>,
+        path: &Path,
+        buf: &[u8],
+    ) -> Result<u64> {
+        if self.needs_transcoding(buf) {
+            return Err(io::Error::other(format!(
+                "{}: this input requires transcoding, which only the \
+                 streaming core supports", path.display())).into());
+        }
+        Ok(self.search_buffer(printer, path, buf))
+    }
+
+    /// Search the given file, trusting the caller-provided metadata for
+    /// strategy selection instead of issuing another `stat`.
+    ///
+    /// Directory walkers typically already have each file's metadata in
+    /// hand, and re-fetching it per file is measurable on big trees over
+    /// network filesystems. The metadata is revalidated only if acting on
+    /// it fails (e.g. the file was truncated since the caller fetched it).
+    #[allow(dead_code)]
+    pub fn search_file_with_metadata<W: WriteColor>(
+        &mut self,
+        printer: &mut Printer<W>,
+        path: &Path,
+        file: &File,
+        md: &Metadata,
+    ) -> Result<u64> {
+        if self.use_mmap(path, file, Some(md)) {
+            self.search_mmap(printer, path, file, Some(md))
+        } else {
+            self.search(printer, path, file)
+        }
+    }
+
+    /// Returns true if this worker considers memory maps at all; the
+    /// per-file decision still weighs the policy callback, size
+    /// thresholds and metadata.
+    #[allow(dead_code)]
+    pub fn mmap_enabled(&self) -> bool {
+        self.opts.mmap || self.opts.mmap_always
+            || self.opts.strategy == Some(SearchStrategy::Mmap)
+    }
+
+    /// The search core this worker is pinned to, if any.
+    #[allow(dead_code)]
+    pub fn forced_strategy(&self) -> Option<SearchStrategy> {
+        self.opts.strategy
+    }
+
+    /// The reason the most recent mmap-eligible search fell back to
+    /// incremental reads, if it did. This is cleared at the start of each
+    /// mmap-eligible search, and is chiefly useful with `mmap_always`,
+    /// which degrades to reads instead of failing when a map is genuinely
+    /// impossible.
+    #[allow(dead_code)]
+    pub fn last_mmap_fallback(&self) -> Option<&'static str> {
+        self.mmap_fallback
+    }
+
+    /// Decide whether the given file should be searched with a memory map,
+    /// consulting the per-file policy callback if one is set. Metadata
+    /// provided by the caller is used as-is; otherwise it is fetched.
+    fn use_mmap(&self, path: &Path, file: &File, md: Option<&Metadata>) -> bool {
+        if let Some(strategy) = self.opts.strategy {
+            let mmap = strategy == SearchStrategy::Mmap;
+            debug!(
+                "{}: strategy forced to {:?}, {} memory maps",
+                path.display(),
+                strategy,
+                if mmap { "using" } else { "not using" });
+            return mmap;
+        }
+        if self.opts.mmap_always {
+            debug!("{}: mmap forced on, bypassing heuristics", path.display());
+            return true;
+        }
+        let policy = match self.mmap_policy {
+            None => return self.auto_mmap(path, file, md),
+            Some(ref policy) => policy,
+        };
+        let decision = match md {
+            Some(md) => policy(path, md),
+            None => match file.metadata() {
+                Ok(md) => policy(path, &md),
+                Err(_) => MmapDecision::Auto,
+            },
+        };
+        let mmap = match decision {
+            MmapDecision::Map => true,
+            MmapDecision::Read => false,
+            MmapDecision::Auto => self.auto_mmap(path, file, md),
+        };
+        debug!(
+            "{}: mmap policy decided {:?}, {} memory maps",
+            path.display(),
+            decision,
+            if mmap { "using" } else { "not using" });
+        mmap
+    }
+
+    /// The automatic mmap decision: the configured default, trimmed by
+    /// the optional file size thresholds. Files whose size cannot be
+    /// determined use the configured default untrimmed.
+    fn auto_mmap(&self, path: &Path, file: &File, md: Option<&Metadata>) -> bool {
+        if !self.opts.mmap {
+            return false;
+        }
+        if self.opts.mmap_min_size.is_none()
+            && self.opts.mmap_max_size.is_none() {
+            return true;
+        }
+        let len = match md {
+            Some(md) => md.len(),
+            None => match file.metadata() {
+                Ok(md) => md.len(),
+                Err(_) => return true,
+            },
+        };
+        if self.opts.mmap_min_size.is_some_and(|min| len < min) {
+            debug!(
+                "{}: {} bytes is below the mmap minimum, reading instead",
+                path.display(), len);
+            return false;
+        }
+        if self.opts.mmap_max_size.is_some_and(|max| len > max) {
+            debug!(
+                "{}: {} bytes is above the mmap maximum, reading instead",
+                path.display(), len);
+            return false;
+        }
+        true
+    }
+
     fn search<R: io::Read, W: WriteColor>(
         &mut self,
         printer: &mut Printer<W>,
@@ -300,9 +990,11 @@ impl Worker {
         rdr: R,
     ) -> Result<u64> {
         let rdr = DecodeReader::new(
-            rdr, &mut self.decodebuf, self.opts.encoding);
+            rdr, &mut self.scratch.decodebuf, self.opts.encoding)
+            .encoding_detection(self.opts.encoding_detection)
+            .bom_sniffing(self.opts.bom_sniffing);
         let searcher = Searcher::new(
-            &mut self.inpbuf, printer, &self.grep, path, rdr);
+            &mut self.scratch.inpbuf, printer, &self.grep, path, rdr);
         searcher
             .after_context(self.opts.after_context)
             .before_context(self.opts.before_context)
@@ -326,27 +1018,99 @@ impl Worker {
         printer: &mut Printer<W>,
         path: &Path,
         file: &File,
+        md: Option<&Metadata>,
     ) -> Result<u64> {
-        if file.metadata()?.len() == 0 {
+        self.mmap_fallback = None;
+        let len = match md {
+            Some(md) => md.len(),
+            None => file.metadata()?.len(),
+        };
+        let forced = self.opts.strategy == Some(SearchStrategy::Mmap);
+        if len == 0 {
+            if forced {
+                return Err(ForcedStrategyError::new(
+                    path, SearchStrategy::Mmap,
+                    "empty files cannot be memory mapped").into());
+            }
             // Opening a memory map with an empty file results in an error.
             // However, this may not actually be an empty file! For example,
             // /proc/cpuinfo reports itself as an empty file, but it can
             // produce data when it's read from. Therefore, we fall back to
             // regular read calls.
+            self.mmap_fallback = Some("the file reports a length of zero");
             return self.search(printer, path, file);
         }
-        let mmap = match self.mmap(file)? {
-            None => return self.search(printer, path, file),
-            Some(mmap) => mmap,
+        let mmap = match self.mmap_provider.map(file) {
+            Ok(Some(mmap)) => mmap,
+            Ok(None) => {
+                if forced {
+                    return Err(ForcedStrategyError::new(
+                        path, SearchStrategy::Mmap,
+                        "the mmap provider declined to map this input")
+                        .into());
+                }
+                if self.opts.mmap_required {
+                    return Err(MmapUnavailableError::new(path).into());
+                }
+                self.mmap_fallback =
+                    Some("the mmap provider declined to map this input");
+                return self.search(printer, path, file);
+            }
+            Err(err) => {
+                // Caller-provided metadata may be stale: if the file has
+                // since been truncated to nothing, take the empty-file
+                // fallback instead of failing.
+                if md.is_some() && file.metadata()?.len() == 0 {
+                    self.mmap_fallback =
+                        Some("the file reports a length of zero");
+                    return self.search(printer, path, file);
+                }
+                return Err(err.into());
+            }
         };
         let buf = &*mmap;
-        if buf.len() >= 3 && Encoding::for_bom(buf).is_some() {
-            // If we have a UTF-16 bom in our memory map, then we need to fall
-            // back to the stream reader, which will do transcoding.
+        if self.needs_transcoding(buf) {
+            if forced {
+                return Err(ForcedStrategyError::new(
+                    path, SearchStrategy::Mmap,
+                    "this input requires transcoding, which only the \
+                     streaming core supports").into());
+            }
+            // Transcoding only exists on the streaming path, so fall back
+            // to the stream reader. This also ensures that binary detection
+            // runs over the transcoded bytes (the thing actually searched)
+            // instead of the raw bytes, which for UTF-16 always contain
+            // NULs.
+            self.mmap_fallback =
+                Some("this input requires the streaming transcoder");
             return self.search(printer, path, file);
         }
+        Ok(self.search_buffer(printer, path, buf))
+    }
+
+    /// Returns true if the given raw bytes can't be searched in place
+    /// because they must pass through the streaming transcoder first.
+    fn needs_transcoding(&self, buf: &[u8]) -> bool {
+        self.opts.encoding.is_some()
+            || (self.opts.bom_sniffing
+                && buf.len() >= 3 && Encoding::for_bom(buf).is_some())
+            || (self.opts.encoding_detection == EncodingDetection::Auto
+                && {
+                    let sniff_upto = cmp::min(8 * (1 << 10), buf.len());
+                    decoder::detect_encoding(&buf[..sniff_upto]).is_some()
+                })
+    }
+
+    /// Run the slice driver over the given bytes with this worker's
+    /// configuration.
+    fn search_buffer<W: WriteColor>(
+        &self,
+        printer: &mut Printer<W>,
+        path: &Path,
+        buf: &[u8],
+    ) -> u64 {
         let searcher = BufferSearcher::new(printer, &self.grep, path, buf);
-        Ok(searcher
+        searcher
             .byte_offset(self.opts.byte_offset)
             .count(self.opts.count)
             .count_matches(self.opts.count_matches)
@@ -358,33 +1122,702 @@ impl Worker {
             .max_count(self.opts.max_count)
             .quiet(self.opts.quiet)
             .text(self.opts.text)
-            .run())
+            .run()
     }
 
-    #[cfg(not(unix))]
-    fn mmap(&self, file: &File) -> Result<Option<Mmap>> {
-        Ok(Some(mmap_readonly(file)?))
+}
+
+/// Advise the kernel that the pages backing the given file are no longer
+/// needed. This is purely a hint: failure is ignored.
+#[cfg(target_os = "linux")]
+fn advise_dontneed(file: &File) {
+    use std::os::unix::io::AsRawFd;
+
+    unsafe {
+        libc::posix_fadvise(
+            file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
     }
+}
 
-    #[cfg(unix)]
-    fn mmap(&self, file: &File) -> Result<Option<Mmap>> {
-        use libc::{EOVERFLOW, ENODEV, ENOMEM};
+/// Advising the page cache is only supported on Linux.
+#[cfg(not(target_os = "linux"))]
+fn advise_dontneed(_: &File) {}
 
-        let err = match mmap_readonly(file) {
-            Ok(mmap) => return Ok(Some(mmap)),
-            Err(err) => err,
-        };
-        let code = err.raw_os_error();
-        if code == Some(EOVERFLOW)
-            || code == Some(ENODEV)
-            || code == Some(ENOMEM)
-        {
-            return Ok(None);
-        }
-        Err(From::from(err))
+/// Returns a handle to stdin if and only if stdin has been redirected from
+/// a regular file, which can be fstat'd, memory mapped and searched by
+/// size. Pipes and terminals return `None` and must be streamed.
+fn stdin_as_file() -> Option<Handle> {
+    let handle = match Handle::stdin() {
+        Ok(handle) => handle,
+        Err(_) => return None,
+    };
+    match handle.as_file().metadata() {
+        Ok(md) if md.file_type().is_file() => Some(handle),
+        _ => None,
     }
 }
 
 fn mmap_readonly(file: &File) -> io::Result<Mmap> {
     unsafe { Mmap::map(file) }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io;
+    use std::path::PathBuf;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::{MappedSlice, MmapProvider};
+
+    static NEXT_DIR_ID: AtomicUsize = AtomicUsize::new(0);
+
+    /// Create a fresh scratch directory for a test, in the image of the
+    /// integration suite's `WorkDir`: rooted next to the test executable
+    /// and unique per process and invocation, so concurrent test runs
+    /// neither collide on a fixed `/tmp` name nor leave litter behind.
+    fn tmpdir(name: &str) -> PathBuf {
+        let id = NEXT_DIR_ID.fetch_add(1, Ordering::SeqCst);
+        let dir = env::current_exe().unwrap()
+            .parent().expect("executable's directory")
+            .join("rg-worker-tests")
+            .join(name)
+            .join(format!("{}-{}", process::id(), id));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A provider that hands out an in-memory "map" regardless of the file.
+    struct FakeProvider(&'static [u8]);
+
+    impl MmapProvider for FakeProvider {
+        fn map(&self, _: &File) -> io::Result<Option<MappedSlice>> {
+            Ok(Some(MappedSlice::new(self.0.to_vec())))
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fake_mmap_provider() {
+        let provider = FakeProvider(b"foo\nbar\n");
+        let file = File::open("/dev/null").unwrap();
+        let map = provider.map(&file).unwrap().unwrap();
+        assert_eq!(b"foo\nbar\n", &*map);
+    }
+
+    /// A provider that refuses to map anything.
+    struct NoProvider;
+
+    impl MmapProvider for NoProvider {
+        fn map(&self, _: &File) -> io::Result<Option<MappedSlice>> {
+            Ok(None)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mmap_required_unmappable() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        let path = tmpdir("mmap-required").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\n").unwrap();
+        let file = File::open(&path).unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap(true)
+            .mmap_required(true)
+            .mmap_provider(Box::new(NoProvider))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let err = worker
+            .search_mmap(&mut pp, &path, &file, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("cannot be memory mapped"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mmap_policy_decisions() {
+        use std::path::Path;
+
+        use grep::GrepBuilder;
+
+        use super::{MmapDecision, WorkerBuilder};
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let worker = WorkerBuilder::new(grep)
+            .mmap(false)
+            .mmap_policy(Box::new(|path: &Path, _: &_| {
+                if path.to_str().is_some_and(|p| p.contains("tmpfs")) {
+                    MmapDecision::Map
+                } else if path.to_str().is_some_and(|p| p.contains("nfs")) {
+                    MmapDecision::Read
+                } else {
+                    MmapDecision::Auto
+                }
+            }))
+            .build();
+        let file = File::open("/dev/null").unwrap();
+        assert!(worker.use_mmap(Path::new("/tmpfs/a"), &file, None));
+        assert!(!worker.use_mmap(Path::new("/nfs/a"), &file, None));
+        // Auto defers to the configured default (off here).
+        assert!(!worker.use_mmap(Path::new("/other/a"), &file, None));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn force_stream_never_maps() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::{SearchStrategy, WorkerBuilder};
+
+        // Even with mmap enabled, a forced Stream strategy must never touch
+        // the provider.
+        let path = tmpdir("force-stream").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\nbar\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let md = file.metadata().unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap(true)
+            .mmap_provider(Box::new(PanicProvider))
+            .force_strategy(Some(SearchStrategy::Stream))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file_with_metadata(&mut pp, &path, &file, &md)
+            .unwrap();
+        assert_eq!(1, count);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mmap_always_bypasses_heuristics() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        // Even with mmap disabled, `mmap_always` maps. The fake provider
+        // serves different content than the file, so the count proves
+        // which path ran.
+        let path = tmpdir("mmap-always").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let md = file.metadata().unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap(false)
+            .mmap_always(true)
+            .mmap_provider(Box::new(FakeProvider(b"foo\nfoo\nfoo\n")))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file_with_metadata(&mut pp, &path, &file, &md)
+            .unwrap();
+        assert_eq!(3, count);
+        assert_eq!(None, worker.last_mmap_fallback());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mmap_always_falls_back() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        // Without a strict flag, a declined map degrades to reads and the
+        // reason is queryable.
+        let path = tmpdir("mmap-always-fallback").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\nbar\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let md = file.metadata().unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap_always(true)
+            .mmap_provider(Box::new(NoProvider))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file_with_metadata(&mut pp, &path, &file, &md)
+            .unwrap();
+        assert_eq!(1, count);
+        assert!(worker.last_mmap_fallback().unwrap().contains("declined"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mmap_size_thresholds() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        // The file is 8 bytes. Outside the configured window the worker
+        // must stream (the provider would panic); inside it, the fake
+        // map's content proves the buffer path ran.
+        let path = tmpdir("mmap-thresholds").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\nbar\n").unwrap();
+
+        let run = |min: Option<u64>, max: Option<u64>, panic: bool| {
+            let file = File::open(&path).unwrap();
+            let md = file.metadata().unwrap();
+            let grep = GrepBuilder::new("foo").build().unwrap();
+            let provider: Box<dyn super::MmapProvider + Send + Sync> =
+                if panic {
+                    Box::new(PanicProvider)
+                } else {
+                    Box::new(FakeProvider(b"foo\nfoo\n"))
+                };
+            let mut worker = WorkerBuilder::new(grep)
+                .mmap(true)
+                .mmap_provider(provider)
+                .mmap_min_file_size(min)
+                .mmap_max_file_size(max)
+                .build();
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf);
+            worker
+                .search_file_with_metadata(
+                    &mut pp, &path, &file, &md)
+                .unwrap()
+        };
+        // Below the minimum and above the maximum: streamed.
+        assert_eq!(1, run(Some(100), None, true));
+        assert_eq!(1, run(None, Some(4), true));
+        // Inside the window: mapped.
+        assert_eq!(2, run(Some(4), Some(100), false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn buffer_capacity_streams() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        // A tiny initial capacity is only a starting point; the buffer
+        // grows on demand and the search is unaffected.
+        let path = tmpdir("buffer-capacity").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\nbar\nfoo\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let md = file.metadata().unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .buffer_capacity(Some(1))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file_with_metadata(&mut pp, &path, &file, &md)
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn search_map_reuses_caller_slice() {
+        use std::path::Path;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let worker = WorkerBuilder::new(grep).build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_map(&mut pp, Path::new("map"), b"foo\nbar\nfoo\n")
+            .unwrap();
+        assert_eq!(2, count);
+
+        // Binary detection sniffs the prefix, as on the internal path.
+        let count = worker
+            .search_map(&mut pp, Path::new("map"), b"foo\x00\nfoo\n")
+            .unwrap();
+        assert_eq!(0, count);
+    }
+
+    #[test]
+    fn search_map_rejects_transcoding() {
+        use std::path::Path;
+
+        use encoding_rs::UTF_16LE;
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let worker = WorkerBuilder::new(grep)
+            .encoding(Some(UTF_16LE))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let err = worker
+            .search_map(&mut pp, Path::new("map"), b"f\x00o\x00o\x00")
+            .unwrap_err();
+        assert!(err.to_string().contains("transcoding"));
+    }
+
+    #[test]
+    fn search_map_shared_across_threads() {
+        use std::path::Path;
+        use std::sync::Arc;
+        use std::thread;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        // One un-cloned worker serves all threads through the shared
+        // reference.
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let worker =
+            Arc::new(WorkerBuilder::new(grep).line_number(true).build());
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let worker = worker.clone();
+                thread::spawn(move || {
+                    let outbuf = termcolor::NoColor::new(vec![]);
+                    let mut pp = Printer::new(outbuf);
+                    let count = worker
+                        .search_map(
+                            &mut pp,
+                            Path::new("map"),
+                            b"foo\nbar\nfoo\n",
+                        )
+                        .unwrap();
+                    (count, pp.into_inner().into_inner())
+                })
+            })
+            .collect();
+        let results: Vec<_> =
+            handles.into_iter().map(|h| h.join().unwrap()).collect();
+        for result in &results {
+            assert_eq!(results[0], *result);
+            assert_eq!(2, result.0);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn search_file_presizes_buffer() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep).build();
+
+        // A file several times the default read size, so the incremental
+        // path would otherwise take many fills.
+        let dir = tmpdir("search-file-presizes");
+        let path = dir.join("big");
+        let mut tmp = File::create(&path).unwrap();
+        for _ in 0..1000 {
+            tmp.write_all(b"foo and some padding to fill the file\n")
+                .unwrap();
+        }
+        let file = File::open(&path).unwrap();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file(&mut pp, &path, &file)
+            .unwrap();
+        assert_eq!(1000, count);
+
+        // A file smaller than the default read size works the same.
+        let path = dir.join("small");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\nbar\nfoo\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file(&mut pp, &path, &file)
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn search_path_opens_and_reports() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        let dir = tmpdir("search-path");
+        let path = dir.join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\nbar\nfoo\n").unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep).build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker.search_path(&mut pp, &path).unwrap();
+        assert_eq!(2, count);
+
+        // Open errors come back to the caller, naming the path.
+        let err = worker
+            .search_path(&mut pp, dir.join("does-not-exist"))
+            .unwrap_err();
+        assert!(err.to_string().contains("does-not-exist"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn clones_agree_across_threads() {
+        use std::io::Write;
+        use std::thread;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        let path = tmpdir("clone").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\nbar\nfoo\n").unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let worker = WorkerBuilder::new(grep).line_number(true).build();
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let mut worker = worker.clone();
+                let path = path.clone();
+                thread::spawn(move || {
+                    let outbuf = termcolor::NoColor::new(vec![]);
+                    let mut pp = Printer::new(outbuf);
+                    let count = worker.search_path(&mut pp, &path).unwrap();
+                    (count, pp.into_inner().into_inner())
+                })
+            })
+            .collect();
+        let results: Vec<_> =
+            handles.into_iter().map(|h| h.join().unwrap()).collect();
+        for result in &results {
+            assert_eq!(results[0], *result);
+            assert_eq!(2, result.0);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn force_mmap_uses_map() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::{SearchStrategy, WorkerBuilder};
+
+        // With a forced Mmap strategy and a fake provider, the search must
+        // run over the provider's bytes, proving which core ran.
+        let path = tmpdir("force-mmap").join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"zzz\n").unwrap();
+        let file = File::open(&path).unwrap();
+        let md = file.metadata().unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap_provider(Box::new(FakeProvider(b"foo\nfoo\nbar\n")))
+            .force_strategy(Some(SearchStrategy::Mmap))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file_with_metadata(&mut pp, &path, &file, &md)
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn force_mmap_impossible_inputs() {
+        use std::io::Write;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::{SearchStrategy, WorkerBuilder};
+
+        let dir = tmpdir("force-mmap-impossible");
+        let path = dir.join("haystack");
+        let mut tmp = File::create(&path).unwrap();
+        tmp.write_all(b"foo\n").unwrap();
+        let file = File::open(&path).unwrap();
+
+        // A provider that declines must fail instead of falling back.
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap_provider(Box::new(NoProvider))
+            .force_strategy(Some(SearchStrategy::Mmap))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let err = worker
+            .search_mmap(&mut pp, &path, &file, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("forced Mmap strategy"),
+                "{}", err);
+
+        // An empty file can never be mapped.
+        let empty_path = dir.join("empty");
+        File::create(&empty_path).unwrap();
+        let empty = File::open(&empty_path).unwrap();
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap_provider(Box::new(FakeProvider(b"foo\n")))
+            .force_strategy(Some(SearchStrategy::Mmap))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let err = worker
+            .search_mmap(&mut pp, &empty_path, &empty, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("empty files"), "{}", err);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn advise_dontneed_is_a_hint() {
+        // The advice must never fail a search, even on odd files.
+        let file = File::open("/dev/null").unwrap();
+        super::advise_dontneed(&file);
+    }
+
+    #[test]
+    fn scratch_shared_across_workers() {
+        use std::mem;
+        use std::path::Path;
+
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::{SearchScratch, WorkerBuilder};
+
+        // Undersized scratch must be repaired before use.
+        let mut scratch = SearchScratch::new();
+        scratch.decodebuf.clear();
+        scratch.prepare(b'\n');
+        assert!(scratch.decodebuf.len() >= 8 * (1 << 10));
+
+        // Two differently-configured workers can share it.
+        for &invert in &[false, true] {
+            let grep = GrepBuilder::new("foo").build().unwrap();
+            let mut worker = WorkerBuilder::new(grep)
+                .invert_match(invert)
+                .build();
+            mem::swap(&mut worker.scratch, &mut scratch);
+            let outbuf = termcolor::NoColor::new(vec![]);
+            let mut pp = Printer::new(outbuf);
+            let count = worker
+                .search(
+                    &mut pp,
+                    Path::new("x"),
+                    io::Cursor::new(b"foo\nbar\n".to_vec()))
+                .unwrap();
+            mem::swap(&mut worker.scratch, &mut scratch);
+            assert_eq!(1, count);
+        }
+    }
+
+    /// A provider that fails the test if the worker tries to map at all.
+    struct PanicProvider;
+
+    impl MmapProvider for PanicProvider {
+        fn map(&self, _: &File) -> io::Result<Option<MappedSlice>> {
+            panic!("no mapping should be attempted");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn prefetched_metadata_trusted() {
+        use grep::GrepBuilder;
+        use printer::Printer;
+        use termcolor;
+
+        use super::WorkerBuilder;
+
+        // An empty file with caller-provided metadata must take the
+        // empty-file fallback without mapping or re-fetching metadata.
+        let path = tmpdir("prefetched-metadata").join("haystack");
+        File::create(&path).unwrap();
+        let file = File::open(&path).unwrap();
+        let md = file.metadata().unwrap();
+
+        let grep = GrepBuilder::new("foo").build().unwrap();
+        let mut worker = WorkerBuilder::new(grep)
+            .mmap(true)
+            .mmap_provider(Box::new(PanicProvider))
+            .build();
+        let outbuf = termcolor::NoColor::new(vec![]);
+        let mut pp = Printer::new(outbuf);
+        let count = worker
+            .search_file_with_metadata(&mut pp, &path, &file, &md)
+            .unwrap();
+        assert_eq!(0, count);
+    }
+}
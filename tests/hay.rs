@@ -1,4 +1,4 @@
-pub const SHERLOCK: &'static str = "\
+pub const SHERLOCK: &str = "\
 For the Doctor Watsons of this world, as opposed to the Sherlock
 Holmeses, success in the province of detective work must always
 be, to a very large extent, the result of luck. Sherlock Holmes
@@ -7,7 +7,7 @@ but Doctor Watson has to have it taken out for him and dusted,
 and exhibited clearly, with a label attached.
 ";
 
-pub const CODE: &'static str = "\
+pub const CODE: &str = "\
 extern crate snap;
 
 use std::io;
@@ -1055,7 +1055,7 @@ clean!(
     r#"#(?:parse|include)\s*\(\s*(?:"|')[./A-Za-z_-]+(?:"|')"#,
     "testcase.txt",
 |wd: WorkDir, mut cmd: Command| {
-    const TESTCASE: &'static str = r#"#parse('widgets/foo_bar_macros.vm')
+    const TESTCASE: &str = r#"#parse('widgets/foo_bar_macros.vm')
 #parse ( 'widgets/mobile/foo_bar_macros.vm' )
 #parse ("widgets/foobarhiddenformfields.vm")
 #parse ( "widgets/foo_bar_legal.vm" )
@@ -1285,7 +1285,7 @@ and exhibited clearly, with a label attached.
 // See: https://github.com/BurntSushi/ripgrep/issues/599
 clean!(regression_599, "^$", "input.txt", |wd: WorkDir, mut cmd: Command| {
     wd.create("input.txt", "\n\ntest\n");
-    cmd.args(&[
+    cmd.args([
         "--color", "ansi",
         "--colors", "path:none",
         "--colors", "line:none",
@@ -1352,6 +1352,53 @@ clean!(feature_1_utf16_explicit, "Шерлок Холмс", ".",
     assert_eq!(lines, "foo:Шерлок Холмс\n");
 });
 
+// An explicit encoding must disable the memory map searcher, which has no
+// transcoding layer. Without the fallback, binary detection sees the NULs
+// inherent to UTF-16 and skips the file entirely.
+clean!(feature_1_utf16_explicit_mmap, "Шерлок Холмс", ".",
+|wd: WorkDir, mut cmd: Command| {
+    let sherlock =
+        b"(\x045\x04@\x04;\x04>\x04:\x04 \x00%\x04>\x04;\x04<\x04A\x04";
+    wd.create_bytes("foo", &sherlock[..]);
+    cmd.arg("-Eutf-16le").arg("--mmap");
+
+    let lines: String = wd.stdout(&mut cmd);
+    assert_eq!(lines, "foo:Шерлок Холмс\n");
+});
+
+// Binary detection runs over the transcoded bytes, so a file that decodes to
+// text containing a NUL (an encoded U+0000) is still considered binary. The
+// search stops there, but matches on lines before the NUL are still reported.
+clean!(feature_1_utf16_binary, "foo", ".",
+|wd: WorkDir, mut cmd: Command| {
+    wd.create_bytes("foo", &b"f\x00o\x00o\x00\n\x00\x00\x00"[..]);
+    cmd.arg("-Eutf-16le").arg("--mmap");
+
+    let lines: String = wd.stdout(&mut cmd);
+    assert_eq!(lines, "foo:foo\n");
+});
+
+// Standard input redirected from a regular file takes the file strategy
+// (including memory maps) instead of streaming, and must produce the same
+// results as a pipe.
+clean!(feature_1_stdin_redirected_file, "Sherlock", "-",
+|wd: WorkDir, mut cmd: Command| {
+    use std::fs::File;
+    use std::process::Stdio;
+
+    wd.create("sherlock", hay::SHERLOCK);
+    cmd.arg("--mmap");
+    cmd.stdin(Stdio::from(
+        File::open(wd.path().join("sherlock")).unwrap()));
+
+    let lines: String = wd.stdout(&mut cmd);
+    let expected = "\
+For the Doctor Watsons of this world, as opposed to the Sherlock
+be, to a very large extent, the result of luck. Sherlock Holmes
+";
+    assert_eq!(lines, expected);
+});
+
 // See: https://github.com/BurntSushi/ripgrep/issues/1
 clean!(feature_1_eucjp, "Шерлок Холмс", ".",
 |wd: WorkDir, mut cmd: Command| {
@@ -1827,7 +1874,7 @@ fn compressed_failing_gzip() {
 
     let output = cmd.output().unwrap();
     let err = String::from_utf8_lossy(&output.stderr);
-    assert_eq!(err.contains("not in gzip format"), true);
+    assert!(err.contains("not in gzip format"));
 }
 
 sherlock!(feature_196_persistent_config, "sherlock",
@@ -1851,10 +1898,10 @@ sherlock!(feature_411_single_threaded_search_stats,
     cmd.arg("--stats");
 
     let lines: String = wd.stdout(&mut cmd);
-    assert_eq!(lines.contains("2 matched lines"), true);
-    assert_eq!(lines.contains("1 files contained matches"), true);
-    assert_eq!(lines.contains("1 files searched"), true);
-    assert_eq!(lines.contains("seconds"), true);
+    assert!(lines.contains("2 matched lines"));
+    assert!(lines.contains("1 files contained matches"));
+    assert!(lines.contains("1 files searched"));
+    assert!(lines.contains("seconds"));
 });
 
 #[test]
@@ -1869,10 +1916,10 @@ fn feature_411_parallel_search_stats() {
     cmd.arg("./");
 
     let lines: String = wd.stdout(&mut cmd);
-    assert_eq!(lines.contains("4 matched lines"), true);
-    assert_eq!(lines.contains("2 files contained matches"), true);
-    assert_eq!(lines.contains("2 files searched"), true);
-    assert_eq!(lines.contains("seconds"), true);
+    assert!(lines.contains("4 matched lines"));
+    assert!(lines.contains("2 files contained matches"));
+    assert!(lines.contains("2 files searched"));
+    assert!(lines.contains("seconds"));
 }
 
 sherlock!(feature_411_ignore_stats_1, |wd: WorkDir, mut cmd: Command| {
@@ -1880,7 +1927,7 @@ sherlock!(feature_411_ignore_stats_1, |wd: WorkDir, mut cmd: Command| {
     cmd.arg("--stats");
 
     let lines: String = wd.stdout(&mut cmd);
-    assert_eq!(lines.contains("seconds"), false);
+    assert!(!lines.contains("seconds"));
 });
 
 sherlock!(feature_411_ignore_stats_2, |wd: WorkDir, mut cmd: Command| {
@@ -1888,7 +1935,7 @@ sherlock!(feature_411_ignore_stats_2, |wd: WorkDir, mut cmd: Command| {
     cmd.arg("--stats");
 
     let lines: String = wd.stdout(&mut cmd);
-    assert_eq!(lines.contains("seconds"), false);
+    assert!(!lines.contains("seconds"));
 });
 
 #[test]
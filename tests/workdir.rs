@@ -6,12 +6,12 @@ use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process;
 use std::str::FromStr;
-use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
 use std::time::Duration;
 
-static TEST_DIR: &'static str = "ripgrep-tests";
-static NEXT_ID: AtomicUsize = ATOMIC_USIZE_INIT;
+static TEST_DIR: &str = "ripgrep-tests";
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
 
 /// `WorkDir` represents a directory in which tests are run.
 ///
@@ -33,11 +33,11 @@ impl WorkDir {
         let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
         let root = env::current_exe().unwrap()
             .parent().expect("executable's directory").to_path_buf();
-        let dir = root.join(TEST_DIR).join(name).join(&format!("{}", id));
+        let dir = root.join(TEST_DIR).join(name).join(format!("{}", id));
         nice_err(&dir, repeat(|| fs::create_dir_all(&dir)));
         WorkDir {
-            root: root,
-            dir: dir,
+            root,
+            dir,
         }
     }
 
@@ -92,7 +92,7 @@ impl WorkDir {
     /// Creates a new command that is set to use the ripgrep executable in
     /// this working directory.
     pub fn command(&self) -> process::Command {
-        let mut cmd = process::Command::new(&self.bin());
+        let mut cmd = process::Command::new(self.bin());
         cmd.env_remove("RIPGREP_CONFIG_PATH");
         cmd.current_dir(&self.dir);
         cmd